@@ -0,0 +1,76 @@
+//! Lookups per second of the in memory providers while other threads write, run with
+//! `cargo bench --bench concurrent`.
+
+use outpost_config::provider::concurrent::ConcurrentProvider;
+use outpost_config::provider::in_memory::InMemoryProvider;
+use outpost_config::ConfigProvider;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const KEYS: usize = 1_000;
+const READERS: usize = 8;
+const WRITERS: usize = 2;
+const DURATION: Duration = Duration::from_secs(2);
+
+fn main() {
+    println!("{:<12} {:>16}", "provider", "lookups/s");
+    report(
+        "unsharded",
+        bench(Arc::new(InMemoryProvider::new().with_shards(1))),
+    );
+    report("in_memory", bench(Arc::new(InMemoryProvider::new())));
+    report("concurrent", bench(Arc::new(ConcurrentProvider::new())));
+}
+
+/// Count the lookups the readers complete while the writers keep changing random keys.
+fn bench<P>(provider: Arc<P>) -> f64
+where
+    P: ConfigProvider + Send + Sync + 'static,
+{
+    for i in 0..KEYS {
+        provider.put(&format!("route.{}", i), i).unwrap();
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let spawn = |write: bool, seed: usize| {
+        let provider = provider.clone();
+        let stop = stop.clone();
+        thread::spawn(move || {
+            let mut lookups = 0_u64;
+            let mut key = seed;
+            while !stop.load(Ordering::Relaxed) {
+                key = (key * 7919 + 1) % KEYS;
+                let key = format!("route.{}", key);
+                if write {
+                    provider.put(&key, lookups).unwrap();
+                } else {
+                    black_box(provider.get::<u64>(&key).unwrap());
+                }
+                lookups += 1;
+            }
+            if write {
+                0
+            } else {
+                lookups
+            }
+        })
+    };
+
+    let threads: Vec<_> = (0..READERS)
+        .map(|seed| spawn(false, seed))
+        .chain((0..WRITERS).map(|seed| spawn(true, seed)))
+        .collect();
+    let start = Instant::now();
+    thread::sleep(DURATION);
+    stop.store(true, Ordering::Relaxed);
+
+    let lookups: u64 = threads.into_iter().map(|t| t.join().unwrap()).sum();
+    lookups as f64 / start.elapsed().as_secs_f64()
+}
+
+fn report(name: &str, lookups_per_sec: f64) {
+    println!("{:<12} {:>16.0}", name, lookups_per_sec);
+}
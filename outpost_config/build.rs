@@ -0,0 +1,40 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc::compile();
+}
+
+/// The `ConfigService` client and server, generated from the service description below as
+/// there is no `.proto` file to compile. The messages live in `src/grpc.rs`.
+#[cfg(feature = "grpc")]
+mod grpc {
+    use tonic_build::manual::{Builder, Method, MethodBuilder, Service};
+
+    fn method(name: &str, route: &str, input: &str, output: &str) -> MethodBuilder {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::{}", input))
+            .output_type(format!("crate::grpc::{}", output))
+            .codec_path("tonic::codec::ProstCodec")
+    }
+
+    pub fn compile() {
+        let service = Service::builder()
+            .name("ConfigService")
+            .package("outpost.config")
+            .method(method("get", "Get", "KeyRequest", "GetResponse").build())
+            .method(method("version", "Version", "KeyRequest", "VersionResponse").build())
+            .method(method("put", "Put", "PutRequest", "Written").build())
+            .method(method("delete", "Delete", "KeyRequest", "Written").build())
+            .method(method("list", "List", "ListRequest", "ListResponse").build())
+            .method(
+                method("watch", "Watch", "WatchRequest", "WatchEvent")
+                    .server_streaming()
+                    .build(),
+            )
+            .build();
+
+        // the generated `connect` relies on the 2021 prelude, channels are connected by hand
+        Builder::new().build_transport(false).compile(&[service]);
+    }
+}
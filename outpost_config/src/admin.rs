@@ -0,0 +1,669 @@
+//! HTTP admin API managing the config of a provider remotely
+//!
+//! | Request                   | Answer                                                  |
+//! |---------------------------|---------------------------------------------------------|
+//! | `GET /config/{key}`       | the value as JSON, its version as `ETag`                |
+//! | `PUT /config/{key}`       | stores the JSON body, `If-Match` only if the version matches |
+//! | `DELETE /config/{key}`    | removes the key                                         |
+//! | `GET /config?prefix={p}`  | an object of all keys below the prefix and their values |
+//! | `GET /stats`              | the [`ProviderStats`](crate::ProviderStats) of the provider, times in unix seconds |
+//!
+//! Errors are answered with `{"error": "..."}` and the status matching the `ConfigError`, e.g.
+//! 404 for a missing key or 412 if the version given in `If-Match` is outdated. The server speaks
+//! plain HTTP/1.1 with one request per connection, put it behind a TLS terminating proxy when
+//! it's reachable from outside.
+//!
+//! Slow or oversized requests can't tie up the server: connections are answered by a bounded
+//! number of threads, reads and writes time out, and the request line, headers and body are
+//! limited in size. The bearer token is checked before the body is read.
+
+use crate::{constant_time_eq, ConfigError, ConfigProvider};
+use serde_json::{json, Map, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Largest request body accepted, config values are small.
+const MAX_BODY: usize = 1 << 20;
+
+/// Longest request line or header line accepted, including the line break.
+const MAX_LINE: usize = 8 << 10;
+
+/// Most header lines accepted in a request.
+const MAX_HEADERS: usize = 64;
+
+/// Connections answered at the same time by default.
+const DEFAULT_MAX_CONNECTIONS: usize = 16;
+
+/// How long reading a request or writing an answer may stall by default.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP admin API in front of a provider
+pub struct AdminApi<P> {
+    provider: Arc<P>,
+    token: Option<String>,
+    max_connections: usize,
+    timeout: Duration,
+}
+
+/// A parsed request.
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    authorization: Option<String>,
+    if_match: Option<String>,
+    content_length: usize,
+    body: Vec<u8>,
+}
+
+/// Counting semaphore limiting the connections answered at once.
+struct Slots {
+    free: Mutex<usize>,
+    freed: Condvar,
+}
+
+/// A taken slot, given back when dropped.
+struct Slot<'a>(&'a Slots);
+
+/// An answer to a request.
+struct Response {
+    status: u16,
+    etag: Option<String>,
+    body: Option<Value>,
+}
+
+impl<P> AdminApi<P>
+where
+    P: ConfigProvider + Send + Sync + 'static,
+{
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            token: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Only answer requests sending the token as `Authorization: Bearer <token>`.
+    pub fn with_bearer_token<S>(mut self, token: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Answer at most the given number of connections at once, at least one. Further
+    /// connections wait to be accepted until one of them is answered.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
+    /// Drop connections that stall reading the request or receiving the answer for longer than
+    /// the given duration.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Answer the connections of the listener until accepting one fails, each one on its own
+    /// thread while at most the configured number of them are answered at once.
+    pub fn serve(self, listener: TcpListener) -> Result<(), ConfigError> {
+        let slots = Arc::new(Slots {
+            free: Mutex::new(self.max_connections),
+            freed: Condvar::new(),
+        });
+        let api = Arc::new(self);
+        loop {
+            slots.wait();
+            let (stream, _) = listener.accept().map_err(|err| {
+                slots.release();
+                ConfigError::io("admin", err)
+            })?;
+            let api = Arc::clone(&api);
+            let slots = Arc::clone(&slots);
+            thread::spawn(move || {
+                let _slot = Slot(&slots);
+                // the client is gone if the answer can't be sent, there is nobody to tell
+                let _ = api.answer(stream);
+            });
+        }
+    }
+
+    fn answer(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let mut reader = BufReader::new(stream);
+
+        let response = match read_head(&mut reader) {
+            Ok(mut request) => {
+                if !self.authorized(&request) {
+                    error(401, "missing or wrong bearer token")
+                } else if request.content_length > MAX_BODY {
+                    error(413, "body too large")
+                } else {
+                    match read_body(&mut reader, &mut request) {
+                        Ok(()) => self.handle(&request),
+                        Err(err) => error(400, &err.to_string()),
+                    }
+                }
+            }
+            Err(err) => error(400, &err.to_string()),
+        };
+
+        write_response(reader.get_mut(), &response)?;
+
+        // closing with unread data resets the connection, which may discard the answer, so a
+        // refused request is drained a little until the client hangs up or times out
+        reader.get_mut().shutdown(Shutdown::Write)?;
+        io::copy(&mut reader.take(MAX_LINE as u64), &mut io::sink())?;
+
+        Ok(())
+    }
+
+    /// Whether the request carries the bearer token, compared in constant time.
+    fn authorized(&self, request: &Request) -> bool {
+        let token = match &self.token {
+            Some(token) => token,
+            None => return true,
+        };
+        let sent = match request.authorization.as_deref() {
+            Some(authorization) => authorization.strip_prefix("Bearer ").unwrap_or(""),
+            None => "",
+        };
+
+        constant_time_eq(sent.as_bytes(), token.as_bytes())
+    }
+
+    fn handle(&self, request: &Request) -> Response {
+        let result = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/config") => self.list(request),
+            ("GET", "/stats") => self.stats(),
+            (method, path) => match path.strip_prefix("/config/") {
+                Some(key) if !key.is_empty() => {
+                    let key = percent_decode(key);
+                    match method {
+                        "GET" => self.get(&key),
+                        "PUT" => self.put(&key, request),
+                        "DELETE" => self.delete(&key),
+                        _ => return error(405, "method not allowed"),
+                    }
+                }
+                _ => return error(404, "no such route"),
+            },
+        };
+
+        result.unwrap_or_else(|err| error(status_of(&err), &err.to_string()))
+    }
+
+    fn get(&self, key: &str) -> Result<Response, ConfigError> {
+        let value = self.provider.get_value(key)?;
+
+        Ok(Response {
+            status: 200,
+            etag: self.provider.version(key)?,
+            body: Some(value),
+        })
+    }
+
+    fn put(&self, key: &str, request: &Request) -> Result<Response, ConfigError> {
+        let value: Value = serde_json::from_slice(&request.body)
+            .map_err(|err| ConfigError::deserialization("admin", err).with_key(key))?;
+
+        match request.if_match.as_deref() {
+            Some("*") => match self.provider.version(key)? {
+                Some(version) => self.provider.put_if_version(key, value, Some(&version))?,
+                None => return Err(ConfigError::conflict("admin", key)),
+            },
+            Some(version) => self.provider.put_if_version(key, value, Some(version))?,
+            None => self.provider.put_value(key, value)?,
+        }
+
+        Ok(Response {
+            status: 204,
+            etag: self.provider.version(key)?,
+            body: None,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<Response, ConfigError> {
+        self.provider.delete(key)?;
+
+        Ok(Response {
+            status: 204,
+            etag: None,
+            body: None,
+        })
+    }
+
+    fn list(&self, request: &Request) -> Result<Response, ConfigError> {
+        let prefix = request
+            .query
+            .as_deref()
+            .unwrap_or("")
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("prefix="))
+            .map(|prefix| percent_decode(&prefix.replace('+', " ")))
+            .unwrap_or_default();
+        let mut entries = self.provider.entries(&prefix)?;
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let entries: Map<String, Value> = entries.into_iter().collect();
+
+        Ok(Response {
+            status: 200,
+            etag: None,
+            body: Some(Value::Object(entries)),
+        })
+    }
+
+    fn stats(&self) -> Result<Response, ConfigError> {
+        let stats = self.provider.stats()?;
+
+        Ok(Response {
+            status: 200,
+            etag: None,
+            body: Some(json!({
+                "keys": stats.keys,
+                "bytes": stats.bytes,
+                "hits": stats.hits,
+                "misses": stats.misses,
+                "last_load": unix_seconds(stats.last_load),
+                "last_save": unix_seconds(stats.last_save),
+            })),
+        })
+    }
+}
+
+/// Seconds since the unix epoch, `null` if the time is unknown.
+fn unix_seconds(time: Option<SystemTime>) -> Value {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(Value::Null, |since| json!(since.as_secs_f64()))
+}
+
+/// The HTTP status answering a failed operation.
+fn status_of(err: &ConfigError) -> u16 {
+    match err {
+        ConfigError::NotFound { .. } => 404,
+        ConfigError::Deserialization { .. } | ConfigError::InvalidKey { .. } => 400,
+        ConfigError::PermissionDenied { .. } => 403,
+        ConfigError::ReadOnly { .. } => 405,
+        ConfigError::Conflict { .. } => 412,
+        ConfigError::QuotaExceeded { .. } => 413,
+        ConfigError::Validation { .. } => 422,
+        ConfigError::Serialization { .. }
+        | ConfigError::Corrupted { .. }
+        | ConfigError::Io { .. }
+        | ConfigError::Backend { .. } => 500,
+    }
+}
+
+fn error(status: u16, message: &str) -> Response {
+    Response {
+        status,
+        etag: None,
+        body: Some(json!({ "error": message })),
+    }
+}
+
+impl Slots {
+    /// Wait for a free slot and take it, it is given back with `release`.
+    fn wait(&self) {
+        let mut free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+        while *free == 0 {
+            free = self
+                .freed
+                .wait(free)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *free -= 1;
+    }
+
+    fn release(&self) {
+        *self.free.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+        self.freed.notify_one();
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Read a line of at most `MAX_LINE` bytes, failing for longer ones. Returns 0 at the end of
+/// the stream.
+fn read_line<R>(reader: &mut R, line: &mut String) -> io::Result<usize>
+where
+    R: BufRead,
+{
+    line.clear();
+    let read = reader.take(MAX_LINE as u64).read_line(line)?;
+    if read == MAX_LINE && !line.ends_with('\n') {
+        return Err(invalid("line too long"));
+    }
+
+    Ok(read)
+}
+
+/// Read the request line and headers, the body is left to `read_body`.
+fn read_head<R>(reader: &mut R) -> io::Result<Request>
+where
+    R: BufRead,
+{
+    let mut line = String::new();
+    read_line(reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(invalid("malformed request line")),
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target, None),
+    };
+
+    let mut request = Request {
+        method,
+        path,
+        query,
+        authorization: None,
+        if_match: None,
+        content_length: 0,
+        body: Vec::new(),
+    };
+    let mut headers = 0;
+    loop {
+        if read_line(reader, &mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed header"))?;
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "authorization" => request.authorization = Some(value.to_string()),
+            "if-match" => request.if_match = Some(value.trim_matches('"').to_string()),
+            "content-length" => {
+                request.content_length = value.parse().map_err(|_| invalid("malformed length"))?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(request)
+}
+
+/// Read the body announced by the headers, whose length has to be checked against `MAX_BODY`
+/// beforehand.
+fn read_body<R>(reader: &mut R, request: &mut Request) -> io::Result<()>
+where
+    R: Read,
+{
+    request.body.resize(request.content_length, 0);
+    reader.read_exact(&mut request.body)
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        412 => "Precondition Failed",
+        413 => "Content Too Large",
+        422 => "Unprocessable Entity",
+        _ => "Internal Server Error",
+    };
+    let body = response
+        .body
+        .as_ref()
+        .map(Value::to_string)
+        .unwrap_or_default();
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nConnection: close\r\n",
+        response.status, reason
+    );
+    if let Some(etag) = &response.etag {
+        head.push_str(&format!("ETag: \"{}\"\r\n", etag));
+    }
+    if response.status == 401 {
+        head.push_str("WWW-Authenticate: Bearer\r\n");
+    }
+    if response.body.is_some() {
+        head.push_str("Content-Type: application/json\r\n");
+    }
+    head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+/// Decode the `%XX` escapes of a path segment or query value, malformed escapes are kept
+/// verbatim.
+fn percent_decode(raw: &str) -> String {
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+
+    /// Send a request to the server and return the status line, headers and body.
+    fn send(addr: &str, request: &str) -> (u16, String, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).unwrap();
+
+        let (head, body) = answer.split_once("\r\n\r\n").unwrap();
+        let status = head[9..12].parse().unwrap();
+        (status, head.to_string(), body.to_string())
+    }
+
+    fn put(addr: &str, key: &str, body: &str, if_match: &str) -> u16 {
+        let request = format!(
+            "PUT /config/{} HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n{}Content-Length: {}\r\n\r\n{}",
+            key,
+            if_match,
+            body.len(),
+            body
+        );
+        send(addr, &request).0
+    }
+
+    /// Serve the API on a free port and return its address.
+    fn start(api: AdminApi<InMemoryProvider>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || api.serve(listener));
+        addr
+    }
+
+    #[test]
+    fn oversized_requests_are_refused() {
+        let addr = start(AdminApi::new(InMemoryProvider::new()));
+
+        let long_path = format!("GET /config/{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(send(&addr, &long_path).0, 400);
+        let many_headers = format!(
+            "GET /stats HTTP/1.1\r\n{}\r\n",
+            "X-Filler: 1\r\n".repeat(100)
+        );
+        assert_eq!(send(&addr, &many_headers).0, 400);
+
+        // the length is refused before any of the body is sent
+        let large = format!(
+            "PUT /config/blob HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert_eq!(send(&addr, &large).0, 413);
+    }
+
+    #[test]
+    fn the_token_is_checked_before_the_body_is_read() {
+        let addr = start(AdminApi::new(InMemoryProvider::new()).with_bearer_token("s3cret"));
+
+        // the body never arrives, yet the answer does
+        let request = "PUT /config/workers HTTP/1.1\r\nAuthorization: Bearer guess\r\nContent-Length: 100\r\n\r\n";
+        assert_eq!(send(&addr, request).0, 401);
+        assert_eq!(
+            send(
+                &addr,
+                "GET /stats HTTP/1.1\r\nAuthorization: s3cret\r\n\r\n"
+            )
+            .0,
+            401
+        );
+
+        assert!(constant_time_eq(b"s3cret", b"s3cret"));
+        assert!(!constant_time_eq(b"s3cret", b"s3creT"));
+        assert!(!constant_time_eq(b"s3cret", b"s3cre"));
+    }
+
+    #[test]
+    fn stalled_clients_are_dropped() {
+        let addr = start(
+            AdminApi::new(InMemoryProvider::new())
+                .with_max_connections(1)
+                .with_timeout(Duration::from_millis(100)),
+        );
+
+        // holds the only slot without ever finishing its request
+        let mut stalled = TcpStream::connect(&addr).unwrap();
+        stalled.write_all(b"GET /stats HTTP/1.1\r\n").unwrap();
+
+        // answered once the stalled client timed out
+        assert_eq!(send(&addr, "GET /stats HTTP/1.1\r\n\r\n").0, 200);
+        let mut answer = String::new();
+        stalled.read_to_string(&mut answer).unwrap();
+        assert!(answer.starts_with("HTTP/1.1 400"), "{}", answer);
+    }
+
+    /// Send an authorized request without a body.
+    fn call(addr: &str, method: &str, path: &str) -> (u16, String, String) {
+        let request = format!(
+            "{} {} HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n",
+            method, path
+        );
+        send(addr, &request)
+    }
+
+    fn start_authorized() -> String {
+        start(AdminApi::new(InMemoryProvider::new()).with_bearer_token("s3cret"))
+    }
+
+    #[test]
+    fn written_values_are_read_back() {
+        let addr = start_authorized();
+
+        assert_eq!(put(&addr, "policy.admin", r#"{"allow": ["ops"]}"#, ""), 204);
+
+        let (status, _, body) = call(&addr, "GET", "/config/policy%2Eadmin");
+        assert_eq!(status, 200);
+        assert_eq!(body, r#"{"allow":["ops"]}"#);
+    }
+
+    #[test]
+    fn invalid_json_is_refused() {
+        let addr = start_authorized();
+
+        assert_eq!(put(&addr, "policy.broken", "{", ""), 400);
+    }
+
+    #[test]
+    fn stale_versions_are_refused() {
+        let addr = start_authorized();
+        assert_eq!(put(&addr, "policy.admin", r#"{"allow": ["ops"]}"#, ""), 204);
+        let (_, head, _) = call(&addr, "GET", "/config/policy.admin");
+        let etag = head
+            .lines()
+            .find_map(|line| line.strip_prefix("ETag: "))
+            .unwrap()
+            .to_string();
+
+        // a second admin saves first
+        let fresh = format!("If-Match: {}\r\n", etag);
+        assert_eq!(put(&addr, "policy.admin", "{}", &fresh), 204);
+        assert_eq!(put(&addr, "policy.admin", r#"{"allow": []}"#, &fresh), 412);
+    }
+
+    #[test]
+    fn keys_are_listed_by_prefix() {
+        let addr = start_authorized();
+        assert_eq!(put(&addr, "policy.admin", "{}", ""), 204);
+        assert_eq!(put(&addr, "policy.guest", r#""deny""#, ""), 204);
+        assert_eq!(put(&addr, "workers", "4", ""), 204);
+
+        let (status, _, body) = call(&addr, "GET", "/config?prefix=policy.");
+        assert_eq!(status, 200);
+        assert_eq!(body, r#"{"policy.admin":{},"policy.guest":"deny"}"#);
+    }
+
+    #[test]
+    fn stats_count_the_keys() {
+        let addr = start_authorized();
+        assert_eq!(put(&addr, "policy.admin", "{}", ""), 204);
+        assert_eq!(put(&addr, "policy.guest", r#""deny""#, ""), 204);
+
+        let (status, _, body) = call(&addr, "GET", "/stats");
+        assert_eq!(status, 200);
+        let stats: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(stats["keys"], 2);
+        assert_eq!(stats["last_save"], Value::Null);
+    }
+
+    #[test]
+    fn deleted_keys_are_not_found() {
+        let addr = start_authorized();
+        assert_eq!(put(&addr, "policy.guest", r#""deny""#, ""), 204);
+
+        assert_eq!(call(&addr, "DELETE", "/config/policy.guest").0, 204);
+        assert_eq!(call(&addr, "GET", "/config/policy.guest").0, 404);
+    }
+
+    #[test]
+    fn requests_without_the_token_are_refused() {
+        let addr = start_authorized();
+
+        assert_eq!(send(&addr, "GET /config HTTP/1.1\r\n\r\n").0, 401);
+    }
+}
@@ -0,0 +1,535 @@
+//! Minimal async HTTP/1.1 client for the async variants of the HTTP based providers
+//!
+//! Sends one request per connection with `Connection: close` and reads the whole answer, which
+//! is all the JSON APIs of the providers need, without depending on a large HTTP stack. `https`
+//! urls are served through rustls with the Mozilla roots. Answers with a body larger than 64 MiB
+//! are rejected.
+
+use crate::ConfigError;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, RootCertStore};
+use serde::de::DeserializeOwned;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time;
+use tokio_rustls::TlsConnector;
+
+/// How long a request may take by default, from connecting to the end of the answer.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest status or header line accepted, including the line break.
+const MAX_LINE: u64 = 16 << 10;
+
+/// Most header lines accepted in an answer.
+const MAX_HEADERS: usize = 128;
+
+/// Largest body accepted in an answer.
+const MAX_BODY: u64 = 64 << 20;
+
+/// Async HTTP client shared by the calls of a provider
+#[derive(Clone)]
+pub(crate) struct AsyncClient {
+    tls: TlsConnector,
+    timeout: Duration,
+}
+
+/// A complete answer of the server.
+pub(crate) struct AsyncResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// A scheme, host and port and the path of a url.
+#[derive(Debug, PartialEq)]
+struct Target {
+    tls: bool,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+/// An answer with a status signaling failure, like `ureq::Error::Status`.
+#[derive(Debug)]
+pub(crate) struct StatusError {
+    status: u16,
+    body: String,
+}
+
+impl AsyncClient {
+    pub(crate) fn new() -> Self {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .expect("the ring provider supports the default protocol versions")
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+
+        Self {
+            tls: TlsConnector::from(Arc::new(config)),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Fail requests taking longer than the given duration as a whole.
+    pub(crate) fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send the request and read the whole answer, whatever its status.
+    pub(crate) async fn send(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(String, String)],
+        body: Option<Vec<u8>>,
+    ) -> io::Result<AsyncResponse> {
+        let target = Target::parse(url)?;
+        let request = encode_request(method, &target, headers, body.as_deref());
+
+        let exchange = async {
+            let stream = TcpStream::connect((target.host.as_str(), target.port)).await?;
+            if target.tls {
+                let name = ServerName::try_from(target.host.clone())
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+                let stream = self.tls.connect(name, stream).await?;
+                exchange(stream, method, &request).await
+            } else {
+                exchange(stream, method, &request).await
+            }
+        };
+
+        time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "the request timed out"))?
+    }
+
+    /// Send a JSON body and fail for answers with an error status, see [`check`].
+    pub(crate) async fn send_json(
+        &self,
+        provider: &'static str,
+        method: &str,
+        url: &str,
+        headers: &[(String, String)],
+        body: &serde_json::Value,
+    ) -> Result<AsyncResponse, ConfigError> {
+        let mut headers = headers.to_vec();
+        headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        let body =
+            serde_json::to_vec(body).map_err(|err| ConfigError::serialization(provider, err))?;
+
+        let response = self
+            .send(method, url, &headers, Some(body))
+            .await
+            .map_err(|err| ConfigError::backend(provider, err))?;
+
+        check(provider, response)
+    }
+}
+
+impl AsyncResponse {
+    #[cfg(feature = "http")]
+    pub(crate) fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The value of the first header with the given name, compared case insensitively.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(crate) fn json<T>(&self, provider: &'static str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        serde_json::from_slice(&self.body)
+            .map_err(|err| ConfigError::deserialization(provider, err))
+    }
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the server answered with status {}: {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl Error for StatusError {}
+
+/// Map an answer with an error status to a ConfigError like [`http_error`](crate::http::http_error)
+/// does, rejected credentials to ConfigError::PermissionDenied and everything else to
+/// ConfigError::Backend.
+pub(crate) fn check(
+    provider: &'static str,
+    response: AsyncResponse,
+) -> Result<AsyncResponse, ConfigError> {
+    if response.status < 400 {
+        return Ok(response);
+    }
+
+    let err = StatusError {
+        status: response.status,
+        body: String::from_utf8_lossy(&response.body).into_owned(),
+    };
+    Err(match response.status {
+        401 | 403 => ConfigError::permission_denied(provider, err),
+        _ => ConfigError::backend(provider, err),
+    })
+}
+
+/// The status of a failed request, if the server answered it.
+#[cfg(feature = "http")]
+pub(crate) fn status_of(err: &ConfigError) -> Option<u16> {
+    let source: &(dyn Error + 'static) = match err {
+        ConfigError::Backend { source, .. } | ConfigError::PermissionDenied { source, .. } => {
+            source.as_ref()
+        }
+        _ => return None,
+    };
+
+    source.downcast_ref::<StatusError>().map(|err| err.status)
+}
+
+impl Target {
+    fn parse(url: &str) -> io::Result<Self> {
+        let invalid =
+            || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid url {}", url));
+
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            return Err(invalid());
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+
+        // an ipv6 address is enclosed in brackets, its colons don't separate the port
+        let (host, port) = match authority.rfind(':') {
+            Some(colon) if !authority[colon..].contains(']') => {
+                let port = authority[colon + 1..].parse().map_err(|_| invalid())?;
+                (&authority[..colon], Some(port))
+            }
+            _ => (authority, None),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            port: port.unwrap_or(if tls { 443 } else { 80 }),
+            path: path.to_string(),
+        })
+    }
+
+    /// The value of the `Host` header.
+    fn host_header(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+
+        match (self.tls, self.port) {
+            (true, 443) | (false, 80) => host,
+            (_, port) => format!("{}:{}", host, port),
+        }
+    }
+}
+
+fn encode_request(
+    method: &str,
+    target: &Target,
+    headers: &[(String, String)],
+    body: Option<&[u8]>,
+) -> Vec<u8> {
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+        method,
+        target.path,
+        target.host_header()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\n\r\n",
+        body.map_or(0, <[u8]>::len)
+    ));
+
+    let mut request = head.into_bytes();
+    request.extend_from_slice(body.unwrap_or_default());
+    request
+}
+
+async fn exchange<S>(mut stream: S, method: &str, request: &[u8]) -> io::Result<AsyncResponse>
+where
+    S: AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    stream.write_all(request).await?;
+    stream.flush().await?;
+
+    read_response(&mut BufReader::new(stream), method).await
+}
+
+/// Read the final answer to a request with the given method, skipping interim ones.
+async fn read_response<R>(reader: &mut R, method: &str) -> io::Result<AsyncResponse>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut response = read_head(reader).await?;
+    // interim answers (1xx) have no body and precede the final one
+    while response.status < 200 {
+        response = read_head(reader).await?;
+    }
+
+    // answers to HEAD and the ones without content only have the headers
+    if method == "HEAD" || response.status == 204 || response.status == 304 {
+        return Ok(response);
+    }
+
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let chunked = response
+        .header("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+    let length = response.header("Content-Length").map(str::parse::<u64>);
+
+    response.body = if chunked {
+        read_chunked(reader).await?
+    } else if let Some(length) = length {
+        let length = length.map_err(|_| invalid("malformed length"))?;
+        if length > MAX_BODY {
+            return Err(invalid("body too large"));
+        }
+        let mut body = vec![0; length as usize];
+        reader.read_exact(&mut body).await?;
+        body
+    } else {
+        // the connection is closed after the answer
+        let mut body = Vec::new();
+        reader.take(MAX_BODY + 1).read_to_end(&mut body).await?;
+        if body.len() as u64 > MAX_BODY {
+            return Err(invalid("body too large"));
+        }
+        body
+    };
+
+    Ok(response)
+}
+
+/// Read the status line and headers of an answer.
+async fn read_head<R>(reader: &mut R) -> io::Result<AsyncResponse>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let line = read_line(reader).await?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("malformed status line"))?;
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader).await?;
+        if line.trim_end().is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed header"))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    Ok(AsyncResponse {
+        status,
+        headers,
+        body: Vec::new(),
+    })
+}
+
+async fn read_chunked<R>(reader: &mut R) -> io::Result<Vec<u8>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader).await?;
+        let size = line.trim_end().split(';').next().unwrap_or("");
+        let size =
+            u64::from_str_radix(size.trim(), 16).map_err(|_| invalid("malformed chunk size"))?;
+        if size == 0 {
+            // skip the trailers up to the empty line ending the answer
+            while !read_line(reader).await?.trim_end().is_empty() {}
+            return Ok(body);
+        }
+        if body.len() as u64 + size > MAX_BODY {
+            return Err(invalid("body too large"));
+        }
+
+        let start = body.len();
+        body.resize(start + size as usize, 0);
+        reader.read_exact(&mut body[start..]).await?;
+        read_line(reader).await?;
+    }
+}
+
+/// Read a line of at most `MAX_LINE` bytes, failing at the end of the stream.
+async fn read_line<R>(reader: &mut R) -> io::Result<String>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = String::new();
+    let read = reader.take(MAX_LINE).read_line(&mut line).await?;
+    if read == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if !line.ends_with('\n') && read as u64 == MAX_LINE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn urls_are_split_into_targets() {
+        assert_eq!(
+            Target::parse("http://127.0.0.1:2379/v3/kv/range").unwrap(),
+            Target {
+                tls: false,
+                host: "127.0.0.1".to_string(),
+                port: 2379,
+                path: "/v3/kv/range".to_string(),
+            }
+        );
+        let target = Target::parse("https://config.example.com").unwrap();
+        assert_eq!((target.port, target.path.as_str()), (443, "/"));
+        assert_eq!(target.host_header(), "config.example.com");
+        let target = Target::parse("http://[::1]:8080/gatekeeper.json").unwrap();
+        assert_eq!((target.host.as_str(), target.port), ("::1", 8080));
+        assert_eq!(target.host_header(), "[::1]:8080");
+
+        assert!(Target::parse("ftp://example.com").is_err());
+        assert!(Target::parse("http://example.com:port/").is_err());
+    }
+
+    #[test]
+    fn answers_are_read_by_length_chunks_or_until_closed() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let answer = |raw: &'static str| {
+            runtime
+                .block_on(read_response(&mut BufReader::new(raw.as_bytes()), "GET"))
+                .unwrap()
+        };
+
+        let response = answer("HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 2\r\n\r\n{}");
+        assert_eq!(
+            (response.status, response.body.as_slice()),
+            (200, &b"{}"[..])
+        );
+        assert_eq!(response.header("etag"), Some("\"v1\""));
+
+        let response = answer(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"a\"\r\n3;x=y\r\n:1}\r\n0\r\n\r\n",
+        );
+        assert_eq!(response.json::<serde_json::Value>("http").unwrap()["a"], 1);
+
+        let response = answer("HTTP/1.0 404 Not Found\r\n\r\nno such key");
+        let err = check("etcd", response).err().unwrap();
+        #[cfg(feature = "http")]
+        assert_eq!(status_of(&err), Some(404));
+        assert!(err.to_string().contains("etcd"), "{}", err);
+
+        let response = answer("HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n");
+        assert!(matches!(
+            check("http", response),
+            Err(ConfigError::PermissionDenied { .. })
+        ));
+    }
+
+    #[test]
+    fn answers_without_content_have_no_body() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let answer = |raw: &'static str, method: &str| {
+            runtime
+                .block_on(read_response(&mut BufReader::new(raw.as_bytes()), method))
+                .unwrap()
+        };
+
+        // the length of a HEAD answer is the one a GET would have
+        let response = answer("HTTP/1.1 200 OK\r\nContent-Length: 42\r\n\r\n", "HEAD");
+        assert_eq!((response.status, response.body.len()), (200, 0));
+        let response = answer("HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\n\r\n", "GET");
+        assert_eq!((response.status, response.body.len()), (304, 0));
+        let response = answer("HTTP/1.1 204 No Content\r\n\r\n", "PUT");
+        assert_eq!((response.status, response.body.len()), (204, 0));
+
+        let response = answer(
+            "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}",
+            "PUT",
+        );
+        assert_eq!(
+            (response.status, response.body.as_slice()),
+            (200, &b"{}"[..])
+        );
+    }
+
+    #[test]
+    fn oversized_bodies_are_rejected() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let answer = |raw: String| {
+            runtime.block_on(read_response(&mut BufReader::new(raw.as_bytes()), "GET"))
+        };
+
+        let too_large = MAX_BODY + 1;
+        let err = answer(format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+            too_large
+        ))
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let err = answer(format!(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n",
+            too_large
+        ))
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,460 @@
+//! Minimal AWS client shared by the AWS backed providers
+//!
+//! Requests are signed with Signature Version 4 and sent through `ureq`, which keeps the
+//! providers synchronous and avoids pulling an async runtime into the config crate.
+
+use crate::ConfigError;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use ureq::{Agent, Response};
+
+/// Region, credentials and (optionally) a custom endpoint used to talk to AWS
+#[derive(Clone, Debug)]
+pub struct AwsConfig {
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    /// Overrides the regional endpoint, e.g. `http://localhost:4566` for localstack.
+    pub endpoint: Option<String>,
+}
+
+/// Error reported by an AWS service
+#[derive(Error, Debug)]
+#[error("{kind}: {message}")]
+pub struct AwsError {
+    pub kind: String,
+    pub message: String,
+}
+
+impl AwsError {
+    /// Rejected credentials become ConfigError::PermissionDenied, all other errors
+    /// ConfigError::Backend.
+    pub(crate) fn into_config_error(self, provider: &'static str) -> ConfigError {
+        let denied = self.kind.starts_with("AccessDenied")
+            || matches!(
+                self.kind.as_str(),
+                "UnrecognizedClientException"
+                    | "InvalidSignatureException"
+                    | "ExpiredTokenException"
+                    | "InvalidAccessKeyId"
+                    | "SignatureDoesNotMatch"
+            );
+
+        if denied {
+            ConfigError::permission_denied(provider, self)
+        } else {
+            ConfigError::backend(provider, self)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    #[serde(rename = "__type", default)]
+    kind: String,
+    #[serde(alias = "Message", default)]
+    message: String,
+}
+
+impl AwsConfig {
+    /// Read region and credentials from the standard `AWS_*` environment variables.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let var = |name: &str| {
+            env::var(name)
+                .map_err(|err| ConfigError::backend("aws", format!("can't read {}: {}", name, err)))
+        };
+
+        Ok(Self {
+            region: var("AWS_REGION").or_else(|_| var("AWS_DEFAULT_REGION"))?,
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            endpoint: env::var("AWS_ENDPOINT_URL").ok(),
+        })
+    }
+}
+
+/// Signing client for a single AWS service
+pub(crate) struct AwsClient {
+    agent: Agent,
+    config: AwsConfig,
+    service: &'static str,
+    /// Name of the provider using the client, reported in errors.
+    provider: &'static str,
+}
+
+impl AwsClient {
+    pub(crate) fn new(config: AwsConfig, service: &'static str, provider: &'static str) -> Self {
+        Self {
+            agent: Agent::new(),
+            config,
+            service,
+            provider,
+        }
+    }
+
+    #[cfg(feature = "s3")]
+    pub(crate) fn config(&self) -> &AwsConfig {
+        &self.config
+    }
+
+    /// Base url of the service, without a trailing slash.
+    #[cfg(any(feature = "dynamo", feature = "secrets-manager", feature = "ssm"))]
+    pub(crate) fn endpoint(&self) -> String {
+        match &self.config.endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => format!(
+                "https://{}.{}.amazonaws.com",
+                self.service, self.config.region
+            ),
+        }
+    }
+
+    /// Call an operation of a service speaking the AWS JSON protocol.
+    ///
+    /// Returns `None` if the service reported that the addressed resource does not exist.
+    #[cfg(any(feature = "dynamo", feature = "secrets-manager", feature = "ssm"))]
+    pub(crate) fn call_json<R>(
+        &self,
+        target: &str,
+        json_version: &str,
+        body: &serde_json::Value,
+    ) -> Result<Option<R>, ConfigError>
+    where
+        R: serde::de::DeserializeOwned,
+    {
+        let payload = serde_json::to_vec(body)
+            .map_err(|err| ConfigError::serialization(self.provider, err))?;
+        let content_type = format!("application/x-amz-json-{}", json_version);
+
+        let response = self.send(
+            "POST",
+            &format!("{}/", self.endpoint()),
+            &[],
+            &[
+                ("content-type", content_type.as_str()),
+                ("x-amz-target", target),
+            ],
+            &payload,
+        )?;
+
+        match response {
+            Ok(response) => response
+                .into_json()
+                .map(Some)
+                .map_err(|err| ConfigError::deserialization(self.provider, err)),
+            Err(error) if error.kind.contains("NotFound") => Ok(None),
+            Err(error) => Err(error.into_config_error(self.provider)),
+        }
+    }
+
+    /// Send a signed request to the given url, whose path has to be percent encoded already.
+    ///
+    /// Error answers of the service are returned as the inner `Err`, transport failures as
+    /// the outer one.
+    pub(crate) fn send(
+        &self,
+        method: &str,
+        url: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        payload: &[u8],
+    ) -> Result<Result<Response, AwsError>, ConfigError> {
+        let (host, path) = split_url(url);
+        let payload_hash = hex::encode(Sha256::digest(payload));
+        let (date_time, date) = timestamp(SystemTime::now());
+
+        let mut signed: Vec<(String, String)> = headers
+            .iter()
+            .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+            .collect();
+        signed.push(("host".to_string(), host.to_string()));
+        signed.push(("x-amz-date".to_string(), date_time.clone()));
+        signed.push(("x-amz-content-sha256".to_string(), payload_hash.clone()));
+        if let Some(token) = &self.config.session_token {
+            signed.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        signed.sort();
+
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, true), uri_encode(value, true)))
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let signed_headers = signed
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers: String = signed
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, path, canonical_query, canonical_headers, signed_headers, payload_hash
+        );
+
+        let scope = format!(
+            "{}/{}/{}/aws4_request",
+            date, self.config.region, self.service
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            date_time,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = [
+            date.as_str(),
+            self.config.region.as_str(),
+            self.service,
+            "aws4_request",
+        ]
+        .iter()
+        .fold(
+            format!("AWS4{}", self.config.secret_access_key).into_bytes(),
+            |key, part| hmac(&key, part.as_bytes()),
+        );
+        let signature = hex::encode(hmac(&key, string_to_sign.as_bytes()));
+
+        let url = if canonical_query.is_empty() {
+            url.to_string()
+        } else {
+            format!("{}?{}", url, canonical_query)
+        };
+        let mut request = self.agent.request(method, &url).set(
+            "authorization",
+            &format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.config.access_key_id, scope, signed_headers, signature
+            ),
+        );
+        for (name, value) in &signed {
+            if name != "host" {
+                request = request.set(name, value);
+            }
+        }
+
+        match request.send_bytes(payload) {
+            Ok(response) => Ok(Ok(response)),
+            Err(ureq::Error::Status(status, response)) => {
+                let body = response.into_string().unwrap_or_default();
+
+                Ok(Err(service_error(status, body)))
+            }
+            Err(err) => Err(ConfigError::backend(self.provider, err)),
+        }
+    }
+}
+
+/// The error of an answer with the given status and body.
+fn service_error(status: u16, body: String) -> AwsError {
+    serde_json::from_str::<ErrorBody>(&body)
+        .map(|error| AwsError {
+            // the type may carry a namespace, e.g. `com.amazon.coral#Error`
+            kind: error
+                .kind
+                .rsplit('#')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            message: error.message,
+        })
+        .unwrap_or_else(|_| AwsError {
+            kind: status_kind(status).to_string(),
+            message: body,
+        })
+}
+
+/// Name the error of services that answer without a JSON error document.
+fn status_kind(status: u16) -> &'static str {
+    match status {
+        404 => "NotFound",
+        409 => "Conflict",
+        412 => "PreconditionFailed",
+        _ => "HttpError",
+    }
+}
+
+fn split_url(url: &str) -> (&str, &str) {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let rest = rest.split('?').next().unwrap_or(rest);
+
+    match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent encode everything but the unreserved characters, optionally keeping slashes.
+pub(crate) fn uri_encode(raw: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(raw.len());
+    for byte in raw.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+/// Format the given time as the `(YYYYMMDD'T'HHMMSS'Z', YYYYMMDD)` pair used by SigV4.
+fn timestamp(time: SystemTime) -> (String, String) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let date_time = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    );
+
+    (date_time, date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn formats_sigv4_timestamps() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_440_938_160);
+        assert_eq!(
+            timestamp(time),
+            ("20150830T123600Z".to_string(), "20150830".to_string())
+        );
+    }
+
+    #[test]
+    fn encodes_uris() {
+        assert_eq!(uri_encode("/routes/a b", false), "/routes/a%20b");
+        assert_eq!(uri_encode("a/b=c", true), "a%2Fb%3Dc");
+    }
+
+    #[test]
+    fn splits_urls_into_host_and_path() {
+        assert_eq!(
+            split_url("https://ssm.eu-west-1.amazonaws.com/"),
+            ("ssm.eu-west-1.amazonaws.com", "/")
+        );
+        assert_eq!(
+            split_url("http://localhost:4566/bucket/gk%2Fworkers?versionId=1"),
+            ("localhost:4566", "/bucket/gk%2Fworkers")
+        );
+        assert_eq!(split_url("http://localhost:4566"), ("localhost:4566", "/"));
+    }
+
+    #[cfg(any(feature = "dynamo", feature = "secrets-manager", feature = "ssm"))]
+    #[test]
+    fn endpoints_default_to_the_region() {
+        let mut config = AwsConfig {
+            region: "eu-west-1".to_string(),
+            access_key_id: "AKID".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+            endpoint: None,
+        };
+        let client = AwsClient::new(config.clone(), "ssm", "ssm");
+        assert_eq!(client.endpoint(), "https://ssm.eu-west-1.amazonaws.com");
+
+        config.endpoint = Some("http://localhost:4566/".to_string());
+        let client = AwsClient::new(config, "ssm", "ssm");
+        assert_eq!(client.endpoint(), "http://localhost:4566");
+    }
+
+    #[test]
+    fn service_errors_drop_the_namespace() {
+        let error = service_error(
+            400,
+            r#"{"__type": "com.amazonaws.ssm#ParameterNotFound", "message": "gone"}"#.to_string(),
+        );
+        assert_eq!(
+            (error.kind.as_str(), error.message.as_str()),
+            ("ParameterNotFound", "gone")
+        );
+
+        let error = service_error(
+            400,
+            r#"{"__type": "Throttling", "Message": "slow"}"#.to_string(),
+        );
+        assert_eq!(
+            (error.kind.as_str(), error.message.as_str()),
+            ("Throttling", "slow")
+        );
+
+        // S3 answers in XML, only the status tells what went wrong
+        let error = service_error(412, "<Error/>".to_string());
+        assert_eq!(
+            (error.kind.as_str(), error.message.as_str()),
+            ("PreconditionFailed", "<Error/>")
+        );
+        assert_eq!(service_error(404, String::new()).kind, "NotFound");
+        assert_eq!(service_error(500, String::new()).kind, "HttpError");
+    }
+
+    #[test]
+    fn rejected_credentials_are_denied_permission() {
+        let error = |kind: &str| AwsError {
+            kind: kind.to_string(),
+            message: String::new(),
+        };
+
+        for kind in [
+            "AccessDeniedException",
+            "ExpiredTokenException",
+            "SignatureDoesNotMatch",
+        ] {
+            assert!(matches!(
+                error(kind).into_config_error("ssm"),
+                ConfigError::PermissionDenied { .. }
+            ));
+        }
+        assert!(matches!(
+            error("ThrottlingException").into_config_error("ssm"),
+            ConfigError::Backend { .. }
+        ));
+    }
+}
@@ -0,0 +1,346 @@
+//! `outpost-config`, reads and changes the config of any provider from the command line
+
+use outpost_config::diff::DiffEntry;
+use outpost_config::format::Format;
+use outpost_config::provider::any_file::AnyFileProvider;
+use outpost_config::provider::env::EnvProvider;
+use outpost_config::provider::layered::LayeredProvider;
+use outpost_config::{ConfigError, ConfigProvider, FileAwareConfigProvider, Snapshot};
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "\
+usage: outpost-config [--backend URI] COMMAND [--] [ARGS]
+
+commands:
+    get KEY                   print the value of a key, strings verbatim, others as JSON
+    set KEY VALUE             store a value, valid JSON as the value it describes, others as text
+    delete KEY                remove a key
+    list [PREFIX]             print the keys, optionally only the ones below a prefix
+    export [--format FORMAT]  print all keys and values, as JSON by default
+    import [--format FORMAT] [FILE]
+                              store all keys and values of a file or stdin
+    diff URI                  print the changes turning this config into the one at URI
+
+backends, also read from OUTPOST_CONFIG_BACKEND:
+    PATH, file:PATH           a config file, its format picked by the extension
+    env:PREFIX                the environment variables starting with the prefix
+    dir:PATH                  a directory with a file per key
+    sqlite:PATH               a sqlite database
+    redis://HOST              a redis server
+    etcd+http://HOST:PORT     an etcd cluster
+    consul+http://HOST:PORT   a consul agent";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Get(String),
+    Set(String, String),
+    Delete(String),
+    List(Option<String>),
+    Export(Format),
+    Import(Format, Option<PathBuf>),
+    Diff(String),
+}
+
+impl Command {
+    fn writes(&self) -> bool {
+        matches!(
+            self,
+            Command::Set(..) | Command::Delete(_) | Command::Import(..)
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    backend: String,
+    command: Command,
+}
+
+/// The opened backend, files are written back after a change.
+enum Backend {
+    File(AnyFileProvider, PathBuf),
+    Provider(LayeredProvider),
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1), env::var("OUTPOST_CONFIG_BACKEND").ok()) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("outpost-config: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = execute(&args) {
+        eprintln!("outpost-config: {}", err);
+        process::exit(1);
+    }
+}
+
+/// Parse the arguments, `None` if the usage was asked for.
+///
+/// `-h` and `--help` only ask for it before the command, everything after `--` is positional.
+fn parse_args<I>(args: I, default_backend: Option<String>) -> Result<Option<Args>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut backend = default_backend;
+    let mut format = None;
+    let mut positional = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" if positional.is_empty() => return Ok(None),
+            "--" => positional.extend(&mut args),
+            "--backend" => backend = Some(args.next().ok_or("--backend requires a URI")?),
+            "--format" => {
+                let name = args.next().ok_or("--format requires a format")?;
+                format = Some(
+                    Format::from_extension(&name)
+                        .ok_or_else(|| format!("unsupported format {}", name))?,
+                );
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let backend = backend.ok_or("no backend given")?;
+    let format = format.unwrap_or(Format::Json);
+    let mut positional = positional.into_iter();
+    let command = match positional.next().as_deref() {
+        Some("get") => Command::Get(positional.next().ok_or("get requires a key")?),
+        Some("set") => Command::Set(
+            positional.next().ok_or("set requires a key")?,
+            positional.next().ok_or("set requires a value")?,
+        ),
+        Some("delete") => Command::Delete(positional.next().ok_or("delete requires a key")?),
+        Some("list") => Command::List(positional.next()),
+        Some("export") => Command::Export(format),
+        Some("import") => Command::Import(format, positional.next().map(PathBuf::from)),
+        Some("diff") => Command::Diff(positional.next().ok_or("diff requires a second URI")?),
+        Some(other) => return Err(format!("unknown command {}", other)),
+        None => return Err("no command given".to_string()),
+    };
+
+    match positional.next() {
+        Some(extra) => Err(format!("unexpected argument {}", extra)),
+        None => Ok(Some(Args { backend, command })),
+    }
+}
+
+fn execute(args: &Args) -> Result<(), ConfigError> {
+    let backend = open(&args.backend)?;
+    let mut stdout = io::stdout();
+
+    if let Command::Diff(other) = &args.command {
+        let changes = backend.snapshot()?.diff(&open(other)?.snapshot()?);
+        return print_diff(&changes, &mut stdout);
+    }
+
+    match &backend {
+        Backend::File(provider, path) => {
+            run(provider, &args.command, &mut stdout)?;
+            if args.command.writes() {
+                provider.save(path)?;
+            }
+        }
+        Backend::Provider(provider) => run(provider, &args.command, &mut stdout)?,
+    }
+
+    Ok(())
+}
+
+/// Open the backend the URI points to.
+fn open(uri: &str) -> Result<Backend, ConfigError> {
+    let (scheme, rest) = match uri.split_once(':') {
+        // the drive letter of a windows path isn't a scheme
+        Some((scheme, rest)) if scheme.len() > 1 => (scheme, rest),
+        _ => ("file", uri),
+    };
+
+    if scheme == "file" {
+        let path = PathBuf::from(rest);
+        let file = AnyFileProvider::new();
+        if path.exists() {
+            file.load(&path)?;
+        }
+        return Ok(Backend::File(file, path));
+    }
+
+    let provider = LayeredProvider::new();
+    let provider = match scheme {
+        "env" => provider.with_writable_layer(EnvProvider::new(rest)),
+        #[cfg(feature = "dir")]
+        "dir" => {
+            provider.with_writable_layer(outpost_config::provider::dir::DirProvider::new(rest))
+        }
+        #[cfg(feature = "sqlite")]
+        "sqlite" => provider.with_writable_layer(
+            outpost_config::provider::sqlite::SqliteProvider::open(rest)?,
+        ),
+        #[cfg(feature = "redis")]
+        "redis" | "rediss" => provider.with_writable_layer(
+            outpost_config::provider::redis::RedisProvider::connect(uri)?,
+        ),
+        #[cfg(feature = "etcd")]
+        "etcd+http" | "etcd+https" => provider.with_writable_layer(
+            outpost_config::provider::etcd::EtcdProvider::new(&uri["etcd+".len()..]),
+        ),
+        #[cfg(feature = "consul")]
+        "consul+http" | "consul+https" => provider.with_writable_layer(
+            outpost_config::provider::consul::ConsulProvider::new(&uri["consul+".len()..]),
+        ),
+        _ => {
+            return Err(ConfigError::backend(
+                "outpost-config",
+                format!("unsupported backend {}", uri),
+            ))
+        }
+    };
+
+    Ok(Backend::Provider(provider))
+}
+
+impl Backend {
+    fn snapshot(&self) -> Result<Snapshot, ConfigError> {
+        match self {
+            Backend::File(provider, _) => provider.snapshot(),
+            Backend::Provider(provider) => provider.snapshot(),
+        }
+    }
+}
+
+fn run<P, W>(provider: &P, command: &Command, out: &mut W) -> Result<(), ConfigError>
+where
+    P: ConfigProvider,
+    W: Write,
+{
+    match command {
+        Command::Get(key) => print(out, &provider.get_raw(key)?),
+        Command::Set(key, value) => provider.put_raw(key, value),
+        Command::Delete(key) => provider.delete(key),
+        Command::List(prefix) => {
+            let mut keys = provider.list_prefix(prefix.as_deref().unwrap_or(""))?;
+            keys.sort();
+            keys.iter().try_for_each(|key| print(out, key))
+        }
+        Command::Export(format) => {
+            provider.export(*format, &mut *out)?;
+            print(out, "")
+        }
+        Command::Import(format, Some(path)) => provider.import(
+            File::open(path).map_err(|err| ConfigError::io("outpost-config", err))?,
+            *format,
+        ),
+        Command::Import(format, None) => {
+            let mut input = Vec::new();
+            io::stdin()
+                .read_to_end(&mut input)
+                .map_err(|err| ConfigError::io("outpost-config", err))?;
+            provider.import(input.as_slice(), *format)
+        }
+        Command::Diff(_) => unreachable!("diffs are between two backends"),
+    }
+}
+
+fn print_diff<W>(changes: &[DiffEntry], out: &mut W) -> Result<(), ConfigError>
+where
+    W: Write,
+{
+    changes.iter().try_for_each(|change| match change {
+        DiffEntry::Added { key, value } => print(out, &format!("+ {} = {}", key, value)),
+        DiffEntry::Removed { key, value } => print(out, &format!("- {} = {}", key, value)),
+        DiffEntry::Changed { key, old, new } => {
+            print(out, &format!("~ {} = {} -> {}", key, old, new))
+        }
+    })
+}
+
+fn print<W>(out: &mut W, line: &str) -> Result<(), ConfigError>
+where
+    W: Write,
+{
+    writeln!(out, "{}", line).map_err(|err| ConfigError::io("outpost-config", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Option<Args>, String> {
+        parse_args(line.split_whitespace().map(str::to_string), None)
+    }
+
+    #[test]
+    fn commands_change_the_file_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        let uri = path.to_str().unwrap();
+        let exec = |line: &str| {
+            execute(
+                &args(&format!("--backend {} {}", uri, line))
+                    .unwrap()
+                    .unwrap(),
+            )
+        };
+
+        exec("set listen :8080").unwrap();
+        exec("set workers 4").unwrap();
+        exec("set legacy true").unwrap();
+        exec("delete legacy").unwrap();
+
+        let file = AnyFileProvider::new();
+        file.load(&path).unwrap();
+        assert_eq!(file.get::<String>("listen").unwrap(), ":8080");
+        assert_eq!(file.get::<u32>("workers").unwrap(), 4);
+        assert!(!file.has("legacy").unwrap());
+
+        let mut out = Vec::new();
+        run(&file, &Command::List(None), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "listen\nworkers\n");
+        let mut out = Vec::new();
+        run(&file, &Command::Get("listen".to_string()), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), ":8080\n");
+    }
+
+    #[test]
+    fn arguments_are_checked() {
+        assert_eq!(
+            args("--backend file:gk.toml diff env:GK_").unwrap(),
+            Some(Args {
+                backend: "file:gk.toml".to_string(),
+                command: Command::Diff("env:GK_".to_string()),
+            })
+        );
+        assert!(args("--backend gk.json set listen").is_err());
+        assert!(args("get listen").is_err());
+        assert!(args("--backend gk.json frobnicate").is_err());
+        assert!(args("--backend gk.json export --format xml").is_err());
+    }
+
+    #[test]
+    fn help_is_only_asked_for_before_the_command() {
+        assert_eq!(args("--help").unwrap(), None);
+        assert_eq!(args("--backend gk.json -h set motd hello").unwrap(), None);
+
+        let command = |line| args(line).unwrap().unwrap().command;
+        assert_eq!(
+            command("--backend gk.json set motd --help"),
+            Command::Set("motd".to_string(), "--help".to_string())
+        );
+        assert_eq!(
+            command("--backend gk.json set -- motd --format"),
+            Command::Set("motd".to_string(), "--format".to_string())
+        );
+        assert!(args("--backend gk.json get -h extra").is_err());
+    }
+}
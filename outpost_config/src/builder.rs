@@ -0,0 +1,144 @@
+#[cfg(feature = "fs")]
+use crate::provider::any_file::AnyFileProvider;
+use crate::provider::cached::CachedProvider;
+use crate::provider::env::EnvProvider;
+use crate::provider::layered::LayeredProvider;
+#[cfg(feature = "fs")]
+use crate::FileAwareConfigProvider;
+use crate::{ConfigError, ConfigProvider};
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::time::Duration;
+
+/// Composes the usual stack of config sources into one provider
+///
+/// Sources are consulted in the order they were added like the layers of a
+/// [`LayeredProvider`], so the first one added has the highest priority:
+///
+/// ```ignore
+/// let config = ConfigBuilder::new()
+///     .env("GK_")
+///     .file("gatekeeper.toml")
+///     .writable_layer(redis)
+///     .cached(Duration::from_secs(60))
+///     .build()?;
+/// ```
+///
+/// Errors of a step, e.g. a config file that can't be read, are returned by `build`.
+#[derive(Default)]
+pub struct ConfigBuilder {
+    layered: LayeredProvider,
+    cache_ttl: Option<Duration>,
+    error: Option<ConfigError>,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the values of a config file, whose format is picked from its extension like
+    /// [`AnyFileProvider`] does. The file is read once by this call and never written.
+    #[cfg(feature = "fs")]
+    pub fn file<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let file = AnyFileProvider::new();
+        match file.load(path) {
+            Ok(()) => self.layered = self.layered.with_layer(file),
+            Err(err) => {
+                self.error.get_or_insert(err);
+            }
+        }
+        self
+    }
+
+    /// Add the environment variables starting with the prefix, see [`EnvProvider`].
+    pub fn env<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.layered = self.layered.with_layer(EnvProvider::new(prefix));
+        self
+    }
+
+    /// Add a read only provider, e.g. a remote backend.
+    pub fn layer<P>(mut self, provider: P) -> Self
+    where
+        P: ConfigProvider + Send + Sync + 'static,
+    {
+        self.layered = self.layered.with_layer(provider);
+        self
+    }
+
+    /// Add the provider receiving all writes, see [`LayeredProvider::with_writable_layer`].
+    pub fn writable_layer<P>(mut self, provider: P) -> Self
+    where
+        P: ConfigProvider + Send + Sync + 'static,
+    {
+        self.layered = self.layered.with_writable_layer(provider);
+        self
+    }
+
+    /// Remember the values of the whole stack for the given time, see [`CachedProvider`].
+    pub fn cached(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// The provider consulting all added sources, or the first error of a step.
+    pub fn build(self) -> Result<LayeredProvider, ConfigError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        Ok(match self.cache_ttl {
+            Some(ttl) => {
+                LayeredProvider::new().with_writable_layer(CachedProvider::new(self.layered, ttl))
+            }
+            None => self.layered,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::env;
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn sources_stack_in_the_order_they_were_added() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        fs::write(&path, r#"{"workers": 4, "listen": ":80"}"#).unwrap();
+        env::set_var("OUTPOST_TEST_BUILDER_WORKERS", "8");
+        let remote = Arc::new(InMemoryProvider::new());
+        remote.put("listen", ":8080".to_string()).unwrap();
+        remote.put("region", "eu".to_string()).unwrap();
+
+        let config = ConfigBuilder::new()
+            .env("OUTPOST_TEST_BUILDER_")
+            .file(&path)
+            .writable_layer(remote.clone())
+            .cached(Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        assert_eq!(config.get::<u32>("workers").unwrap(), 8);
+        assert_eq!(config.get::<String>("listen").unwrap(), ":80");
+        assert_eq!(config.get::<String>("region").unwrap(), "eu");
+
+        config.put("region", "us".to_string()).unwrap();
+        assert_eq!(remote.get::<String>("region").unwrap(), "us");
+        assert_eq!(config.get::<String>("region").unwrap(), "us");
+
+        assert!(ConfigBuilder::new()
+            .file(dir.path().join("missing.json"))
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! Typed values kept deserialized between reads, see [`TypedCell`]
+
+use crate::{ChangeEvent, ConfigError, WatchableConfigProvider};
+use serde::de::DeserializeOwned;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Handle to a key whose value is deserialized once and shared until it changes
+///
+/// Reading a hot key like the route table through `get` parses its JSON on every call. A cell
+/// keeps the parsed value and hands out clones of an `Arc` instead, it only reads the key again
+/// after the provider reported a change of it. Changes are noticed on the next `get`, so a value
+/// written through the provider is seen right away.
+///
+/// ```ignore
+/// let routes = TypedCell::<_, RouteTable>::new(provider.clone(), "routes")?;
+/// let table = routes.get()?;
+/// ```
+pub struct TypedCell<P, T> {
+    provider: P,
+    key: String,
+    state: Mutex<State<T>>,
+}
+
+/// The changes of the key not seen yet and the value read last.
+struct State<T> {
+    changes: Receiver<ChangeEvent>,
+    cached: Option<Arc<T>>,
+}
+
+impl<P, T> TypedCell<P, T>
+where
+    P: WatchableConfigProvider,
+    T: DeserializeOwned,
+{
+    /// Watch the key, the value is read on the first `get`.
+    pub fn new(provider: P, key: &str) -> Result<Self, ConfigError> {
+        let changes = provider.watch(key)?;
+
+        Ok(Self {
+            provider,
+            key: key.to_string(),
+            state: Mutex::new(State {
+                changes,
+                cached: None,
+            }),
+        })
+    }
+
+    /// The current value of the key, deserialized only if it changed since the last call.
+    pub fn get(&self) -> Result<Arc<T>, ConfigError> {
+        // the value is read while the changes are locked, so no change is drained in between
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let changed = state
+            .changes
+            .try_iter()
+            .filter(|event| event.key() == self.key)
+            .count()
+            > 0;
+        if changed {
+            state.cached = None;
+        }
+
+        if let Some(value) = &state.cached {
+            return Ok(value.clone());
+        }
+        let value = Arc::new(self.provider.get::<T>(&self.key)?);
+        state.cached = Some(value.clone());
+
+        Ok(value)
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use crate::ConfigProvider;
+
+    #[test]
+    fn values_are_parsed_again_only_after_a_change() {
+        let provider = Arc::new(InMemoryProvider::new());
+        provider.put("routes", vec!["/api".to_string()]).unwrap();
+        let routes = TypedCell::<_, Vec<String>>::new(provider.clone(), "routes").unwrap();
+
+        let first = routes.get().unwrap();
+        assert!(Arc::ptr_eq(&first, &routes.get().unwrap()));
+
+        // keys sharing the prefix don't invalidate the value
+        provider.put("routes_legacy", true).unwrap();
+        assert!(Arc::ptr_eq(&first, &routes.get().unwrap()));
+
+        provider
+            .put("routes", vec!["/api".to_string(), "/admin".to_string()])
+            .unwrap();
+        assert_eq!(*routes.get().unwrap(), vec!["/api", "/admin"]);
+
+        provider.delete("routes").unwrap();
+        assert!(routes.get().unwrap_err().is_not_found());
+    }
+}
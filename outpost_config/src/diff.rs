@@ -0,0 +1,105 @@
+//! Differences between two configs, e.g. what a deploy would change
+
+use crate::{ConfigError, ConfigProvider, Snapshot};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// A key that differs between two configs
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum DiffEntry {
+    /// The key only exists in the second config.
+    Added { key: String, value: Value },
+    /// The key only exists in the first config.
+    Removed { key: String, value: Value },
+    /// The key exists in both configs with different values.
+    Changed { key: String, old: Value, new: Value },
+}
+
+impl DiffEntry {
+    pub fn key(&self) -> &str {
+        match self {
+            DiffEntry::Added { key, .. }
+            | DiffEntry::Removed { key, .. }
+            | DiffEntry::Changed { key, .. } => key,
+        }
+    }
+}
+
+impl Snapshot {
+    /// The changes turning this snapshot into the other one, ordered by key.
+    pub fn diff(&self, other: &Snapshot) -> Vec<DiffEntry> {
+        let keys: BTreeSet<&String> = self.values().keys().chain(other.values().keys()).collect();
+
+        keys.into_iter()
+            .filter_map(
+                |key| match (self.values().get(key), other.values().get(key)) {
+                    (None, Some(new)) => Some(DiffEntry::Added {
+                        key: key.clone(),
+                        value: new.clone(),
+                    }),
+                    (Some(old), None) => Some(DiffEntry::Removed {
+                        key: key.clone(),
+                        value: old.clone(),
+                    }),
+                    (Some(old), Some(new)) if old != new => Some(DiffEntry::Changed {
+                        key: key.clone(),
+                        old: old.clone(),
+                        new: new.clone(),
+                    }),
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+}
+
+/// The changes turning the config of `a` into the config of `b`, ordered by key.
+pub fn diff<A, B>(a: &A, b: &B) -> Result<Vec<DiffEntry>, ConfigError>
+where
+    A: ConfigProvider,
+    B: ConfigProvider,
+{
+    Ok(a.snapshot()?.diff(&b.snapshot()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use serde_json::json;
+
+    #[test]
+    fn reports_added_removed_and_changed_keys() {
+        let live = InMemoryProvider::new();
+        live.put("listen", ":80".to_string()).unwrap();
+        live.put("workers", 4).unwrap();
+        live.put("legacy", true).unwrap();
+
+        let planned = InMemoryProvider::new();
+        planned.put("listen", ":80".to_string()).unwrap();
+        planned.put("workers", 8).unwrap();
+        planned.put("tls.cert", "pem".to_string()).unwrap();
+
+        assert_eq!(
+            diff(&live, &planned).unwrap(),
+            vec![
+                DiffEntry::Removed {
+                    key: "legacy".to_string(),
+                    value: json!(true)
+                },
+                DiffEntry::Added {
+                    key: "tls.cert".to_string(),
+                    value: json!("pem")
+                },
+                DiffEntry::Changed {
+                    key: "workers".to_string(),
+                    old: json!(4),
+                    new: json!(8)
+                },
+            ]
+        );
+        assert!(diff(&live, &live).unwrap().is_empty());
+    }
+}
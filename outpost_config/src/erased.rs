@@ -0,0 +1,191 @@
+//! Object safe form of [`ConfigProvider`], to pick a backend at runtime
+
+use crate::{ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Object safe view of a [`ConfigProvider`], passing values as JSON
+///
+/// Every provider implements it, so any of them can be stored as
+/// `Box<dyn ErasedConfigProvider + Send + Sync>`. The trait object implements [`ConfigProvider`]
+/// again, which brings back the typed `get` and `put`:
+///
+/// ```ignore
+/// let provider: Box<dyn ErasedConfigProvider + Send + Sync> = match backend {
+///     "redis" => Box::new(RedisProvider::connect(url)?),
+///     _ => Box::new(InMemoryProvider::new()),
+/// };
+/// let workers: u32 = provider.get("workers")?;
+/// ```
+///
+/// The methods are prefixed with `erased_` so they don't clash with the ones of
+/// [`ConfigProvider`] when both traits are in scope.
+pub trait ErasedConfigProvider {
+    fn erased_get(&self, key: &str) -> Result<Value, ConfigError>;
+
+    fn erased_has(&self, key: &str) -> Result<bool, ConfigError>;
+
+    fn erased_put(&self, key: &str, value: Value) -> Result<(), ConfigError>;
+
+    fn erased_delete(&self, key: &str) -> Result<(), ConfigError>;
+
+    fn erased_list(&self) -> Result<Vec<String>, ConfigError>;
+
+    fn erased_list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError>;
+
+    fn erased_version(&self, key: &str) -> Result<Option<String>, ConfigError>;
+
+    fn erased_put_if_version(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>;
+}
+
+impl<P> ErasedConfigProvider for P
+where
+    P: ConfigProvider,
+{
+    fn erased_get(&self, key: &str) -> Result<Value, ConfigError> {
+        self.get_value(key)
+    }
+
+    fn erased_has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.has(key)
+    }
+
+    fn erased_put(&self, key: &str, value: Value) -> Result<(), ConfigError> {
+        self.put_value(key, value)
+    }
+
+    fn erased_delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.delete(key)
+    }
+
+    fn erased_list(&self) -> Result<Vec<String>, ConfigError> {
+        self.list()
+    }
+
+    fn erased_list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.list_prefix(prefix)
+    }
+
+    fn erased_version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.version(key)
+    }
+
+    fn erased_put_if_version(
+        &self,
+        key: &str,
+        value: Value,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError> {
+        self.put_if_version(key, value, expected_version)
+    }
+}
+
+/// Implement [`ConfigProvider`] for a trait object of [`ErasedConfigProvider`].
+macro_rules! typed_provider {
+    ($erased:ty) => {
+        impl ConfigProvider for $erased {
+            fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+            where
+                T: DeserializeOwned,
+            {
+                serde_json::from_value(self.erased_get(key)?)
+                    .map_err(|err| ConfigError::deserialization("erased", err).with_key(key))
+            }
+
+            fn has(&self, key: &str) -> Result<bool, ConfigError> {
+                self.erased_has(key)
+            }
+
+            fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+            where
+                T: DeserializeOwned + Serialize,
+            {
+                let value = serde_json::to_value(value)
+                    .map_err(|err| ConfigError::serialization("erased", err).with_key(key))?;
+
+                self.erased_put(key, value)
+            }
+
+            fn delete(&self, key: &str) -> Result<(), ConfigError> {
+                self.erased_delete(key)
+            }
+
+            fn list(&self) -> Result<Vec<String>, ConfigError> {
+                self.erased_list()
+            }
+
+            fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+                self.erased_list_prefix(prefix)
+            }
+
+            fn get_value(&self, key: &str) -> Result<Value, ConfigError> {
+                self.erased_get(key)
+            }
+
+            fn put_value(&self, key: &str, value: Value) -> Result<(), ConfigError> {
+                self.erased_put(key, value)
+            }
+
+            fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+                self.erased_version(key)
+            }
+
+            fn put_if_version<T>(
+                &self,
+                key: &str,
+                value: T,
+                expected_version: Option<&str>,
+            ) -> Result<(), ConfigError>
+            where
+                T: DeserializeOwned + Serialize,
+            {
+                let value = serde_json::to_value(value)
+                    .map_err(|err| ConfigError::serialization("erased", err).with_key(key))?;
+
+                self.erased_put_if_version(key, value, expected_version)
+            }
+        }
+    };
+}
+
+typed_provider!(dyn ErasedConfigProvider + '_);
+typed_provider!(dyn ErasedConfigProvider + Send + Sync + '_);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::env::EnvProvider;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::sync::Arc;
+
+    fn open(backend: &str) -> Box<dyn ErasedConfigProvider + Send + Sync> {
+        match backend {
+            "env" => Box::new(EnvProvider::new("OUTPOST_TEST_ERASED_")),
+            _ => Box::new(InMemoryProvider::new()),
+        }
+    }
+
+    #[test]
+    fn backends_are_picked_at_runtime() {
+        let provider = open("memory");
+        provider.put("workers", 4).unwrap();
+        provider.put("listen", ":8080".to_string()).unwrap();
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        assert_eq!(provider.get_or("timeout", 30).unwrap(), 30);
+        assert_eq!(provider.list_prefix("work").unwrap(), vec!["workers"]);
+
+        std::env::set_var("OUTPOST_TEST_ERASED_WORKERS", "8");
+        assert_eq!(open("env").get::<u32>("workers").unwrap(), 8);
+
+        // shared trait objects plug into the wrappers like any provider
+        let shared: Arc<dyn ErasedConfigProvider + Send + Sync> = Arc::from(provider);
+        let scoped = crate::provider::scoped::ScopedProvider::new(shared, "work");
+        assert_eq!(scoped.get::<u32>("ers").unwrap(), 4);
+    }
+}
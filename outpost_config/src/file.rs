@@ -0,0 +1,669 @@
+use crate::{fnv1a, fnv1a_continue, ConfigError};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, Visitor};
+use serde_json::value::RawValue;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Version of the format written by [`write_checked`]. Files of a plain object of raw values
+/// predate it and are still read.
+const FORMAT_VERSION: u64 = 1;
+
+/// Write a file through a temporary sibling and move it to its final destination afterwards,
+/// so readers never observe a half written config.
+pub(crate) fn write_atomic<P, F>(
+    provider: &'static str,
+    path: P,
+    write: F,
+) -> Result<(), ConfigError>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut File) -> Result<(), ConfigError>,
+{
+    write_durable(provider, path, 0, write, |_| Ok(()))
+}
+
+/// Like [`write_atomic`], but check the written temporary file with `verify` before it replaces
+/// the destination and keep the given number of previous versions as `<file>.<n>.bak`, the
+/// newest one being `<file>.1.bak`.
+///
+/// The temporary file and, on unix, its directory are synced to disk before and after the
+/// rename, so a power loss leaves either the old or the new config behind.
+pub(crate) fn write_durable<P, F, V>(
+    provider: &'static str,
+    path: P,
+    backups: usize,
+    write: F,
+    verify: V,
+) -> Result<(), ConfigError>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut File) -> Result<(), ConfigError>,
+    V: FnOnce(&Path) -> Result<(), ConfigError>,
+{
+    let path = path.as_ref();
+
+    // try to create the directory
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| ConfigError::io(provider, err))?;
+    }
+
+    // create a temporary file to work with so we don't end up with a broken config
+    let tmp_path = {
+        let mut tmp_path = path.to_path_buf();
+        tmp_path.set_extension("tmp");
+        tmp_path
+    };
+
+    let mut tmp_file = File::create(&tmp_path).map_err(|err| ConfigError::io(provider, err))?;
+
+    write(&mut tmp_file)?;
+    tmp_file
+        .sync_all()
+        .map_err(|err| ConfigError::io(provider, err))?;
+    drop(tmp_file);
+
+    if let Err(err) = verify(&tmp_path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+
+    if backups > 0 && path.exists() {
+        rotate_backups(provider, path, backups)?;
+    }
+
+    // move the temporary file to its final destination
+    fs::rename(&tmp_path, path).map_err(|err| ConfigError::io(provider, err))?;
+    sync_parent(provider, path)
+}
+
+/// Write the raw values of the entries together with the format version and a checksum of the
+/// values, which [`read_checked`] verifies. The entries must come in key order, they are written
+/// one at a time as they come and the checksum follows them, so the values are never all held
+/// in memory.
+pub(crate) fn write_checked<W, I, K, V>(
+    provider: &'static str,
+    entries: I,
+    mut writer: W,
+) -> Result<(), ConfigError>
+where
+    W: Write,
+    I: IntoIterator<Item = Result<(K, V), ConfigError>>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let io = |err| ConfigError::io(provider, err);
+    let json = |err| ConfigError::serialization(provider, err);
+
+    write!(
+        writer,
+        "{{\n  \"$format\": {},\n  \"values\": {{",
+        FORMAT_VERSION
+    )
+    .map_err(io)?;
+    let mut checksum = Checksum::new();
+    for entry in entries {
+        let (key, raw) = entry?;
+        let separator = if checksum.is_empty() {
+            "\n    "
+        } else {
+            ",\n    "
+        };
+        writer.write_all(separator.as_bytes()).map_err(io)?;
+        serde_json::to_writer(&mut writer, key.as_ref()).map_err(json)?;
+        writer.write_all(b": ").map_err(io)?;
+        serde_json::to_writer(&mut writer, raw.as_ref()).map_err(json)?;
+        checksum.add(key.as_ref(), raw.as_ref());
+    }
+    let end = if checksum.is_empty() { "}" } else { "\n  }" };
+    writeln!(
+        writer,
+        "{},\n  \"$checksum\": \"{}\"\n}}",
+        end,
+        checksum.finish()
+    )
+    .map_err(io)?;
+
+    writer.flush().map_err(io)
+}
+
+/// Read the raw values of a file written by [`write_checked`] or of a plain object of them.
+/// Files that are cut short, aren't JSON or whose values don't match their checksum fail with
+/// `ConfigError::Corrupted`.
+pub(crate) fn read_checked<R>(
+    provider: &'static str,
+    path: &Path,
+    reader: R,
+) -> Result<HashMap<String, String>, ConfigError>
+where
+    R: Read,
+{
+    let mut values = HashMap::new();
+    visit_checked(provider, path, reader, |key, raw| {
+        values.insert(key, raw);
+    })?;
+
+    Ok(values)
+}
+
+/// Check a file like [`read_checked`] without keeping its values.
+pub(crate) fn verify_checked<R>(
+    provider: &'static str,
+    path: &Path,
+    reader: R,
+) -> Result<(), ConfigError>
+where
+    R: Read,
+{
+    visit_checked(provider, path, reader, |_, _| {})
+}
+
+/// Index the text of a file written by [`write_checked`] or of a plain object of raw values
+/// without parsing the values, returning the range of the text holding the JSON string of every
+/// raw value. The checksum is verified against the JSON strings as they are written, which is
+/// what `write_checked` hashes.
+pub(crate) fn index_checked(
+    provider: &'static str,
+    path: &Path,
+    text: &str,
+) -> Result<Vec<(String, Range<usize>)>, ConfigError> {
+    let corrupted = |message: String| ConfigError::corrupted(provider, path, message);
+    let invalid = |err: serde_json::Error| corrupted(err.to_string());
+    let range = |raw: &RawValue| {
+        let start = raw.get().as_ptr() as usize - text.as_ptr() as usize;
+        start..start + raw.get().len()
+    };
+
+    let mut file: HashMap<String, &RawValue> = serde_json::from_str(text).map_err(invalid)?;
+    let format: u64 = match file.remove("$format") {
+        Some(format) => serde_json::from_str(format.get()).map_err(invalid)?,
+        None => {
+            return Ok(file
+                .into_iter()
+                .map(|(key, raw)| (key, range(raw)))
+                .collect())
+        }
+    };
+    if format > FORMAT_VERSION {
+        return Err(ConfigError::deserialization(
+            provider,
+            format!("unsupported format version {}", format),
+        ));
+    }
+
+    let checksum: String = match file.remove("$checksum") {
+        Some(checksum) => serde_json::from_str(checksum.get()).map_err(invalid)?,
+        None => return Err(corrupted("the checksum is missing".to_string())),
+    };
+    let values: BTreeMap<String, &RawValue> = match file.remove("values") {
+        Some(values) => serde_json::from_str(values.get()).map_err(invalid)?,
+        None => return Err(corrupted("the values are missing".to_string())),
+    };
+    if !file.is_empty() {
+        return Err(corrupted("the file has unknown fields".to_string()));
+    }
+
+    let mut computed = Checksum::new();
+    for (key, raw) in &values {
+        computed.add_json(key, raw.get());
+    }
+    if computed.finish() != checksum {
+        return Err(corrupted("the values don't match the checksum".to_string()));
+    }
+
+    Ok(values
+        .into_iter()
+        .map(|(key, raw)| (key, range(raw)))
+        .collect())
+}
+
+/// Parse the file one entry at a time, passing every raw value to `entry` as it is read.
+fn visit_checked<R, F>(
+    provider: &'static str,
+    path: &Path,
+    reader: R,
+    mut entry: F,
+) -> Result<(), ConfigError>
+where
+    R: Read,
+    F: FnMut(String, String),
+{
+    let mut state = State::default();
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let parsed = deserializer
+        .deserialize_map(Entries {
+            state: &mut state,
+            entry: &mut entry,
+        })
+        .and_then(|_| deserializer.end());
+    if let Some(format) = state.format.filter(|format| *format > FORMAT_VERSION) {
+        return Err(ConfigError::deserialization(
+            provider,
+            format!("unsupported format version {}", format),
+        ));
+    }
+    parsed.map_err(|err| {
+        if err.is_io() {
+            ConfigError::io(provider, err.into())
+        } else {
+            ConfigError::corrupted(provider, path, err.to_string())
+        }
+    })?;
+
+    let corrupted = |message| Err(ConfigError::corrupted(provider, path, message));
+    match state {
+        // a plain object of raw values, written before the checksum was added
+        State {
+            format: None,
+            computed: None,
+            ..
+        } => Ok(()),
+        State { format: None, .. } => corrupted("the format version is missing"),
+        State { unknown: true, .. } => corrupted("the file has unknown fields"),
+        State {
+            checksum: Some(checksum),
+            computed: Some(computed),
+            ..
+        } if checksum == computed => Ok(()),
+        State { computed: None, .. } => corrupted("the values are missing"),
+        State { checksum: None, .. } => corrupted("the checksum is missing"),
+        State { .. } => corrupted("the values don't match the checksum"),
+    }
+}
+
+/// What [`visit_checked`] found in a file besides the values.
+#[derive(Default)]
+struct State {
+    format: Option<u64>,
+    checksum: Option<String>,
+    /// Checksum of the values as read.
+    computed: Option<String>,
+    /// Whether there were fields other than the format, the checksum and the values.
+    unknown: bool,
+}
+
+/// Visitor of the top level of a file and of its values.
+struct Entries<'a, F> {
+    state: &'a mut State,
+    entry: &'a mut F,
+}
+
+impl<'de, F> Visitor<'de> for Entries<'_, F>
+where
+    F: FnMut(String, String),
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an object of raw values")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "$format" => self.state.format = Some(map.next_value()?),
+                "$checksum" => self.state.checksum = Some(map.next_value()?),
+                "values" => map.next_value_seed(Values {
+                    state: &mut *self.state,
+                    entry: &mut *self.entry,
+                })?,
+                _ => {
+                    self.state.unknown = true;
+                    (self.entry)(key, map.next_value()?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Visitor of the values of a file, or of the value of a plain object's key named `values`.
+struct Values<'a, F> {
+    state: &'a mut State,
+    entry: &'a mut F,
+}
+
+impl<'de, F> DeserializeSeed<'de> for Values<'_, F>
+where
+    F: FnMut(String, String),
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, F> Visitor<'de> for Values<'_, F>
+where
+    F: FnMut(String, String),
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an object of raw values")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut checksum = Checksum::new();
+        while let Some((key, raw)) = map.next_entry::<String, String>()? {
+            checksum.add(&key, &raw);
+            (self.entry)(key, raw);
+        }
+        self.state.computed = Some(checksum.finish());
+
+        Ok(())
+    }
+
+    fn visit_str<E>(self, raw: &str) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        self.state.unknown = true;
+        (self.entry)("values".to_string(), raw.to_string());
+
+        Ok(())
+    }
+}
+
+/// FNV-1a of the compact JSON of the values, fed one entry at a time in key order and named by
+/// its algorithm.
+struct Checksum {
+    hash: u64,
+    entries: usize,
+}
+
+impl Checksum {
+    fn new() -> Self {
+        Self {
+            hash: fnv1a(b"{"),
+            entries: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    fn add(&mut self, key: &str, raw: &str) {
+        self.add_key(key);
+        serde_json::to_writer(&mut *self, raw).expect("hashing never fails");
+    }
+
+    /// Add an entry whose raw value is given as the JSON string written for it.
+    fn add_json(&mut self, key: &str, json: &str) {
+        self.add_key(key);
+        self.feed(json.as_bytes());
+    }
+
+    fn add_key(&mut self, key: &str) {
+        if !self.is_empty() {
+            self.feed(b",");
+        }
+        self.entries += 1;
+        serde_json::to_writer(&mut *self, key).expect("hashing never fails");
+        self.feed(b":");
+    }
+
+    fn feed(&mut self, bytes: &[u8]) {
+        self.hash = fnv1a_continue(self.hash, bytes);
+    }
+
+    fn finish(mut self) -> String {
+        self.feed(b"}");
+        format!("fnv1a:{:016x}", self.hash)
+    }
+}
+
+impl Write for Checksum {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.feed(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Advisory lock of a config file, released when it is dropped.
+pub(crate) struct FileLock {
+    _file: Option<File>,
+}
+
+/// Wait for a shared lock of the file, held by every reader.
+pub(crate) fn lock_shared(provider: &'static str, path: &Path) -> Result<FileLock, ConfigError> {
+    lock(provider, path, false)
+}
+
+/// Wait for an exclusive lock of the file, held by the one writer.
+pub(crate) fn lock_exclusive(provider: &'static str, path: &Path) -> Result<FileLock, ConfigError> {
+    // the lock file lives next to the config, whose directory may not exist yet
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| ConfigError::io(provider, err))?;
+    }
+
+    lock(provider, path, true)
+}
+
+/// Wait for a lock of the file, held by the one writer or shared by all readers.
+///
+/// The lock is taken on the sibling `<file>.lock` rather than the file itself, which is replaced
+/// on every save. Lock files are never removed, another process may be waiting for one. The
+/// lock is advisory, it only keeps out processes using it as well.
+///
+/// Configs on a read-only file system or in a directory the process can't write to can still be
+/// read: a reader uses an existing lock file opened for reading only, and goes without a lock if
+/// there is none, as nobody can write the config there anyway.
+fn lock(provider: &'static str, path: &Path, exclusive: bool) -> Result<FileLock, ConfigError> {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".lock");
+    let name = PathBuf::from(name);
+
+    let opened = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&name);
+    let file = match opened {
+        Ok(file) => file,
+        Err(err) if exclusive => return Err(ConfigError::io(provider, err)),
+        Err(_) => match File::open(&name) {
+            Ok(file) => file,
+            Err(_) => return Ok(FileLock { _file: None }),
+        },
+    };
+
+    let locked = if exclusive {
+        file.lock()
+    } else {
+        file.lock_shared()
+    };
+    locked.map_err(|err| ConfigError::io(provider, err))?;
+
+    Ok(FileLock { _file: Some(file) })
+}
+
+/// Shift the existing backups of a file by one, dropping the oldest, and copy the file to the
+/// newest backup.
+fn rotate_backups(provider: &'static str, path: &Path, backups: usize) -> Result<(), ConfigError> {
+    for generation in (1..backups).rev() {
+        let older = backup_path(path, generation);
+        if older.exists() {
+            fs::rename(&older, backup_path(path, generation + 1))
+                .map_err(|err| ConfigError::io(provider, err))?;
+        }
+    }
+
+    // copy instead of moving, the config must not vanish before the new one is in place
+    fs::copy(path, backup_path(path, 1)).map_err(|err| ConfigError::io(provider, err))?;
+
+    Ok(())
+}
+
+pub(crate) fn backup_path(path: &Path, generation: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(OsString::from(format!(".{}.bak", generation)));
+    PathBuf::from(name)
+}
+
+/// Persist the directory entry of a renamed file.
+#[cfg(unix)]
+fn sync_parent(provider: &'static str, path: &Path) -> Result<(), ConfigError> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    File::open(parent)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|err| ConfigError::io(provider, err))
+}
+
+/// Directories can't be opened on windows, the rename is flushed by the file system.
+#[cfg(not(unix))]
+fn sync_parent(_provider: &'static str, _path: &Path) -> Result<(), ConfigError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_verification_keeps_the_old_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        fs::write(&path, "{}").unwrap();
+
+        let result = write_durable(
+            "test",
+            &path,
+            1,
+            |file| {
+                file.write_all(b"{\"trunc")
+                    .map_err(|err| ConfigError::io("test", err))
+            },
+            |written| {
+                let raw = fs::read(written).map_err(|err| ConfigError::io("test", err))?;
+                serde_json::from_slice::<serde_json::Value>(&raw)
+                    .map(drop)
+                    .map_err(|err| ConfigError::deserialization("test", err))
+            },
+        );
+
+        assert!(matches!(result, Err(ConfigError::Deserialization { .. })));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+        assert!(!path.with_extension("tmp").exists());
+        assert!(!backup_path(&path, 1).exists());
+    }
+
+    #[test]
+    fn checked_files_are_written_and_read_one_entry_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        let entries = (0..100_000).map(|i| Ok((format!("routes.{:06}", i), format!("/api/{}", i))));
+        write_checked(
+            "test",
+            entries,
+            io::BufWriter::new(File::create(&path).unwrap()),
+        )
+        .unwrap();
+
+        let file = io::BufReader::new(File::open(&path).unwrap());
+        let values = read_checked("test", &path, file).unwrap();
+        assert_eq!(values.len(), 100_000);
+        assert_eq!(values["routes.000042"], "/api/42");
+
+        // the checksum is the one of all values serialized at once, as in earlier versions
+        let values = [("listen", ":8080"), ("tls.cert", "-----BEGIN\n\"")];
+        let mut written = Vec::new();
+        write_checked("test", values.iter().copied().map(Ok), &mut written).unwrap();
+        let json = serde_json::to_vec(&values.iter().copied().collect::<BTreeMap<_, _>>()).unwrap();
+        let checksum = format!("fnv1a:{:016x}", fnv1a(&json));
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.contains(&checksum), "{}", written);
+        verify_checked("test", &path, written.as_bytes()).unwrap();
+
+        let mut empty = Vec::new();
+        write_checked(
+            "test",
+            std::iter::empty::<Result<(&str, &str), _>>(),
+            &mut empty,
+        )
+        .unwrap();
+        assert!(read_checked("test", &path, empty.as_slice())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn configs_in_read_only_directories_can_be_read() {
+        use crate::provider::in_memory::InMemoryProvider;
+        use crate::{ConfigProvider, FileAwareConfigProvider};
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config_dir = dir.path().join("config");
+        let path = config_dir.join("gatekeeper.json");
+        let written = InMemoryProvider::new();
+        written.put("workers", 4).unwrap();
+        written.save(&path).unwrap();
+
+        let load = || {
+            let read_only = fs::Permissions::from_mode(0o555);
+            fs::set_permissions(&config_dir, read_only).unwrap();
+            let provider = InMemoryProvider::new();
+            let loaded = provider.load(&path);
+            fs::set_permissions(&config_dir, fs::Permissions::from_mode(0o755)).unwrap();
+            loaded.unwrap();
+            assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        };
+
+        // with the lock file left behind by the save, and without any
+        load();
+        fs::remove_file(config_dir.join("gatekeeper.json.lock")).unwrap();
+        load();
+    }
+
+    #[test]
+    fn readers_fall_back_if_the_lock_file_cant_be_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        // can't be opened for writing, not even by root
+        fs::create_dir(dir.path().join("gatekeeper.json.lock")).unwrap();
+
+        assert!(lock_shared("test", &path).is_ok());
+        assert!(lock_exclusive("test", &path).is_err());
+    }
+
+    #[test]
+    fn writers_exclude_readers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        let other = || File::open(dir.path().join("gatekeeper.json.lock")).unwrap();
+
+        let reader = lock_shared("test", &path).unwrap();
+        assert!(other().try_lock_shared().is_ok());
+        assert!(other().try_lock().is_err());
+        drop(reader);
+
+        let _writer = lock_exclusive("test", &path).unwrap();
+        assert!(other().try_lock_shared().is_err());
+    }
+}
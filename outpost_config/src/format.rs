@@ -0,0 +1,118 @@
+//! Serialization formats for [`ConfigProvider::export`](crate::ConfigProvider::export) and
+//! [`ConfigProvider::import`](crate::ConfigProvider::import)
+
+use crate::ConfigError;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// Format of an exported config
+///
+/// Every format but JSON requires the cargo feature of the provider using it. The exported
+/// document maps every key to its value at the top level, keys aren't nested.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "yaml")]
+    Yaml,
+    #[cfg(feature = "ron")]
+    Ron,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Format {
+    /// The format usually stored in files with the given extension, e.g. `toml` or `yml`.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            #[cfg(feature = "toml")]
+            "toml" => Some(Self::Toml),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Some(Self::Yaml),
+            #[cfg(feature = "ron")]
+            "ron" => Some(Self::Ron),
+            #[cfg(feature = "msgpack")]
+            "msgpack" | "mpk" => Some(Self::MessagePack),
+            #[cfg(feature = "cbor")]
+            "cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn write<W>(
+    format: Format,
+    values: &BTreeMap<String, Value>,
+    mut writer: W,
+) -> Result<(), ConfigError>
+where
+    W: Write,
+{
+    match format {
+        Format::Json => serde_json::to_writer_pretty(&mut writer, values)
+            .map_err(|err| ConfigError::serialization("export", err))?,
+        #[cfg(feature = "toml")]
+        Format::Toml => {
+            let rendered = toml_edit::ser::to_string_pretty(values)
+                .map_err(|err| ConfigError::serialization("export", err))?;
+            writer
+                .write_all(rendered.as_bytes())
+                .map_err(|err| ConfigError::io("export", err))?;
+        }
+        #[cfg(feature = "yaml")]
+        Format::Yaml => serde_yaml::to_writer(&mut writer, values)
+            .map_err(|err| ConfigError::serialization("export", err))?,
+        #[cfg(feature = "ron")]
+        Format::Ron => {
+            let rendered = ron::ser::to_string_pretty(values, ron::ser::PrettyConfig::default())
+                .map_err(|err| ConfigError::serialization("export", err))?;
+            writer
+                .write_all(rendered.as_bytes())
+                .map_err(|err| ConfigError::io("export", err))?;
+        }
+        #[cfg(feature = "msgpack")]
+        Format::MessagePack => rmp_serde::encode::write_named(&mut writer, values)
+            .map_err(|err| ConfigError::serialization("export", err))?,
+        #[cfg(feature = "cbor")]
+        Format::Cbor => ciborium::into_writer(values, &mut writer)
+            .map_err(|err| ConfigError::serialization("export", err))?,
+    }
+
+    writer.flush().map_err(|err| ConfigError::io("export", err))
+}
+
+pub(crate) fn read<R>(format: Format, reader: R) -> Result<BTreeMap<String, Value>, ConfigError>
+where
+    R: Read,
+{
+    match format {
+        Format::Json => serde_json::from_reader(reader)
+            .map_err(|err| ConfigError::deserialization("import", err)),
+        #[cfg(feature = "toml")]
+        Format::Toml => {
+            let raw =
+                std::io::read_to_string(reader).map_err(|err| ConfigError::io("import", err))?;
+            toml_edit::de::from_str(&raw).map_err(|err| ConfigError::deserialization("import", err))
+        }
+        #[cfg(feature = "yaml")]
+        Format::Yaml => serde_yaml::from_reader(reader)
+            .map_err(|err| ConfigError::deserialization("import", err)),
+        #[cfg(feature = "ron")]
+        Format::Ron => {
+            ron::de::from_reader(reader).map_err(|err| ConfigError::deserialization("import", err))
+        }
+        #[cfg(feature = "msgpack")]
+        Format::MessagePack => {
+            rmp_serde::from_read(reader).map_err(|err| ConfigError::deserialization("import", err))
+        }
+        #[cfg(feature = "cbor")]
+        Format::Cbor => {
+            ciborium::from_reader(reader).map_err(|err| ConfigError::deserialization("import", err))
+        }
+    }
+}
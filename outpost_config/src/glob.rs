@@ -0,0 +1,130 @@
+//! Glob patterns as understood by [`ConfigProvider::list_glob`](crate::ConfigProvider::list_glob)
+//!
+//! The syntax follows Redis `SCAN MATCH`: `*` matches any number of characters, `?` matches a
+//! single character, `[abc]`, `[a-z]` and `[^abc]` match one character of a set and `\`
+//! escapes the next character.
+
+/// Checks if the whole text matches the pattern.
+pub(crate) fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    match_from(&pattern, &text)
+}
+
+/// The part of the pattern before its first wildcard, every match starts with it.
+pub(crate) fn literal_prefix(pattern: &str) -> String {
+    let mut prefix = String::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '?' | '[' => break,
+            '\\' => match chars.next() {
+                Some(escaped) => prefix.push(escaped),
+                None => prefix.push(c),
+            },
+            _ => prefix.push(c),
+        }
+    }
+
+    prefix
+}
+
+/// Escape the characters of the text that have a meaning in patterns.
+pub(crate) fn escape(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            // consecutive stars match the same as one
+            let rest = &pattern[1..];
+            if rest.first() == Some(&'*') {
+                return match_from(rest, text);
+            }
+            (0..=text.len()).any(|skip| match_from(rest, &text[skip..]))
+        }
+        Some('?') => !text.is_empty() && match_from(&pattern[1..], &text[1..]),
+        Some('[') => match (
+            match_class(&pattern[1..], text.first().copied()),
+            text.first(),
+        ) {
+            (Some((matched, rest)), Some(_)) => matched && match_from(rest, &text[1..]),
+            (Some(_), None) => false,
+            // an unterminated class is a literal bracket
+            (None, _) => literal(pattern, text, 1),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && match_from(&pattern[2..], &text[1..])
+        }
+        Some(_) => literal(pattern, text, 1),
+    }
+}
+
+fn literal(pattern: &[char], text: &[char], len: usize) -> bool {
+    text.first() == pattern.first() && match_from(&pattern[len..], &text[1..])
+}
+
+/// Match a character against the class starting after a `[`, returns whether it matched and
+/// the pattern after the closing `]`, or `None` if the class isn't terminated.
+fn match_class(class: &[char], c: Option<char>) -> Option<(bool, &[char])> {
+    let (negated, mut rest) = match class.first() {
+        Some('^') | Some('!') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut matched = false;
+
+    loop {
+        let first = match rest.first() {
+            Some(']') => return Some((matched != negated, &rest[1..])),
+            Some('\\') if rest.len() > 1 => {
+                rest = &rest[1..];
+                rest[0]
+            }
+            Some(first) => *first,
+            None => return None,
+        };
+
+        if rest.len() > 2 && rest[1] == '-' && rest[2] != ']' {
+            let last = rest[2];
+            matched |= c.is_some_and(|c| first <= c && c <= last);
+            rest = &rest[3..];
+        } else {
+            matched |= c == Some(first);
+            rest = &rest[1..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_like_redis() {
+        assert!(matches("routes.*", "routes.api.upstream"));
+        assert!(!matches("routes.*", "tls.routes.api"));
+        assert!(matches("routes.*.cert", "routes.api.cert"));
+        assert!(matches("node?", "node1"));
+        assert!(!matches("node?", "node10"));
+        assert!(matches("node[0-3]", "node2"));
+        assert!(!matches("node[^0-3]", "node2"));
+        assert!(matches("weird\\*key", "weird*key"));
+        assert!(!matches("weird\\*key", "weird-key"));
+        assert!(matches("[unterminated", "[unterminated"));
+
+        assert_eq!(literal_prefix("routes.*.cert"), "routes.");
+        assert_eq!(literal_prefix("a\\*b?"), "a*b");
+        assert!(matches(&format!("{}*", escape("a[1]*")), "a[1]*.b"));
+    }
+}
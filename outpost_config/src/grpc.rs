@@ -0,0 +1,437 @@
+//! gRPC service managing the config of a provider remotely
+//!
+//! The `outpost.config.ConfigService` has the methods
+//!
+//! | Method    | Answer                                                                  |
+//! |-----------|-------------------------------------------------------------------------|
+//! | `Get`     | the value of the key as JSON                                            |
+//! | `Version` | the version of the key, unset if it doesn't exist                       |
+//! | `Put`     | stores the JSON value, if `conditional` only if the version matches     |
+//! | `Delete`  | removes the key                                                         |
+//! | `List`    | the keys below the prefix                                               |
+//! | `Watch`   | a stream of the changes below the prefix, if the provider can be watched |
+//!
+//! Values travel as JSON text, so any value a provider stores can be sent and the typed
+//! (de)serialization stays on both ends. Errors are answered with the status matching the
+//! `ConfigError`, e.g. `NOT_FOUND` for a missing key or `ABORTED` if the expected version is
+//! outdated. [`GrpcProvider`](crate::provider::grpc::GrpcProvider) is the client.
+//!
+//! ```ignore
+//! let service = ConfigService::new(InMemoryProvider::open("gatekeeper.json")?)
+//!     .with_watch()
+//!     .with_bearer_token("s3cret");
+//! service.serve(TcpListener::bind("0.0.0.0:7070").await?).await?;
+//! ```
+
+use crate::{constant_time_eq, ChangeEvent, ConfigError, ConfigProvider, WatchableConfigProvider};
+use serde_json::Value;
+use std::pin::Pin;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tokio_stream::Stream;
+use tonic::metadata::MetadataMap;
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Server;
+use tonic::{Code, Request, Response, Status};
+
+mod proto {
+    include!(concat!(env!("OUT_DIR"), "/outpost.config.ConfigService.rs"));
+}
+
+pub use proto::config_service_client::ConfigServiceClient;
+pub use proto::config_service_server::ConfigServiceServer;
+
+/// Changes buffered for a watcher that doesn't keep up.
+const WATCH_BUFFER: usize = 64;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyRequest {
+    #[prost(string, tag = "1")]
+    pub key: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetResponse {
+    /// The value as JSON.
+    #[prost(string, tag = "1")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VersionResponse {
+    #[prost(string, optional, tag = "1")]
+    pub version: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PutRequest {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    /// The value as JSON.
+    #[prost(string, tag = "2")]
+    pub value: String,
+    /// Only write if the key has the expected version, an unset one expecting the key not to
+    /// exist.
+    #[prost(bool, tag = "3")]
+    pub conditional: bool,
+    #[prost(string, optional, tag = "4")]
+    pub expected_version: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Written {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListRequest {
+    #[prost(string, tag = "1")]
+    pub prefix: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListResponse {
+    #[prost(string, repeated, tag = "1")]
+    pub keys: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WatchRequest {
+    #[prost(string, tag = "1")]
+    pub prefix: String,
+}
+
+/// A [`ChangeEvent`], a delete if `new` is unset.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WatchEvent {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    /// The previous value as JSON, unset if the key was inserted.
+    #[prost(string, optional, tag = "2")]
+    pub old: Option<String>,
+    /// The new value as JSON, unset if the key was deleted.
+    #[prost(string, optional, tag = "3")]
+    pub new: Option<String>,
+}
+
+impl From<ChangeEvent> for WatchEvent {
+    fn from(event: ChangeEvent) -> Self {
+        match event {
+            ChangeEvent::Put { key, old, new } => WatchEvent {
+                key,
+                old: old.map(|old| old.to_string()),
+                new: Some(new.to_string()),
+            },
+            ChangeEvent::Delete { key, old } => WatchEvent {
+                key,
+                old: Some(old.to_string()),
+                new: None,
+            },
+        }
+    }
+}
+
+/// Interceptor refusing calls without the bearer token, if one is configured
+#[derive(Clone)]
+pub struct BearerToken(Option<String>);
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        match &self.0 {
+            Some(token) if !is_authorized(request.metadata(), token) => {
+                Err(Status::unauthenticated("missing or wrong bearer token"))
+            }
+            _ => Ok(request),
+        }
+    }
+}
+
+type WatchFn = Box<dyn Fn(&str) -> Result<Receiver<ChangeEvent>, ConfigError> + Send + Sync>;
+
+/// gRPC service in front of a provider
+pub struct ConfigService<P> {
+    provider: Arc<P>,
+    watch: Option<WatchFn>,
+    token: Option<String>,
+}
+
+impl<P> ConfigService<P>
+where
+    P: ConfigProvider + Send + Sync + 'static,
+{
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            watch: None,
+            token: None,
+        }
+    }
+
+    /// Only answer requests sending the token as `authorization: Bearer <token>` metadata.
+    pub fn with_bearer_token<S>(mut self, token: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// The service to add to a tonic [`Server`].
+    pub fn into_server(self) -> InterceptedService<ConfigServiceServer<Self>, BearerToken> {
+        let token = BearerToken(self.token.clone());
+        ConfigServiceServer::with_interceptor(self, token)
+    }
+
+    /// Answer the connections of the listener until accepting one fails.
+    pub async fn serve(self, listener: TcpListener) -> Result<(), ConfigError> {
+        Server::builder()
+            .add_service(self.into_server())
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .map_err(|err| ConfigError::backend("grpc", err))
+    }
+
+    /// Run a provider call on the blocking pool, providers may block on their backend.
+    async fn call<F, R>(&self, f: F) -> Result<R, Status>
+    where
+        F: FnOnce(&P) -> Result<R, ConfigError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let provider = Arc::clone(&self.provider);
+        tokio::task::spawn_blocking(move || f(&provider))
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?
+            .map_err(|err| status_of(&err))
+    }
+}
+
+impl<P> ConfigService<P>
+where
+    P: WatchableConfigProvider + Send + Sync + 'static,
+{
+    /// Answer `Watch`, which is refused as unimplemented otherwise.
+    pub fn with_watch(mut self) -> Self {
+        let provider = Arc::clone(&self.provider);
+        self.watch = Some(Box::new(move |prefix| provider.watch(prefix)));
+        self
+    }
+}
+
+#[tonic::async_trait]
+impl<P> proto::config_service_server::ConfigService for ConfigService<P>
+where
+    P: ConfigProvider + Send + Sync + 'static,
+{
+    async fn get(&self, request: Request<KeyRequest>) -> Result<Response<GetResponse>, Status> {
+        let key = request.into_inner().key;
+        let value = self.call(move |provider| provider.get_value(&key)).await?;
+
+        Ok(Response::new(GetResponse {
+            value: value.to_string(),
+        }))
+    }
+
+    async fn version(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<VersionResponse>, Status> {
+        let key = request.into_inner().key;
+        let version = self.call(move |provider| provider.version(&key)).await?;
+
+        Ok(Response::new(VersionResponse { version }))
+    }
+
+    async fn put(&self, request: Request<PutRequest>) -> Result<Response<Written>, Status> {
+        let request = request.into_inner();
+        let value: Value = serde_json::from_str(&request.value).map_err(|err| {
+            status_of(&ConfigError::deserialization("grpc", err).with_key(&request.key))
+        })?;
+
+        self.call(move |provider| {
+            if request.conditional {
+                provider.put_if_version(&request.key, value, request.expected_version.as_deref())
+            } else {
+                provider.put_value(&request.key, value)
+            }
+        })
+        .await?;
+
+        Ok(Response::new(Written {}))
+    }
+
+    async fn delete(&self, request: Request<KeyRequest>) -> Result<Response<Written>, Status> {
+        let key = request.into_inner().key;
+        self.call(move |provider| provider.delete(&key)).await?;
+
+        Ok(Response::new(Written {}))
+    }
+
+    async fn list(&self, request: Request<ListRequest>) -> Result<Response<ListResponse>, Status> {
+        let prefix = request.into_inner().prefix;
+        let keys = self
+            .call(move |provider| provider.list_prefix(&prefix))
+            .await?;
+
+        Ok(Response::new(ListResponse { keys }))
+    }
+
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<WatchEvent, Status>> + Send>>;
+
+    async fn watch(
+        &self,
+        request: Request<WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let watch = self
+            .watch
+            .as_ref()
+            .ok_or_else(|| Status::unimplemented("the provider can't be watched"))?;
+        let changes = watch(&request.into_inner().prefix).map_err(|err| status_of(&err))?;
+
+        // the provider reports on a blocking channel, the thread ends with the next change
+        // after the client went away
+        let (sender, receiver) = mpsc::channel(WATCH_BUFFER);
+        thread::spawn(move || {
+            for event in changes {
+                if sender.blocking_send(Ok(event.into())).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+fn is_authorized(metadata: &MetadataMap, token: &str) -> bool {
+    metadata
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|sent| constant_time_eq(sent.as_bytes(), token.as_bytes()))
+}
+
+/// The status answering an error, [`config_error`] maps it back.
+fn status_of(err: &ConfigError) -> Status {
+    let code = match err {
+        ConfigError::NotFound { .. } => Code::NotFound,
+        ConfigError::Deserialization { .. }
+        | ConfigError::InvalidKey { .. }
+        | ConfigError::Validation { .. } => Code::InvalidArgument,
+        ConfigError::PermissionDenied { .. } => Code::PermissionDenied,
+        ConfigError::ReadOnly { .. } => Code::FailedPrecondition,
+        ConfigError::Conflict { .. } => Code::Aborted,
+        ConfigError::QuotaExceeded { .. } => Code::ResourceExhausted,
+        ConfigError::Serialization { .. }
+        | ConfigError::Corrupted { .. }
+        | ConfigError::Io { .. }
+        | ConfigError::Backend { .. } => Code::Internal,
+    };
+
+    Status::new(code, err.to_string())
+}
+
+/// The error of a call for the given key that was answered with the status.
+pub(crate) fn config_error(key: Option<&str>, status: Status) -> ConfigError {
+    let err = match (status.code(), key) {
+        (Code::NotFound, Some(key)) => return ConfigError::not_found("grpc", key),
+        (Code::Aborted, Some(key)) => return ConfigError::conflict("grpc", key),
+        (Code::ResourceExhausted, Some(key)) => {
+            return ConfigError::quota_exceeded("grpc", key, status.message())
+        }
+        (Code::FailedPrecondition, _) => ConfigError::read_only("grpc"),
+        (Code::PermissionDenied, _) | (Code::Unauthenticated, _) => {
+            ConfigError::permission_denied("grpc", status)
+        }
+        _ => ConfigError::backend("grpc", status),
+    };
+
+    match key {
+        Some(key) => err.with_key(key),
+        None => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn errors_survive_the_round_trip_through_a_status() {
+        let answered = |err: ConfigError| config_error(Some("workers"), status_of(&err));
+
+        assert!(answered(ConfigError::not_found("in_memory", "workers")).is_not_found());
+        assert!(matches!(
+            answered(ConfigError::conflict("in_memory", "workers")),
+            ConfigError::Conflict { .. }
+        ));
+        assert!(matches!(
+            answered(ConfigError::quota_exceeded(
+                "quota",
+                "workers",
+                "too many keys"
+            )),
+            ConfigError::QuotaExceeded { .. }
+        ));
+        assert!(matches!(
+            answered(ConfigError::read_only("read_only")),
+            ConfigError::ReadOnly { key: Some(_), .. }
+        ));
+        assert!(matches!(
+            answered(ConfigError::permission_denied("vault", "forbidden")),
+            ConfigError::PermissionDenied { .. }
+        ));
+
+        let err = answered(ConfigError::backend("redis", "connection refused"));
+        assert!(matches!(err, ConfigError::Backend { .. }));
+        assert!(err.to_string().contains("connection refused"), "{}", err);
+    }
+
+    #[test]
+    fn missing_keys_without_a_key_are_backend_errors() {
+        let err = config_error(None, Status::not_found("gone"));
+        assert!(matches!(err, ConfigError::Backend { key: None, .. }));
+    }
+
+    #[test]
+    fn only_the_bearer_token_is_authorized() {
+        let mut metadata = MetadataMap::new();
+        assert!(!is_authorized(&metadata, "s3cret"));
+
+        metadata.insert("authorization", "s3cret".parse().unwrap());
+        assert!(!is_authorized(&metadata, "s3cret"));
+        metadata.insert("authorization", "Bearer guess".parse().unwrap());
+        assert!(!is_authorized(&metadata, "s3cret"));
+        metadata.insert("authorization", "Bearer s3cret".parse().unwrap());
+        assert!(is_authorized(&metadata, "s3cret"));
+    }
+
+    #[test]
+    fn changes_carry_their_values_as_json() {
+        let put: WatchEvent = ChangeEvent::Put {
+            key: "workers".to_string(),
+            old: None,
+            new: json!(4),
+        }
+        .into();
+        assert_eq!(
+            put,
+            WatchEvent {
+                key: "workers".to_string(),
+                old: None,
+                new: Some("4".to_string()),
+            }
+        );
+
+        let delete: WatchEvent = ChangeEvent::Delete {
+            key: "listen".to_string(),
+            old: json!(":80"),
+        }
+        .into();
+        assert_eq!(delete.old.as_deref(), Some("\":80\""));
+        assert_eq!(delete.new, None);
+    }
+}
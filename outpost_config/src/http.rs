@@ -0,0 +1,76 @@
+use crate::ConfigError;
+
+/// Map a 404 answer of a remote backend to `None`, all other failures to a ConfigError.
+#[cfg(any(
+    feature = "azure",
+    feature = "consul",
+    feature = "gcp",
+    feature = "http",
+    feature = "kube",
+    feature = "vault"
+))]
+pub(crate) fn not_found_as_none(
+    provider: &'static str,
+    result: Result<ureq::Response, ureq::Error>,
+) -> Result<Option<ureq::Response>, ConfigError> {
+    match result {
+        Ok(response) => Ok(Some(response)),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(err) => Err(http_error(provider, err)),
+    }
+}
+
+/// Map rejected credentials to ConfigError::PermissionDenied, all other failures to
+/// ConfigError::Backend.
+pub(crate) fn http_error(provider: &'static str, err: ureq::Error) -> ConfigError {
+    match err {
+        ureq::Error::Status(401, _) | ureq::Error::Status(403, _) => {
+            ConfigError::permission_denied(provider, err)
+        }
+        err => ConfigError::backend(provider, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(status: u16) -> ureq::Error {
+        ureq::Error::Status(status, ureq::Response::new(status, "", "").unwrap())
+    }
+
+    #[test]
+    fn rejected_credentials_are_denied_permission() {
+        for code in [401, 403] {
+            assert!(matches!(
+                http_error("vault", status(code)),
+                ConfigError::PermissionDenied { .. }
+            ));
+        }
+        assert!(matches!(
+            http_error("vault", status(500)),
+            ConfigError::Backend { .. }
+        ));
+    }
+
+    #[cfg(any(
+        feature = "azure",
+        feature = "consul",
+        feature = "gcp",
+        feature = "http",
+        feature = "kube",
+        feature = "vault"
+    ))]
+    #[test]
+    fn missing_entries_are_none() {
+        assert!(not_found_as_none("vault", Err(status(404)))
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            not_found_as_none("vault", Err(status(403))),
+            Err(ConfigError::PermissionDenied { .. })
+        ));
+        let found = ureq::Response::new(200, "OK", "{}").unwrap();
+        assert!(not_found_as_none("vault", Ok(found)).unwrap().is_some());
+    }
+}
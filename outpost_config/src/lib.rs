@@ -1,17 +1,69 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use format::Format;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::SystemTime;
 use thiserror::Error;
 
+#[cfg(feature = "admin")]
+pub mod admin;
+#[cfg(all(feature = "async-http", any(feature = "etcd", feature = "http")))]
+mod async_http;
+#[cfg(feature = "aws")]
+pub mod aws;
+pub mod builder;
+pub mod cell;
+pub mod diff;
+pub mod erased;
+#[cfg(feature = "fs")]
+mod file;
+pub mod format;
+mod glob;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(any(
+    feature = "azure",
+    feature = "consul",
+    feature = "etcd",
+    feature = "gcp",
+    feature = "http",
+    feature = "kube",
+    feature = "vault"
+))]
+mod http;
+pub mod paths;
 pub mod provider;
+pub mod save_guard;
+pub mod section;
+pub mod sync;
+
+pub use builder::ConfigBuilder;
+pub use cell::TypedCell;
+pub use erased::ErasedConfigProvider;
+pub use save_guard::SaveGuard;
+pub use section::ConfigSection;
+
+// lets the derive macros refer to this crate by name inside of it
+extern crate self as outpost_config;
 
 /// Key value config provider
 ///
 /// The implementation may persist its values but is not forced to do so.
 pub trait ConfigProvider {
     /// Get a specific value from the config and deserialize it to the given type.
-    /// Returns a ConfigError::NotFound if the key doesn't or ConfigError::Other if something else
-    /// goes wrong.
+    /// Returns a ConfigError::NotFound if the key doesn't exist or another ConfigError if
+    /// something else goes wrong.
     fn get<T>(&self, key: &str) -> Result<T, ConfigError>
     where
         T: DeserializeOwned;
@@ -20,6 +72,43 @@ pub trait ConfigProvider {
     /// Returns a ConfigError if the config could not be checked for some reason.
     fn has(&self, key: &str) -> Result<bool, ConfigError>;
 
+    /// Like [`ConfigProvider::get`] but returns `None` if the key doesn't exist.
+    fn get_opt<T>(&self, key: &str) -> Result<Option<T>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        match self.get(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(ConfigError::NotFound { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Like [`ConfigProvider::get`] but returns the given default if the key doesn't exist.
+    fn get_or<T>(&self, key: &str, default: T) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(self.get_opt(key)?.unwrap_or(default))
+    }
+
+    /// Like [`ConfigProvider::get`] but computes a default if the key doesn't exist.
+    fn get_or_else<T, F>(&self, key: &str, default: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+        F: FnOnce() -> T,
+    {
+        Ok(self.get_opt(key)?.unwrap_or_else(default))
+    }
+
+    /// Like [`ConfigProvider::get`] but returns `T::default()` if the key doesn't exist.
+    fn get_or_default<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Default,
+    {
+        Ok(self.get_opt(key)?.unwrap_or_default())
+    }
+
     /// Insert a key value pair to the config
     fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
     where
@@ -32,9 +121,522 @@ pub trait ConfigProvider {
     /// Lists all available keys in the config
     /// Returns a ConfigError if the keys could not be listed for some reason.
     fn list(&self) -> Result<Vec<String>, ConfigError>;
+
+    /// Lists the keys starting with the given prefix.
+    /// Providers override this to filter on the backend instead of listing all keys.
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|key| key.starts_with(prefix))
+            .collect())
+    }
+
+    /// Lists the keys matching the given glob pattern, e.g. `routes.*.upstream`.
+    /// `*` matches any number of characters, `?` a single character, `[a-z]` or `[^a-z]` one
+    /// character of a set and `\` escapes the next character.
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        // the literal start of the pattern lets the provider narrow the listing down
+        Ok(self
+            .list_prefix(&glob::literal_prefix(pattern))?
+            .into_iter()
+            .filter(|key| glob::matches(pattern, key))
+            .collect())
+    }
+
+    /// Lists up to `limit` keys in ascending order, starting after the `cursor` returned with
+    /// the previous page, or at the first key if it is `None`.
+    /// Keys written or deleted between the calls may or may not be seen, but no key that
+    /// exists the whole time is skipped or returned twice.
+    /// The default implementation still lists all keys for every page, providers override
+    /// this to use the pagination of their backend.
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        let mut keys: Vec<String> = self
+            .list()?
+            .into_iter()
+            .filter(|key| cursor.is_none_or(|cursor| key.as_str() > cursor))
+            .collect();
+        keys.sort();
+
+        Ok(KeyPage::truncated(keys, limit))
+    }
+
+    /// Get the value of a typed key, see [`ConfigKey`].
+    fn get_key<T>(&self, key: &ConfigKey<T>) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.get(key.name())
+    }
+
+    /// Insert the value of a typed key, see [`ConfigKey`].
+    fn put_key<T>(&self, key: &ConfigKey<T>, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.put(key.name(), value)
+    }
+
+    /// Get the value of the given key, or insert and return the value computed by `f` if the
+    /// key doesn't exist.
+    /// Providers that can do so override this to check and insert in one atomic operation, the
+    /// default implementation may race with concurrent writers.
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        if let Some(value) = self.get_opt(key)? {
+            return Ok(value);
+        }
+
+        let value = f();
+        let serialized = serde_json::to_value(&value)
+            .map_err(|err| ConfigError::serialization("get_or_insert_with", err).with_key(key))?;
+        self.put(key, serialized)?;
+
+        Ok(value)
+    }
+
+    /// The version of the current value of the given key, `None` if the key doesn't exist.
+    /// Pass it to [`ConfigProvider::put_if_version`] to update the key only if nobody changed it
+    /// since it was read. Versions are opaque, wrappers storing a transformed value report the
+    /// version of the stored one.
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        Ok(self.get_opt::<Value>(key)?.as_ref().map(value_version))
+    }
+
+    /// Insert a value only if the key still has the expected version, `None` expecting the key
+    /// not to exist. Fails with `ConfigError::Conflict` if another writer changed it in between.
+    ///
+    /// The default implementation is **not atomic**: it reads the version and writes afterwards,
+    /// so a concurrent writer can change the key in between and lose its update. Every provider
+    /// of this crate that can be shared between writers overrides it, either with the compare and
+    /// set of its backend or by forwarding to the provider it wraps, and backends without one fail
+    /// with `ConfigError::Backend` instead of racing. Only implement it on top of the default for
+    /// providers that have a single writer.
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        if self.version(key)?.as_deref() != expected_version {
+            return Err(ConfigError::conflict("put_if_version", key));
+        }
+
+        self.put(key, value)
+    }
+
+    /// Get the value of the given key without knowing its type.
+    fn get_value(&self, key: &str) -> Result<Value, ConfigError> {
+        self.get(key)
+    }
+
+    /// Insert a value without knowing its type.
+    fn put_value(&self, key: &str, value: Value) -> Result<(), ConfigError> {
+        self.put(key, value)
+    }
+
+    /// Get the part of a structured value the JSON pointer points to, e.g. `/cert_file` of the
+    /// key `tls`. Fails with `ConfigError::NotFound` if the key or the part doesn't exist.
+    fn get_path(&self, key: &str, pointer: &str) -> Result<Value, ConfigError> {
+        self.get_value(key)?
+            .pointer(pointer)
+            .cloned()
+            .ok_or_else(|| ConfigError::not_found("get_path", format!("{}#{}", key, pointer)))
+    }
+
+    /// Replace the part of a structured value the JSON pointer points to and store the whole
+    /// value again. The part has to exist already, the write may race with concurrent writers
+    /// of the same key.
+    fn put_path(&self, key: &str, pointer: &str, value: Value) -> Result<(), ConfigError> {
+        let mut whole = self.get_value(key)?;
+        let part = whole
+            .pointer_mut(pointer)
+            .ok_or_else(|| ConfigError::not_found("put_path", format!("{}#{}", key, pointer)))?;
+        *part = value;
+
+        self.put_value(key, whole)
+    }
+
+    /// Get the value of the given key as text without knowing its type, e.g. to print it in a
+    /// CLI. Strings are returned verbatim, every other value as JSON.
+    fn get_raw(&self, key: &str) -> Result<String, ConfigError> {
+        match self.get_value(key)? {
+            Value::String(raw) => Ok(raw),
+            value => Ok(value.to_string()),
+        }
+    }
+
+    /// Insert a value given as text, the counterpart of [`ConfigProvider::get_raw`].
+    /// Valid JSON is stored as the value it describes, so `8080` becomes a number, anything else
+    /// like a PEM certificate is stored verbatim as a string.
+    fn put_raw(&self, key: &str, raw: &str) -> Result<(), ConfigError> {
+        let value = serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()));
+
+        self.put_value(key, value)
+    }
+
+    /// Get a binary value, e.g. a DER certificate or a compiled policy bundle, stored with
+    /// [`ConfigProvider::put_bytes`]. Fails with `ConfigError::Deserialization` if the value isn't
+    /// base64.
+    fn get_bytes(&self, key: &str) -> Result<Vec<u8>, ConfigError> {
+        STANDARD
+            .decode(self.get::<String>(key)?)
+            .map_err(|err| ConfigError::deserialization("get_bytes", err).with_key(key))
+    }
+
+    /// Insert a binary value. It is stored as a base64 string, so it survives every provider and
+    /// file format.
+    fn put_bytes(&self, key: &str, bytes: &[u8]) -> Result<(), ConfigError> {
+        self.put(key, STANDARD.encode(bytes))
+    }
+
+    /// Figures about the provider for health checks. The default only counts the keys,
+    /// providers knowing more about their values, cache or files override it.
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        Ok(ProviderStats {
+            keys: self.list()?.len(),
+            ..ProviderStats::default()
+        })
+    }
+
+    /// Get several values at once, `None` marks the keys that don't exist.
+    /// Remote providers override this to fetch all keys in one round trip.
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        keys.iter().map(|key| self.get_opt(key)).collect()
+    }
+
+    /// Get all keys starting with the given prefix together with their values.
+    /// The values are fetched with [`ConfigProvider::get_many`], keys deleted in the meantime
+    /// are left out.
+    fn entries<T>(&self, prefix: &str) -> Result<Vec<(String, T)>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let keys = self.list_prefix(prefix)?;
+        let refs: Vec<&str> = keys.iter().map(String::as_str).collect();
+        let values = self.get_many(&refs)?;
+
+        Ok(keys
+            .into_iter()
+            .zip(values)
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .collect())
+    }
+
+    /// Write all keys and their values to the writer in the given format, e.g. to review the
+    /// config of a remote backend as TOML.
+    fn export<W>(&self, format: Format, writer: W) -> Result<(), ConfigError>
+    where
+        W: Write,
+    {
+        let values: BTreeMap<String, Value> = self.entries("")?.into_iter().collect();
+
+        format::write(format, &values, writer)
+    }
+
+    /// Insert all keys and values read from the reader in the given format, e.g. a previous
+    /// [`ConfigProvider::export`]. Keys that aren't part of the input are left untouched.
+    fn import<R>(&self, reader: R, format: Format) -> Result<(), ConfigError>
+    where
+        R: Read,
+    {
+        self.put_many(format::read(format, reader)?.into_iter().collect())
+    }
+
+    /// When the key was last written, `None` if it doesn't exist or the provider doesn't know.
+    /// Only providers keeping track of their changes, like
+    /// [`VersionedProvider`](provider::versioned::VersionedProvider), override this.
+    fn modified(&self, _key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        Ok(None)
+    }
+
+    /// Capture all keys and their values, e.g. to restore them with
+    /// [`TransactionalConfigProvider::restore`] if a change turns out to be bad.
+    /// The keys are read with [`ConfigProvider::entries`], which is not atomic on most
+    /// providers, concurrent writes may or may not be captured.
+    fn snapshot(&self) -> Result<Snapshot, ConfigError> {
+        Ok(Snapshot {
+            values: self.entries("")?.into_iter().collect(),
+        })
+    }
+
+    /// Insert several key value pairs at once.
+    /// Remote providers override this to store all pairs in one round trip, the pairs are not
+    /// necessarily written atomically.
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        for (key, value) in entries {
+            self.put(&key, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Figures about a provider as returned by [`ConfigProvider::stats`], `None` where the provider
+/// doesn't know them
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProviderStats {
+    pub keys: usize,
+    /// Size of the keys and the JSON of their values, roughly the memory they take.
+    pub bytes: Option<u64>,
+    /// Reads a cache answered itself.
+    pub hits: Option<u64>,
+    /// Reads a cache passed on to the provider behind it.
+    pub misses: Option<u64>,
+    pub last_load: Option<SystemTime>,
+    pub last_save: Option<SystemTime>,
+}
+
+/// Keys returned by [`ConfigProvider::list_page`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyPage {
+    pub keys: Vec<String>,
+    /// Cursor to pass for the next page, `None` if this is the last one.
+    pub next: Option<String>,
+}
+
+impl KeyPage {
+    /// Page of the first `limit` of the sorted keys, the last key of the page is the cursor of
+    /// the next one. A limit of zero is treated as one so listing always makes progress.
+    pub fn truncated(mut keys: Vec<String>, limit: usize) -> Self {
+        let limit = limit.max(1);
+        if keys.len() <= limit {
+            return Self { keys, next: None };
+        }
+
+        keys.truncate(limit);
+        let next = keys.last().cloned();
+        Self { keys, next }
+    }
+}
+
+/// All keys and values of a provider at one point, see [`ConfigProvider::snapshot`]
+///
+/// Snapshots can be serialized to keep them around, e.g. next to a deployment.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    values: BTreeMap<String, Value>,
+}
+
+impl Snapshot {
+    pub fn values(&self) -> &BTreeMap<String, Value> {
+        &self.values
+    }
+
+    pub fn into_values(self) -> BTreeMap<String, Value> {
+        self.values
+    }
+}
+
+/// Name of a config key together with the type of its value
+///
+/// Declared once as a constant, e.g.
+/// `const LISTEN_ADDR: ConfigKey<SocketAddr> = ConfigKey::new("listen.addr")`, the key is read
+/// with [`ConfigProvider::get_key`] and every call site agrees on the type of its value.
+pub struct ConfigKey<T> {
+    name: &'static str,
+    // fn() -> T keeps the key Send and Sync whatever T is
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T> ConfigKey<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: PhantomData,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+// derives would require T to implement the traits as well
+impl<T> Clone for ConfigKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ConfigKey<T> {}
+
+impl<T> fmt::Debug for ConfigKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ConfigKey").field(&self.name).finish()
+    }
+}
+
+impl<T> fmt::Display for ConfigKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// Shared providers, e.g. a file backed layer of a
+/// [`LayeredProvider`](provider::layered::LayeredProvider) that is saved elsewhere.
+impl<P> ConfigProvider for Arc<P>
+where
+    P: ConfigProvider + ?Sized,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        (**self).get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        (**self).has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        (**self).put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        (**self).delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        (**self).list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        (**self).list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        (**self).list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        (**self).list_page(cursor, limit)
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        (**self).get_or_insert_with(key, f)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        (**self).version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        (**self).put_if_version(key, value, expected_version)
+    }
+
+    fn get_value(&self, key: &str) -> Result<Value, ConfigError> {
+        (**self).get_value(key)
+    }
+
+    fn put_value(&self, key: &str, value: Value) -> Result<(), ConfigError> {
+        (**self).put_value(key, value)
+    }
+
+    fn get_path(&self, key: &str, pointer: &str) -> Result<Value, ConfigError> {
+        (**self).get_path(key, pointer)
+    }
+
+    fn put_path(&self, key: &str, pointer: &str, value: Value) -> Result<(), ConfigError> {
+        (**self).put_path(key, pointer, value)
+    }
+
+    fn get_raw(&self, key: &str) -> Result<String, ConfigError> {
+        (**self).get_raw(key)
+    }
+
+    fn put_raw(&self, key: &str, raw: &str) -> Result<(), ConfigError> {
+        (**self).put_raw(key, raw)
+    }
+
+    fn get_bytes(&self, key: &str) -> Result<Vec<u8>, ConfigError> {
+        (**self).get_bytes(key)
+    }
+
+    fn put_bytes(&self, key: &str, bytes: &[u8]) -> Result<(), ConfigError> {
+        (**self).put_bytes(key, bytes)
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        (**self).stats()
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        (**self).get_many(keys)
+    }
+
+    fn entries<T>(&self, prefix: &str) -> Result<Vec<(String, T)>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        (**self).entries(prefix)
+    }
+
+    fn export<W>(&self, format: Format, writer: W) -> Result<(), ConfigError>
+    where
+        W: Write,
+    {
+        (**self).export(format, writer)
+    }
+
+    fn import<R>(&self, reader: R, format: Format) -> Result<(), ConfigError>
+    where
+        R: Read,
+    {
+        (**self).import(reader, format)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        (**self).modified(key)
+    }
+
+    fn snapshot(&self) -> Result<Snapshot, ConfigError> {
+        (**self).snapshot()
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        (**self).put_many(entries)
+    }
 }
 
 /// ConfigProvider that supports loading/saving its values from/to a file.
+///
+/// The providers of this crate hold an advisory lock on `<file>.lock` while they read or write
+/// a file, so processes sharing a config file don't interleave their writes.
 pub trait FileAwareConfigProvider: ConfigProvider {
     /// Load values from the given path.
     fn load<P>(&self, path: P) -> Result<(), ConfigError>
@@ -45,12 +647,562 @@ pub trait FileAwareConfigProvider: ConfigProvider {
     fn save<P>(&self, path: P) -> Result<(), ConfigError>
     where
         P: AsRef<Path>;
+
+    /// Whether the values changed since they were last loaded or saved, see [`SaveGuard`].
+    /// Providers that don't keep track of it always report changes.
+    fn is_dirty(&self) -> bool {
+        true
+    }
+
+    /// Load values from the given file in the platform config directory of the application,
+    /// see [`paths::config_dir`].
+    fn load_default(&self, app: &str, file_name: &str) -> Result<(), ConfigError> {
+        self.load(paths::config_file(app, file_name)?)
+    }
+
+    /// Save the providers value to the given file in the platform config directory of the
+    /// application, see [`paths::config_dir`].
+    fn save_default(&self, app: &str, file_name: &str) -> Result<(), ConfigError> {
+        self.save(paths::config_file(app, file_name)?)
+    }
+}
+
+impl<P> FileAwareConfigProvider for Arc<P>
+where
+    P: FileAwareConfigProvider,
+{
+    fn load<Q>(&self, path: Q) -> Result<(), ConfigError>
+    where
+        Q: AsRef<Path>,
+    {
+        (**self).load(path)
+    }
+
+    fn save<Q>(&self, path: Q) -> Result<(), ConfigError>
+    where
+        Q: AsRef<Path>,
+    {
+        (**self).save(path)
+    }
+
+    fn is_dirty(&self) -> bool {
+        (**self).is_dirty()
+    }
+}
+
+/// ConfigProvider that can notify about changes of its keys.
+pub trait WatchableConfigProvider: ConfigProvider {
+    /// Subscribe to all changes of keys starting with the given prefix, an empty prefix watches
+    /// the whole config. The subscription ends when the receiver is dropped.
+    fn watch(&self, key_prefix: &str) -> Result<Receiver<ChangeEvent>, ConfigError>;
+}
+
+impl<P> WatchableConfigProvider for Arc<P>
+where
+    P: WatchableConfigProvider,
+{
+    fn watch(&self, key_prefix: &str) -> Result<Receiver<ChangeEvent>, ConfigError> {
+        (**self).watch(key_prefix)
+    }
+}
+
+/// ConfigProvider that can apply several writes atomically.
+pub trait TransactionalConfigProvider: ConfigProvider {
+    /// Apply all writes of the transaction in order, or none of them if one fails.
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError>;
+
+    /// Collect the writes made by the closure and commit them at once. Nothing is written if the
+    /// closure returns an error.
+    fn transaction<F, R>(&self, f: F) -> Result<R, ConfigError>
+    where
+        F: FnOnce(&mut Transaction) -> Result<R, ConfigError>,
+    {
+        let mut transaction = Transaction::new();
+        let result = f(&mut transaction)?;
+        self.commit(transaction)?;
+
+        Ok(result)
+    }
+
+    /// Bring the config back to the state of the snapshot in one transaction, keys created
+    /// after the snapshot was taken are deleted.
+    fn restore(&self, snapshot: Snapshot) -> Result<(), ConfigError> {
+        let mut transaction = Transaction::new();
+        for key in self.list()? {
+            if !snapshot.values.contains_key(&key) {
+                transaction.delete(&key);
+            }
+        }
+        for (key, value) in snapshot.values {
+            transaction.put(&key, value)?;
+        }
+
+        self.commit(transaction)
+    }
 }
 
+impl<P> TransactionalConfigProvider for Arc<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        (**self).commit(transaction)
+    }
+}
+
+/// Writes collected for a [`TransactionalConfigProvider`]
+///
+/// Values are serialized when they are added, so a value that can't be serialized fails the
+/// transaction before anything is written.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transaction {
+    ops: Vec<TransactionOp>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a key value pair when the transaction is committed.
+    pub fn put<T>(&mut self, key: &str, value: T) -> Result<&mut Self, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("transaction", err).with_key(key))?;
+        self.ops.push(TransactionOp::Put {
+            key: key.to_string(),
+            value,
+        });
+
+        Ok(self)
+    }
+
+    /// Delete the given entry, if it exists, when the transaction is committed.
+    pub fn delete(&mut self, key: &str) -> &mut Self {
+        self.ops.push(TransactionOp::Delete {
+            key: key.to_string(),
+        });
+        self
+    }
+
+    /// The writes of the transaction in the order they were added.
+    pub fn ops(&self) -> &[TransactionOp] {
+        &self.ops
+    }
+
+    pub fn into_ops(self) -> Vec<TransactionOp> {
+        self.ops
+    }
+}
+
+/// Single write of a [`Transaction`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransactionOp {
+    Put {
+        key: String,
+        value: serde_json::Value,
+    },
+    Delete {
+        key: String,
+    },
+}
+
+/// Change of a key reported by a [`WatchableConfigProvider`].
+///
+/// Events carry the value before and after the change, so consumers can apply them
+/// incrementally instead of reading the config again.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChangeEvent {
+    /// The key was inserted or updated.
+    Put {
+        key: String,
+        /// The previous value, `None` if the key was inserted.
+        old: Option<Value>,
+        new: Value,
+    },
+    /// The key was deleted.
+    Delete { key: String, old: Value },
+}
+
+impl ChangeEvent {
+    pub fn key(&self) -> &str {
+        match self {
+            ChangeEvent::Put { key, .. } | ChangeEvent::Delete { key, .. } => key,
+        }
+    }
+}
+
+/// Key value config provider for async code
+///
+/// Mirrors [`ConfigProvider`] for backends that are reached over the network, so lookups don't
+/// block the runtime. The `async` feature only brings the trait and doesn't depend on a runtime,
+/// the `tokio` feature adds the providers that need one. Synchronous providers are always
+/// available, so CLI tools don't pull in a runtime.
+///
+/// Every synchronous provider has an async variant sharing its logic:
+/// [`provider::inline::Inline`] answers in place, for providers that never block like the
+/// `InMemoryProvider`, and `provider::blocking::Blocking` from the `blocking` feature moves the
+/// calls of providers doing i/o onto the blocking thread pool of tokio.
+#[cfg(feature = "async")]
+pub trait AsyncConfigProvider {
+    /// Get a specific value from the config and deserialize it to the given type.
+    /// Returns a ConfigError::NotFound if the key doesn't exist or another ConfigError if
+    /// something else goes wrong.
+    fn get<T>(&self, key: &str) -> impl Future<Output = Result<T, ConfigError>> + Send
+    where
+        T: DeserializeOwned + Send + 'static;
+
+    /// Checks if the config contains the given key.
+    /// Returns a ConfigError if the config could not be checked for some reason.
+    fn has(&self, key: &str) -> impl Future<Output = Result<bool, ConfigError>> + Send;
+
+    /// Insert a key value pair to the config
+    fn put<T>(&self, key: &str, value: T) -> impl Future<Output = Result<(), ConfigError>> + Send
+    where
+        T: DeserializeOwned + Serialize + Send + 'static;
+
+    /// Deletes the given entry if it exists.
+    /// Returns a ConfigError if the entry could not be deleted for some reason.
+    fn delete(&self, key: &str) -> impl Future<Output = Result<(), ConfigError>> + Send;
+
+    /// Lists all available keys in the config
+    /// Returns a ConfigError if the keys could not be listed for some reason.
+    fn list(&self) -> impl Future<Output = Result<Vec<String>, ConfigError>> + Send;
+}
+
+/// Error of a config operation
+///
+/// Every variant names the provider that failed and, if the failure concerns a single key,
+/// that key. Errors of generic code outside of a provider, e.g. of a [`Transaction`], name
+/// that component instead.
 #[derive(Error, Debug)]
 pub enum ConfigError {
-    #[error("not found")]
-    NotFound,
-    #[error("other: {0}")]
-    Other(Box<dyn std::error::Error>),
+    /// The key doesn't exist.
+    #[error("{provider}: {key} not found")]
+    NotFound { key: String, provider: &'static str },
+    /// A value couldn't be serialized to be stored.
+    #[error("{provider}: can't serialize value{}: {source}", of_key(.key))]
+    Serialization {
+        key: Option<String>,
+        provider: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A stored value couldn't be deserialized, e.g. because it has the wrong type.
+    #[error("{provider}: can't deserialize value{}: {source}", of_key(.key))]
+    Deserialization {
+        key: Option<String>,
+        provider: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// Reading or writing a local file failed.
+    #[error("{provider}: i/o error{}: {source}", for_key(.key))]
+    Io {
+        key: Option<String>,
+        provider: &'static str,
+        source: std::io::Error,
+    },
+    /// The backend rejected the credentials or the operation.
+    #[error("{provider}: permission denied{}: {source}", for_key(.key))]
+    PermissionDenied {
+        key: Option<String>,
+        provider: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The backend failed or can't be reached.
+    #[error("{provider}: backend error{}: {source}", for_key(.key))]
+    Backend {
+        key: Option<String>,
+        provider: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The value of a key doesn't match its schema, `path` points to the violating part of the
+    /// value as a JSON pointer.
+    #[error("{provider}: invalid value for {key} at '{path}': {message}")]
+    Validation {
+        key: String,
+        provider: &'static str,
+        path: String,
+        message: String,
+    },
+    /// The key was changed by another writer since its expected version was read.
+    #[error("{provider}: {key} was changed concurrently")]
+    Conflict { key: String, provider: &'static str },
+    /// Writing the key would exceed a limit on the size or number of values.
+    #[error("{provider}: {key} exceeds the quota: {message}")]
+    QuotaExceeded {
+        key: String,
+        provider: &'static str,
+        message: String,
+    },
+    /// The key breaks the naming rules of the config, see
+    /// [`KeyPolicy`](crate::provider::key_policy::KeyPolicy).
+    #[error("{provider}: invalid key {key:?}: {message}")]
+    InvalidKey {
+        key: String,
+        provider: &'static str,
+        message: String,
+    },
+    /// A file failed its integrity check, e.g. because it was truncated or edited by hand.
+    #[error("{provider}: {} is corrupted: {message}", .path.display())]
+    Corrupted {
+        path: PathBuf,
+        provider: &'static str,
+        message: String,
+    },
+    /// The provider doesn't accept writes.
+    #[error("{provider}: read only{}", for_key(.key))]
+    ReadOnly {
+        key: Option<String>,
+        provider: &'static str,
+    },
+}
+
+impl ConfigError {
+    pub fn not_found<K>(provider: &'static str, key: K) -> Self
+    where
+        K: Into<String>,
+    {
+        ConfigError::NotFound {
+            key: key.into(),
+            provider,
+        }
+    }
+
+    pub fn serialization<E>(provider: &'static str, err: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        ConfigError::Serialization {
+            key: None,
+            provider,
+            source: err.into(),
+        }
+    }
+
+    pub fn deserialization<E>(provider: &'static str, err: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        ConfigError::Deserialization {
+            key: None,
+            provider,
+            source: err.into(),
+        }
+    }
+
+    /// Errors of the kind `PermissionDenied` become [`ConfigError::PermissionDenied`], all
+    /// others [`ConfigError::Io`].
+    pub fn io(provider: &'static str, err: std::io::Error) -> Self {
+        if err.kind() == std::io::ErrorKind::PermissionDenied {
+            return ConfigError::permission_denied(provider, err);
+        }
+
+        ConfigError::Io {
+            key: None,
+            provider,
+            source: err,
+        }
+    }
+
+    pub fn permission_denied<E>(provider: &'static str, err: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        ConfigError::PermissionDenied {
+            key: None,
+            provider,
+            source: err.into(),
+        }
+    }
+
+    pub fn backend<E>(provider: &'static str, err: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        ConfigError::Backend {
+            key: None,
+            provider,
+            source: err.into(),
+        }
+    }
+
+    pub fn read_only(provider: &'static str) -> Self {
+        ConfigError::ReadOnly {
+            key: None,
+            provider,
+        }
+    }
+
+    pub fn conflict<K>(provider: &'static str, key: K) -> Self
+    where
+        K: Into<String>,
+    {
+        ConfigError::Conflict {
+            key: key.into(),
+            provider,
+        }
+    }
+
+    pub fn quota_exceeded<K, M>(provider: &'static str, key: K, message: M) -> Self
+    where
+        K: Into<String>,
+        M: Into<String>,
+    {
+        ConfigError::QuotaExceeded {
+            key: key.into(),
+            provider,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_key<K, M>(provider: &'static str, key: K, message: M) -> Self
+    where
+        K: Into<String>,
+        M: Into<String>,
+    {
+        ConfigError::InvalidKey {
+            key: key.into(),
+            provider,
+            message: message.into(),
+        }
+    }
+
+    pub fn corrupted<P, M>(provider: &'static str, path: P, message: M) -> Self
+    where
+        P: Into<PathBuf>,
+        M: Into<String>,
+    {
+        ConfigError::Corrupted {
+            path: path.into(),
+            provider,
+            message: message.into(),
+        }
+    }
+
+    /// Name the key the error is about, unless it already names one.
+    pub fn with_key(mut self, key: &str) -> Self {
+        match &mut self {
+            ConfigError::Serialization { key: slot, .. }
+            | ConfigError::Deserialization { key: slot, .. }
+            | ConfigError::Io { key: slot, .. }
+            | ConfigError::PermissionDenied { key: slot, .. }
+            | ConfigError::Backend { key: slot, .. }
+            | ConfigError::ReadOnly { key: slot, .. } => {
+                slot.get_or_insert_with(|| key.to_string());
+            }
+            ConfigError::NotFound { .. }
+            | ConfigError::Validation { .. }
+            | ConfigError::Conflict { .. }
+            | ConfigError::QuotaExceeded { .. }
+            | ConfigError::InvalidKey { .. }
+            | ConfigError::Corrupted { .. } => {}
+        }
+        self
+    }
+
+    /// The key the error is about, if it concerns a single key.
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            ConfigError::NotFound { key, .. }
+            | ConfigError::Validation { key, .. }
+            | ConfigError::Conflict { key, .. }
+            | ConfigError::QuotaExceeded { key, .. }
+            | ConfigError::InvalidKey { key, .. } => Some(key),
+            ConfigError::Serialization { key, .. }
+            | ConfigError::Deserialization { key, .. }
+            | ConfigError::Io { key, .. }
+            | ConfigError::PermissionDenied { key, .. }
+            | ConfigError::Backend { key, .. }
+            | ConfigError::ReadOnly { key, .. } => key.as_deref(),
+            ConfigError::Corrupted { .. } => None,
+        }
+    }
+
+    /// The name of the provider that failed.
+    pub fn provider(&self) -> &'static str {
+        match self {
+            ConfigError::NotFound { provider, .. }
+            | ConfigError::Serialization { provider, .. }
+            | ConfigError::Deserialization { provider, .. }
+            | ConfigError::Io { provider, .. }
+            | ConfigError::PermissionDenied { provider, .. }
+            | ConfigError::Backend { provider, .. }
+            | ConfigError::Validation { provider, .. }
+            | ConfigError::Conflict { provider, .. }
+            | ConfigError::QuotaExceeded { provider, .. }
+            | ConfigError::InvalidKey { provider, .. }
+            | ConfigError::Corrupted { provider, .. }
+            | ConfigError::ReadOnly { provider, .. } => provider,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, ConfigError::NotFound { .. })
+    }
+}
+
+/// The version of a value as returned by [`ConfigProvider::version`], the FNV-1a hash of its
+/// JSON, so it stays the same across processes and restarts.
+pub(crate) fn value_version(value: &Value) -> String {
+    format!("{:016x}", fnv1a(value.to_string().as_bytes()))
+}
+
+/// Compare the bytes in time depending only on their length, so a wrong token doesn't reveal
+/// how much of it was right.
+#[cfg(any(feature = "admin", feature = "grpc"))]
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// The error of [`ConfigProvider::put_if_version`] for backends that can't compare and set
+/// atomically.
+pub(crate) fn no_compare_and_set(provider: &'static str, key: &str) -> ConfigError {
+    ConfigError::backend(provider, "the backend can't compare and set atomically").with_key(key)
+}
+
+/// The version of a value stored as JSON text, the one of the text as string if it isn't JSON.
+#[cfg(any(
+    feature = "consul",
+    feature = "dynamo",
+    feature = "etcd",
+    feature = "kube",
+    feature = "memcache",
+    feature = "nats",
+    feature = "postgres",
+    feature = "redis",
+    feature = "sled",
+    feature = "sqlite",
+    feature = "zookeeper"
+))]
+pub(crate) fn raw_version(raw: &str) -> String {
+    match serde_json::from_str(raw) {
+        Ok(value) => value_version(&value),
+        Err(_) => value_version(&Value::String(raw.to_string())),
+    }
+}
+
+/// The 64 bit FNV-1a hash of the bytes.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_continue(0xcbf2_9ce4_8422_2325, bytes)
+}
+
+/// The 64 bit FNV-1a hash of the bytes hashed to `hash` followed by the given ones.
+pub(crate) fn fnv1a_continue(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn of_key(key: &Option<String>) -> String {
+    key.as_ref()
+        .map(|key| format!(" of {}", key))
+        .unwrap_or_default()
+}
+
+fn for_key(key: &Option<String>) -> String {
+    key.as_ref()
+        .map(|key| format!(" for {}", key))
+        .unwrap_or_default()
 }
@@ -0,0 +1,75 @@
+//! Platform specific default locations of config files
+
+use crate::ConfigError;
+use std::env;
+use std::path::PathBuf;
+
+/// The per user config directory of the given application.
+///
+/// - Linux and other unix systems: `$XDG_CONFIG_HOME/<app>`, falling back to
+///   `$HOME/.config/<app>`
+/// - macOS: `$HOME/Library/Application Support/<app>`
+/// - Windows: `%APPDATA%\<app>`
+///
+/// Returns `None` if the environment doesn't tell where the directory is.
+pub fn config_dir(app: &str) -> Option<PathBuf> {
+    base_dir().map(|base| base.join(app))
+}
+
+/// The path of a config file in the config directory of the given application.
+pub fn config_file(app: &str, file_name: &str) -> Result<PathBuf, ConfigError> {
+    config_dir(app)
+        .map(|dir| dir.join(file_name))
+        .ok_or_else(|| ConfigError::backend("paths", "can't determine the config directory"))
+}
+
+#[cfg(windows)]
+fn base_dir() -> Option<PathBuf> {
+    non_empty_var("APPDATA")
+}
+
+#[cfg(target_os = "macos")]
+fn base_dir() -> Option<PathBuf> {
+    non_empty_var("HOME").map(|home| home.join("Library").join("Application Support"))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn base_dir() -> Option<PathBuf> {
+    // relative XDG paths are invalid and have to be ignored
+    non_empty_var("XDG_CONFIG_HOME")
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| non_empty_var("HOME").map(|home| home.join(".config")))
+}
+
+fn non_empty_var(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(all(test, feature = "fs", not(any(windows, target_os = "macos"))))]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use crate::{ConfigProvider, FileAwareConfigProvider};
+
+    #[test]
+    fn defaults_live_in_the_xdg_config_home() {
+        let dir = tempfile::tempdir().unwrap();
+        env::set_var("XDG_CONFIG_HOME", dir.path());
+
+        assert_eq!(
+            config_file("gatekeeper", "config.json").unwrap(),
+            dir.path().join("gatekeeper").join("config.json")
+        );
+
+        let provider = InMemoryProvider::new();
+        provider.put("workers", 4).unwrap();
+        provider.save_default("gatekeeper", "config.json").unwrap();
+        assert!(dir.path().join("gatekeeper/config.json").is_file());
+
+        let reloaded = InMemoryProvider::new();
+        reloaded.load_default("gatekeeper", "config.json").unwrap();
+        assert_eq!(reloaded.get::<u32>("workers").unwrap(), 4);
+    }
+}
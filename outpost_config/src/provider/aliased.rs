@@ -0,0 +1,181 @@
+use crate::{ConfigError, ConfigProvider, KeyPage};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::SystemTime;
+
+/// Prefix of the string values that are aliases of another key.
+pub const ALIAS_PREFIX: &str = "@ref:";
+
+/// View of another provider that resolves aliases on `get`
+///
+/// A string value of the form `@ref:other.key` is an alias, reading it returns the value of
+/// `other.key` instead, aliases of aliases are followed as well. A missing target or a cycle of
+/// aliases fails with `ConfigError::Backend`, so the alias isn't mistaken for a missing key.
+///
+/// Writes go to the given key, a `put` replaces an alias instead of the value it points to.
+pub struct AliasedProvider<P> {
+    inner: P,
+}
+
+impl<P> AliasedProvider<P>
+where
+    P: ConfigProvider,
+{
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+
+    /// Make `key` an alias of `target`.
+    pub fn put_alias(&self, key: &str, target: &str) -> Result<(), ConfigError> {
+        self.inner.put(key, format!("{}{}", ALIAS_PREFIX, target))
+    }
+
+    /// The value of the key with all aliases followed.
+    fn resolve(&self, key: &str) -> Result<Value, ConfigError> {
+        let mut chain = vec![key.to_string()];
+        let mut value = self.inner.get_value(key)?;
+
+        while let Some(target) = value
+            .as_str()
+            .and_then(|raw| raw.strip_prefix(ALIAS_PREFIX))
+            .map(str::to_string)
+        {
+            if chain.contains(&target) {
+                chain.push(target);
+                return Err(ConfigError::backend(
+                    "aliased",
+                    format!("cyclic aliases {}", chain.join(" -> ")),
+                )
+                .with_key(key));
+            }
+
+            value = self.inner.get_value(&target).map_err(|err| {
+                if err.is_not_found() {
+                    ConfigError::backend(
+                        "aliased",
+                        format!("{} is an alias of the missing key {}", key, target),
+                    )
+                    .with_key(key)
+                } else {
+                    err
+                }
+            })?;
+            chain.push(target);
+        }
+
+        Ok(value)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("aliased"))]
+impl<P> ConfigProvider for AliasedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        serde_json::from_value(self.resolve(key)?)
+            .map_err(|err| ConfigError::deserialization("aliased", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_many(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use serde_json::json;
+
+    #[test]
+    fn aliases_resolve_to_their_target() {
+        let provider = AliasedProvider::new(InMemoryProvider::new());
+        provider
+            .put_value("shared.db", json!({"url": "postgres://db/gk", "pool": 8}))
+            .unwrap();
+        provider.put_alias("auth.db", "shared.db").unwrap();
+        provider.put_alias("ratelimit.db", "auth.db").unwrap();
+
+        assert_eq!(
+            provider.get_value("ratelimit.db").unwrap(),
+            json!({"url": "postgres://db/gk", "pool": 8})
+        );
+
+        provider.put_alias("a", "b").unwrap();
+        provider.put_alias("b", "a").unwrap();
+        provider.put_alias("c", "missing").unwrap();
+        for key in ["a", "c"] {
+            assert!(matches!(
+                provider.get_opt::<Value>(key),
+                Err(ConfigError::Backend { .. })
+            ));
+        }
+
+        provider
+            .put("auth.db", json!({"url": "sqlite://auth.db"}))
+            .unwrap();
+        assert_eq!(
+            provider.get_value("shared.db").unwrap(),
+            json!({"url": "postgres://db/gk", "pool": 8})
+        );
+    }
+}
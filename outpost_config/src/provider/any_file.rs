@@ -0,0 +1,286 @@
+use crate::file::{lock_exclusive, lock_shared, write_atomic};
+use crate::format::{self, Format};
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{
+    ChangeEvent, ConfigError, ConfigProvider, FileAwareConfigProvider, KeyPage, ProviderStats,
+    Transaction, TransactionalConfigProvider, WatchableConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::File;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::time::SystemTime;
+
+/// File aware provider picking the file format from the extension of the path
+///
+/// `.toml`, `.yaml`/`.yml`, `.ron`, `.msgpack`/`.mpk` and `.cbor` files are read and written
+/// like the provider of that format does, which requires its cargo feature. `.json` files hold
+/// an object mapping every key to its value, like [`Format::Json`] exports.
+/// Loading one format and saving another converts the config, e.g. from `gatekeeper.yaml` to
+/// `gatekeeper.toml`.
+#[derive(Default)]
+pub struct AnyFileProvider {
+    inner: InMemoryProvider,
+}
+
+impl AnyFileProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn format_of(path: &Path) -> Result<Format, ConfigError> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(Format::from_extension)
+        .ok_or_else(|| {
+            ConfigError::backend(
+                "any_file",
+                format!("no supported format for the file {}", path.display()),
+            )
+        })
+}
+
+/// Read a file with the provider of its format.
+#[cfg(any(
+    feature = "toml",
+    feature = "yaml",
+    feature = "ron",
+    feature = "msgpack",
+    feature = "cbor"
+))]
+fn load_with<F>(
+    path: &Path,
+) -> Result<std::collections::BTreeMap<String, serde_json::Value>, ConfigError>
+where
+    F: FileAwareConfigProvider + Default,
+{
+    let provider = F::default();
+    provider.load(path)?;
+
+    Ok(provider.snapshot()?.into_values())
+}
+
+/// Write a file with the provider of its format.
+#[cfg(any(
+    feature = "toml",
+    feature = "yaml",
+    feature = "ron",
+    feature = "msgpack",
+    feature = "cbor"
+))]
+fn save_with<F>(
+    values: std::collections::BTreeMap<String, serde_json::Value>,
+    path: &Path,
+) -> Result<(), ConfigError>
+where
+    F: FileAwareConfigProvider + Default,
+{
+    let provider = F::default();
+    provider.put_many(values.into_iter().collect())?;
+
+    provider.save(path)
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("any_file"))]
+impl ConfigProvider for AnyFileProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.inner.stats()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_many(entries)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("any_file"))]
+impl TransactionalConfigProvider for AnyFileProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl WatchableConfigProvider for AnyFileProvider {
+    fn watch(&self, key_prefix: &str) -> Result<Receiver<ChangeEvent>, ConfigError> {
+        self.inner.watch(key_prefix)
+    }
+}
+
+impl FileAwareConfigProvider for AnyFileProvider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let path = path.as_ref();
+        let values = match format_of(path)? {
+            Format::Json => {
+                let _lock = lock_shared("any_file", path)?;
+                let file = File::open(path).map_err(|err| ConfigError::io("any_file", err))?;
+                format::read(Format::Json, file)?
+            }
+            #[cfg(feature = "toml")]
+            Format::Toml => load_with::<crate::provider::toml::TomlProvider>(path)?,
+            #[cfg(feature = "yaml")]
+            Format::Yaml => load_with::<crate::provider::yaml::YamlProvider>(path)?,
+            #[cfg(feature = "ron")]
+            Format::Ron => load_with::<crate::provider::ron::RonProvider>(path)?,
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => load_with::<crate::provider::msgpack::MsgpackProvider>(path)?,
+            #[cfg(feature = "cbor")]
+            Format::Cbor => load_with::<crate::provider::cbor::CborProvider>(path)?,
+        };
+
+        self.inner.put_many(values.into_iter().collect())?;
+        self.inner.mark_loaded(changes);
+
+        Ok(())
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let path = path.as_ref();
+        let format = format_of(path)?;
+        let values = self.inner.snapshot()?.into_values();
+
+        let saved = match format {
+            Format::Json => {
+                let _lock = lock_exclusive("any_file", path)?;
+                write_atomic("any_file", path, |file| {
+                    format::write(Format::Json, &values, file)
+                })
+            }
+            #[cfg(feature = "toml")]
+            Format::Toml => save_with::<crate::provider::toml::TomlProvider>(values, path),
+            #[cfg(feature = "yaml")]
+            Format::Yaml => save_with::<crate::provider::yaml::YamlProvider>(values, path),
+            #[cfg(feature = "ron")]
+            Format::Ron => save_with::<crate::provider::ron::RonProvider>(values, path),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => {
+                save_with::<crate::provider::msgpack::MsgpackProvider>(values, path)
+            }
+            #[cfg(feature = "cbor")]
+            Format::Cbor => save_with::<crate::provider::cbor::CborProvider>(values, path),
+        };
+        if saved.is_ok() {
+            self.inner.mark_saved(changes);
+        }
+
+        saved
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn picks_the_format_from_the_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = AnyFileProvider::new();
+        provider.put("listen", ":443".to_string()).unwrap();
+        provider.put("routes", json!({"api": "10.0.0.1"})).unwrap();
+        provider.save(dir.path().join("gatekeeper.json")).unwrap();
+
+        let saved: Value =
+            serde_json::from_reader(File::open(dir.path().join("gatekeeper.json")).unwrap())
+                .unwrap();
+        assert_eq!(
+            saved,
+            json!({"listen": ":443", "routes": {"api": "10.0.0.1"}})
+        );
+
+        let loaded = AnyFileProvider::new();
+        loaded.load(dir.path().join("gatekeeper.json")).unwrap();
+        assert_eq!(loaded.snapshot().unwrap(), provider.snapshot().unwrap());
+
+        assert!(provider.save(dir.path().join("gatekeeper.conf")).is_err());
+    }
+
+    #[cfg(all(feature = "yaml", feature = "toml"))]
+    #[test]
+    fn converts_between_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("gatekeeper.yml"),
+            "proxy:\n  timeout: 5\nworkers: 4\n",
+        )
+        .unwrap();
+
+        let provider = AnyFileProvider::new();
+        provider.load(dir.path().join("gatekeeper.yml")).unwrap();
+        assert_eq!(provider.get::<u32>("proxy.timeout").unwrap(), 5);
+        provider.save(dir.path().join("gatekeeper.toml")).unwrap();
+
+        let converted = AnyFileProvider::new();
+        converted.load(dir.path().join("gatekeeper.toml")).unwrap();
+        assert_eq!(converted.snapshot().unwrap(), provider.snapshot().unwrap());
+    }
+}
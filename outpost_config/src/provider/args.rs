@@ -0,0 +1,179 @@
+use crate::provider::env::decode_scalar;
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{ConfigError, ConfigProvider, Transaction, TransactionalConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::env;
+
+const FLAG: &str = "--set";
+
+/// Provider holding config overrides given on the command line
+///
+/// Overrides are passed as `--set key=value` or `--set=key=value`, all other arguments are
+/// ignored, so the provider can look at the same arguments as the actual argument parser.
+/// Values are parsed like environment variables: JSON where possible, bare strings otherwise.
+///
+/// Applications using clap can declare the `--set` argument themselves and hand its values to
+/// [`ArgsProvider::from_pairs`].
+#[derive(Default)]
+pub struct ArgsProvider {
+    inner: InMemoryProvider,
+}
+
+impl ArgsProvider {
+    /// Collect the overrides from the arguments of the current process.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::parse(
+            env::args_os()
+                .skip(1)
+                .map(|arg| arg.to_string_lossy().into_owned()),
+        )
+    }
+
+    /// Collect the overrides from the given arguments, not including the program name.
+    pub fn parse<I, S>(args: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut pairs = Vec::new();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let arg = arg.as_ref();
+
+            if arg == "--" {
+                break;
+            } else if arg == FLAG {
+                let pair = args.next().ok_or_else(|| {
+                    ConfigError::deserialization("args", format!("{} expects `key=value`", FLAG))
+                })?;
+                pairs.push(pair.as_ref().to_string());
+            } else if let Some(pair) = arg
+                .strip_prefix(FLAG)
+                .and_then(|rest| rest.strip_prefix('='))
+            {
+                pairs.push(pair.to_string());
+            }
+        }
+
+        Self::from_pairs(pairs)
+    }
+
+    /// Build the overrides from `key=value` pairs, e.g. the values of a clap argument.
+    ///
+    /// Later pairs override earlier ones for the same key.
+    pub fn from_pairs<I, S>(pairs: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let provider = Self::default();
+
+        for pair in pairs {
+            let pair = pair.as_ref();
+            let (key, raw) = pair.split_once('=').ok_or_else(|| {
+                ConfigError::deserialization(
+                    "args",
+                    format!("{} expects `key=value`, got `{}`", FLAG, pair),
+                )
+            })?;
+
+            let value: Value = decode_scalar(raw)?;
+            provider.inner.put(key.trim(), value)?;
+        }
+
+        Ok(provider)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("args"))]
+impl ConfigProvider for ArgsProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("args"))]
+impl TransactionalConfigProvider for ArgsProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_set_flags_only() {
+        let provider = ArgsProvider::parse([
+            "--verbose",
+            "--set",
+            "listen.port=8080",
+            "--set=upstream.hosts=[\"a\",\"b\"]",
+            "--config",
+            "gatekeeper.toml",
+            "--set=name=gate=keeper",
+            "--",
+            "--set=ignored=true",
+        ])
+        .unwrap();
+
+        let mut keys = provider.list().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["listen.port", "name", "upstream.hosts"]);
+        assert_eq!(provider.get::<u16>("listen.port").unwrap(), 8080);
+        assert_eq!(
+            provider.get::<Vec<String>>("upstream.hosts").unwrap(),
+            vec!["a", "b"]
+        );
+        assert_eq!(provider.get::<String>("name").unwrap(), "gate=keeper");
+
+        assert!(ArgsProvider::parse(["--set"]).is_err());
+        assert!(ArgsProvider::parse(["--set", "missing-value"]).is_err());
+    }
+}
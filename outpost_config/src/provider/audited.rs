@@ -0,0 +1,367 @@
+use crate::{
+    ConfigError, ConfigProvider, KeyPage, Transaction, TransactionOp, TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Provider reporting every change made through it to an audit sink
+///
+/// Each provider is bound to the actor making the changes, plus optional context like a request
+/// id, so a shared provider (e.g. an `Arc`) is wrapped once per actor. Records only contain the
+/// SHA-256 hashes of the old and the new value, secrets don't leak into the audit trail.
+///
+/// Changes are recorded after they were applied, a failing sink fails the operation although
+/// the change was made. Changes made to the inner provider directly aren't recorded.
+pub struct AuditedProvider<P> {
+    inner: P,
+    sink: Arc<dyn AuditSink>,
+    actor: String,
+    context: BTreeMap<String, String>,
+}
+
+/// Destination of the audit records.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord) -> Result<(), ConfigError>;
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&AuditRecord) -> Result<(), ConfigError> + Send + Sync,
+{
+    fn record(&self, record: &AuditRecord) -> Result<(), ConfigError> {
+        self(record)
+    }
+}
+
+/// A single change of a key.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AuditRecord {
+    pub actor: String,
+    pub context: BTreeMap<String, String>,
+    pub action: AuditAction,
+    pub key: String,
+    /// Hex encoded SHA-256 hash of the JSON of the previous value, `None` if the key was new.
+    pub old_hash: Option<String>,
+    /// Hex encoded SHA-256 hash of the JSON of the new value, `None` if the key was deleted.
+    pub new_hash: Option<String>,
+    pub timestamp: SystemTime,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Put,
+    Delete,
+}
+
+/// Sink appending the records to a file, one JSON object per line.
+pub struct JsonLinesSink {
+    file: Mutex<File>,
+}
+
+impl JsonLinesSink {
+    /// Append to the file at the given path, it is created if it doesn't exist.
+    pub fn open<P>(path: P) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| ConfigError::io("audited", err))?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditSink for JsonLinesSink {
+    fn record(&self, record: &AuditRecord) -> Result<(), ConfigError> {
+        let mut line =
+            serde_json::to_vec(record).map_err(|err| ConfigError::serialization("audited", err))?;
+        line.push(b'\n');
+
+        // a single write keeps the lines of concurrent processes apart
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&line)
+            .and_then(|()| file.flush())
+            .map_err(|err| ConfigError::io("audited", err))
+    }
+}
+
+impl<P> AuditedProvider<P> {
+    pub fn new<S>(inner: P, sink: Arc<dyn AuditSink>, actor: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            inner,
+            sink,
+            actor: actor.into(),
+            context: BTreeMap::new(),
+        }
+    }
+
+    /// Add context to all records, e.g. the id of the request making the changes.
+    pub fn with_context<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.context.insert(key.into(), value.into());
+        self
+    }
+
+    fn record(
+        &self,
+        action: AuditAction,
+        key: &str,
+        old: Option<&Value>,
+        new: Option<&Value>,
+    ) -> Result<(), ConfigError> {
+        self.sink.record(&AuditRecord {
+            actor: self.actor.clone(),
+            context: self.context.clone(),
+            action,
+            key: key.to_string(),
+            old_hash: old.map(hash).transpose()?,
+            new_hash: new.map(hash).transpose()?,
+            timestamp: SystemTime::now(),
+        })
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("audited"))]
+impl<P> ConfigProvider for AuditedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("audited", err).with_key(key))?;
+        let old: Option<Value> = self.inner.get_opt(key)?;
+
+        self.inner.put(key, value.clone())?;
+        self.record(AuditAction::Put, key, old.as_ref(), Some(&value))
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("audited", err).with_key(key))?;
+        let old: Option<Value> = self.inner.get_opt(key)?;
+
+        self.inner
+            .put_if_version(key, value.clone(), expected_version)?;
+        self.record(AuditAction::Put, key, old.as_ref(), Some(&value))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let old: Option<Value> = self.inner.get_opt(key)?;
+
+        self.inner.delete(key)?;
+        match old {
+            Some(old) => self.record(AuditAction::Delete, key, Some(&old), None),
+            // nothing changed
+            None => Ok(()),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        let mut inserted = false;
+        let value = self.inner.get_or_insert_with(key, || {
+            inserted = true;
+            f()
+        })?;
+
+        if inserted {
+            let new = serde_json::to_value(&value)
+                .map_err(|err| ConfigError::serialization("audited", err).with_key(key))?;
+            self.record(AuditAction::Put, key, None, Some(&new))?;
+        }
+
+        Ok(value)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get_many(keys)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("audited"))]
+impl<P> TransactionalConfigProvider for AuditedProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let keys: Vec<&str> = transaction
+            .ops()
+            .iter()
+            .map(|op| match op {
+                TransactionOp::Put { key, .. } | TransactionOp::Delete { key } => key.as_str(),
+            })
+            .collect();
+        let mut current: BTreeMap<String, Option<Value>> = keys
+            .iter()
+            .map(|key| key.to_string())
+            .zip(self.inner.get_many::<Value>(&keys)?)
+            .collect();
+
+        self.inner.commit(transaction.clone())?;
+
+        // replay the writes to know the old value of keys written more than once
+        for op in transaction.into_ops() {
+            match op {
+                TransactionOp::Put { key, value } => {
+                    let old = current.insert(key.clone(), Some(value.clone())).flatten();
+                    self.record(AuditAction::Put, &key, old.as_ref(), Some(&value))?;
+                }
+                TransactionOp::Delete { key } => {
+                    if let Some(old) = current.insert(key.clone(), None).flatten() {
+                        self.record(AuditAction::Delete, &key, Some(&old), None)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Hex encoded SHA-256 hash of the JSON of the value.
+fn hash(value: &Value) -> Result<String, ConfigError> {
+    let json =
+        serde_json::to_vec(value).map_err(|err| ConfigError::serialization("audited", err))?;
+
+    Ok(hex::encode(Sha256::digest(json)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::fs;
+
+    #[test]
+    fn records_every_change_with_hashes() {
+        let shared = Arc::new(InMemoryProvider::new());
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let collected = records.clone();
+        let sink: Arc<dyn AuditSink> = Arc::new(move |record: &AuditRecord| {
+            collected.lock().unwrap().push(record.clone());
+            Ok(())
+        });
+
+        let alice = AuditedProvider::new(shared.clone(), sink.clone(), "alice")
+            .with_context("request", "42");
+        let bob = AuditedProvider::new(shared, sink, "bob");
+
+        alice.put("policy.admin", "allow".to_string()).unwrap();
+        bob.put("policy.admin", "deny".to_string()).unwrap();
+        bob.delete("policy.admin").unwrap();
+        bob.delete("policy.admin").unwrap();
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].actor, "alice");
+        assert_eq!(records[0].context["request"], "42");
+        assert_eq!(records[0].old_hash, None);
+        assert_eq!(records[1].actor, "bob");
+        assert_eq!(records[1].old_hash, records[0].new_hash);
+        assert_eq!(records[2].action, AuditAction::Delete);
+        assert_eq!(records[2].old_hash, records[1].new_hash);
+        assert_eq!(records[2].new_hash, None);
+        assert_eq!(
+            records[0].new_hash.as_deref(),
+            Some(hash(&Value::from("allow")).unwrap().as_str())
+        );
+    }
+
+    #[test]
+    fn json_lines_sink_appends() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let sink: Arc<dyn AuditSink> = Arc::new(JsonLinesSink::open(&path).unwrap());
+        let provider = AuditedProvider::new(InMemoryProvider::new(), sink, "admin");
+
+        provider
+            .transaction(|txn| {
+                txn.put("tls.cert", "pem".to_string())?
+                    .put("tls.cert", "pem2".to_string())?;
+                Ok(())
+            })
+            .unwrap();
+
+        let log = fs::read_to_string(&path).unwrap();
+        let lines: Vec<Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["action"], "put");
+        assert_eq!(lines[1]["old_hash"], lines[0]["new_hash"]);
+        assert!(!log.contains("pem"));
+    }
+}
@@ -0,0 +1,386 @@
+use crate::http::{http_error, not_found_as_none};
+use crate::{no_compare_and_set, ConfigError, ConfigProvider};
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use ureq::{Agent, Request};
+
+const API_VERSION: &str = "7.4";
+const RESOURCE: &str = "https://vault.azure.net";
+
+/// Token endpoint of the instance metadata service available on Azure VMs and AKS.
+const IMDS_TOKEN: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// Provider mapping config keys to Azure Key Vault secrets
+///
+/// Every key is a secret named `<prefix><key>` with dots replaced by `--`, as secret names only
+/// allow letters, digits and dashes. Secret values are parsed as JSON and fall back to plain
+/// strings. Deleted secrets are only soft deleted if the vault has soft delete enabled, their
+/// names can't be reused until they are purged.
+///
+/// Requests are authenticated through the managed identity of the host by default, which
+/// covers VMs, AKS with pod identity and App Service, or through a service principal secret.
+pub struct AzureKeyVaultProvider {
+    agent: Agent,
+    vault_url: String,
+    prefix: String,
+    credential: Credential,
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+enum Credential {
+    ManagedIdentity {
+        client_id: Option<String>,
+    },
+    ClientSecret {
+        tenant: String,
+        client_id: String,
+        secret: String,
+    },
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    // the metadata endpoints send a string, Azure AD a number
+    expires_in: Value,
+}
+
+#[derive(Deserialize)]
+struct SecretBundle {
+    value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SecretList {
+    #[serde(default)]
+    value: Vec<SecretItem>,
+    next_link: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SecretItem {
+    id: String,
+}
+
+impl AzureKeyVaultProvider {
+    /// Create a provider for the vault at the given url, e.g. `https://gk.vault.azure.net`.
+    pub fn new<S>(vault_url: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            agent: Agent::new(),
+            vault_url: vault_url.into().trim_end_matches('/').to_string(),
+            prefix: String::new(),
+            credential: Credential::ManagedIdentity { client_id: None },
+            token: Mutex::new(None),
+        }
+    }
+
+    /// Name all secrets with the given prefix, e.g. `gatekeeper-`.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Authenticate as the user assigned managed identity with the given client id.
+    pub fn with_managed_identity<S>(mut self, client_id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.credential = Credential::ManagedIdentity {
+            client_id: Some(client_id.into()),
+        };
+        self
+    }
+
+    /// Authenticate as a service principal with a client secret.
+    pub fn with_client_secret<T, C, S>(mut self, tenant: T, client_id: C, secret: S) -> Self
+    where
+        T: Into<String>,
+        C: Into<String>,
+        S: Into<String>,
+    {
+        self.credential = Credential::ClientSecret {
+            tenant: tenant.into(),
+            client_id: client_id.into(),
+            secret: secret.into(),
+        };
+        self
+    }
+
+    fn request_token(&self) -> Result<TokenResponse, ConfigError> {
+        let response = match &self.credential {
+            Credential::ClientSecret {
+                tenant,
+                client_id,
+                secret,
+            } => {
+                let url = format!(
+                    "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+                    tenant
+                );
+                let scope = format!("{}/.default", RESOURCE);
+
+                self.agent.post(&url).send_form(&[
+                    ("grant_type", "client_credentials"),
+                    ("client_id", client_id),
+                    ("client_secret", secret),
+                    ("scope", &scope),
+                ])
+            }
+            Credential::ManagedIdentity { client_id } => {
+                // App Service and Functions expose their own endpoint instead of IMDS
+                let app_service = (env::var("IDENTITY_ENDPOINT"), env::var("IDENTITY_HEADER"));
+                let mut request = match app_service {
+                    (Ok(endpoint), Ok(header)) => self
+                        .agent
+                        .get(&endpoint)
+                        .set("X-IDENTITY-HEADER", &header)
+                        .query("api-version", "2019-08-01"),
+                    _ => self
+                        .agent
+                        .get(IMDS_TOKEN)
+                        .set("Metadata", "true")
+                        .query("api-version", "2018-02-01"),
+                };
+                request = request.query("resource", RESOURCE);
+                if let Some(client_id) = client_id {
+                    request = request.query("client_id", client_id);
+                }
+
+                request.call()
+            }
+        };
+
+        response
+            .map_err(|err| ConfigError::backend("azure_key_vault", err))?
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("azure_key_vault", err))
+    }
+
+    fn access_token(&self) -> Result<String, ConfigError> {
+        let mut cached = self.token.lock().unwrap();
+        if let Some((token, expires)) = &*cached {
+            if Instant::now() < *expires {
+                return Ok(token.clone());
+            }
+        }
+
+        let response = self.request_token()?;
+        let expires_in = seconds(&response.expires_in);
+
+        // refresh a minute early so requests in flight don't run into an expired token
+        let lifetime = Duration::from_secs(expires_in.saturating_sub(60));
+        *cached = Some((response.access_token.clone(), Instant::now() + lifetime));
+
+        Ok(response.access_token)
+    }
+
+    fn request(&self, method: &str, url: &str) -> Result<Request, ConfigError> {
+        Ok(self
+            .agent
+            .request(method, url)
+            .query("api-version", API_VERSION)
+            .set("Authorization", &format!("Bearer {}", self.access_token()?)))
+    }
+
+    fn secret_url(&self, key: &str) -> String {
+        format!(
+            "{}/secrets/{}{}",
+            self.vault_url,
+            self.prefix,
+            key.replace('.', "--")
+        )
+    }
+
+    fn fetch(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        let request = self.request("GET", &self.secret_url(key))?;
+        let response = match not_found_as_none("azure_key_vault", request.call())? {
+            Some(response) => response,
+            None => return Ok(None),
+        };
+
+        let bundle: SecretBundle = response
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("azure_key_vault", err).with_key(key))?;
+
+        Ok(Some(bundle.value))
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("azure_key_vault"))]
+impl ConfigProvider for AzureKeyVaultProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .fetch(key)?
+            .ok_or_else(|| ConfigError::not_found("azure_key_vault", key))?;
+
+        parse_value(key, &raw)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.fetch(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("azure_key_vault", err).with_key(key))?;
+
+        self.request("PUT", &self.secret_url(key))?
+            .send_json(json!({ "value": serialized }))
+            .map_err(|err| http_error("azure_key_vault", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        not_found_as_none(
+            "azure_key_vault",
+            self.request("DELETE", &self.secret_url(key))?.call(),
+        )?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let mut keys = Vec::new();
+        let mut request = self.request("GET", &format!("{}/secrets", self.vault_url))?;
+
+        loop {
+            let response: SecretList = request
+                .call()
+                .map_err(|err| http_error("azure_key_vault", err))?
+                .into_json()
+                .map_err(|err| ConfigError::deserialization("azure_key_vault", err))?;
+
+            // ids look like `<vault url>/secrets/<name>`
+            keys.extend(
+                response
+                    .value
+                    .into_iter()
+                    .filter_map(|secret| secret_key(&self.prefix, &secret.id)),
+            );
+
+            // the next link already carries the api version
+            match response.next_link {
+                Some(next_link) => {
+                    request = self
+                        .agent
+                        .get(&next_link)
+                        .set("Authorization", &format!("Bearer {}", self.access_token()?));
+                }
+                None => return Ok(keys),
+            }
+        }
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        _value: T,
+        _expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(no_compare_and_set("azure_key_vault", key))
+    }
+}
+
+/// The token lifetime in seconds, sent as a number or a string depending on the endpoint.
+fn seconds(expires_in: &Value) -> u64 {
+    match expires_in {
+        Value::Number(number) => number.as_u64(),
+        Value::String(string) => string.parse().ok(),
+        _ => None,
+    }
+    .unwrap_or(0)
+}
+
+/// The key of the secret with the given id, unless its name lacks the prefix.
+fn secret_key(prefix: &str, id: &str) -> Option<String> {
+    let name = id.rsplit('/').next()?;
+    let key = name.strip_prefix(prefix)?;
+
+    Some(key.replace("--", "."))
+}
+
+/// Parse a secret value as JSON, falling back to the plain string.
+fn parse_value<T>(key: &str, raw: &str) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_str(raw).or_else(|_| {
+        let deserializer: StrDeserializer<ValueError> = raw.into_deserializer();
+        T::deserialize(deserializer)
+            .map_err(|err| ConfigError::deserialization("azure_key_vault", err).with_key(key))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dots_are_escaped_in_secret_names() {
+        let provider = AzureKeyVaultProvider::new("https://gk.vault.azure.net/");
+        assert_eq!(
+            provider.secret_url("tls.cert"),
+            "https://gk.vault.azure.net/secrets/tls--cert"
+        );
+
+        let provider = provider.with_prefix("gatekeeper-");
+        assert_eq!(
+            provider.secret_url("routes.api"),
+            "https://gk.vault.azure.net/secrets/gatekeeper-routes--api"
+        );
+    }
+
+    #[test]
+    fn listed_ids_map_back_to_keys() {
+        let id = "https://gk.vault.azure.net/secrets/gatekeeper-tls--cert";
+        assert_eq!(secret_key("gatekeeper-", id).as_deref(), Some("tls.cert"));
+        assert_eq!(secret_key("", id).as_deref(), Some("gatekeeper-tls.cert"));
+        assert_eq!(
+            secret_key("gatekeeper-", "https://gk.vault.azure.net/secrets/other"),
+            None
+        );
+    }
+
+    #[test]
+    fn token_lifetimes_are_numbers_or_strings() {
+        assert_eq!(seconds(&json!(3599)), 3599);
+        assert_eq!(seconds(&json!("86399")), 86399);
+        assert_eq!(seconds(&json!("soon")), 0);
+        assert_eq!(seconds(&Value::Null), 0);
+    }
+
+    #[test]
+    fn secret_values_fall_back_to_plain_strings() {
+        assert_eq!(parse_value::<u32>("workers", "4").unwrap(), 4);
+        assert_eq!(
+            parse_value::<String>("password", "hunter2").unwrap(),
+            "hunter2"
+        );
+
+        let err = parse_value::<u32>("workers", "four").unwrap_err();
+        assert!(matches!(err, ConfigError::Deserialization { .. }));
+        assert_eq!(err.key(), Some("workers"));
+    }
+}
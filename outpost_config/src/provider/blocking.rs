@@ -0,0 +1,104 @@
+use crate::{AsyncConfigProvider, ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::task;
+
+/// Adapter exposing a synchronous provider as an [`AsyncConfigProvider`]
+///
+/// Every call runs on the blocking thread pool of the tokio runtime, so providers talking to
+/// remote backends through blocking clients (etcd, Consul, HTTP, ...) don't stall other tasks.
+/// Must be used from within a tokio runtime.
+pub struct Blocking<P> {
+    inner: Arc<P>,
+}
+
+impl<P> Blocking<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Access the wrapped provider, e.g. for provider specific methods.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    async fn run<F, R>(&self, call: F) -> Result<R, ConfigError>
+    where
+        P: Send + Sync + 'static,
+        F: FnOnce(&P) -> Result<R, ConfigError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let inner = self.inner.clone();
+
+        task::spawn_blocking(move || call(&inner))
+            .await
+            .map_err(|err| ConfigError::backend("blocking", err))?
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("blocking"))]
+impl<P> AsyncConfigProvider for Blocking<P>
+where
+    P: ConfigProvider + Send + Sync + 'static,
+{
+    async fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let key = key.to_string();
+        self.run(move |inner| inner.get(&key)).await
+    }
+
+    async fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let key = key.to_string();
+        self.run(move |inner| inner.has(&key)).await
+    }
+
+    async fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize + Send + 'static,
+    {
+        let key = key.to_string();
+        self.run(move |inner| inner.put(&key, value)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let key = key.to_string();
+        self.run(move |inner| inner.delete(&key)).await
+    }
+
+    async fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.run(|inner| inner.list()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn calls_reach_the_wrapped_provider() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let provider = Blocking::new(InMemoryProvider::new());
+
+        runtime.block_on(async {
+            provider.put("workers", 4).await.unwrap();
+            assert!(provider.has("workers").await.unwrap());
+            assert_eq!(provider.get::<u32>("workers").await.unwrap(), 4);
+            assert_eq!(provider.list().await.unwrap(), vec!["workers"]);
+
+            provider.delete("workers").await.unwrap();
+            assert!(matches!(
+                provider.get::<u32>("workers").await,
+                Err(ConfigError::NotFound { .. })
+            ));
+        });
+
+        assert!(!provider.inner().has("workers").unwrap());
+    }
+}
@@ -0,0 +1,493 @@
+use crate::{
+    ConfigError, ConfigProvider, KeyPage, ProviderStats, Transaction, TransactionOp,
+    TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Number of keys cached at most by default.
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Provider remembering the values of a slow provider for a while
+///
+/// `get` and `has` are answered from the cache while the entry is younger than the ttl, missing
+/// keys are cached as well. Writes through this provider invalidate the written keys, changes
+/// made to the inner provider by anyone else show up once the cached entries have expired or
+/// were invalidated with [`CachedProvider::invalidate`]. `list` is always passed through. A value
+/// fetched while its key was invalidated isn't cached, it may predate the write.
+///
+/// At most [`CachedProvider::with_capacity`] keys are cached. Expired entries are purged once
+/// per ttl and when the cache is full, and if none has expired the oldest entry makes room.
+///
+/// `stats` reports the figures of the inner provider together with the number of lookups the
+/// cache answered and passed on.
+pub struct CachedProvider<P> {
+    inner: P,
+    ttl: Duration,
+    capacity: usize,
+    cache: Mutex<Cache>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// The cached values and when they were fetched.
+struct Cache {
+    entries: HashMap<String, (Option<Value>, Instant)>,
+    /// Number of invalidations so far, a fetch started before one isn't stored.
+    generation: u64,
+    last_purge: Instant,
+}
+
+impl<P> CachedProvider<P> {
+    pub fn new(inner: P, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            capacity: DEFAULT_CAPACITY,
+            cache: Mutex::new(Cache {
+                entries: HashMap::new(),
+                generation: 0,
+                last_purge: Instant::now(),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Cache at most the given number of keys, at least one.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Forget the cached value of the key.
+    pub fn invalidate(&self, key: &str) {
+        let mut cache = self.lock();
+        cache.entries.remove(key);
+        cache.generation += 1;
+    }
+
+    /// Forget all cached values.
+    pub fn clear(&self) {
+        let mut cache = self.lock();
+        cache.entries.clear();
+        cache.generation += 1;
+    }
+
+    // the cache only holds copies, the values left by a panicking thread are as good as any
+    fn lock(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The current generation, to pass to `store` with the values fetched after it.
+    fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    /// The cached value of the key, the outer `None` means nothing valid is cached. Counts the
+    /// lookup as hit or miss.
+    fn cached(&self, key: &str) -> Option<Option<Value>> {
+        let entries = &mut self.lock().entries;
+
+        let cached = match entries.get(key) {
+            Some((value, cached_at)) if cached_at.elapsed() < self.ttl => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        let counter = match cached {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        cached
+    }
+
+    /// Cache the value fetched since the given generation, unless a key was invalidated
+    /// meanwhile.
+    fn store(&self, key: &str, value: Option<Value>, generation: u64) {
+        let mut cache = self.lock();
+        if cache.generation != generation {
+            return;
+        }
+
+        let full = cache.entries.len() >= self.capacity && !cache.entries.contains_key(key);
+        if full || cache.last_purge.elapsed() >= self.ttl {
+            let ttl = self.ttl;
+            cache
+                .entries
+                .retain(|_, (_, cached_at)| cached_at.elapsed() < ttl);
+            cache.last_purge = Instant::now();
+        }
+        if cache.entries.len() >= self.capacity && !cache.entries.contains_key(key) {
+            let oldest = cache
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, cached_at))| *cached_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                cache.entries.remove(&oldest);
+            }
+        }
+
+        cache
+            .entries
+            .insert(key.to_string(), (value, Instant::now()));
+    }
+}
+
+impl<P> CachedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn lookup(&self, key: &str) -> Result<Option<Value>, ConfigError> {
+        if let Some(value) = self.cached(key) {
+            return Ok(value);
+        }
+
+        let generation = self.generation();
+        let value = self.inner.get_opt(key)?;
+        self.store(key, value.clone(), generation);
+
+        Ok(value)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("cached"))]
+impl<P> ConfigProvider for CachedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let value = self
+            .lookup(key)?
+            .ok_or_else(|| ConfigError::not_found("cached", key))?;
+
+        serde_json::from_value(value)
+            .map_err(|err| ConfigError::deserialization("cached", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.lookup(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        // invalidate even if the write fails, it may have been applied anyway
+        let result = self.inner.put(key, value);
+        self.invalidate(key);
+
+        result
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        // a version has to be current to be compared against, so it is never cached
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let result = self.inner.put_if_version(key, value, expected_version);
+        self.invalidate(key);
+
+        result
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let result = self.inner.delete(key);
+        self.invalidate(key);
+
+        result
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        Ok(ProviderStats {
+            hits: Some(self.hits.load(Ordering::Relaxed)),
+            misses: Some(self.misses.load(Ordering::Relaxed)),
+            ..self.inner.stats()?
+        })
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        let result = self.inner.get_or_insert_with(key, f);
+        self.invalidate(key);
+
+        result
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let mut values: Vec<Option<Option<Value>>> =
+            keys.iter().map(|key| self.cached(key)).collect();
+
+        // fetch everything that isn't cached in one batch
+        let missing: Vec<&str> = keys
+            .iter()
+            .zip(&values)
+            .filter(|(_, value)| value.is_none())
+            .map(|(key, _)| *key)
+            .collect();
+        if !missing.is_empty() {
+            let generation = self.generation();
+            let mut fetched = self.inner.get_many::<Value>(&missing)?.into_iter();
+            for (key, value) in keys.iter().zip(values.iter_mut()) {
+                if value.is_none() {
+                    let fetched = fetched.next().flatten();
+                    self.store(key, fetched.clone(), generation);
+                    *value = Some(fetched);
+                }
+            }
+        }
+
+        values
+            .into_iter()
+            .map(|value| {
+                value
+                    .flatten()
+                    .map(serde_json::from_value)
+                    .transpose()
+                    .map_err(|err| ConfigError::deserialization("cached", err))
+            })
+            .collect()
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let keys: Vec<String> = entries.iter().map(|(key, _)| key.clone()).collect();
+        let result = self.inner.put_many(entries);
+        for key in keys {
+            self.invalidate(&key);
+        }
+
+        result
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("cached"))]
+impl<P> TransactionalConfigProvider for CachedProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let keys: Vec<String> = transaction
+            .ops()
+            .iter()
+            .map(|op| match op {
+                TransactionOp::Put { key, .. } | TransactionOp::Delete { key } => key.clone(),
+            })
+            .collect();
+        let result = self.inner.commit(transaction);
+        for key in keys {
+            self.invalidate(&key);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn serves_cached_values_until_they_expire() {
+        let backend = Arc::new(InMemoryProvider::new());
+        backend.put("policy.admin", "allow".to_string()).unwrap();
+        let provider = CachedProvider::new(backend.clone(), Duration::from_millis(50));
+
+        assert_eq!(provider.get::<String>("policy.admin").unwrap(), "allow");
+        assert!(!provider.has("policy.guest").unwrap());
+
+        // changes behind the cache's back stay hidden until the entries expire
+        backend.put("policy.admin", "deny".to_string()).unwrap();
+        backend.put("policy.guest", "allow".to_string()).unwrap();
+        assert_eq!(provider.get::<String>("policy.admin").unwrap(), "allow");
+        assert_eq!(
+            provider.get_many::<String>(&["policy.guest"]).unwrap(),
+            vec![None]
+        );
+
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(provider.get::<String>("policy.admin").unwrap(), "deny");
+        assert!(provider.has("policy.guest").unwrap());
+
+        // local writes are visible immediately
+        provider.put("policy.admin", "audit".to_string()).unwrap();
+        assert_eq!(provider.get::<String>("policy.admin").unwrap(), "audit");
+        provider.delete("policy.guest").unwrap();
+        assert!(!provider.has("policy.guest").unwrap());
+
+        let stats = provider.stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (Some(2), Some(6)));
+        assert_eq!(stats.keys, 1);
+        assert!(stats.bytes.is_some());
+    }
+
+    /// Backend whose reads report that they have read and then block until the test lets
+    /// them finish.
+    struct Gated {
+        inner: InMemoryProvider,
+        read: Mutex<Sender<()>>,
+        gate: Mutex<Receiver<()>>,
+    }
+
+    impl ConfigProvider for Gated {
+        fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+        where
+            T: DeserializeOwned,
+        {
+            let value = self.inner.get(key);
+            self.read.lock().unwrap().send(()).unwrap();
+            self.gate.lock().unwrap().recv().unwrap();
+            value
+        }
+
+        fn has(&self, key: &str) -> Result<bool, ConfigError> {
+            self.inner.has(key)
+        }
+
+        fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+        where
+            T: DeserializeOwned + Serialize,
+        {
+            self.inner.put(key, value)
+        }
+
+        fn delete(&self, key: &str) -> Result<(), ConfigError> {
+            self.inner.delete(key)
+        }
+
+        fn list(&self) -> Result<Vec<String>, ConfigError> {
+            self.inner.list()
+        }
+    }
+
+    #[test]
+    fn values_fetched_during_a_write_are_not_cached() {
+        let (read, reads) = mpsc::channel();
+        let (open, gate) = mpsc::channel();
+        let backend = Gated {
+            inner: InMemoryProvider::new(),
+            read: Mutex::new(read),
+            gate: Mutex::new(gate),
+        };
+        backend
+            .inner
+            .put("policy.admin", "allow".to_string())
+            .unwrap();
+        let provider = Arc::new(CachedProvider::new(backend, Duration::from_secs(60)));
+
+        // the read fetches the old value, then the write lands before it is stored
+        let reader = {
+            let provider = provider.clone();
+            thread::spawn(move || provider.get::<String>("policy.admin").unwrap())
+        };
+        reads.recv().unwrap();
+        provider.put("policy.admin", "deny".to_string()).unwrap();
+        open.send(()).unwrap();
+        assert_eq!(reader.join().unwrap(), "allow");
+
+        open.send(()).unwrap();
+        assert_eq!(provider.get::<String>("policy.admin").unwrap(), "deny");
+    }
+
+    #[test]
+    fn the_cache_is_bounded() {
+        let backend = InMemoryProvider::new();
+        for i in 0..10 {
+            backend.put(&format!("route.{}", i), i).unwrap();
+        }
+        let provider = CachedProvider::new(backend, Duration::from_secs(60)).with_capacity(4);
+
+        for i in 0..10 {
+            assert_eq!(provider.get::<u32>(&format!("route.{}", i)).unwrap(), i);
+        }
+        assert_eq!(provider.lock().entries.len(), 4);
+        // the oldest entries made room, the latest are still cached
+        assert_eq!(provider.get::<u32>("route.9").unwrap(), 9);
+        assert_eq!(provider.stats().unwrap().hits, Some(1));
+    }
+
+    #[test]
+    fn expired_entries_are_purged() {
+        let backend = InMemoryProvider::new();
+        backend.put("workers", 4).unwrap();
+        backend.put("listen", ":8080".to_string()).unwrap();
+        let provider = CachedProvider::new(backend, Duration::from_millis(20));
+
+        assert!(provider.has("workers").unwrap());
+        thread::sleep(Duration::from_millis(30));
+        assert!(provider.has("listen").unwrap());
+        assert_eq!(provider.lock().entries.len(), 1);
+    }
+
+    #[test]
+    fn a_poisoned_cache_keeps_working() {
+        let backend = InMemoryProvider::new();
+        backend.put("workers", 4).unwrap();
+        let provider = Arc::new(CachedProvider::new(backend, Duration::from_secs(60)));
+
+        let poisoner = provider.clone();
+        let _ = thread::spawn(move || {
+            let _cache = poisoner.lock();
+            panic!("poison the cache");
+        })
+        .join();
+
+        assert!(provider.cache.is_poisoned());
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        provider.invalidate("workers");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::file::{lock_exclusive, lock_shared, write_atomic};
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, ProviderStats, Transaction,
+    TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// File aware provider persisting its values as CBOR
+///
+/// The file holds a single map from config keys to values, encoded the way outpost device
+/// firmware expects it.
+#[derive(Default)]
+pub struct CborProvider {
+    inner: InMemoryProvider,
+}
+
+impl CborProvider {
+    pub fn new() -> Self {
+        Self {
+            inner: InMemoryProvider::new(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("cbor"))]
+impl ConfigProvider for CborProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.inner.stats()
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("cbor"))]
+impl TransactionalConfigProvider for CborProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl FileAwareConfigProvider for CborProvider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_shared("cbor", path.as_ref())?;
+        let file = File::open(path).map_err(|err| ConfigError::io("cbor", err))?;
+
+        let values: HashMap<String, Value> = ciborium::from_reader(BufReader::new(file))
+            .map_err(|err| ConfigError::deserialization("cbor", err))?;
+
+        let mut write_guard = self.inner.write()?;
+
+        for (k, v) in values {
+            let serialized =
+                serde_json::to_string(&v).map_err(|err| ConfigError::serialization("cbor", err))?;
+            write_guard.insert(k, serialized);
+        }
+        self.inner.mark_loaded(changes);
+
+        Ok(())
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_exclusive("cbor", path.as_ref())?;
+        write_atomic("cbor", path, |file| {
+            let read_guard = self.inner.read()?;
+
+            let values = read_guard
+                .persistent()
+                .map(|(k, v)| Ok((k.as_str(), serde_json::from_str(v)?)))
+                .collect::<Result<BTreeMap<&str, Value>, serde_json::Error>>()
+                .map_err(|err| ConfigError::deserialization("cbor", err))?;
+
+            ciborium::into_writer(&values, file)
+                .map_err(|err| ConfigError::serialization("cbor", err))
+        })?;
+        self.inner.mark_saved(changes);
+
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn values_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.cbor");
+
+        let provider = CborProvider::new();
+        provider.put("name", "outpost-7".to_string()).unwrap();
+        provider.put("ratio", 0.5).unwrap();
+        provider.put("enabled", true).unwrap();
+        provider.save(&path).unwrap();
+
+        // a map with three entries
+        assert_eq!(fs::read(&path).unwrap()[0], 0xa3);
+
+        let reloaded = CborProvider::new();
+        reloaded.load(&path).unwrap();
+        assert_eq!(reloaded.get::<String>("name").unwrap(), "outpost-7");
+        assert_eq!(reloaded.get::<f64>("ratio").unwrap(), 0.5);
+        assert!(reloaded.get::<bool>("enabled").unwrap());
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{
+    ConfigError, ConfigProvider, KeyPage, Transaction, TransactionOp, TransactionalConfigProvider,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::time::SystemTime;
+
+/// Name of the only supported compression, stored in every envelope.
+const ALGORITHM: &str = "deflate";
+
+/// Size of the serialized value from which on [`CompressedProvider::new`] compresses it.
+const DEFAULT_THRESHOLD: usize = 16 * 1024;
+
+/// Provider compressing large values before they reach the inner provider
+///
+/// Values whose JSON is at least as large as the threshold are compressed with deflate and
+/// stored as an envelope holding the base64 of the compressed JSON, which keeps multi megabyte
+/// route tables below the value limits of backends like etcd and shrinks the files they are
+/// saved to. Smaller values and values that don't get smaller are stored unchanged, so the
+/// provider can wrap a config written without it.
+pub struct CompressedProvider<P> {
+    inner: P,
+    threshold: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Envelope {
+    #[serde(rename = "$compressed")]
+    alg: String,
+    data: String,
+}
+
+impl<P> CompressedProvider<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+
+    /// Compress values whose JSON has at least the given number of bytes.
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// The wrapped provider, holding the compressed values.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// The value to store for the given one, compressed if that is worth it.
+    fn compress<T>(&self, key: &str, value: T) -> Result<Value, ConfigError>
+    where
+        T: Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("compressed", err).with_key(key))?;
+        let json = serde_json::to_vec(&value)
+            .map_err(|err| ConfigError::serialization("compressed", err).with_key(key))?;
+        if json.len() < self.threshold {
+            return Ok(value);
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        let compressed = encoder
+            .write_all(&json)
+            .and_then(|_| encoder.finish())
+            .map_err(|err| ConfigError::serialization("compressed", err).with_key(key))?;
+        // the envelope adds a third through base64, incompressible values are kept as they are
+        let data = STANDARD.encode(compressed);
+        if data.len() >= json.len() {
+            return Ok(value);
+        }
+
+        serde_json::to_value(Envelope {
+            alg: ALGORITHM.to_string(),
+            data,
+        })
+        .map_err(|err| ConfigError::serialization("compressed", err).with_key(key))
+    }
+
+    /// The value a stored one stands for, decompressed if it is an envelope.
+    fn decompress<T>(&self, key: &str, stored: Value) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let value = if stored.get("$compressed").is_some() {
+            let envelope: Envelope = serde_json::from_value(stored)
+                .map_err(|err| ConfigError::deserialization("compressed", err).with_key(key))?;
+            if envelope.alg != ALGORITHM {
+                return Err(ConfigError::deserialization(
+                    "compressed",
+                    format!("unsupported compression {}", envelope.alg),
+                )
+                .with_key(key));
+            }
+
+            let compressed = STANDARD
+                .decode(&envelope.data)
+                .map_err(|err| ConfigError::deserialization("compressed", err).with_key(key))?;
+            let mut json = Vec::new();
+            DeflateDecoder::new(compressed.as_slice())
+                .read_to_end(&mut json)
+                .map_err(|err| ConfigError::deserialization("compressed", err).with_key(key))?;
+
+            serde_json::from_slice(&json)
+                .map_err(|err| ConfigError::deserialization("compressed", err).with_key(key))?
+        } else {
+            stored
+        };
+
+        serde_json::from_value(value)
+            .map_err(|err| ConfigError::deserialization("compressed", err).with_key(key))
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("compressed"))]
+impl<P> ConfigProvider for CompressedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let stored = self.inner.get_value(key)?;
+
+        self.decompress(key, stored)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let stored = self.compress(key, value)?;
+
+        self.inner.put_value(key, stored)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let stored = self.compress(key, value)?;
+
+        self.inner.put_if_version(key, stored, expected_version)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner
+            .get_many::<Value>(keys)?
+            .into_iter()
+            .zip(keys)
+            .map(|(stored, key)| {
+                stored
+                    .map(|stored| self.decompress(key, stored))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let mut compressed = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let stored = self.compress(&key, value)?;
+            compressed.push((key, stored));
+        }
+
+        self.inner.put_many(compressed)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("compressed"))]
+impl<P> TransactionalConfigProvider for CompressedProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let mut compressed = Transaction::new();
+        for op in transaction.into_ops() {
+            match op {
+                TransactionOp::Put { key, value } => {
+                    let stored = self.compress(&key, value)?;
+                    compressed.put(&key, stored)?;
+                }
+                TransactionOp::Delete { key } => {
+                    compressed.delete(&key);
+                }
+            }
+        }
+
+        self.inner.commit(compressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::sync::Arc;
+
+    #[test]
+    fn large_values_are_stored_compressed() {
+        let store = Arc::new(InMemoryProvider::new());
+        let provider = CompressedProvider::new(store.clone()).with_threshold(1024);
+        let routes: Vec<String> = (0..1000).map(|i| format!("/api/v1/route/{}", i)).collect();
+        provider.put("routes", routes.clone()).unwrap();
+        provider.put("workers", 4).unwrap();
+
+        assert_eq!(provider.get::<Vec<String>>("routes").unwrap(), routes);
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        let stored = store.get_raw("routes").unwrap();
+        assert!(
+            stored.starts_with(r#"{"$compressed":"deflate""#),
+            "{}",
+            stored
+        );
+        assert!(stored.len() < serde_json::to_string(&routes).unwrap().len() / 4);
+        assert_eq!(store.get::<u32>("workers").unwrap(), 4);
+
+        let many = provider.get_many::<Value>(&["routes", "missing"]).unwrap();
+        assert_eq!(many[0], Some(serde_json::to_value(&routes).unwrap()));
+        assert_eq!(many[1], None);
+    }
+
+    #[test]
+    fn versions_are_those_of_the_stored_values() {
+        let provider = CompressedProvider::new(InMemoryProvider::new()).with_threshold(16);
+        let routes: Vec<String> = (0..100).map(|i| format!("/api/v1/route/{}", i)).collect();
+        provider
+            .put_if_version("routes", routes.clone(), None)
+            .unwrap();
+
+        let version = provider.version("routes").unwrap();
+        assert!(matches!(
+            provider.put_if_version("routes", Vec::<String>::new(), None),
+            Err(ConfigError::Conflict { .. })
+        ));
+        provider
+            .put_if_version("routes", routes[1..].to_vec(), version.as_deref())
+            .unwrap();
+        assert_eq!(provider.get::<Vec<String>>("routes").unwrap().len(), 99);
+    }
+}
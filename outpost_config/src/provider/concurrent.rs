@@ -0,0 +1,310 @@
+use crate::provider::sharded::{ShardMut, ShardedMap};
+use crate::{
+    value_version, ChangeEvent, ConfigError, ConfigProvider, Transaction, TransactionOp,
+    TransactionalConfigProvider, WatchableConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, PoisonError, RwLockReadGuard};
+
+/// In memory provider for many threads reading and writing at once
+///
+/// Keys are spread by hash over independently locked shards like in the `InMemoryProvider`, so
+/// a lookup only waits for writers of keys in the same shard. `list` and transactions lock all
+/// shards they touch in a fixed order, so they see and make changes atomically across shards.
+///
+/// Unlike the `InMemoryProvider` it keeps no ttl per entry and can't load or save files, which
+/// spares lookups the check for expired entries. Changes are still reported to the watchers of
+/// their keys.
+#[derive(Default)]
+pub struct ConcurrentProvider {
+    store: ShardedMap<String>,
+    watchers: Mutex<Vec<Watcher>>,
+}
+
+/// Key prefix of a watch and the channel its changes are sent to.
+type Watcher = (String, Sender<ChangeEvent>);
+
+impl ConcurrentProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spread the keys over the given number of shards, at least one.
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            store: ShardedMap::new(shards),
+            watchers: Mutex::default(),
+        }
+    }
+
+    fn read(&self, key: &str) -> Result<RwLockReadGuard<'_, HashMap<String, String>>, ConfigError> {
+        self.store.read(key).map_err(poisoned)
+    }
+
+    fn write(&self, key: &str) -> Result<ShardMut<'_, String>, ConfigError> {
+        self.store.write(key).map_err(poisoned)
+    }
+
+    /// Send the event to every watcher of the changed key and forget the dropped ones.
+    fn notify(&self, event: ChangeEvent) {
+        let key = event.key();
+
+        // the list of watchers stays intact even if a thread panicked while holding the lock
+        let mut watchers = self.watchers.lock().unwrap_or_else(PoisonError::into_inner);
+        watchers.retain(|(prefix, sender)| {
+            !key.starts_with(prefix.as_str()) || sender.send(event.clone()).is_ok()
+        });
+    }
+
+    fn notify_put(&self, key: &str, previous: Option<String>, raw: &str) {
+        self.notify(ChangeEvent::Put {
+            key: key.to_string(),
+            old: previous.as_deref().map(parse_raw),
+            new: parse_raw(raw),
+        });
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("concurrent"))]
+impl ConfigProvider for ConcurrentProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let read_guard = self.read(key)?;
+        let raw = read_guard
+            .get(key)
+            .ok_or_else(|| ConfigError::not_found("concurrent", key))?;
+
+        serde_json::from_str(raw)
+            .map_err(|err| ConfigError::deserialization("concurrent", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.read(key)?.contains_key(key))
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("concurrent", err).with_key(key))?;
+        let previous = self.write(key)?.insert(key.to_string(), serialized.clone());
+
+        self.notify_put(key, previous, &serialized);
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let removed = self.write(key)?.remove(key);
+
+        if let Some(removed) = removed {
+            self.notify(ChangeEvent::Delete {
+                key: key.to_string(),
+                old: parse_raw(&removed),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        // hold every shard until all are read, so no transaction is seen half applied
+        let read_guard = self.store.read_all().map_err(poisoned)?;
+
+        Ok(read_guard.iter().map(|(key, _)| key.clone()).collect())
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        let mut write_guard = self.write(key)?;
+        if let Some(raw) = write_guard.get(key) {
+            return serde_json::from_str(raw)
+                .map_err(|err| ConfigError::deserialization("concurrent", err).with_key(key));
+        }
+
+        let value = f();
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("concurrent", err).with_key(key))?;
+        write_guard.insert(key.to_string(), serialized.clone());
+        drop(write_guard);
+
+        self.notify_put(key, None, &serialized);
+
+        Ok(value)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("concurrent", err).with_key(key))?;
+        let mut write_guard = self.write(key)?;
+        let version = write_guard
+            .get(key)
+            .map(|raw| value_version(&parse_raw(raw)));
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("concurrent", key));
+        }
+
+        let previous = write_guard.insert(key.to_string(), serialized.clone());
+        drop(write_guard);
+
+        self.notify_put(key, previous, &serialized);
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("concurrent"))]
+impl TransactionalConfigProvider for ConcurrentProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let mut ops = Vec::new();
+        for op in transaction.into_ops() {
+            let op = match op {
+                TransactionOp::Put { key, value } => {
+                    let serialized = serde_json::to_string(&value)
+                        .map_err(|err| ConfigError::serialization("concurrent", err))?;
+                    (key, Some((serialized, value)))
+                }
+                TransactionOp::Delete { key } => (key, None),
+            };
+            ops.push(op);
+        }
+
+        // readers see either none or all of the writes
+        let mut write_guard = self
+            .store
+            .write_keys(ops.iter().map(|(key, _)| key.as_str()))
+            .map_err(poisoned)?;
+
+        let mut events = Vec::new();
+        for (key, put) in ops {
+            match put {
+                Some((serialized, value)) => {
+                    let previous = write_guard.insert(key.clone(), serialized);
+                    events.push(ChangeEvent::Put {
+                        key,
+                        old: previous.as_deref().map(parse_raw),
+                        new: value,
+                    });
+                }
+                None => {
+                    if let Some(removed) = write_guard.remove(&key) {
+                        events.push(ChangeEvent::Delete {
+                            key,
+                            old: parse_raw(&removed),
+                        });
+                    }
+                }
+            }
+        }
+        drop(write_guard);
+
+        for event in events {
+            self.notify(event);
+        }
+
+        Ok(())
+    }
+}
+
+impl WatchableConfigProvider for ConcurrentProvider {
+    fn watch(&self, key_prefix: &str) -> Result<Receiver<ChangeEvent>, ConfigError> {
+        let (sender, receiver) = mpsc::channel();
+        self.watchers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((key_prefix.to_string(), sender));
+
+        Ok(receiver)
+    }
+}
+
+/// The value of a stored entry.
+fn parse_raw(raw: &str) -> Value {
+    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+fn poisoned<T>(_: PoisonError<T>) -> ConfigError {
+    ConfigError::backend(
+        "concurrent",
+        "a thread panicked while changing the provider",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn writers_of_all_shards_are_seen() {
+        let provider = Arc::new(ConcurrentProvider::with_shards(8));
+        let changes = provider.watch("route.").unwrap();
+
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let provider = provider.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        provider.put(&format!("route.{}.{}", writer, i), i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        writers
+            .into_iter()
+            .for_each(|writer| writer.join().unwrap());
+
+        assert_eq!(provider.list().unwrap().len(), 800);
+        assert_eq!(provider.get::<u32>("route.3.42").unwrap(), 42);
+        assert_eq!(changes.try_iter().count(), 800);
+    }
+
+    #[test]
+    fn transactions_span_shards() {
+        let provider = ConcurrentProvider::with_shards(8);
+        provider.put("route.0.0", 0).unwrap();
+
+        let mut transaction = Transaction::new();
+        transaction
+            .delete("route.0.0")
+            .put("listen", ":8080".to_string())
+            .unwrap();
+        provider.commit(transaction).unwrap();
+        assert!(!provider.has("route.0.0").unwrap());
+        assert_eq!(provider.get::<String>("listen").unwrap(), ":8080");
+    }
+
+    #[test]
+    fn put_if_version_rejects_stale_versions() {
+        let provider = ConcurrentProvider::with_shards(8);
+        provider.put("listen", ":8080".to_string()).unwrap();
+
+        let version = provider.version("listen").unwrap();
+        provider
+            .put_if_version("listen", ":9090".to_string(), version.as_deref())
+            .unwrap();
+        assert!(matches!(
+            provider.put_if_version("listen", ":7070".to_string(), version.as_deref()),
+            Err(ConfigError::Conflict { .. })
+        ));
+    }
+}
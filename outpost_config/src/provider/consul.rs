@@ -0,0 +1,411 @@
+use crate::http::{http_error, not_found_as_none};
+use crate::{raw_version, ConfigError, ConfigProvider};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::thread;
+use std::time::Duration;
+use ureq::{Agent, Request};
+
+/// Provider storing its values in the Consul KV store below a key prefix
+///
+/// Values are written as JSON through the HTTP API (`http://127.0.0.1:8500` by default).
+/// Concurrent writers can coordinate through [`ConsulProvider::lock`], which holds a Consul
+/// session based lock for as long as the returned guard is alive.
+pub struct ConsulProvider {
+    agent: Agent,
+    endpoint: String,
+    prefix: String,
+    lock_prefix: String,
+    token: Option<String>,
+    session_ttl: Duration,
+}
+
+/// A lock held through a Consul session, released once dropped
+pub struct ConsulLock<'a> {
+    provider: &'a ConsulProvider,
+    key: String,
+    session: String,
+}
+
+#[derive(Deserialize)]
+struct SessionResponse {
+    #[serde(rename = "ID")]
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct KvEntry {
+    #[serde(rename = "ModifyIndex")]
+    modify_index: u64,
+    #[serde(rename = "Value", default)]
+    value: Option<String>,
+}
+
+impl ConsulProvider {
+    /// Create a provider talking to the Consul agent at the given url.
+    pub fn new<S>(endpoint: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            agent: Agent::new(),
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            prefix: String::new(),
+            lock_prefix: "outpost-locks/".to_string(),
+            token: None,
+            session_ttl: Duration::from_secs(15),
+        }
+    }
+
+    /// Store all keys below the given prefix.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Keep the lock keys used by [`ConsulProvider::lock`] below the given prefix.
+    ///
+    /// The prefix should lie outside of the config prefix, otherwise the lock keys show up in
+    /// `list`.
+    pub fn with_lock_prefix<S>(mut self, lock_prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.lock_prefix = lock_prefix.into();
+        self
+    }
+
+    /// Authenticate all requests with the given ACL token.
+    pub fn with_token<S>(mut self, token: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Let Consul invalidate lock sessions that were not renewed within the given duration.
+    pub fn with_session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+
+    /// Try to acquire the lock with the given name without waiting.
+    ///
+    /// Returns `None` if the lock is currently held by another session.
+    pub fn try_lock(&self, name: &str) -> Result<Option<ConsulLock<'_>>, ConfigError> {
+        let session = self.create_session()?;
+        let key = format!("{}{}", self.lock_prefix, name);
+
+        match self.acquire(&key, &session) {
+            Ok(true) => Ok(Some(ConsulLock {
+                provider: self,
+                key,
+                session,
+            })),
+            Ok(false) => {
+                let _ = self.destroy_session(&session);
+                Ok(None)
+            }
+            Err(err) => {
+                let _ = self.destroy_session(&session);
+                Err(err)
+            }
+        }
+    }
+
+    /// Acquire the lock with the given name, waiting until it becomes available.
+    pub fn lock(&self, name: &str) -> Result<ConsulLock<'_>, ConfigError> {
+        loop {
+            if let Some(lock) = self.try_lock(name)? {
+                return Ok(lock);
+            }
+
+            // consul delays re-acquisition after a session got invalidated, so polling is fine
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+
+    fn consul_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn request(&self, method: &str, path: &str) -> Request {
+        let request = self
+            .agent
+            .request(method, &format!("{}/v1/{}", self.endpoint, path));
+
+        match &self.token {
+            Some(token) => request.set("X-Consul-Token", token),
+            None => request,
+        }
+    }
+
+    fn create_session(&self) -> Result<String, ConfigError> {
+        let response: SessionResponse = self
+            .request("PUT", "session/create")
+            .send_json(json!({
+                "Name": "outpost-config",
+                "TTL": session_ttl(self.session_ttl),
+                "Behavior": "release",
+            }))
+            .map_err(|err| http_error("consul", err))?
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("consul", err))?;
+
+        Ok(response.id)
+    }
+
+    fn destroy_session(&self, session: &str) -> Result<(), ConfigError> {
+        self.request("PUT", &format!("session/destroy/{}", session))
+            .call()
+            .map_err(|err| http_error("consul", err))?;
+
+        Ok(())
+    }
+
+    fn acquire(&self, key: &str, session: &str) -> Result<bool, ConfigError> {
+        self.request("PUT", &format!("kv/{}", key))
+            .query("acquire", session)
+            .call()
+            .map_err(|err| http_error("consul", err).with_key(key))?
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("consul", err).with_key(key))
+    }
+
+    fn release(&self, key: &str, session: &str) -> Result<(), ConfigError> {
+        self.request("PUT", &format!("kv/{}", key))
+            .query("release", session)
+            .call()
+            .map_err(|err| http_error("consul", err).with_key(key))?;
+
+        Ok(())
+    }
+}
+
+impl Default for ConsulProvider {
+    fn default() -> Self {
+        Self::new("http://127.0.0.1:8500")
+    }
+}
+
+impl ConsulLock<'_> {
+    /// Reset the session TTL, so Consul keeps the lock alive.
+    pub fn renew(&self) -> Result<(), ConfigError> {
+        self.provider
+            .request("PUT", &format!("session/renew/{}", self.session))
+            .call()
+            .map_err(|err| http_error("consul", err))?;
+
+        Ok(())
+    }
+}
+
+impl Drop for ConsulLock<'_> {
+    fn drop(&mut self) {
+        // the session expires on its own if this fails
+        let _ = self.provider.release(&self.key, &self.session);
+        let _ = self.provider.destroy_session(&self.session);
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("consul"))]
+impl ConfigProvider for ConsulProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let response = not_found_as_none(
+            "consul",
+            self.request("GET", &format!("kv/{}", self.consul_key(key)))
+                .query("raw", "")
+                .call(),
+        )?
+        .ok_or_else(|| ConfigError::not_found("consul", key))?;
+
+        response
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("consul", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let response = not_found_as_none(
+            "consul",
+            self.request("GET", &format!("kv/{}", self.consul_key(key)))
+                .call(),
+        )?;
+
+        Ok(response.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("consul", err).with_key(key))?;
+
+        self.request("PUT", &format!("kv/{}", self.consul_key(key)))
+            .send_string(&serialized)
+            .map_err(|err| http_error("consul", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.request("DELETE", &format!("kv/{}", self.consul_key(key)))
+            .call()
+            .map_err(|err| http_error("consul", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.list_prefix("")
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        let response = match not_found_as_none(
+            "consul",
+            self.request("GET", &format!("kv/{}", self.consul_key(prefix)))
+                .query("keys", "")
+                .call(),
+        )? {
+            Some(response) => response,
+            None => return Ok(Vec::new()),
+        };
+
+        let keys: Vec<String> = response
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("consul", err))?;
+
+        Ok(relative_keys(&self.prefix, keys))
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("consul", err).with_key(key))?;
+        let path = format!("kv/{}", self.consul_key(key));
+
+        let current = match not_found_as_none("consul", self.request("GET", &path).call())? {
+            Some(response) => {
+                let entries: Vec<KvEntry> = response
+                    .into_json()
+                    .map_err(|err| ConfigError::deserialization("consul", err).with_key(key))?;
+                entries.into_iter().next()
+            }
+            None => None,
+        };
+        let version = match &current {
+            Some(entry) => Some(raw_version(&decode_value(entry)?)),
+            None => None,
+        };
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("consul", key));
+        }
+
+        // consul only writes if the modify index is unchanged, 0 only if the key doesn't exist
+        let cas = current.map_or(0, |entry| entry.modify_index);
+        let written: bool = self
+            .request("PUT", &path)
+            .query("cas", &cas.to_string())
+            .send_string(&serialized)
+            .map_err(|err| http_error("consul", err).with_key(key))?
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("consul", err).with_key(key))?;
+
+        if written {
+            Ok(())
+        } else {
+            Err(ConfigError::conflict("consul", key))
+        }
+    }
+}
+
+/// The session TTL as consul takes it, which refuses TTLs below 10 seconds.
+fn session_ttl(ttl: Duration) -> String {
+    format!("{}s", ttl.as_secs().max(10))
+}
+
+/// The keys relative to the prefix, dropping the ones outside of it.
+fn relative_keys(prefix: &str, keys: Vec<String>) -> Vec<String> {
+    keys.into_iter()
+        .filter_map(|key| key.strip_prefix(prefix).map(str::to_string))
+        .collect()
+}
+
+/// The value of a KV entry, which consul encodes as base64.
+fn decode_value(entry: &KvEntry) -> Result<String, ConfigError> {
+    let raw = STANDARD
+        .decode(entry.value.as_deref().unwrap_or_default())
+        .map_err(|err| ConfigError::deserialization("consul", err))?;
+
+    String::from_utf8(raw).map_err(|err| ConfigError::deserialization("consul", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kv_entries_decode_their_value() {
+        let entries: Vec<KvEntry> = serde_json::from_str(
+            r#"[{"Key": "gk/workers", "ModifyIndex": 42, "Value": "OA==", "Flags": 0}]"#,
+        )
+        .unwrap();
+        assert_eq!(entries[0].modify_index, 42);
+        assert_eq!(decode_value(&entries[0]).unwrap(), "8");
+
+        // consul sends null for an empty value
+        let entry: KvEntry = serde_json::from_str(r#"{"ModifyIndex": 7, "Value": null}"#).unwrap();
+        assert_eq!(decode_value(&entry).unwrap(), "");
+    }
+
+    #[test]
+    fn undecodable_values_fail_to_deserialize() {
+        let entry: KvEntry = serde_json::from_str(r#"{"ModifyIndex": 7, "Value": "*"}"#).unwrap();
+        assert!(matches!(
+            decode_value(&entry),
+            Err(ConfigError::Deserialization { .. })
+        ));
+        // base64 of an invalid UTF-8 byte
+        let entry: KvEntry =
+            serde_json::from_str(r#"{"ModifyIndex": 7, "Value": "/w=="}"#).unwrap();
+        assert!(matches!(
+            decode_value(&entry),
+            Err(ConfigError::Deserialization { .. })
+        ));
+    }
+
+    #[test]
+    fn keys_live_below_the_prefix() {
+        let provider = ConsulProvider::new("http://consul:8500/").with_prefix("gk/");
+        assert_eq!(provider.endpoint, "http://consul:8500");
+        assert_eq!(provider.consul_key("routes.api"), "gk/routes.api");
+        assert_eq!(ConsulProvider::default().consul_key("workers"), "workers");
+
+        let keys = vec!["gk/workers".to_string(), "other/workers".to_string()];
+        assert_eq!(relative_keys("gk/", keys), vec!["workers".to_string()]);
+    }
+
+    #[test]
+    fn session_ttls_are_at_least_ten_seconds() {
+        assert_eq!(session_ttl(Duration::from_secs(30)), "30s");
+        assert_eq!(session_ttl(Duration::from_secs(1)), "10s");
+        assert_eq!(session_ttl(Duration::from_millis(10_500)), "10s");
+    }
+}
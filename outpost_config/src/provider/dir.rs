@@ -0,0 +1,186 @@
+use crate::file::write_atomic;
+use crate::{no_compare_and_set, ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Provider storing every key as its own JSON file below a root directory
+///
+/// Dots in keys map to subdirectories, so `upstream.timeout` lives in
+/// `<root>/upstream/timeout.json`. A `put` only rewrites the file of its key, and the tree
+/// can be diffed or synced with the usual file tools.
+pub struct DirProvider {
+    root: PathBuf,
+}
+
+impl DirProvider {
+    /// Create a provider storing its files below the given directory.
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self { root: root.into() }
+    }
+
+    fn file_path(&self, key: &str) -> Result<PathBuf, ConfigError> {
+        let mut path = self.root.clone();
+
+        for segment in key.split('.') {
+            // no hidden files, parent directories or separators sneaking in
+            let valid =
+                !segment.is_empty() && !segment.starts_with('.') && !segment.contains(['/', '\\']);
+
+            if !valid {
+                return Err(ConfigError::backend(
+                    "dir",
+                    format!("key {} can't be mapped to a file", key),
+                )
+                .with_key(key));
+            }
+
+            path.push(segment);
+        }
+
+        path.set_extension("json");
+
+        Ok(path)
+    }
+
+    fn collect_keys(
+        &self,
+        dir: &Path,
+        prefix: &str,
+        keys: &mut Vec<String>,
+    ) -> Result<(), ConfigError> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(ConfigError::io("dir", err)),
+        };
+
+        for entry in entries {
+            let entry = entry.map_err(|err| ConfigError::backend("dir", err))?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if path.is_dir() {
+                self.collect_keys(&path, &format!("{}{}.", prefix, name), keys)?;
+            } else if let Some(stem) = name.strip_suffix(".json") {
+                keys.push(format!("{}{}", prefix, stem));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("dir"))]
+impl ConfigProvider for DirProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = match fs::read(self.file_path(key)?) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(ConfigError::not_found("dir", key))
+            }
+            Err(err) => return Err(ConfigError::io("dir", err).with_key(key)),
+        };
+        let deserialized = serde_json::from_slice(&raw)
+            .map_err(|err| ConfigError::deserialization("dir", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.file_path(key)?.is_file())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_vec_pretty(&value)
+            .map_err(|err| ConfigError::serialization("dir", err).with_key(key))?;
+
+        write_atomic("dir", self.file_path(key)?, |file| {
+            file.write_all(&serialized)
+                .map_err(|err| ConfigError::io("dir", err).with_key(key))
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let path = self.file_path(key)?;
+
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(ConfigError::io("dir", err).with_key(key)),
+        }
+
+        // prune directories left empty, removing a non empty directory simply fails
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            if current == self.root || fs::remove_dir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let mut keys = Vec::new();
+        self.collect_keys(&self.root, "", &mut keys)?;
+
+        Ok(keys)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        _value: T,
+        _expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(no_compare_and_set("dir", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_map_to_nested_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = DirProvider::new(dir.path());
+
+        provider.put("upstream", "backend".to_string()).unwrap();
+        provider.put("upstream.timeout", 5).unwrap();
+        provider.put("upstream.tls.verify", true).unwrap();
+        assert!(dir.path().join("upstream/tls/verify.json").is_file());
+
+        let mut keys = provider.list().unwrap();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["upstream", "upstream.timeout", "upstream.tls.verify"]
+        );
+        assert_eq!(provider.get::<u32>("upstream.timeout").unwrap(), 5);
+        assert_eq!(provider.get::<String>("upstream").unwrap(), "backend");
+
+        provider.delete("upstream.tls.verify").unwrap();
+        assert!(!provider.has("upstream.tls.verify").unwrap());
+        assert!(!dir.path().join("upstream/tls").exists());
+        assert!(provider.get::<bool>("upstream.tls.verify").is_err());
+
+        assert!(provider.put("../escape", 1).is_err());
+    }
+}
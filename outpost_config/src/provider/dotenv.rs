@@ -0,0 +1,356 @@
+use crate::file::{lock_exclusive, lock_shared, write_atomic};
+use crate::provider::env::{decode_scalar, encode_scalar};
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, ProviderStats, Transaction,
+    TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// File aware provider reading and writing `.env` files
+///
+/// Keys are the variable names as written in the file. Unquoted values are parsed like
+/// environment variables (JSON where possible, bare strings otherwise), quoted values are
+/// always strings. Double quoted values understand the usual `\n`, `\"` and `\\` escapes,
+/// single quoted values are taken literally.
+#[derive(Default)]
+pub struct DotenvProvider {
+    inner: InMemoryProvider,
+}
+
+impl DotenvProvider {
+    pub fn new() -> Self {
+        Self {
+            inner: InMemoryProvider::new(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("dotenv"))]
+impl ConfigProvider for DotenvProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.inner.stats()
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("dotenv"))]
+impl TransactionalConfigProvider for DotenvProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl FileAwareConfigProvider for DotenvProvider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_shared("dotenv", path.as_ref())?;
+        let raw = fs::read_to_string(path).map_err(|err| ConfigError::io("dotenv", err))?;
+        let values = parse(&raw)?;
+
+        let mut write_guard = self.inner.write()?;
+
+        for (k, v) in values {
+            let serialized = serde_json::to_string(&v)
+                .map_err(|err| ConfigError::serialization("dotenv", err))?;
+            write_guard.insert(k, serialized);
+        }
+        self.inner.mark_loaded(changes);
+
+        Ok(())
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_exclusive("dotenv", path.as_ref())?;
+        write_atomic("dotenv", path, |file| {
+            let read_guard = self.inner.read()?;
+
+            let mut lines = BTreeMap::new();
+            for (k, v) in read_guard.persistent() {
+                let value: Value = serde_json::from_str(v)
+                    .map_err(|err| ConfigError::deserialization("dotenv", err))?;
+                lines.insert(k.as_str(), render_value(value)?);
+            }
+
+            let rendered: String = lines
+                .into_iter()
+                .map(|(k, v)| format!("{}={}\n", k, v))
+                .collect();
+
+            file.write_all(rendered.as_bytes())
+                .map_err(|err| ConfigError::io("dotenv", err))
+        })?;
+        self.inner.mark_saved(changes);
+
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+/// Render a value so that `parse` reads back the same value.
+fn render_value(value: Value) -> Result<String, ConfigError> {
+    let s = match value {
+        Value::String(s) => s,
+        other => return encode_scalar(other),
+    };
+
+    let needs_quotes = s.is_empty()
+        || s.trim() != s
+        || s.contains(['#', '"', '\'', '\\', '\n', '$', '`'])
+        || s.contains(char::is_whitespace)
+        || serde_json::from_str::<Value>(&s).is_ok();
+
+    if !needs_quotes {
+        return Ok(s);
+    }
+
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            // keep shells and dotenv loaders from expanding variables
+            '$' => quoted.push_str("\\$"),
+            '`' => quoted.push_str("\\`"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    Ok(quoted)
+}
+
+/// Parse `.env` content into key value pairs.
+fn parse(raw: &str) -> Result<Vec<(String, Value)>, ConfigError> {
+    let mut values = Vec::new();
+    let mut lines = raw.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let error = |reason: &str| {
+            ConfigError::deserialization("dotenv", format!("line {}: {}", index + 1, reason))
+        };
+
+        let (key, rest) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected `KEY=value`"))?;
+        let key = key.trim().to_string();
+        let rest = rest.trim_start();
+
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            // double quoted values may span several lines
+            let mut content = quoted.to_string();
+            loop {
+                if let Some(end) = closing_quote(&content) {
+                    content.truncate(end);
+                    break;
+                }
+                match lines.next() {
+                    Some((_, next)) => {
+                        content.push('\n');
+                        content.push_str(next);
+                    }
+                    None => return Err(error("unterminated double quoted value")),
+                }
+            }
+            Value::String(unescape(&content))
+        } else if let Some(quoted) = rest.strip_prefix('\'') {
+            let end = quoted
+                .find('\'')
+                .ok_or_else(|| error("unterminated single quoted value"))?;
+            Value::String(quoted[..end].to_string())
+        } else {
+            // an unquoted value ends at a comment
+            let value = match rest.find(" #") {
+                Some(comment) => &rest[..comment],
+                None => rest,
+            };
+            decode_scalar(value.trim_end())?
+        };
+
+        values.push((key, value));
+    }
+
+    Ok(values)
+}
+
+/// Position of the first unescaped double quote.
+fn closing_quote(content: &str) -> Option<usize> {
+    let mut escaped = false;
+
+    for (index, c) in content.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(index),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn unescape(content: &str) -> String {
+    let mut unescaped = String::with_capacity(content.len());
+    let mut chars = content.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENV: &str = "# database\nexport DATABASE_URL=postgres://localhost/gk # local\nPORT=8080\n\
+                       GREETING=\"hello\\n\\\"world\\\"\"\nRAW='$HOME'\nCERT=\"line one\nline two\"\n";
+
+    fn loaded(dir: &Path) -> DotenvProvider {
+        let path = dir.join(".env");
+        fs::write(&path, ENV).unwrap();
+
+        let provider = DotenvProvider::new();
+        provider.load(&path).unwrap();
+        provider
+    }
+
+    #[test]
+    fn exports_and_comments_are_stripped() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = loaded(dir.path());
+
+        assert_eq!(
+            provider.get::<String>("DATABASE_URL").unwrap(),
+            "postgres://localhost/gk"
+        );
+        assert_eq!(provider.get::<u16>("PORT").unwrap(), 8080);
+    }
+
+    #[test]
+    fn double_quoted_values_are_unescaped() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = loaded(dir.path());
+
+        assert_eq!(
+            provider.get::<String>("GREETING").unwrap(),
+            "hello\n\"world\""
+        );
+        assert_eq!(
+            provider.get::<String>("CERT").unwrap(),
+            "line one\nline two"
+        );
+    }
+
+    #[test]
+    fn single_quoted_values_are_raw() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = loaded(dir.path());
+
+        assert_eq!(provider.get::<String>("RAW").unwrap(), "$HOME");
+    }
+
+    #[test]
+    fn quoting_survives_a_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = loaded(dir.path());
+        let path = dir.path().join(".env");
+
+        provider.put("VERSION", "2".to_string()).unwrap();
+        provider.save(&path).unwrap();
+
+        let reloaded = DotenvProvider::new();
+        reloaded.load(&path).unwrap();
+        for key in &["DATABASE_URL", "GREETING", "RAW", "CERT", "VERSION"] {
+            assert_eq!(
+                reloaded.get::<String>(key).unwrap(),
+                provider.get::<String>(key).unwrap()
+            );
+        }
+        assert_eq!(reloaded.get::<u16>("PORT").unwrap(), 8080);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::aws::{AwsClient, AwsConfig, AwsError};
+use crate::{raw_version, ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// Provider storing one DynamoDB item per key
+///
+/// The table needs a string partition key `pk` and a string sort key `sk`. All items of a
+/// provider share the partition key, which makes `list` a single (paginated) Query; the
+/// config key is the sort key and the JSON encoded value lives in the `value` attribute.
+pub struct DynamoProvider {
+    client: AwsClient,
+    table: String,
+    partition: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetItemResponse {
+    item: Option<Item>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct QueryResponse {
+    #[serde(default)]
+    items: Vec<Item>,
+    last_evaluated_key: Option<Map<String, Value>>,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    sk: Option<StringAttribute>,
+    value: Option<StringAttribute>,
+}
+
+#[derive(Deserialize)]
+struct StringAttribute {
+    #[serde(rename = "S")]
+    s: String,
+}
+
+impl DynamoProvider {
+    /// Create a provider storing its items in the given table below the given partition key.
+    pub fn new<T, P>(config: AwsConfig, table: T, partition: P) -> Self
+    where
+        T: Into<String>,
+        P: Into<String>,
+    {
+        Self {
+            client: AwsClient::new(config, "dynamodb", "dynamo"),
+            table: table.into(),
+            partition: partition.into(),
+        }
+    }
+
+    fn item_key(&self, key: &str) -> Value {
+        json!({ "pk": { "S": self.partition }, "sk": { "S": key } })
+    }
+
+    /// The item holding the JSON encoded value of the key.
+    fn item(&self, key: &str, serialized: String) -> Value {
+        let mut item = self.item_key(key);
+        item["value"] = json!({ "S": serialized });
+        item
+    }
+
+    /// The `PutItem` request only writing if the key still has the current value.
+    fn conditional_put(&self, key: &str, serialized: String, current: Option<String>) -> Value {
+        let mut body = json!({ "TableName": self.table, "Item": self.item(key, serialized) });
+
+        match current {
+            Some(current) => {
+                body["ConditionExpression"] = json!("#value = :current");
+                body["ExpressionAttributeNames"] = json!({ "#value": "value" });
+                body["ExpressionAttributeValues"] = json!({ ":current": { "S": current } });
+            }
+            None => body["ConditionExpression"] = json!("attribute_not_exists(sk)"),
+        }
+
+        body
+    }
+
+    fn fetch(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        let response: GetItemResponse = self.call(
+            "GetItem",
+            json!({
+                "TableName": self.table,
+                "Key": self.item_key(key),
+                "ConsistentRead": true,
+            }),
+        )?;
+
+        Ok(response
+            .item
+            .and_then(|item| item.value)
+            .map(|value| value.s))
+    }
+
+    /// DynamoDB reports a missing table as not found, which must not be mistaken for a
+    /// missing key.
+    fn call<R>(&self, operation: &str, body: Value) -> Result<R, ConfigError>
+    where
+        R: DeserializeOwned,
+    {
+        self.client
+            .call_json(&format!("DynamoDB_20120810.{}", operation), "1.0", &body)?
+            .ok_or_else(|| {
+                ConfigError::backend(
+                    "dynamo",
+                    AwsError {
+                        kind: "ResourceNotFoundException".to_string(),
+                        message: format!("table {} not found", self.table),
+                    },
+                )
+            })
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("dynamo"))]
+impl ConfigProvider for DynamoProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .fetch(key)?
+            .ok_or_else(|| ConfigError::not_found("dynamo", key))?;
+        let deserialized = serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("dynamo", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.fetch(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("dynamo", err).with_key(key))?;
+
+        let item = self.item(key, serialized);
+        let _: Value = self.call("PutItem", json!({ "TableName": self.table, "Item": item }))?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let _: Value = self.call(
+            "DeleteItem",
+            json!({ "TableName": self.table, "Key": self.item_key(key) }),
+        )?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let mut keys = Vec::new();
+        let mut start_key = None;
+
+        loop {
+            let mut body = json!({
+                "TableName": self.table,
+                "KeyConditionExpression": "pk = :pk",
+                "ExpressionAttributeValues": { ":pk": { "S": self.partition } },
+                "ProjectionExpression": "sk",
+            });
+            if let Some(start_key) = start_key {
+                body["ExclusiveStartKey"] = Value::Object(start_key);
+            }
+
+            let response: QueryResponse = self.call("Query", body)?;
+            keys.extend(
+                response
+                    .items
+                    .into_iter()
+                    .filter_map(|item| item.sk.map(|sk| sk.s)),
+            );
+
+            start_key = response.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("dynamo", err).with_key(key))?;
+
+        let current = self.fetch(key)?;
+        if current.as_deref().map(raw_version).as_deref() != expected_version {
+            return Err(ConfigError::conflict("dynamo", key));
+        }
+
+        // dynamo refuses the write if the value that was compared got replaced
+        let body = self.conditional_put(key, serialized, current);
+        self.call::<Value>("PutItem", body)
+            .map_err(|err| condition_error(key, err))?;
+
+        Ok(())
+    }
+}
+
+/// Map a failed write condition to ConfigError::Conflict.
+fn condition_error(key: &str, err: ConfigError) -> ConfigError {
+    match err {
+        ConfigError::Backend { source, .. }
+            if source
+                .downcast_ref::<AwsError>()
+                .is_some_and(|err| err.kind.contains("ConditionalCheckFailed")) =>
+        {
+            ConfigError::conflict("dynamo", key)
+        }
+        err => err.with_key(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> DynamoProvider {
+        let config = AwsConfig {
+            region: "eu-west-1".to_string(),
+            access_key_id: "AKID".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+            endpoint: None,
+        };
+
+        DynamoProvider::new(config, "outpost", "gatekeeper")
+    }
+
+    #[test]
+    fn items_are_keyed_by_partition_and_key() {
+        assert_eq!(
+            provider().item("workers", "4".to_string()),
+            json!({ "pk": { "S": "gatekeeper" }, "sk": { "S": "workers" }, "value": { "S": "4" } })
+        );
+    }
+
+    #[test]
+    fn conditional_puts_compare_the_current_value() {
+        let body = provider().conditional_put("workers", "8".to_string(), Some("4".to_string()));
+        assert_eq!(body["TableName"], "outpost");
+        assert_eq!(body["ConditionExpression"], "#value = :current");
+        assert_eq!(body["ExpressionAttributeValues"][":current"]["S"], "4");
+
+        let body = provider().conditional_put("workers", "8".to_string(), None);
+        assert_eq!(body["ConditionExpression"], "attribute_not_exists(sk)");
+        assert!(body.get("ExpressionAttributeValues").is_none());
+    }
+
+    #[test]
+    fn failed_conditions_conflict() {
+        let failed = ConfigError::backend(
+            "dynamo",
+            AwsError {
+                kind: "ConditionalCheckFailedException".to_string(),
+                message: "The conditional request failed".to_string(),
+            },
+        );
+        assert!(matches!(
+            condition_error("workers", failed),
+            ConfigError::Conflict { .. }
+        ));
+
+        let throttled = ConfigError::backend(
+            "dynamo",
+            AwsError {
+                kind: "ProvisionedThroughputExceededException".to_string(),
+                message: String::new(),
+            },
+        );
+        assert!(matches!(
+            condition_error("workers", throttled),
+            ConfigError::Backend { key: Some(_), .. }
+        ));
+    }
+
+    #[test]
+    fn responses_may_omit_items() {
+        let response: GetItemResponse = serde_json::from_str("{}").unwrap();
+        assert!(response.item.is_none());
+
+        let response: QueryResponse = serde_json::from_str(
+            r#"{"Items": [{"sk": {"S": "workers"}}], "LastEvaluatedKey": {"sk": {"S": "workers"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(response.items[0].sk.as_ref().unwrap().s, "workers");
+        assert!(response.items[0].value.is_none());
+        assert!(response.last_evaluated_key.is_some());
+    }
+}
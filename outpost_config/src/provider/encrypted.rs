@@ -0,0 +1,459 @@
+use crate::{
+    ConfigError, ConfigProvider, KeyPage, Transaction, TransactionOp, TransactionalConfigProvider,
+};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+
+/// Name of the only supported cipher, stored in every envelope.
+const ALGORITHM: &str = "A256GCM";
+
+/// Provider encrypting all values before they reach the inner provider
+///
+/// Values are serialized to JSON and encrypted with AES-256-GCM under a fresh random nonce. The
+/// inner provider stores an envelope with the nonce and the ciphertext instead of the value, so
+/// e.g. the file written by [`InMemoryProvider::save`](crate::FileAwareConfigProvider::save)
+/// or a SQLite database never contain plaintext. The key name is authenticated along with the
+/// value, so a ciphertext copied to another key fails to decrypt. Key names themselves are
+/// stored in plaintext.
+///
+/// Encryption keys are numbered, the key passed to the constructors is version 1. Every
+/// envelope records the version of its key, so values stay readable while
+/// [`EncryptedProvider::rotate_key`] moves them to a new key.
+pub struct EncryptedProvider<P> {
+    inner: P,
+    keyring: RwLock<Keyring>,
+    // keeps a rotation from overwriting concurrent writes with stale values
+    write_lock: Mutex<()>,
+}
+
+struct Keyring {
+    current: u32,
+    ciphers: HashMap<u32, Aes256Gcm>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    alg: String,
+    // envelopes written before keys were versioned all use the first key
+    #[serde(default = "first_key_version")]
+    key: u32,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn first_key_version() -> u32 {
+    1
+}
+
+impl<P> EncryptedProvider<P> {
+    /// Encrypt the values with the given 256 bit key.
+    pub fn new(inner: P, key: &[u8]) -> Result<Self, ConfigError> {
+        let version = first_key_version();
+        let mut ciphers = HashMap::new();
+        ciphers.insert(version, cipher(key)?);
+
+        Ok(Self {
+            inner,
+            keyring: RwLock::new(Keyring {
+                current: version,
+                ciphers,
+            }),
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    /// Register another version of the key and encrypt all new values with it. The keys
+    /// registered before remain available to decrypt the values written with them.
+    pub fn with_key(self, version: u32, key: &[u8]) -> Result<Self, ConfigError> {
+        self.add_key(version, key)?;
+        self.keyring.write().unwrap().current = version;
+
+        Ok(self)
+    }
+
+    /// Register another version of the key for decryption and for
+    /// [`EncryptedProvider::rotate_key`], without encrypting new values with it yet.
+    pub fn add_key(&self, version: u32, key: &[u8]) -> Result<(), ConfigError> {
+        let cipher = cipher(key)?;
+        self.keyring
+            .write()
+            .unwrap()
+            .ciphers
+            .insert(version, cipher);
+
+        Ok(())
+    }
+
+    /// The version of the key new values are encrypted with.
+    pub fn current_key(&self) -> u32 {
+        self.keyring.read().unwrap().current
+    }
+
+    /// Encrypt the values with the base64 encoded key in the given environment variable.
+    pub fn from_env(inner: P, var: &str) -> Result<Self, ConfigError> {
+        let encoded = env::var(var).map_err(|err| ConfigError::backend("encrypted", err))?;
+        let key = STANDARD
+            .decode(encoded.trim())
+            .map_err(|err| ConfigError::deserialization("encrypted", err))?;
+
+        Self::new(inner, &key)
+    }
+
+    /// Encrypt the values with the key returned by the callback, e.g. a data key decrypted by a
+    /// KMS.
+    pub fn from_key_fn<F>(inner: P, f: F) -> Result<Self, ConfigError>
+    where
+        F: FnOnce() -> Result<Vec<u8>, ConfigError>,
+    {
+        let key = f()?;
+
+        Self::new(inner, &key)
+    }
+
+    /// The wrapped provider, holding the encrypted values.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn encrypt<T>(&self, key: &str, value: T) -> Result<Envelope, ConfigError>
+    where
+        T: Serialize,
+    {
+        let plaintext = serde_json::to_vec(&value)
+            .map_err(|err| ConfigError::serialization("encrypted", err).with_key(key))?;
+        let keyring = self.keyring.read().unwrap();
+
+        seal(
+            key,
+            &plaintext,
+            keyring.current,
+            &keyring.ciphers[&keyring.current],
+        )
+    }
+
+    fn decrypt<T>(&self, key: &str, envelope: Envelope) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        if envelope.alg != ALGORITHM {
+            return Err(ConfigError::deserialization(
+                "encrypted",
+                format!("unsupported algorithm {}", envelope.alg),
+            )
+            .with_key(key));
+        }
+
+        let nonce = STANDARD
+            .decode(&envelope.nonce)
+            .map_err(|err| ConfigError::deserialization("encrypted", err).with_key(key))?;
+        if nonce.len() != 12 {
+            return Err(ConfigError::deserialization("encrypted", "invalid nonce").with_key(key));
+        }
+        let ciphertext = STANDARD
+            .decode(&envelope.ciphertext)
+            .map_err(|err| ConfigError::deserialization("encrypted", err).with_key(key))?;
+        let payload = Payload {
+            msg: &ciphertext,
+            aad: key.as_bytes(),
+        };
+
+        let keyring = self.keyring.read().unwrap();
+        let cipher = keyring.ciphers.get(&envelope.key).ok_or_else(|| {
+            ConfigError::deserialization(
+                "encrypted",
+                format!("unknown key version {}", envelope.key),
+            )
+            .with_key(key)
+        })?;
+
+        // the error doesn't tell a wrong key from tampering, both fail authentication
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| {
+                ConfigError::deserialization("encrypted", "failed to decrypt").with_key(key)
+            })?;
+
+        serde_json::from_slice(&plaintext)
+            .map_err(|err| ConfigError::deserialization("encrypted", err).with_key(key))
+    }
+}
+
+impl<P> EncryptedProvider<P>
+where
+    P: ConfigProvider,
+{
+    /// Re-encrypt all values written with the key version `old` with the key version `new`,
+    /// which is used for all new values from now on. Both versions have to be registered.
+    /// Returns the number of re-encrypted values, afterwards the old key is no longer needed.
+    ///
+    /// Writes through this provider wait for the rotation to finish.
+    pub fn rotate_key(&self, old: u32, new: u32) -> Result<usize, ConfigError> {
+        let _guard = self.write_lock.lock().unwrap();
+
+        {
+            let mut keyring = self.keyring.write().unwrap();
+            for version in [old, new] {
+                if !keyring.ciphers.contains_key(&version) {
+                    return Err(ConfigError::backend(
+                        "encrypted",
+                        format!("unknown key version {}", version),
+                    ));
+                }
+            }
+            keyring.current = new;
+        }
+
+        let mut rotated = 0;
+        for key in self.inner.list()? {
+            let envelope: Envelope = match self.inner.get(&key) {
+                Ok(envelope) => envelope,
+                // deleted since listing
+                Err(ConfigError::NotFound { .. }) => continue,
+                Err(err) => return Err(err),
+            };
+            if envelope.key != old || old == new {
+                continue;
+            }
+
+            let value: Value = self.decrypt(&key, envelope)?;
+            let envelope = self.encrypt(&key, value)?;
+            self.inner.put(&key, envelope)?;
+            rotated += 1;
+        }
+
+        Ok(rotated)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("encrypted"))]
+impl<P> ConfigProvider for EncryptedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let envelope = self.inner.get(key)?;
+
+        self.decrypt(key, envelope)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let _guard = self.write_lock.lock().unwrap();
+        let envelope = self.encrypt(key, value)?;
+
+        self.inner.put(key, envelope)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let _guard = self.write_lock.lock().unwrap();
+        let envelope = self.encrypt(key, value)?;
+
+        self.inner.put_if_version(key, envelope, expected_version)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner
+            .get_many::<Envelope>(keys)?
+            .into_iter()
+            .zip(keys)
+            .map(|(envelope, key)| {
+                envelope
+                    .map(|envelope| self.decrypt(key, envelope))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let _guard = self.write_lock.lock().unwrap();
+        let mut encrypted = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let envelope = self.encrypt(&key, value)?;
+            encrypted.push((key, envelope));
+        }
+
+        self.inner.put_many(encrypted)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("encrypted"))]
+impl<P> TransactionalConfigProvider for EncryptedProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let _guard = self.write_lock.lock().unwrap();
+        let mut encrypted = Transaction::new();
+        for op in transaction.into_ops() {
+            match op {
+                TransactionOp::Put { key, value } => {
+                    let envelope = self.encrypt(&key, value)?;
+                    encrypted.put(&key, envelope)?;
+                }
+                TransactionOp::Delete { key } => {
+                    encrypted.delete(&key);
+                }
+            }
+        }
+
+        self.inner.commit(encrypted)
+    }
+}
+
+fn seal(
+    key: &str,
+    plaintext: &[u8],
+    version: u32,
+    cipher: &Aes256Gcm,
+) -> Result<Envelope, ConfigError> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: plaintext,
+        aad: key.as_bytes(),
+    };
+
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| ConfigError::serialization("encrypted", "failed to encrypt").with_key(key))?;
+
+    Ok(Envelope {
+        alg: ALGORITHM.to_string(),
+        key: version,
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+fn cipher(key: &[u8]) -> Result<Aes256Gcm, ConfigError> {
+    if key.len() != 32 {
+        return Err(ConfigError::backend(
+            "encrypted",
+            format!("expected a 32 byte key, got {} bytes", key.len()),
+        ));
+    }
+
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)))
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use crate::FileAwareConfigProvider;
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn values_are_never_stored_in_plaintext() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.json");
+
+        let store = Arc::new(InMemoryProvider::new());
+        let provider = EncryptedProvider::new(store.clone(), &[7; 32]).unwrap();
+        provider
+            .put("upstream.password", "hunter2".to_string())
+            .unwrap();
+        assert_eq!(
+            provider.get::<String>("upstream.password").unwrap(),
+            "hunter2"
+        );
+
+        store.save(&path).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("hunter2"));
+
+        // moving a ciphertext to another key is detected
+        let envelope: Value = store.get("upstream.password").unwrap();
+        store.put("admin.password", envelope).unwrap();
+        assert!(provider.get::<String>("admin.password").is_err());
+
+        let wrong_key = EncryptedProvider::new(store, &[8; 32]).unwrap();
+        assert!(wrong_key.get::<String>("upstream.password").is_err());
+        assert!(EncryptedProvider::new(InMemoryProvider::new(), &[0; 16]).is_err());
+    }
+
+    #[test]
+    fn rotation_moves_values_to_the_new_key() {
+        let store = Arc::new(InMemoryProvider::new());
+        let provider = EncryptedProvider::new(store.clone(), &[1; 32]).unwrap();
+        provider.put("tls.key", "pem".to_string()).unwrap();
+        provider.put("upstream.token", 42).unwrap();
+
+        provider.add_key(2, &[2; 32]).unwrap();
+        assert_eq!(provider.rotate_key(1, 2).unwrap(), 2);
+        assert_eq!(provider.current_key(), 2);
+        assert_eq!(provider.rotate_key(1, 2).unwrap(), 0);
+
+        // the old key is no longer needed
+        let restarted = EncryptedProvider::new(store.clone(), &[9; 32])
+            .unwrap()
+            .with_key(2, &[2; 32])
+            .unwrap();
+        assert_eq!(restarted.get::<String>("tls.key").unwrap(), "pem");
+        assert_eq!(restarted.get::<u32>("upstream.token").unwrap(), 42);
+
+        let stale = EncryptedProvider::new(store, &[1; 32]).unwrap();
+        assert!(stale.get::<String>("tls.key").is_err());
+        assert!(provider.rotate_key(2, 3).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{no_compare_and_set, ConfigError, ConfigProvider};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::Serialize;
+use serde_json::Value;
+use std::env::{self, VarError};
+
+/// Provider exposing the environment variables of the current process
+///
+/// A key maps to an upper cased variable name below the prefix, with dots replaced by double
+/// underscores: `listen.addr` becomes `OUTPOST_LISTEN__ADDR` for the default prefix.
+/// Values are parsed as JSON and fall back to plain strings, so both `8080` and `localhost`
+/// can be read without quoting.
+pub struct EnvProvider {
+    prefix: String,
+}
+
+impl EnvProvider {
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    fn var_name(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key.replace('.', "__").to_uppercase())
+    }
+
+    fn key_name(&self, var: &str) -> Option<String> {
+        var.strip_prefix(&self.prefix)
+            .filter(|rest| !rest.is_empty())
+            .map(|rest| rest.to_lowercase().replace("__", "."))
+    }
+}
+
+impl Default for EnvProvider {
+    fn default() -> Self {
+        Self::new("OUTPOST_")
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("env"))]
+impl ConfigProvider for EnvProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = match env::var(self.var_name(key)) {
+            Ok(raw) => raw,
+            Err(VarError::NotPresent) => return Err(ConfigError::not_found("env", key)),
+            Err(err) => return Err(ConfigError::backend("env", err).with_key(key)),
+        };
+
+        decode_scalar(&raw)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(env::var_os(self.var_name(key)).is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        env::set_var(self.var_name(key), encode_scalar(value)?);
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        env::remove_var(self.var_name(key));
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        Ok(env::vars_os()
+            .filter_map(|(var, _)| var.into_string().ok())
+            .filter_map(|var| self.key_name(&var))
+            .collect())
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        _value: T,
+        _expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(no_compare_and_set("env", key))
+    }
+}
+
+/// Deserialize a raw string that is either JSON or a bare string.
+pub(crate) fn decode_scalar<T>(raw: &str) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_str(raw).or_else(|_| {
+        T::deserialize(Value::String(raw.to_string()).into_deserializer())
+            .map_err(|err| ConfigError::deserialization("env", err))
+    })
+}
+
+/// Serialize a value to JSON, leaving strings unquoted.
+pub(crate) fn encode_scalar<T>(value: T) -> Result<String, ConfigError>
+where
+    T: Serialize,
+{
+    match serde_json::to_value(value).map_err(|err| ConfigError::serialization("env", err))? {
+        Value::String(s) => Ok(s),
+        other => Ok(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_scalars_and_json() {
+        env::set_var("OUTPOST_TEST_READ_PORT", "8080");
+        env::set_var("OUTPOST_TEST_READ_HOST", "localhost");
+        env::set_var("OUTPOST_TEST_READ_UPSTREAM__HOSTS", "[\"a\",\"b\"]");
+
+        let provider = EnvProvider::new("OUTPOST_TEST_READ_");
+        assert_eq!(provider.get::<u16>("port").unwrap(), 8080);
+        assert_eq!(provider.get::<String>("port").unwrap(), "8080");
+        assert_eq!(provider.get::<String>("host").unwrap(), "localhost");
+        assert_eq!(
+            provider.get::<Vec<String>>("upstream.hosts").unwrap(),
+            vec!["a", "b"]
+        );
+        assert!(matches!(
+            provider.get::<String>("missing"),
+            Err(ConfigError::NotFound { .. })
+        ));
+
+        let mut keys = provider.list().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["host", "port", "upstream.hosts"]);
+    }
+
+    #[test]
+    fn put_and_delete_modify_the_environment() {
+        let provider = EnvProvider::new("OUTPOST_TEST_WRITE_");
+        provider.put("name", "gatekeeper".to_string()).unwrap();
+        provider.put("workers", 4).unwrap();
+
+        assert_eq!(env::var("OUTPOST_TEST_WRITE_NAME").unwrap(), "gatekeeper");
+        assert_eq!(env::var("OUTPOST_TEST_WRITE_WORKERS").unwrap(), "4");
+
+        provider.delete("name").unwrap();
+        assert!(!provider.has("name").unwrap());
+    }
+}
@@ -0,0 +1,675 @@
+#[cfg(feature = "async-http")]
+use crate::async_http::AsyncClient;
+use crate::http::http_error;
+#[cfg(feature = "async-http")]
+use crate::AsyncConfigProvider;
+use crate::{
+    raw_version, ChangeEvent, ConfigError, ConfigProvider, KeyPage, WatchableConfigProvider,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use ureq::Agent;
+
+/// Provider storing its values in etcd below a key prefix
+///
+/// Talks to the JSON gateway of the etcd v3 API (`http://127.0.0.1:2379` by default), so no
+/// gRPC stack is required. [`AsyncEtcdProvider`] is its async counterpart.
+///
+/// Watches report the previous value of a changed key from etcd's `prev_kv`.
+pub struct EtcdProvider {
+    agent: Agent,
+    endpoint: String,
+    prefix: String,
+}
+
+#[derive(Deserialize)]
+struct KeyValue {
+    key: String,
+    #[serde(default)]
+    value: String,
+    // the gateway encodes 64 bit integers as strings
+    #[serde(default)]
+    mod_revision: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RangeResponse {
+    #[serde(default)]
+    kvs: Vec<KeyValue>,
+    #[serde(default)]
+    more: bool,
+}
+
+#[derive(Deserialize)]
+struct TxnResponse {
+    #[serde(default)]
+    succeeded: bool,
+}
+
+#[derive(Deserialize)]
+struct WatchResponse {
+    result: Option<WatchResult>,
+}
+
+#[derive(Deserialize)]
+struct WatchResult {
+    #[serde(default)]
+    events: Vec<WatchEvent>,
+}
+
+#[derive(Deserialize)]
+struct WatchEvent {
+    // etcd omits the type for puts because it is the zero value of the enum
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    kv: KeyValue,
+    // only sent if the key existed before, as requested by `prev_kv`
+    #[serde(default)]
+    prev_kv: Option<KeyValue>,
+}
+
+impl EtcdProvider {
+    /// Create a provider talking to the etcd endpoint at the given url.
+    pub fn new<S>(endpoint: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            agent: Agent::new(),
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            prefix: String::new(),
+        }
+    }
+
+    /// Store all keys below the given prefix.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn etcd_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/v3/{}", self.endpoint, path)
+    }
+
+    fn call<R>(&self, path: &str, body: Value) -> Result<R, ConfigError>
+    where
+        R: DeserializeOwned,
+    {
+        self.agent
+            .post(&self.url(path))
+            .send_json(body)
+            .map_err(|err| http_error("etcd", err))?
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("etcd", err))
+    }
+
+    fn range(&self, body: Value) -> Result<Vec<KeyValue>, ConfigError> {
+        let response: RangeResponse = self.call("kv/range", body)?;
+
+        Ok(response.kvs)
+    }
+}
+
+impl Default for EtcdProvider {
+    fn default() -> Self {
+        Self::new("http://127.0.0.1:2379")
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("etcd"))]
+impl ConfigProvider for EtcdProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let kv = self
+            .range(json!({ "key": encode(&self.etcd_key(key)) }))?
+            .pop()
+            .ok_or_else(|| ConfigError::not_found("etcd", key))?;
+        let raw = decode(&kv.value)?;
+        let deserialized = serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("etcd", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let kvs = self.range(json!({
+            "key": encode(&self.etcd_key(key)),
+            "keys_only": true,
+        }))?;
+
+        Ok(!kvs.is_empty())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("etcd", err).with_key(key))?;
+
+        let _: Value = self.call(
+            "kv/put",
+            json!({
+                "key": encode(&self.etcd_key(key)),
+                "value": encode(&serialized),
+            }),
+        )?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let _: Value = self.call(
+            "kv/deleterange",
+            json!({ "key": encode(&self.etcd_key(key)) }),
+        )?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.list_prefix("")
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        let kvs = self.range(prefix_range(&self.etcd_key(prefix)))?;
+
+        strip_prefix(kvs, &self.prefix)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        // appending a zero byte gives the smallest key after the cursor
+        let start = match cursor {
+            Some(cursor) => format!("{}\0", self.etcd_key(cursor)),
+            None => self.prefix.clone(),
+        };
+        let response: RangeResponse = self.call(
+            "kv/range",
+            json!({
+                "key": encode_bytes(&range_start(&start)),
+                "range_end": encode_bytes(&range_end(&self.prefix)),
+                "limit": limit.max(1),
+                "keys_only": true,
+            }),
+        )?;
+
+        let keys = strip_prefix(response.kvs, &self.prefix)?;
+        let next = if response.more {
+            keys.last().cloned()
+        } else {
+            None
+        };
+
+        Ok(KeyPage { keys, next })
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("etcd", err).with_key(key))?;
+        let etcd_key = self.etcd_key(key);
+
+        let current = self.range(json!({ "key": encode(&etcd_key) }))?.pop();
+        let version = match &current {
+            Some(kv) => Some(raw_version(&decode(&kv.value)?)),
+            None => None,
+        };
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("etcd", key));
+        }
+
+        // etcd only puts the value if the key wasn't modified since it was read
+        let revision = current.and_then(|kv| kv.mod_revision);
+        let response: TxnResponse = self.call(
+            "kv/txn",
+            compare_and_put(&etcd_key, &serialized, revision.as_deref()),
+        )?;
+
+        if response.succeeded {
+            Ok(())
+        } else {
+            Err(ConfigError::conflict("etcd", key))
+        }
+    }
+}
+
+impl WatchableConfigProvider for EtcdProvider {
+    /// The watch runs on a background thread until the returned receiver is dropped and the next
+    /// event arrives, or until the connection to etcd is lost.
+    fn watch(&self, key_prefix: &str) -> Result<Receiver<ChangeEvent>, ConfigError> {
+        let start = self.etcd_key(key_prefix);
+        let body = json!({
+            "create_request": {
+                "key": encode_bytes(&range_start(&start)),
+                "range_end": encode_bytes(&range_end(&start)),
+                // the previous values become the old values of the events
+                "prev_kv": true,
+            }
+        });
+
+        let response = self
+            .agent
+            .post(&self.url("watch"))
+            .send_json(body)
+            .map_err(|err| http_error("etcd", err))?;
+
+        let (sender, receiver) = mpsc::channel();
+        let prefix = self.prefix.clone();
+
+        thread::spawn(move || {
+            let stream = serde_json::Deserializer::from_reader(response.into_reader())
+                .into_iter::<WatchResponse>();
+
+            for message in stream {
+                let result = match message {
+                    Ok(WatchResponse {
+                        result: Some(result),
+                    }) => result,
+                    Ok(_) => continue,
+                    Err(_) => return,
+                };
+
+                for event in result.events {
+                    let event = match change_event(event, &prefix) {
+                        Some(event) => event,
+                        None => continue,
+                    };
+
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+}
+
+/// Async counterpart of [`EtcdProvider`] talking to the same JSON gateway without blocking
+///
+/// Stores keys exactly like the [`EtcdProvider`], so both can be used on the same cluster.
+/// Requires the `async-http` feature.
+#[cfg(feature = "async-http")]
+pub struct AsyncEtcdProvider {
+    client: AsyncClient,
+    endpoint: String,
+    prefix: String,
+}
+
+#[cfg(feature = "async-http")]
+impl AsyncEtcdProvider {
+    /// Create a provider talking to the etcd endpoint at the given url.
+    pub fn new<S>(endpoint: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            client: AsyncClient::new(),
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            prefix: String::new(),
+        }
+    }
+
+    /// Store all keys below the given prefix.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Fail calls that take longer than the given duration, 30 seconds by default.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.client = self.client.with_timeout(timeout);
+        self
+    }
+
+    fn etcd_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    async fn call<R>(&self, path: &str, body: Value) -> Result<R, ConfigError>
+    where
+        R: DeserializeOwned,
+    {
+        let url = format!("{}/v3/{}", self.endpoint, path);
+
+        self.client
+            .send_json("etcd", "POST", &url, &[], &body)
+            .await?
+            .json("etcd")
+    }
+
+    async fn range(&self, body: Value) -> Result<Vec<KeyValue>, ConfigError> {
+        let response: RangeResponse = self.call("kv/range", body).await?;
+
+        Ok(response.kvs)
+    }
+}
+
+#[cfg(feature = "async-http")]
+impl Default for AsyncEtcdProvider {
+    fn default() -> Self {
+        Self::new("http://127.0.0.1:2379")
+    }
+}
+
+#[cfg(feature = "async-http")]
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("etcd"))]
+impl AsyncConfigProvider for AsyncEtcdProvider {
+    async fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let kv = self
+            .range(json!({ "key": encode(&self.etcd_key(key)) }))
+            .await?
+            .pop()
+            .ok_or_else(|| ConfigError::not_found("etcd", key))?;
+        let raw = decode(&kv.value)?;
+        let deserialized = serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("etcd", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    async fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let kvs = self
+            .range(json!({
+                "key": encode(&self.etcd_key(key)),
+                "keys_only": true,
+            }))
+            .await?;
+
+        Ok(!kvs.is_empty())
+    }
+
+    async fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize + Send + 'static,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("etcd", err).with_key(key))?;
+
+        let _: Value = self
+            .call(
+                "kv/put",
+                json!({
+                    "key": encode(&self.etcd_key(key)),
+                    "value": encode(&serialized),
+                }),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let _: Value = self
+            .call(
+                "kv/deleterange",
+                json!({ "key": encode(&self.etcd_key(key)) }),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let kvs = self.range(prefix_range(&self.prefix)).await?;
+
+        strip_prefix(kvs, &self.prefix)
+    }
+}
+
+/// The decoded keys of the key values relative to the prefix they were stored below.
+fn strip_prefix(kvs: Vec<KeyValue>, prefix: &str) -> Result<Vec<String>, ConfigError> {
+    kvs.into_iter()
+        .map(|kv| {
+            let key = decode(&kv.key)?;
+            Ok(key[prefix.len()..].to_string())
+        })
+        .collect()
+}
+
+/// The change a watched event reports relative to the prefix, skipping keys outside of it and
+/// values that aren't JSON.
+fn change_event(event: WatchEvent, prefix: &str) -> Option<ChangeEvent> {
+    let key = decode(&event.kv.key)
+        .ok()?
+        .strip_prefix(prefix)?
+        .to_string();
+    let parse = |kv: &KeyValue| {
+        decode(&kv.value)
+            .ok()
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+    };
+    let old = match &event.prev_kv {
+        Some(prev_kv) => Some(parse(prev_kv)?),
+        None => None,
+    };
+
+    match event.kind.as_deref() {
+        // a delete of a compacted revision has no previous value left to report
+        Some("DELETE") => Some(ChangeEvent::Delete { key, old: old? }),
+        _ => Some(ChangeEvent::Put {
+            key,
+            old,
+            new: parse(&event.kv)?,
+        }),
+    }
+}
+
+/// The body of a range request over the keys starting with the given prefix.
+fn prefix_range(prefix: &str) -> Value {
+    json!({
+        "key": encode_bytes(&range_start(prefix)),
+        "range_end": encode_bytes(&range_end(prefix)),
+        "keys_only": true,
+    })
+}
+
+/// The body of a transaction putting the value only if the key still has the given modification
+/// revision, `None` only if the key doesn't exist.
+fn compare_and_put(key: &str, value: &str, mod_revision: Option<&str>) -> Value {
+    json!({
+        "compare": [{
+            "key": encode(key),
+            "target": "MOD",
+            "result": "EQUAL",
+            // a missing key has the revision 0
+            "mod_revision": mod_revision.unwrap_or("0"),
+        }],
+        "success": [{
+            "request_put": { "key": encode(key), "value": encode(value) },
+        }],
+    })
+}
+
+fn encode(raw: &str) -> String {
+    encode_bytes(raw.as_bytes())
+}
+
+fn encode_bytes(raw: &[u8]) -> String {
+    STANDARD.encode(raw)
+}
+
+fn decode(encoded: &str) -> Result<String, ConfigError> {
+    let raw = STANDARD
+        .decode(encoded)
+        .map_err(|err| ConfigError::deserialization("etcd", err))?;
+
+    String::from_utf8(raw).map_err(|err| ConfigError::deserialization("etcd", err))
+}
+
+/// The first key starting with the given prefix. etcd rejects an empty key, a zero byte starts
+/// the range at the beginning of the keyspace instead.
+fn range_start(prefix: &str) -> Vec<u8> {
+    if prefix.is_empty() {
+        vec![0]
+    } else {
+        prefix.as_bytes().to_vec()
+    }
+}
+
+/// The first key after all keys starting with the given prefix, as used by etcd range requests.
+fn range_end(prefix: &str) -> Vec<u8> {
+    let mut end = prefix.as_bytes().to_vec();
+
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+
+    // an empty prefix (or one consisting of 0xff bytes only) ranges over the whole keyspace
+    vec![0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_end_covers_the_prefix() {
+        assert_eq!(range_end("routes."), b"routes/".to_vec());
+        assert_eq!(range_end(""), vec![0]);
+    }
+
+    #[test]
+    fn an_empty_prefix_ranges_over_the_whole_keyspace() {
+        let body = prefix_range("");
+        assert_eq!(body["key"], encode("\0"));
+        assert_eq!(body["range_end"], encode("\0"));
+
+        let body = prefix_range("gk/");
+        assert_eq!(body["key"], encode("gk/"));
+        assert_eq!(body["range_end"], encode("gk0"));
+    }
+
+    #[test]
+    fn watched_events_carry_the_previous_value() {
+        let kv = |key: &str, value: &str| KeyValue {
+            key: encode(key),
+            value: encode(value),
+            mod_revision: None,
+        };
+        let event =
+            |kind: Option<&str>, current: KeyValue, previous: Option<KeyValue>| WatchEvent {
+                kind: kind.map(str::to_string),
+                kv: current,
+                prev_kv: previous,
+            };
+
+        assert_eq!(
+            change_event(event(None, kv("gk/workers", "4"), None), "gk/"),
+            Some(ChangeEvent::Put {
+                key: "workers".to_string(),
+                old: None,
+                new: json!(4),
+            })
+        );
+        assert_eq!(
+            change_event(
+                event(None, kv("gk/workers", "8"), Some(kv("gk/workers", "4"))),
+                "gk/"
+            ),
+            Some(ChangeEvent::Put {
+                key: "workers".to_string(),
+                old: Some(json!(4)),
+                new: json!(8),
+            })
+        );
+        assert_eq!(
+            change_event(
+                event(
+                    Some("DELETE"),
+                    kv("gk/workers", ""),
+                    Some(kv("gk/workers", "8"))
+                ),
+                "gk/"
+            ),
+            Some(ChangeEvent::Delete {
+                key: "workers".to_string(),
+                old: json!(8),
+            })
+        );
+        assert_eq!(
+            change_event(event(None, kv("other/workers", "4"), None), "gk/"),
+            None
+        );
+    }
+
+    #[test]
+    fn compare_and_put_checks_the_revision() {
+        let body = compare_and_put("gk/workers", "8", Some("42"));
+        assert_eq!(body["compare"][0]["target"], "MOD");
+        assert_eq!(body["compare"][0]["mod_revision"], "42");
+        assert_eq!(body["compare"][0]["key"], encode("gk/workers"));
+        assert_eq!(body["success"][0]["request_put"]["value"], encode("8"));
+
+        let body = compare_and_put("gk/workers", "8", None);
+        assert_eq!(body["compare"][0]["mod_revision"], "0");
+    }
+
+    #[cfg(feature = "async-http")]
+    #[test]
+    fn async_reads_go_through_the_gateway() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let body = json!({ "kvs": [{ "key": encode("gk/workers"), "value": encode("4") }] })
+                .to_string();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            let mut request = String::new();
+            stream.read_to_string(&mut request).unwrap();
+            request
+        });
+
+        let provider = AsyncEtcdProvider::new(endpoint).with_prefix("gk/");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        assert_eq!(runtime.block_on(provider.get::<u32>("workers")).unwrap(), 4);
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v3/kv/range HTTP/1.1\r\n"));
+        assert!(request.contains(&encode("gk/workers")));
+    }
+}
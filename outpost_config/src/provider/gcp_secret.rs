@@ -0,0 +1,353 @@
+use crate::http::{http_error, not_found_as_none};
+use crate::{no_compare_and_set, ConfigError, ConfigProvider};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use ureq::{Agent, Request};
+
+const SECRET_MANAGER: &str = "https://secretmanager.googleapis.com/v1";
+
+/// Token endpoint of the metadata server available on GCE, GKE and Cloud Run.
+const METADATA_TOKEN: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// Provider mapping config keys to secrets of GCP Secret Manager
+///
+/// Every key is a secret named `<prefix><key>` with dots replaced by `__`, as secret ids only
+/// allow letters, digits, `-` and `_`. Lookups always resolve the latest version, and writes
+/// add a new version (creating the secret with automatic replication first if needed).
+/// Payloads are parsed as JSON and fall back to plain strings.
+///
+/// Requests are authenticated with the service account of the instance, fetched from the
+/// metadata server, unless a static access token is configured.
+pub struct GcpSecretProvider {
+    agent: Agent,
+    project: String,
+    prefix: String,
+    static_token: Option<String>,
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct AccessResponse {
+    payload: Payload,
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListResponse {
+    #[serde(default)]
+    secrets: Vec<Secret>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Secret {
+    name: String,
+}
+
+impl GcpSecretProvider {
+    /// Create a provider for the secrets of the given project id.
+    pub fn new<S>(project: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            agent: Agent::new(),
+            project: project.into(),
+            prefix: String::new(),
+            static_token: None,
+            token: Mutex::new(None),
+        }
+    }
+
+    /// Name all secrets with the given prefix, e.g. `gatekeeper-`.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Authenticate with the given OAuth access token instead of asking the metadata server.
+    pub fn with_token<S>(mut self, token: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.static_token = Some(token.into());
+        self
+    }
+
+    fn access_token(&self) -> Result<String, ConfigError> {
+        if let Some(token) = &self.static_token {
+            return Ok(token.clone());
+        }
+
+        let mut cached = self.token.lock().unwrap();
+        if let Some((token, expires)) = &*cached {
+            if Instant::now() < *expires {
+                return Ok(token.clone());
+            }
+        }
+
+        let response: TokenResponse = self
+            .agent
+            .get(METADATA_TOKEN)
+            .set("Metadata-Flavor", "Google")
+            .call()
+            .map_err(|err| http_error("gcp_secret", err))?
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("gcp_secret", err))?;
+
+        // refresh a minute early so requests in flight don't run into an expired token
+        let lifetime = Duration::from_secs(response.expires_in.saturating_sub(60));
+        *cached = Some((response.access_token.clone(), Instant::now() + lifetime));
+
+        Ok(response.access_token)
+    }
+
+    fn request(&self, method: &str, path: &str) -> Result<Request, ConfigError> {
+        let url = format!("{}/projects/{}/{}", SECRET_MANAGER, self.project, path);
+
+        Ok(self
+            .agent
+            .request(method, &url)
+            .set("Authorization", &format!("Bearer {}", self.access_token()?)))
+    }
+
+    fn secret_id(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key.replace('.', "__"))
+    }
+
+    fn fetch(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        let path = format!("secrets/{}/versions/latest:access", self.secret_id(key));
+        let response = match not_found_as_none("gcp_secret", self.request("GET", &path)?.call())? {
+            Some(response) => response,
+            None => return Ok(None),
+        };
+
+        let access: AccessResponse = response
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("gcp_secret", err).with_key(key))?;
+
+        decode_payload(key, &access.payload.data).map(Some)
+    }
+
+    fn add_version(&self, secret_id: &str, data: &str) -> Result<bool, ConfigError> {
+        let path = format!("secrets/{}:addVersion", secret_id);
+        let body = json!({ "payload": { "data": data } });
+
+        Ok(
+            not_found_as_none("gcp_secret", self.request("POST", &path)?.send_json(body))?
+                .is_some(),
+        )
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("gcp_secret"))]
+impl ConfigProvider for GcpSecretProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .fetch(key)?
+            .ok_or_else(|| ConfigError::not_found("gcp_secret", key))?;
+
+        parse_value(key, &raw)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.fetch(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("gcp_secret", err).with_key(key))?;
+        let data = STANDARD.encode(serialized);
+        let secret_id = self.secret_id(key);
+
+        if self.add_version(&secret_id, &data)? {
+            return Ok(());
+        }
+
+        // versions can only be added to existing secrets
+        let path = format!("secrets?secretId={}", secret_id);
+        let body = json!({ "replication": { "automatic": {} } });
+        match self.request("POST", &path)?.send_json(body) {
+            // someone else created it in the meantime
+            Ok(_) | Err(ureq::Error::Status(409, _)) => {}
+            Err(err) => return Err(http_error("gcp_secret", err).with_key(key)),
+        }
+
+        if self.add_version(&secret_id, &data)? {
+            Ok(())
+        } else {
+            Err(ConfigError::not_found("gcp_secret", key))
+        }
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let path = format!("secrets/{}", self.secret_id(key));
+        not_found_as_none("gcp_secret", self.request("DELETE", &path)?.call())?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let mut keys = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut request = self.request("GET", "secrets")?.query("pageSize", "250");
+            if !self.prefix.is_empty() {
+                request = request.query("filter", &format!("name:{}", self.prefix));
+            }
+            if let Some(page_token) = &page_token {
+                request = request.query("pageToken", page_token);
+            }
+
+            let response: ListResponse = request
+                .call()
+                .map_err(|err| http_error("gcp_secret", err))?
+                .into_json()
+                .map_err(|err| ConfigError::deserialization("gcp_secret", err))?;
+
+            // names look like `projects/<number>/secrets/<id>`, the filter also matches
+            // ids merely containing the prefix
+            keys.extend(
+                response
+                    .secrets
+                    .into_iter()
+                    .filter_map(|secret| secret_key(&self.prefix, &secret.name)),
+            );
+
+            match response.next_page_token {
+                Some(next) if !next.is_empty() => page_token = Some(next),
+                _ => return Ok(keys),
+            }
+        }
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        _value: T,
+        _expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(no_compare_and_set("gcp_secret", key))
+    }
+}
+
+/// The key of the secret with the given resource name, unless its id lacks the prefix.
+fn secret_key(prefix: &str, name: &str) -> Option<String> {
+    let id = name.rsplit('/').next()?;
+    let key = id.strip_prefix(prefix)?;
+
+    Some(key.replace("__", "."))
+}
+
+fn decode_payload(key: &str, data: &str) -> Result<String, ConfigError> {
+    let data = STANDARD
+        .decode(data)
+        .map_err(|err| ConfigError::deserialization("gcp_secret", err).with_key(key))?;
+
+    String::from_utf8(data)
+        .map_err(|err| ConfigError::deserialization("gcp_secret", err).with_key(key))
+}
+
+/// Parse a payload as JSON, falling back to the plain string.
+fn parse_value<T>(key: &str, raw: &str) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_str(raw).or_else(|_| {
+        let deserializer: StrDeserializer<ValueError> = raw.into_deserializer();
+        T::deserialize(deserializer)
+            .map_err(|err| ConfigError::deserialization("gcp_secret", err).with_key(key))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dots_are_escaped_in_secret_ids() {
+        let provider = GcpSecretProvider::new("gatekeeper-prod").with_token("token");
+        assert_eq!(provider.secret_id("tls.cert"), "tls__cert");
+
+        let provider = provider.with_prefix("gatekeeper-");
+        assert_eq!(
+            provider.secret_id("routes.api.upstream"),
+            "gatekeeper-routes__api__upstream"
+        );
+    }
+
+    #[test]
+    fn listed_names_map_back_to_keys() {
+        let name = "projects/123/secrets/gatekeeper-tls__cert";
+        assert_eq!(secret_key("gatekeeper-", name).as_deref(), Some("tls.cert"));
+        assert_eq!(secret_key("", name).as_deref(), Some("gatekeeper-tls.cert"));
+
+        // the list filter also matches ids merely containing the prefix
+        assert_eq!(
+            secret_key("gatekeeper-", "projects/123/secrets/old-gatekeeper-x"),
+            None
+        );
+    }
+
+    #[test]
+    fn payloads_are_base64_encoded_utf8() {
+        assert_eq!(
+            decode_payload("listen", "Ijo4MDgwIg==").unwrap(),
+            "\":8080\""
+        );
+
+        let err = decode_payload("listen", "not base64!").unwrap_err();
+        assert!(matches!(err, ConfigError::Deserialization { .. }));
+        assert_eq!(err.key(), Some("listen"));
+
+        let invalid_utf8 = STANDARD.encode([0xff, 0xfe]);
+        assert!(matches!(
+            decode_payload("listen", &invalid_utf8),
+            Err(ConfigError::Deserialization { .. })
+        ));
+    }
+
+    #[test]
+    fn payloads_fall_back_to_plain_strings() {
+        assert_eq!(parse_value::<u32>("workers", "4").unwrap(), 4);
+        assert_eq!(
+            parse_value::<String>("password", "hunter2").unwrap(),
+            "hunter2"
+        );
+
+        let err = parse_value::<u32>("workers", "four").unwrap_err();
+        assert!(matches!(err, ConfigError::Deserialization { .. }));
+        assert_eq!(err.key(), Some("workers"));
+    }
+}
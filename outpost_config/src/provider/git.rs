@@ -0,0 +1,297 @@
+use crate::file::{lock_exclusive, write_atomic};
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, ProviderStats, Transaction,
+    TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ffi::OsStr;
+use std::io::BufWriter;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// File aware provider committing the config file to a git repository on every save
+///
+/// Saves the same JSON as the [`InMemoryProvider`] with sorted keys, but afterwards commits the file to
+/// the repository it lives in and optionally pushes the commit, so every config change gets
+/// its history. Saves that don't change the file don't create commits. Runs the `git`
+/// command, so credentials and hooks configured for the repository apply.
+pub struct GitProvider {
+    inner: InMemoryProvider,
+    message: String,
+    author: Option<(String, String)>,
+    push: Option<(String, String)>,
+}
+
+impl Default for GitProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GitProvider {
+    pub fn new() -> Self {
+        Self {
+            inner: InMemoryProvider::new(),
+            message: "Update config".to_string(),
+            author: None,
+            push: None,
+        }
+    }
+
+    /// Use the given commit message instead of `Update config`.
+    pub fn with_message<S>(mut self, message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.message = message.into();
+        self
+    }
+
+    /// Commit as the given author instead of the identity configured for the repository.
+    pub fn with_author<N, E>(mut self, name: N, email: E) -> Self
+    where
+        N: Into<String>,
+        E: Into<String>,
+    {
+        self.author = Some((name.into(), email.into()));
+        self
+    }
+
+    /// Push every commit to the given remote branch, e.g. `origin` and `main`.
+    pub fn with_push<R, B>(mut self, remote: R, branch: B) -> Self
+    where
+        R: Into<String>,
+        B: Into<String>,
+    {
+        self.push = Some((remote.into(), branch.into()));
+        self
+    }
+
+    /// Run git in the given directory.
+    fn git<S>(&self, dir: &Path, args: &[S]) -> Result<Output, ConfigError>
+    where
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new("git");
+        command.current_dir(dir);
+
+        if let Some((name, email)) = &self.author {
+            command
+                .arg("-c")
+                .arg(format!("user.name={}", name))
+                .arg("-c")
+                .arg(format!("user.email={}", email));
+        }
+
+        command
+            .args(args)
+            .output()
+            .map_err(|err| ConfigError::backend("git", err))
+    }
+
+    /// Run git in the given directory, failing with its error output if it doesn't succeed.
+    fn run<S>(&self, dir: &Path, args: &[S]) -> Result<(), ConfigError>
+    where
+        S: AsRef<OsStr>,
+    {
+        let output = self.git(dir, args)?;
+
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let command_line: Vec<_> = args
+            .iter()
+            .map(|arg| arg.as_ref().to_string_lossy())
+            .collect();
+
+        Err(ConfigError::backend(
+            "git",
+            format!(
+                "git {} failed: {}",
+                command_line.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ))
+    }
+
+    fn commit(&self, path: &Path) -> Result<(), ConfigError> {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let file = path.file_name().ok_or_else(|| {
+            ConfigError::backend("git", format!("{} is not a file", path.display()))
+        })?;
+
+        self.run(dir, &[OsStr::new("add"), OsStr::new("--"), file])?;
+
+        // nothing staged, the save didn't change the file
+        let staged = self.git(
+            dir,
+            &[
+                OsStr::new("diff"),
+                OsStr::new("--cached"),
+                OsStr::new("--quiet"),
+                OsStr::new("--"),
+                file,
+            ],
+        )?;
+        if staged.status.success() {
+            return Ok(());
+        }
+
+        self.run(
+            dir,
+            &[
+                OsStr::new("commit"),
+                OsStr::new("--quiet"),
+                OsStr::new("-m"),
+                OsStr::new(&self.message),
+                OsStr::new("--"),
+                file,
+            ],
+        )?;
+
+        if let Some((remote, branch)) = &self.push {
+            self.run(
+                dir,
+                &["push", "--quiet", remote, &format!("HEAD:{}", branch)],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("git"))]
+impl ConfigProvider for GitProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.inner.stats()
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("git"))]
+impl TransactionalConfigProvider for GitProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl FileAwareConfigProvider for GitProvider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        self.inner.load(path)
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_exclusive("git", path.as_ref())?;
+        // the values are written in key order, which keeps the diffs between commits small
+        write_atomic("git", &path, |file| {
+            self.inner.write_values("git", BufWriter::new(file))
+        })?;
+        self.inner.mark_saved(changes);
+
+        self.commit(path.as_ref())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit_count(dir: &Path) -> usize {
+        let output = Command::new("git")
+            .current_dir(dir)
+            .args(["rev-list", "--count", "HEAD"])
+            .output()
+            .unwrap();
+
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn saves_commit_changes_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        Command::new("git")
+            .current_dir(dir.path())
+            .args(["init", "--quiet"])
+            .status()
+            .unwrap();
+
+        let provider = GitProvider::new().with_author("gatekeeper", "gatekeeper@localhost");
+        provider.put("workers", 4).unwrap();
+        provider.save(&path).unwrap();
+        assert_eq!(commit_count(dir.path()), 1);
+
+        provider.save(&path).unwrap();
+        assert_eq!(commit_count(dir.path()), 1);
+
+        provider.put("workers", 8).unwrap();
+        provider.save(&path).unwrap();
+        assert_eq!(commit_count(dir.path()), 2);
+    }
+}
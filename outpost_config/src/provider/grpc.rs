@@ -0,0 +1,444 @@
+use crate::grpc::{
+    config_error, ConfigServiceClient, KeyRequest, ListRequest, PutRequest, WatchEvent,
+    WatchRequest,
+};
+use crate::{ChangeEvent, ConfigError, ConfigProvider, WatchableConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use tokio::runtime::{Builder, Runtime};
+use tonic::transport::{Channel, Endpoint};
+use tonic::Request;
+
+/// Provider reading and writing the config of a [`ConfigService`](crate::grpc::ConfigService)
+///
+/// Every operation is a call to the service, values travel as JSON and are (de)serialized on
+/// this end, so the service needs no knowledge of their types. Changes made through the service
+/// can be followed through [`WatchableConfigProvider::watch`] if the service answers `Watch`.
+/// The client runs on a small private runtime, so the provider can be used from synchronous
+/// code.
+///
+/// ```ignore
+/// let config = GrpcProvider::connect("http://gatekeeper.internal:7070")?
+///     .with_bearer_token("s3cret");
+/// let workers: u32 = config.get("workers")?;
+/// ```
+pub struct GrpcProvider {
+    runtime: Runtime,
+    client: ConfigServiceClient<Channel>,
+    token: Option<String>,
+}
+
+impl GrpcProvider {
+    /// Connect to the service at the given url, e.g. `http://127.0.0.1:7070`.
+    pub fn connect(url: &str) -> Result<Self, ConfigError> {
+        // one worker keeps the connection alive between the blocking calls
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(|err| ConfigError::backend("grpc", err))?;
+
+        let endpoint = Endpoint::from_shared(url.to_string())
+            .map_err(|err| ConfigError::backend("grpc", err))?;
+        let channel = runtime
+            .block_on(endpoint.connect())
+            .map_err(|err| ConfigError::backend("grpc", err))?;
+
+        Ok(Self {
+            runtime,
+            client: ConfigServiceClient::new(channel),
+            token: None,
+        })
+    }
+
+    /// Send the token as `authorization: Bearer <token>` metadata with every call.
+    pub fn with_bearer_token<S>(mut self, token: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.token = Some(token.into());
+        self
+    }
+
+    fn request<T>(&self, message: T) -> Result<Request<T>, ConfigError> {
+        let mut request = Request::new(message);
+        if let Some(token) = &self.token {
+            let value = format!("Bearer {}", token)
+                .parse()
+                .map_err(|err| ConfigError::permission_denied("grpc", err))?;
+            request.metadata_mut().insert("authorization", value);
+        }
+
+        Ok(request)
+    }
+
+    fn version_of(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        let request = self.request(KeyRequest {
+            key: key.to_string(),
+        })?;
+        let response = self
+            .runtime
+            .block_on(self.client.clone().version(request))
+            .map_err(|status| config_error(Some(key), status))?;
+
+        Ok(response.into_inner().version)
+    }
+
+    fn send(&self, key: &str, message: PutRequest) -> Result<(), ConfigError> {
+        let request = self.request(message)?;
+        self.runtime
+            .block_on(self.client.clone().put(request))
+            .map_err(|status| config_error(Some(key), status))?;
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("grpc"))]
+impl ConfigProvider for GrpcProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let request = self.request(KeyRequest {
+            key: key.to_string(),
+        })?;
+        let response = self
+            .runtime
+            .block_on(self.client.clone().get(request))
+            .map_err(|status| config_error(Some(key), status))?;
+
+        serde_json::from_str(&response.into_inner().value)
+            .map_err(|err| ConfigError::deserialization("grpc", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.version_of(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("grpc", err).with_key(key))?;
+
+        self.send(
+            key,
+            PutRequest {
+                key: key.to_string(),
+                value,
+                conditional: false,
+                expected_version: None,
+            },
+        )
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let request = self.request(KeyRequest {
+            key: key.to_string(),
+        })?;
+        self.runtime
+            .block_on(self.client.clone().delete(request))
+            .map_err(|status| config_error(Some(key), status))?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.list_prefix("")
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        let request = self.request(ListRequest {
+            prefix: prefix.to_string(),
+        })?;
+        let response = self
+            .runtime
+            .block_on(self.client.clone().list(request))
+            .map_err(|status| config_error(None, status))?;
+
+        Ok(response.into_inner().keys)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.version_of(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("grpc", err).with_key(key))?;
+
+        // the service compares and sets with the provider behind it
+        self.send(
+            key,
+            PutRequest {
+                key: key.to_string(),
+                value,
+                conditional: true,
+                expected_version: expected_version.map(str::to_string),
+            },
+        )
+    }
+}
+
+impl WatchableConfigProvider for GrpcProvider {
+    /// The stream runs on a background thread until the returned receiver is dropped and the
+    /// next change arrives, or until the connection to the service is lost.
+    fn watch(&self, key_prefix: &str) -> Result<Receiver<ChangeEvent>, ConfigError> {
+        let request = self.request(WatchRequest {
+            prefix: key_prefix.to_string(),
+        })?;
+        let mut stream = self
+            .runtime
+            .block_on(self.client.clone().watch(request))
+            .map_err(|status| config_error(None, status))?
+            .into_inner();
+
+        let (sender, receiver) = mpsc::channel();
+        let handle = self.runtime.handle().clone();
+
+        thread::spawn(move || {
+            while let Ok(Some(event)) = handle.block_on(stream.message()) {
+                let event = match change_event(event) {
+                    Some(event) => event,
+                    None => continue,
+                };
+
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+}
+
+/// The change a streamed event reports, skipping values that aren't JSON.
+fn change_event(event: WatchEvent) -> Option<ChangeEvent> {
+    let parse = |json: &str| serde_json::from_str(json).ok();
+
+    match (event.old, event.new) {
+        (old, Some(new)) => Some(ChangeEvent::Put {
+            key: event.key,
+            old: match old {
+                Some(old) => Some(parse(&old)?),
+                None => None,
+            },
+            new: parse(&new)?,
+        }),
+        (Some(old), None) => Some(ChangeEvent::Delete {
+            key: event.key,
+            old: parse(&old)?,
+        }),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grpc::ConfigService;
+    use crate::provider::in_memory::InMemoryProvider;
+    use serde_json::json;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    /// Serve the provider on a free port and return a url to connect to.
+    fn start(service: ConfigService<InMemoryProvider>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+            runtime.block_on(async {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                service.serve(listener).await
+            })
+        });
+
+        url
+    }
+
+    fn connect() -> GrpcProvider {
+        let url = start(ConfigService::new(InMemoryProvider::new()).with_watch());
+        GrpcProvider::connect(&url).unwrap()
+    }
+
+    #[test]
+    fn written_values_are_read_back() {
+        let provider = connect();
+
+        provider.put("workers", 4).unwrap();
+        provider
+            .put("listen", vec![":80".to_string(), ":443".to_string()])
+            .unwrap();
+
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        assert_eq!(
+            provider.get::<Vec<String>>("listen").unwrap(),
+            vec![":80", ":443"]
+        );
+        assert!(provider.has("workers").unwrap());
+    }
+
+    #[test]
+    fn missing_keys_are_not_found() {
+        let provider = connect();
+
+        assert!(provider.get::<u32>("workers").unwrap_err().is_not_found());
+        assert!(!provider.has("workers").unwrap());
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_fail_to_deserialize() {
+        let provider = connect();
+        provider.put("workers", "four".to_string()).unwrap();
+
+        let err = provider.get::<u32>("workers").unwrap_err();
+        assert!(
+            matches!(err, ConfigError::Deserialization { .. }),
+            "{}",
+            err
+        );
+        assert_eq!(err.key(), Some("workers"));
+    }
+
+    #[test]
+    fn keys_are_listed_by_prefix_and_deleted() {
+        let provider = connect();
+        provider.put("policy.admin", "allow".to_string()).unwrap();
+        provider.put("policy.guest", "deny".to_string()).unwrap();
+        provider.put("workers", 4).unwrap();
+
+        let mut keys = provider.list_prefix("policy.").unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["policy.admin", "policy.guest"]);
+
+        provider.delete("policy.guest").unwrap();
+        assert_eq!(provider.list().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn stale_versions_are_conflicts() {
+        let provider = connect();
+        provider.put_if_version("workers", 4, None).unwrap();
+        assert!(matches!(
+            provider.put_if_version("workers", 4, None),
+            Err(ConfigError::Conflict { .. })
+        ));
+
+        let version = provider.version("workers").unwrap();
+        provider
+            .put_if_version("workers", 8, version.as_deref())
+            .unwrap();
+        assert!(matches!(
+            provider.put_if_version("workers", 16, version.as_deref()),
+            Err(ConfigError::Conflict { .. })
+        ));
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 8);
+    }
+
+    #[test]
+    fn changes_are_streamed_to_watchers() {
+        let provider = connect();
+        let changes = provider.watch("policy.").unwrap();
+
+        provider.put("workers", 4).unwrap();
+        provider.put("policy.admin", "allow".to_string()).unwrap();
+        provider.delete("policy.admin").unwrap();
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            changes.recv_timeout(timeout).unwrap(),
+            ChangeEvent::Put {
+                key: "policy.admin".to_string(),
+                old: None,
+                new: json!("allow"),
+            }
+        );
+        assert_eq!(
+            changes.recv_timeout(timeout).unwrap(),
+            ChangeEvent::Delete {
+                key: "policy.admin".to_string(),
+                old: json!("allow"),
+            }
+        );
+    }
+
+    #[test]
+    fn services_without_watch_refuse_watchers() {
+        let url = start(ConfigService::new(InMemoryProvider::new()));
+        let provider = GrpcProvider::connect(&url).unwrap();
+
+        assert!(matches!(
+            provider.watch(""),
+            Err(ConfigError::Backend { .. })
+        ));
+    }
+
+    #[test]
+    fn calls_need_the_bearer_token() {
+        let url = start(ConfigService::new(InMemoryProvider::new()).with_bearer_token("s3cret"));
+
+        let anonymous = GrpcProvider::connect(&url).unwrap();
+        assert!(matches!(
+            anonymous.put("workers", 4),
+            Err(ConfigError::PermissionDenied { .. })
+        ));
+        let guessing = GrpcProvider::connect(&url)
+            .unwrap()
+            .with_bearer_token("guess");
+        assert!(matches!(
+            guessing.get::<u32>("workers"),
+            Err(ConfigError::PermissionDenied { .. })
+        ));
+
+        let authorized = GrpcProvider::connect(&url)
+            .unwrap()
+            .with_bearer_token("s3cret");
+        authorized.put("workers", 4).unwrap();
+        assert_eq!(authorized.get::<u32>("workers").unwrap(), 4);
+    }
+
+    #[test]
+    fn streamed_events_map_to_changes() {
+        let event = |old: Option<&str>, new: Option<&str>| {
+            change_event(WatchEvent {
+                key: "workers".to_string(),
+                old: old.map(str::to_string),
+                new: new.map(str::to_string),
+            })
+        };
+
+        assert_eq!(
+            event(Some("4"), Some("8")),
+            Some(ChangeEvent::Put {
+                key: "workers".to_string(),
+                old: Some(json!(4)),
+                new: json!(8),
+            })
+        );
+        assert_eq!(
+            event(Some("4"), None),
+            Some(ChangeEvent::Delete {
+                key: "workers".to_string(),
+                old: json!(4),
+            })
+        );
+        assert_eq!(event(None, Some("four")), None);
+        assert_eq!(event(None, None), None);
+    }
+}
@@ -0,0 +1,659 @@
+#[cfg(feature = "async-http")]
+use crate::async_http::{check, status_of, AsyncClient};
+use crate::http::{http_error, not_found_as_none};
+#[cfg(feature = "async-http")]
+use crate::AsyncConfigProvider;
+use crate::{value_version, ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use ureq::{Agent, Request};
+
+/// Provider reading its values from a JSON document served over HTTP(S)
+///
+/// The document is a single object mapping config keys to values. It is fetched lazily and
+/// revalidated once the poll interval passed, sending the last `ETag` as `If-None-Match` so an
+/// unchanged document isn't downloaded again. The provider is read-only unless write back is
+/// enabled, in which case every `put` and `delete` POSTs the whole updated document back to
+/// the same url. A write only replaces the document it was based on, a document changed on the
+/// server in the meantime fails it with `ConfigError::Conflict`. [`AsyncHttpProvider`] is its
+/// async counterpart.
+pub struct HttpProvider {
+    agent: Agent,
+    url: String,
+    headers: Vec<(String, String)>,
+    poll_interval: Duration,
+    write_back: bool,
+    document: Mutex<Document>,
+}
+
+#[derive(Default)]
+struct Document {
+    values: BTreeMap<String, Value>,
+    etag: Option<String>,
+    fetched: Option<Instant>,
+    /// Whether the server had a document when it was last fetched.
+    published: bool,
+}
+
+impl Document {
+    fn is_stale(&self, poll_interval: Duration) -> bool {
+        match self.fetched {
+            Some(fetched) => fetched.elapsed() >= poll_interval,
+            None => true,
+        }
+    }
+
+    /// Take the values the server has published, or `None` if it has no document.
+    fn published(&mut self, published: Option<(BTreeMap<String, Value>, Option<String>)>) {
+        match published {
+            Some((values, etag)) => {
+                self.values = values;
+                self.etag = etag;
+                self.published = true;
+            }
+            None => {
+                self.values.clear();
+                self.etag = None;
+                self.published = false;
+            }
+        }
+    }
+
+    /// Take the values posted to the server, which answered with the given `ETag`.
+    fn posted(&mut self, values: BTreeMap<String, Value>, etag: Option<String>) {
+        self.published(Some((values, etag)));
+        // without an ETag the next lookup has to fetch the document again
+        if self.etag.is_none() {
+            self.fetched = None;
+        }
+    }
+
+    /// The header making the server refuse a document if it changed since it was fetched.
+    fn precondition(&self, key: &str) -> Result<(&'static str, String), ConfigError> {
+        match (&self.etag, self.published) {
+            (Some(etag), _) => Ok(("If-Match", etag.clone())),
+            (None, false) => Ok(("If-None-Match", "*".to_string())),
+            (None, true) => Err(ConfigError::backend(
+                "http",
+                "the server sends no ETag to compare the document against",
+            )
+            .with_key(key)),
+        }
+    }
+}
+
+impl HttpProvider {
+    /// Create a provider reading the document at the given url.
+    pub fn new<S>(url: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            agent: Agent::new(),
+            url: url.into(),
+            headers: Vec::new(),
+            poll_interval: Duration::from_secs(60),
+            write_back: false,
+            document: Mutex::new(Document::default()),
+        }
+    }
+
+    /// Send the given header with every request, e.g. an `Authorization` header.
+    pub fn with_header<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Revalidate the document once it is older than the given interval.
+    ///
+    /// A zero interval revalidates on every lookup.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// POST the updated document back to the url on every `put` and `delete`.
+    pub fn with_write_back(mut self) -> Self {
+        self.write_back = true;
+        self
+    }
+
+    /// Revalidate the document now, regardless of the poll interval.
+    pub fn refresh(&self) -> Result<(), ConfigError> {
+        let mut document = self.lock_document();
+        self.fetch(&mut document)
+    }
+
+    fn lock_document(&self) -> MutexGuard<'_, Document> {
+        // the cached document stays usable even if a thread panicked while holding the lock
+        self.document.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn request(&self, method: &str) -> Request {
+        self.headers
+            .iter()
+            .fold(self.agent.request(method, &self.url), |request, (k, v)| {
+                request.set(k, v)
+            })
+    }
+
+    fn fetch(&self, document: &mut Document) -> Result<(), ConfigError> {
+        let mut request = self.request("GET");
+        if let Some(etag) = &document.etag {
+            request = request.set("If-None-Match", etag);
+        }
+
+        match not_found_as_none("http", request.call())? {
+            Some(response) if response.status() == 304 => {}
+            Some(response) => {
+                let etag = response.header("ETag").map(str::to_string);
+                let values = response
+                    .into_json()
+                    .map_err(|err| ConfigError::deserialization("http", err))?;
+                document.published(Some((values, etag)));
+            }
+            // nothing published yet
+            None => document.published(None),
+        }
+
+        document.fetched = Some(Instant::now());
+
+        Ok(())
+    }
+
+    /// Lock the document, revalidating it first if it is stale.
+    fn current(&self) -> Result<MutexGuard<'_, Document>, ConfigError> {
+        let mut document = self.lock_document();
+
+        if document.is_stale(self.poll_interval) {
+            self.fetch(&mut document)?;
+        }
+
+        Ok(document)
+    }
+
+    fn update<F>(&self, key: &str, update: F) -> Result<(), ConfigError>
+    where
+        F: FnOnce(&mut BTreeMap<String, Value>),
+    {
+        if !self.write_back {
+            return Err(ConfigError::read_only("http"));
+        }
+
+        let mut document = self.current()?;
+        // only replace the version we know about, or create the document if there was none
+        let precondition = document.precondition(key)?;
+        let mut values = document.values.clone();
+        update(&mut values);
+
+        self.post(&mut document, values, Some(precondition), |err| {
+            write_error(key, err)
+        })
+    }
+
+    /// POST the values as the new document, sending the precondition header if there is one.
+    fn post<E>(
+        &self,
+        document: &mut Document,
+        values: BTreeMap<String, Value>,
+        precondition: Option<(&str, String)>,
+        map_err: E,
+    ) -> Result<(), ConfigError>
+    where
+        E: FnOnce(ureq::Error) -> ConfigError,
+    {
+        let mut request = self.request("POST");
+        if let Some((header, value)) = precondition {
+            request = request.set(header, &value);
+        }
+
+        let body =
+            serde_json::to_value(&values).map_err(|err| ConfigError::serialization("http", err))?;
+        let response = request.send_json(body).map_err(map_err)?;
+        document.posted(values, response.header("ETag").map(str::to_string));
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("http"))]
+impl ConfigProvider for HttpProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let value = self
+            .current()?
+            .values
+            .get(key)
+            .cloned()
+            .ok_or_else(|| ConfigError::not_found("http", key))?;
+
+        serde_json::from_value(value)
+            .map_err(|err| ConfigError::deserialization("http", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.current()?.values.contains_key(key))
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("http", err).with_key(key))?;
+
+        self.update(key, |values| {
+            values.insert(key.to_string(), value);
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.update(key, |values| {
+            values.remove(key);
+        })
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        Ok(self.current()?.values.keys().cloned().collect())
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("http", err).with_key(key))?;
+        if !self.write_back {
+            return Err(ConfigError::read_only("http"));
+        }
+
+        // compare against the published document rather than a copy that may be stale
+        let mut document = self.lock_document();
+        self.fetch(&mut document)?;
+        if document.values.get(key).map(value_version).as_deref() != expected_version {
+            return Err(ConfigError::conflict("http", key));
+        }
+
+        // the server refuses the document if it changed since it was fetched
+        let precondition = document.precondition(key)?;
+        let mut values = document.values.clone();
+        values.insert(key.to_string(), value);
+
+        self.post(&mut document, values, Some(precondition), |err| {
+            write_error(key, err)
+        })
+    }
+}
+
+/// Map a refused write of the document, the server refuses it with 412 if it changed since it
+/// was fetched.
+fn write_error(key: &str, err: ureq::Error) -> ConfigError {
+    match err {
+        ureq::Error::Status(412, _) => ConfigError::conflict("http", key),
+        err => http_error("http", err).with_key(key),
+    }
+}
+
+/// Async counterpart of [`HttpProvider`] fetching and posting the document without blocking
+///
+/// Caches, revalidates and writes back the document exactly like the [`HttpProvider`]. Calls
+/// wait for each other while the document is fetched or posted. Requires the `async-http`
+/// feature.
+#[cfg(feature = "async-http")]
+pub struct AsyncHttpProvider {
+    client: AsyncClient,
+    url: String,
+    headers: Vec<(String, String)>,
+    poll_interval: Duration,
+    write_back: bool,
+    document: tokio::sync::Mutex<Document>,
+}
+
+#[cfg(feature = "async-http")]
+impl AsyncHttpProvider {
+    /// Create a provider reading the document at the given url.
+    pub fn new<S>(url: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            client: AsyncClient::new(),
+            url: url.into(),
+            headers: Vec::new(),
+            poll_interval: Duration::from_secs(60),
+            write_back: false,
+            document: tokio::sync::Mutex::new(Document::default()),
+        }
+    }
+
+    /// Send the given header with every request, e.g. an `Authorization` header.
+    pub fn with_header<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Revalidate the document once it is older than the given interval.
+    ///
+    /// A zero interval revalidates on every lookup.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// POST the updated document back to the url on every `put` and `delete`.
+    pub fn with_write_back(mut self) -> Self {
+        self.write_back = true;
+        self
+    }
+
+    /// Fail requests that take longer than the given duration, 30 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.with_timeout(timeout);
+        self
+    }
+
+    /// Revalidate the document now, regardless of the poll interval.
+    pub async fn refresh(&self) -> Result<(), ConfigError> {
+        let mut document = self.document.lock().await;
+        self.fetch(&mut document).await
+    }
+
+    async fn fetch(&self, document: &mut Document) -> Result<(), ConfigError> {
+        let mut headers = self.headers.clone();
+        if let Some(etag) = &document.etag {
+            headers.push(("If-None-Match".to_string(), etag.clone()));
+        }
+
+        let response = self
+            .client
+            .send("GET", &self.url, &headers, None)
+            .await
+            .map_err(|err| ConfigError::backend("http", err))?;
+        match check("http", response) {
+            Ok(response) if response.status() == 304 => {}
+            Ok(response) => {
+                let etag = response.header("ETag").map(str::to_string);
+                document.published(Some((response.json("http")?, etag)));
+            }
+            // nothing published yet
+            Err(err) if status_of(&err) == Some(404) => document.published(None),
+            Err(err) => return Err(err),
+        }
+
+        document.fetched = Some(Instant::now());
+
+        Ok(())
+    }
+
+    /// Lock the document, revalidating it first if it is stale.
+    async fn current(&self) -> Result<tokio::sync::MutexGuard<'_, Document>, ConfigError> {
+        let mut document = self.document.lock().await;
+
+        if document.is_stale(self.poll_interval) {
+            self.fetch(&mut document).await?;
+        }
+
+        Ok(document)
+    }
+
+    async fn update<F>(&self, key: &str, update: F) -> Result<(), ConfigError>
+    where
+        F: FnOnce(&mut BTreeMap<String, Value>),
+    {
+        if !self.write_back {
+            return Err(ConfigError::read_only("http"));
+        }
+
+        let mut document = self.current().await?;
+        // only replace the version we know about, or create the document if there was none
+        let (header, value) = document.precondition(key)?;
+        let mut values = document.values.clone();
+        update(&mut values);
+
+        let mut headers = self.headers.clone();
+        headers.push((header.to_string(), value));
+        let body =
+            serde_json::to_value(&values).map_err(|err| ConfigError::serialization("http", err))?;
+        let response = self
+            .client
+            .send_json("http", "POST", &self.url, &headers, &body)
+            .await
+            .map_err(|err| match status_of(&err) {
+                Some(412) => ConfigError::conflict("http", key),
+                _ => err.with_key(key),
+            })?;
+        document.posted(values, response.header("ETag").map(str::to_string));
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async-http")]
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("http"))]
+impl AsyncConfigProvider for AsyncHttpProvider {
+    async fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let value = self
+            .current()
+            .await?
+            .values
+            .get(key)
+            .cloned()
+            .ok_or_else(|| ConfigError::not_found("http", key))?;
+
+        serde_json::from_value(value)
+            .map_err(|err| ConfigError::deserialization("http", err).with_key(key))
+    }
+
+    async fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.current().await?.values.contains_key(key))
+    }
+
+    async fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize + Send + 'static,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("http", err).with_key(key))?;
+
+        self.update(key, |values| {
+            values.insert(key.to_string(), value);
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.update(key, |values| {
+            values.remove(key);
+        })
+        .await
+    }
+
+    async fn list(&self) -> Result<Vec<String>, ConfigError> {
+        Ok(self.current().await?.values.keys().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    #[cfg(feature = "async-http")]
+    use tokio::runtime::Builder;
+
+    /// Answer one connection after another with the given responses, returning the requests.
+    fn serve(answers: Vec<&'static str>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/gatekeeper.json", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for answer in answers {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+                requests.push(request);
+                reader.get_mut().write_all(answer.as_bytes()).unwrap();
+            }
+            requests
+        });
+
+        (url, server)
+    }
+
+    #[test]
+    fn documents_are_revalidated_and_written_back() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 13\r\n\r\n{\"workers\":4}",
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nETag: \"v2\"\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 412 Precondition Failed\r\nContent-Length: 0\r\n\r\n",
+        ]);
+        let provider = HttpProvider::new(url)
+            .with_header("Authorization", "Bearer secret")
+            .with_poll_interval(Duration::ZERO)
+            .with_write_back();
+
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        // unchanged, the cached document is kept and revalidated before every write
+        assert!(provider.has("workers").unwrap());
+        provider.put("workers", 8).unwrap();
+        // the next write is refused by the server
+        assert!(matches!(
+            provider.delete("workers"),
+            Err(ConfigError::Conflict { .. })
+        ));
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /gatekeeper.json HTTP/1.1\r\n"));
+        assert!(requests[0].contains("Authorization: Bearer secret\r\n"));
+        assert!(requests[1].contains("If-None-Match: \"v1\"\r\n"));
+        assert!(requests[3].starts_with("POST "));
+        assert!(requests[3].contains("If-Match: \"v1\"\r\n"));
+        assert!(requests[3].ends_with("{\"workers\":8}"));
+        assert!(requests[4].contains("If-None-Match: \"v2\"\r\n"));
+        assert!(requests[5].contains("If-Match: \"v2\"\r\n"));
+    }
+
+    #[test]
+    fn a_missing_document_is_only_created_if_it_still_is_missing() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 412 Precondition Failed\r\nContent-Length: 0\r\n\r\n",
+        ]);
+        let provider = HttpProvider::new(url).with_write_back();
+
+        assert!(matches!(
+            provider.put("workers", 8),
+            Err(ConfigError::Conflict { .. })
+        ));
+
+        let requests = server.join().unwrap();
+        assert!(requests[1].starts_with("POST "));
+        assert!(requests[1].contains("If-None-Match: *\r\n"));
+    }
+
+    #[test]
+    fn documents_are_read_only_without_write_back() {
+        let provider = HttpProvider::new("http://127.0.0.1:1/gatekeeper.json");
+
+        assert!(matches!(
+            provider.put("workers", 8),
+            Err(ConfigError::ReadOnly { .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "async-http")]
+    fn async_documents_are_revalidated_and_written_back() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 13\r\n\r\n{\"workers\":4}",
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nETag: \"v2\"\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 412 Precondition Failed\r\nContent-Length: 0\r\n\r\n",
+        ]);
+        let provider = AsyncHttpProvider::new(url)
+            .with_header("Authorization", "Bearer secret")
+            .with_poll_interval(Duration::ZERO)
+            .with_write_back();
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+
+        runtime.block_on(async {
+            // nothing published yet
+            assert!(provider.list().await.unwrap().is_empty());
+            assert_eq!(provider.get::<u32>("workers").await.unwrap(), 4);
+            // unchanged, the cached document is kept and revalidated before every write
+            assert!(provider.has("workers").await.unwrap());
+            provider.put("workers", 8).await.unwrap();
+            // the next write is refused by the server
+            assert!(matches!(
+                provider.delete("workers").await,
+                Err(ConfigError::Conflict { .. })
+            ));
+        });
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /gatekeeper.json HTTP/1.1\r\n"));
+        assert!(requests[0].contains("Authorization: Bearer secret\r\n"));
+        assert!(requests[2].contains("If-None-Match: \"v1\"\r\n"));
+        assert!(requests[4].starts_with("POST "));
+        assert!(requests[4].contains("If-Match: \"v1\"\r\n"));
+        assert!(requests[4].ends_with("{\"workers\":8}"));
+        assert!(requests[5].contains("If-None-Match: \"v2\"\r\n"));
+        assert!(requests[6].contains("If-Match: \"v2\"\r\n"));
+    }
+
+    #[test]
+    #[cfg(feature = "async-http")]
+    fn async_documents_are_read_only_without_write_back() {
+        let provider = AsyncHttpProvider::new("http://127.0.0.1:1/gatekeeper.json");
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        assert!(matches!(
+            runtime.block_on(provider.put("workers", 8)),
+            Err(ConfigError::ReadOnly { .. })
+        ));
+    }
+}
@@ -1,120 +1,1554 @@
-use crate::{ConfigError, ConfigProvider, FileAwareConfigProvider};
+#[cfg(feature = "fs")]
+use crate::file::{
+    lock_exclusive, lock_shared, read_checked, verify_checked, write_checked, write_durable,
+};
+use crate::provider::sharded::{ReadAll, ShardedMap, WriteAll};
+#[cfg(feature = "fs")]
+use crate::FileAwareConfigProvider;
+use crate::{
+    value_version, ChangeEvent, ConfigError, ConfigProvider, ProviderStats, Transaction,
+    TransactionOp, TransactionalConfigProvider, WatchableConfigProvider,
+};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::collections::HashMap;
-use std::fs::{self, File};
+use serde_json::Value;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::{BufReader, BufWriter, Write};
+#[cfg(feature = "fs")]
 use std::path::Path;
-use std::sync::{Arc, RwLock};
+#[cfg(feature = "threads")]
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+#[cfg(feature = "threads")]
+use std::sync::Weak;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "threads")]
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Number of shards holding entries whose least recently used entry is considered for eviction.
+const EVICTION_SAMPLES: usize = 4;
+
+/// Longest pause of the thread removing expired entries.
+#[cfg(feature = "threads")]
+const MAX_REAP_INTERVAL: Duration = Duration::from_secs(1);
 
 /// File aware in memory provider
+///
+/// Keys are spread by hash over independently locked shards, so writers of unrelated keys don't
+/// block each other or the readers. `list`, `load`, `save` and transactions lock all shards they
+/// touch in a fixed order and see or make their changes atomically across shards. `save` only
+/// lists the keys that way and then writes the values one shard lock at a time.
+///
+/// Changes made through `put`, `delete` and `load` are reported to all watchers of the changed
+/// keys.
+///
+/// Entries inserted with [`InMemoryProvider::put_with_ttl`] are invisible once their ttl has
+/// passed and are removed by a background thread, which reports them as deleted to the watchers.
+/// The thread is only started by the first entry with a ttl and ends with the provider. Without
+/// the `threads` feature expired entries stay hidden in the store until they are written again.
+///
+/// A provider built with [`InMemoryProvider::with_capacity`] holds at most that many entries,
+/// which makes it a bounded read-through cache in front of remote providers. A put beyond the
+/// capacity evicts an expired entry, or else the least recently read or written entry of a few
+/// shards, which approximates the least recently used one overall without locking all shards.
+/// Evicted entries are reported as deleted.
+///
+/// Loading and saving files requires the `fs` feature. Saved files carry a format version and a
+/// checksum of the values, `load` fails with `ConfigError::Corrupted` for files that were cut
+/// short or changed by hand instead of loading part of them.
+///
+/// A thread panicking while it changes the provider leaves the shard it changed poisoned, every
+/// later access to it fails with `ConfigError::Backend` instead of panicking as well.
 #[derive(Default)]
 pub struct InMemoryProvider {
-    store: Arc<RwLock<HashMap<String, String>>>,
+    store: Arc<ShardedMap<Entry>>,
+    watchers: Arc<Mutex<Vec<Watcher>>>,
+    file_times: Mutex<FileTimes>,
+    /// Number of changes made so far and how many of them were loaded or saved.
+    changes: Arc<AtomicU64>,
+    saved_changes: AtomicU64,
+    capacity: Option<usize>,
+    /// Shard the next search for an entry to evict starts at.
+    eviction_cursor: AtomicUsize,
+    #[cfg(feature = "threads")]
+    reaper_started: AtomicBool,
+    #[cfg(feature = "fs")]
+    backups: usize,
+}
+
+/// Key prefix of a watch and the channel its changes are sent to.
+type Watcher = (String, Sender<ChangeEvent>);
+
+/// When the values were last loaded from and saved to a file, reported by `stats`.
+#[derive(Default)]
+struct FileTimes {
+    load: Option<SystemTime>,
+    save: Option<SystemTime>,
+}
+
+/// Source of the use stamps of the entries, a later use gets a larger stamp.
+static CLOCK: AtomicU64 = AtomicU64::new(0);
+
+/// A stored value as JSON, when it expires and when it was used last.
+pub(crate) struct Entry {
+    raw: String,
+    deadline: Option<Instant>,
+    used: AtomicU64,
+}
+
+/// All entries of a provider, locked for reading.
+pub(crate) struct Entries<'a> {
+    shards: ReadAll<'a, Entry>,
+    now: Instant,
+}
+
+/// Entries of a provider, locked for writing.
+pub(crate) struct EntriesMut<'a> {
+    shards: WriteAll<'a, Entry>,
+    now: Instant,
 }
 
 impl InMemoryProvider {
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spread the keys over the given number of shards, at least one. One shard makes every
+    /// writer block all other accesses.
+    pub fn with_shards(mut self, shards: usize) -> Self {
+        self.store = Arc::new(ShardedMap::new(shards));
+        self
+    }
+
+    /// Hold at most the given number of entries, evicting the least recently used ones.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Keep the given number of previous versions of the file on `save`, see
+    /// [`FileAwareConfigProvider::save`](crate::FileAwareConfigProvider::save).
+    #[cfg(feature = "fs")]
+    pub fn with_backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Insert a key value pair that expires after the given duration.
+    ///
+    /// A later `put` of the same key makes it permanent again. Entries with a ttl are left out
+    /// when the values are saved, so they don't outlive it after a reload.
+    pub fn put_with_ttl<T>(&self, key: &str, value: T, ttl: Duration) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|e| ConfigError::serialization("in_memory", e).with_key(key))?;
+        let now = Instant::now();
+        let entry = Entry {
+            deadline: Some(now + ttl),
+            ..Entry::new(serialized.clone())
+        };
+        let mut write_guard = self.store.write(key).map_err(poisoned)?;
+        let previous = write_guard.insert(key.to_string(), entry);
+        // reported before the shard is released, so events of a key arrive in order
+        self.notify_put(key, previous.and_then(|entry| entry.live(now)), &serialized);
+        drop(write_guard);
+
+        self.start_reaper();
+        self.evict()
+    }
+
+    /// Lock all entries for reading.
+    pub(crate) fn read(&self) -> Result<Entries<'_>, ConfigError> {
+        Ok(Entries {
+            shards: self.store.read_all().map_err(poisoned)?,
+            now: Instant::now(),
+        })
+    }
+
+    /// Lock all entries for writing.
+    #[cfg(any(feature = "fs", feature = "s3"))]
+    pub(crate) fn write(&self) -> Result<EntriesMut<'_>, ConfigError> {
+        Ok(EntriesMut {
+            shards: self.store.write_all().map_err(poisoned)?,
+            now: Instant::now(),
+        })
+    }
+
+    /// Whether the values changed since they were last loaded or saved.
+    pub fn is_dirty(&self) -> bool {
+        self.changes.load(Ordering::SeqCst) > self.saved_changes.load(Ordering::SeqCst)
+    }
+
+    /// The number of changes made so far, to pass to `mark_saved` once the values read after
+    /// it are saved.
+    #[cfg(any(feature = "fs", feature = "s3"))]
+    pub(crate) fn changes(&self) -> u64 {
+        self.changes.load(Ordering::SeqCst)
+    }
+
+    /// Record a load of the values, `changes` is the count taken before it began. The loaded
+    /// values only count as saved if there were no unsaved changes before, edits made before
+    /// the load keep the provider dirty. Providers loading into this one call it themselves.
+    #[cfg(any(feature = "fs", feature = "s3"))]
+    pub(crate) fn mark_loaded(&self, changes: u64) {
+        let loaded = self.changes.load(Ordering::SeqCst);
+        let _ = self.saved_changes.compare_exchange(
+            changes,
+            loaded,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+        self.lock_file_times().load = Some(SystemTime::now());
+    }
+
+    /// Record a save of the values, changes made after the given count stay unsaved. Providers
+    /// saving this one call it themselves.
+    #[cfg(any(feature = "fs", feature = "s3"))]
+    pub(crate) fn mark_saved(&self, changes: u64) {
+        self.saved_changes.fetch_max(changes, Ordering::SeqCst);
+        self.lock_file_times().save = Some(SystemTime::now());
+    }
+
+    /// Write the entries without a ttl to a file in the checked format, in key order. Only the keys are
+    /// collected under the lock of all shards, the values are read and written one at a time
+    /// while locking just their shard, so a save of a large config neither holds up writers
+    /// for long nor copies all values. A value changed during the save may or may not be saved.
+    #[cfg(feature = "fs")]
+    pub(crate) fn write_values<W>(
+        &self,
+        provider: &'static str,
+        writer: W,
+    ) -> Result<(), ConfigError>
+    where
+        W: Write,
+    {
+        let mut keys: Vec<String> = self
+            .read()?
+            .persistent()
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort_unstable();
+
+        let entries = keys.into_iter().filter_map(|key| {
+            let raw = match self.store.read(&key) {
+                Ok(shard) => shard
+                    .get(&key)
+                    .filter(|entry| entry.deadline.is_none())
+                    .map(|entry| entry.raw.clone()),
+                Err(err) => return Some(Err(poisoned(err))),
+            };
+            // keys deleted or given a ttl since they were listed are skipped
+            raw.map(|raw| Ok((key, raw)))
+        });
+
+        write_checked(provider, entries, writer)
+    }
+
+    fn lock_file_times(&self) -> MutexGuard<'_, FileTimes> {
+        self.file_times
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Without threads expired entries are never removed, only hidden.
+    #[cfg(not(feature = "threads"))]
+    fn start_reaper(&self) {}
+
+    /// Spawn the thread removing expired entries, unless it is already running.
+    #[cfg(feature = "threads")]
+    fn start_reaper(&self) {
+        if self.reaper_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let store = Arc::downgrade(&self.store);
+        let watchers = Arc::downgrade(&self.watchers);
+        let changes = Arc::downgrade(&self.changes);
+
+        thread::spawn(move || {
+            while let Some(pause) = reap(&store, &watchers, &changes) {
+                thread::sleep(pause);
+            }
+        });
+    }
+
+    /// Note a read of the entry, only bounded providers keep track of that.
+    fn touch(&self, entry: &Entry) {
+        if self.capacity.is_some() {
+            entry.used.store(stamp(), Ordering::Relaxed);
+        }
+    }
+
+    /// Remove entries while there are more than the capacity, expired ones first and else the
+    /// least recently used one of a few sampled shards. Only the shard of an evicted entry is
+    /// locked for writing, the others stay available meanwhile.
+    fn evict(&self) -> Result<(), ConfigError> {
+        let capacity = match self.capacity {
+            Some(capacity) => capacity,
+            None => return Ok(()),
+        };
+
+        while self.store.len() > capacity {
+            let (shard, key, used) = match self.eviction_candidate()? {
+                Some(candidate) => candidate,
+                None => return Ok(()),
+            };
+
+            let mut write_guard = self.store.write_shard(shard).map_err(poisoned)?;
+            // another thread may have evicted meanwhile, or used the candidate again
+            if self.store.len() <= capacity {
+                break;
+            }
+            let unchanged = write_guard
+                .get(&key)
+                .is_some_and(|entry| entry.used.load(Ordering::Relaxed) == used);
+            if !unchanged {
+                continue;
+            }
+            let removed = write_guard.remove(&key);
+
+            if let Some(removed) = removed.and_then(|entry| entry.live(Instant::now())) {
+                self.notify(ChangeEvent::Delete {
+                    key,
+                    old: parse_raw(&removed),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The shard, key and use stamp of the entry to evict next: the first expired one or the
+    /// least recently used one of the next few shards holding entries.
+    fn eviction_candidate(&self) -> Result<Option<(usize, String, u64)>, ConfigError> {
+        let shards = self.store.shard_count();
+        let start = self.eviction_cursor.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+
+        let mut candidate: Option<(usize, String, u64)> = None;
+        let mut sampled = 0;
+        for shard in (start..start + shards).map(|shard| shard % shards) {
+            let read_guard = self.store.read_shard(shard).map_err(poisoned)?;
+            for (key, entry) in read_guard.iter() {
+                let used = entry.used.load(Ordering::Relaxed);
+                if !entry.is_live(now) {
+                    return Ok(Some((shard, key.clone(), used)));
+                }
+                if candidate
+                    .as_ref()
+                    .is_none_or(|(_, _, oldest)| used < *oldest)
+                {
+                    candidate = Some((shard, key.clone(), used));
+                }
+            }
+
+            if !read_guard.is_empty() {
+                sampled += 1;
+                if sampled == EVICTION_SAMPLES {
+                    break;
+                }
+            }
+        }
+
+        Ok(candidate)
+    }
+
+    /// The deserialized value of the key, unless it is missing or has expired.
+    fn live_value<T>(&self, key: &str) -> Result<Option<Result<T, ConfigError>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let read_guard = self.store.read(key).map_err(poisoned)?;
+        let entry = read_guard
+            .get(key)
+            .filter(|entry| entry.is_live(Instant::now()));
+
+        Ok(entry.map(|entry| {
+            self.touch(entry);
+            serde_json::from_str(&entry.raw)
+                .map_err(|err| ConfigError::deserialization("in_memory", err).with_key(key))
+        }))
+    }
+
+    fn notify(&self, event: ChangeEvent) {
+        self.changes.fetch_add(1, Ordering::SeqCst);
+        notify(&self.watchers, event);
+    }
+
+    /// Report the new raw value of a key, `previous` is its raw value if it was visible before.
+    fn notify_put(&self, key: &str, previous: Option<String>, raw: &str) {
+        self.notify(ChangeEvent::Put {
+            key: key.to_string(),
+            old: previous.as_deref().map(parse_raw),
+            new: parse_raw(raw),
+        });
+    }
+}
+
+impl Entry {
+    fn new(raw: String) -> Self {
         Self {
-            store: Arc::new(RwLock::new(HashMap::new())),
+            raw,
+            deadline: None,
+            used: AtomicU64::new(stamp()),
         }
     }
+
+    fn is_live(&self, now: Instant) -> bool {
+        self.deadline.is_none_or(|deadline| deadline > now)
+    }
+
+    /// The raw value, unless the entry has expired.
+    fn live(self, now: Instant) -> Option<String> {
+        self.is_live(now).then_some(self.raw)
+    }
 }
 
+impl Entries<'_> {
+    /// The keys and raw values of the entries that haven't expired.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        let now = self.now;
+        self.shards
+            .iter()
+            .filter(move |(_, entry)| entry.is_live(now))
+            .map(|(key, entry)| (key, &entry.raw))
+    }
+
+    /// The keys and raw values of the entries without a ttl, the ones saved to files.
+    #[cfg(any(feature = "fs", feature = "s3"))]
+    pub(crate) fn persistent(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.shards
+            .iter()
+            .filter(|(_, entry)| entry.deadline.is_none())
+            .map(|(key, entry)| (key, &entry.raw))
+    }
+}
+
+impl EntriesMut<'_> {
+    /// Store the raw value without a ttl and return the previous one if it hadn't expired.
+    pub(crate) fn insert(&mut self, key: String, raw: String) -> Option<String> {
+        self.shards
+            .insert(key, Entry::new(raw))
+            .and_then(|entry| entry.live(self.now))
+    }
+
+    /// Remove the entry and return its raw value if it hadn't expired.
+    pub(crate) fn remove(&mut self, key: &str) -> Option<String> {
+        self.shards
+            .remove(key)
+            .and_then(|entry| entry.live(self.now))
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("in_memory"))]
 impl ConfigProvider for InMemoryProvider {
     fn get<T>(&self, key: &str) -> Result<T, ConfigError>
     where
         T: DeserializeOwned,
     {
-        let read_guard = self.store.read().unwrap();
-        let raw = read_guard.get(key).ok_or(ConfigError::NotFound)?;
-        let deserialized =
-            serde_json::from_str(&raw).map_err(|err| ConfigError::Other(Box::new(err)))?;
-
-        Ok(deserialized)
+        self.live_value(key)?
+            .unwrap_or_else(|| Err(ConfigError::not_found("in_memory", key)))
     }
 
     fn has(&self, key: &str) -> Result<bool, ConfigError> {
-        let read_guard = self.store.read().unwrap();
+        let read_guard = self.store.read(key).map_err(poisoned)?;
 
-        Ok(read_guard.contains_key(key))
+        let entry = read_guard
+            .get(key)
+            .filter(|entry| entry.is_live(Instant::now()));
+        if let Some(entry) = entry {
+            self.touch(entry);
+        }
+
+        Ok(entry.is_some())
     }
 
     fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
     where
         T: DeserializeOwned + Serialize,
     {
-        let serialized =
-            serde_json::to_string(&value).map_err(|e| ConfigError::Other(Box::new(e)))?;
-        let mut write_guard = self.store.write().unwrap();
-        let _ = write_guard.insert(key.to_string(), serialized);
+        let serialized = serde_json::to_string(&value)
+            .map_err(|e| ConfigError::serialization("in_memory", e).with_key(key))?;
+        let mut write_guard = self.store.write(key).map_err(poisoned)?;
+        let previous = write_guard.insert(key.to_string(), Entry::new(serialized.clone()));
+        let previous = previous.and_then(|entry| entry.live(Instant::now()));
+        // reported before the shard is released, so events of a key arrive in order
+        self.notify_put(key, previous, &serialized);
+        drop(write_guard);
 
-        Ok(())
+        self.evict()
     }
 
     fn delete(&self, key: &str) -> Result<(), ConfigError> {
-        let mut write_guard = self.store.write().unwrap();
-        let _ = write_guard.remove(key);
+        let mut write_guard = self.store.write(key).map_err(poisoned)?;
+        let removed = write_guard.remove(key);
+
+        if let Some(removed) = removed.and_then(|entry| entry.live(Instant::now())) {
+            self.notify(ChangeEvent::Delete {
+                key: key.to_string(),
+                old: parse_raw(&removed),
+            });
+        }
 
         Ok(())
     }
 
     fn list(&self) -> Result<Vec<String>, ConfigError> {
-        let read_guard = self.store.read().unwrap();
+        // hold every shard until all are read, so no transaction is seen half applied
+        let read_guard = self.read()?;
 
-        Ok(read_guard.keys().cloned().collect())
+        Ok(read_guard.iter().map(|(key, _)| key.clone()).collect())
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        let (keys, bytes) = self
+            .read()?
+            .iter()
+            .fold((0, 0), |(keys, bytes), (key, raw)| {
+                (keys + 1, bytes + key.len() + raw.len())
+            });
+        let file_times = self.lock_file_times();
+
+        Ok(ProviderStats {
+            keys,
+            bytes: Some(bytes as u64),
+            hits: None,
+            misses: None,
+            last_load: file_times.load,
+            last_save: file_times.save,
+        })
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        if let Some(existing) = self.live_value(key)? {
+            return existing;
+        }
+
+        // computed without holding the shard, f may use this provider itself
+        let value = f();
+        let serialized = serde_json::to_string(&value)
+            .map_err(|e| ConfigError::serialization("in_memory", e).with_key(key))?;
+
+        let mut write_guard = self.store.write(key).map_err(poisoned)?;
+        // a value inserted while it was computed wins
+        if let Some(entry) = write_guard.get(key) {
+            if entry.is_live(Instant::now()) {
+                self.touch(entry);
+                return serde_json::from_str(&entry.raw)
+                    .map_err(|err| ConfigError::deserialization("in_memory", err).with_key(key));
+            }
+        }
+        // an existing entry has expired and doesn't count as previous value
+        let _ = write_guard.insert(key.to_string(), Entry::new(serialized.clone()));
+        self.notify_put(key, None, &serialized);
+        drop(write_guard);
+
+        self.evict()?;
+
+        Ok(value)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|e| ConfigError::serialization("in_memory", e).with_key(key))?;
+        let mut write_guard = self.store.write(key).map_err(poisoned)?;
+        let now = Instant::now();
+        let previous = write_guard
+            .get(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| entry.raw.clone());
+        let version = previous
+            .as_deref()
+            .map(|raw| value_version(&parse_raw(raw)));
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("in_memory", key));
+        }
+
+        write_guard.insert(key.to_string(), Entry::new(serialized.clone()));
+        self.notify_put(key, previous, &serialized);
+        drop(write_guard);
+
+        self.evict()
     }
 }
 
+#[cfg(feature = "fs")]
 impl FileAwareConfigProvider for InMemoryProvider {
     fn load<P>(&self, path: P) -> Result<(), ConfigError>
     where
         P: AsRef<Path>,
     {
-        let file = File::open(path).map_err(|err| ConfigError::Other(Box::new(err)))?;
-
-        let mut write_guard = self.store.write().unwrap();
+        let changes = self.changes();
+        let path = path.as_ref();
+        let _lock = lock_shared("in_memory", path)?;
+        let file = File::open(path).map_err(|err| ConfigError::io("in_memory", err))?;
+        let values = read_checked("in_memory", path, BufReader::new(file))?;
 
-        let values: HashMap<String, String> =
-            serde_json::from_reader(file).map_err(|err| ConfigError::Other(Box::new(err)))?;
+        let mut write_guard = self.write()?;
 
-        for (k, v) in values {
-            write_guard.insert(k, v);
+        for (k, v) in &values {
+            let previous = write_guard.insert(k.clone(), v.clone());
+            self.notify_put(k, previous, v);
         }
+        drop(write_guard);
+        self.mark_loaded(changes);
 
-        Ok(())
+        self.evict()
     }
 
     fn save<P>(&self, path: P) -> Result<(), ConfigError>
     where
         P: AsRef<Path>,
     {
-        let path = path.as_ref();
+        let _lock = lock_exclusive("in_memory", path.as_ref())?;
+        let changes = self.changes();
+        write_durable(
+            "in_memory",
+            path,
+            self.backups,
+            |file| self.write_values("in_memory", BufWriter::new(file)),
+            |written| {
+                // a truncated or garbled file must not replace the last good one
+                let file = File::open(written).map_err(|err| ConfigError::io("in_memory", err))?;
+                verify_checked("in_memory", written, BufReader::new(file))
+            },
+        )?;
+        self.mark_saved(changes);
 
-        // try to create the directory
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).map_err(|err| ConfigError::Other(Box::new(err)))?;
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        InMemoryProvider::is_dirty(self)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("in_memory"))]
+impl TransactionalConfigProvider for InMemoryProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let mut ops = Vec::new();
+        for op in transaction.into_ops() {
+            let op = match op {
+                TransactionOp::Put { key, value } => {
+                    let serialized = serde_json::to_string(&value)
+                        .map_err(|err| ConfigError::serialization("in_memory", err))?;
+                    (key, Some((serialized, value)))
+                }
+                TransactionOp::Delete { key } => (key, None),
+            };
+            ops.push(op);
         }
 
-        // create a temporary file to work with so we don't end up with a broken config
-        let tmp_path = {
-            let mut tmp_path = path.to_path_buf();
-            tmp_path.set_extension("tmp");
-            tmp_path
+        // readers see either none or all of the writes
+        let mut write_guard = EntriesMut {
+            shards: self
+                .store
+                .write_keys(ops.iter().map(|(key, _)| key.as_str()))
+                .map_err(poisoned)?,
+            now: Instant::now(),
         };
 
-        let mut tmp_file =
-            File::create(&tmp_path).map_err(|err| ConfigError::Other(Box::new(err)))?;
+        // reported before the shards are released, so events of a key arrive in order
+        for (key, put) in ops {
+            match put {
+                Some((serialized, value)) => {
+                    let previous = write_guard.insert(key.clone(), serialized);
+                    self.notify(ChangeEvent::Put {
+                        key,
+                        old: previous.as_deref().map(parse_raw),
+                        new: value,
+                    });
+                }
+                None => {
+                    if let Some(removed) = write_guard.remove(&key) {
+                        self.notify(ChangeEvent::Delete {
+                            key,
+                            old: parse_raw(&removed),
+                        });
+                    }
+                }
+            }
+        }
+        drop(write_guard);
+
+        self.evict()
+    }
+}
+
+/// The value of a stored entry, entries loaded from a file may hold plain strings.
+fn parse_raw(raw: &str) -> Value {
+    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
 
-        // acquire a read guard once the file is ready
-        let read_guard = self.store.read().unwrap();
+fn poisoned<T>(_: PoisonError<T>) -> ConfigError {
+    ConfigError::backend("in_memory", "a thread panicked while changing the provider")
+}
 
-        // serialize the providers values and write it to the file
-        serde_json::to_writer_pretty(&mut tmp_file, &*read_guard)
-            .map_err(|err| ConfigError::Other(Box::new(err)))?;
+/// Send the event to every watcher of the changed key and forget the dropped ones.
+fn notify(watchers: &Mutex<Vec<Watcher>>, event: ChangeEvent) {
+    let key = event.key();
 
-        // move the temporary file to its final destination
-        std::fs::rename(&tmp_path, path).map_err(|err| ConfigError::Other(Box::new(err)))?;
+    // the list of watchers stays intact even if a thread panicked while holding the lock
+    let mut watchers = watchers.lock().unwrap_or_else(PoisonError::into_inner);
+    watchers.retain(|(prefix, sender)| {
+        !key.starts_with(prefix.as_str()) || sender.send(event.clone()).is_ok()
+    });
+}
 
-        Ok(())
+/// The next use stamp.
+fn stamp() -> u64 {
+    CLOCK.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Remove the expired entries of a provider and return how long to wait for the next ones, or
+/// `None` once the provider is gone.
+#[cfg(feature = "threads")]
+fn reap(
+    store: &Weak<ShardedMap<Entry>>,
+    watchers: &Weak<Mutex<Vec<Watcher>>>,
+    changes: &Weak<AtomicU64>,
+) -> Option<Duration> {
+    let store = store.upgrade()?;
+    let watchers = watchers.upgrade()?;
+    let changes = changes.upgrade()?;
+
+    let now = Instant::now();
+    let mut next = now + MAX_REAP_INTERVAL;
+    // one shard at a time, so the others stay available meanwhile
+    for shard in 0..store.shard_count() {
+        // a poisoned shard fails every access, there is nothing left to reap
+        let mut write_guard = match store.write_shard(shard) {
+            Ok(write_guard) => write_guard,
+            Err(_) => continue,
+        };
+        let mut expired = Vec::new();
+        write_guard.retain(|key, entry| {
+            match entry.deadline {
+                Some(deadline) if deadline <= now => {
+                    expired.push((key.clone(), entry.raw.clone()));
+                    return false;
+                }
+                Some(deadline) => next = next.min(deadline),
+                None => {}
+            }
+            true
+        });
+
+        // reported before the shard is released, so events of a key arrive in order
+        for (key, removed) in expired {
+            changes.fetch_add(1, Ordering::SeqCst);
+            notify(
+                &watchers,
+                ChangeEvent::Delete {
+                    key,
+                    old: parse_raw(&removed),
+                },
+            );
+        }
+    }
+
+    Some(next.duration_since(now))
+}
+
+impl WatchableConfigProvider for InMemoryProvider {
+    fn watch(&self, key_prefix: &str) -> Result<Receiver<ChangeEvent>, ConfigError> {
+        let (sender, receiver) = mpsc::channel();
+        self.watchers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((key_prefix.to_string(), sender));
+
+        Ok(receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use serde_json::json;
+    use std::thread;
+
+    #[test]
+    fn watchers_receive_changes_below_their_prefix() {
+        let provider = InMemoryProvider::new();
+        let policies = provider.watch("policy.").unwrap();
+
+        provider
+            .put("policy.admin", vec!["read".to_string()])
+            .unwrap();
+        provider.put("workers", 4).unwrap();
+        provider
+            .put(
+                "policy.admin",
+                vec!["read".to_string(), "write".to_string()],
+            )
+            .unwrap();
+        provider.delete("policy.admin").unwrap();
+
+        let events: Vec<_> = policies.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent::Put {
+                    key: "policy.admin".to_string(),
+                    old: None,
+                    new: json!(["read"]),
+                },
+                ChangeEvent::Put {
+                    key: "policy.admin".to_string(),
+                    old: Some(json!(["read"])),
+                    new: json!(["read", "write"]),
+                },
+                ChangeEvent::Delete {
+                    key: "policy.admin".to_string(),
+                    old: json!(["read", "write"]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn concurrent_writes_of_a_key_report_chained_events() {
+        let provider = Arc::new(InMemoryProvider::new());
+        let events = provider.watch("workers").unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|writer| {
+                let provider = Arc::clone(&provider);
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        provider.put("workers", writer * 100 + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // every event starts from the value the one before it left
+        let mut current = None;
+        for event in events.try_iter() {
+            match event {
+                ChangeEvent::Put { old, new, .. } => {
+                    assert_eq!(old, current);
+                    current = Some(new);
+                }
+                ChangeEvent::Delete { .. } => panic!("nothing was deleted"),
+            }
+        }
+        assert_eq!(current, Some(provider.get::<Value>("workers").unwrap()));
+    }
+
+    #[test]
+    fn watchers_without_a_prefix_receive_every_change() {
+        let provider = InMemoryProvider::new();
+        let all = provider.watch("").unwrap();
+
+        provider.put("policy.admin", "read".to_string()).unwrap();
+        provider.put("workers", 4).unwrap();
+        provider.delete("workers").unwrap();
+
+        assert_eq!(all.try_iter().count(), 3);
+    }
+
+    #[test]
+    fn deleting_a_missing_key_changes_nothing() {
+        let provider = InMemoryProvider::new();
+        let all = provider.watch("").unwrap();
+
+        provider.delete("policy.admin").unwrap();
+
+        assert_eq!(all.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn dropped_watchers_are_removed_on_the_next_change() {
+        let provider = InMemoryProvider::new();
+        let _policies = provider.watch("policy.").unwrap();
+        let all = provider.watch("").unwrap();
+
+        drop(all);
+        provider.put("workers", 8).unwrap();
+        assert_eq!(provider.watchers.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn missing_keys_fall_back_to_defaults() {
+        let provider = InMemoryProvider::new();
+        provider.put("workers", 8).unwrap();
+
+        assert_eq!(provider.get_opt::<u32>("workers").unwrap(), Some(8));
+        assert_eq!(provider.get_opt::<u32>("threads").unwrap(), None);
+        assert_eq!(provider.get_or("threads", 2).unwrap(), 2);
+        assert_eq!(provider.get_or_else("workers", || 1).unwrap(), 8);
+        assert_eq!(
+            provider
+                .get_or_default::<Vec<String>>("hosts")
+                .unwrap()
+                .len(),
+            0
+        );
+        // a value of the wrong type is still an error
+        assert!(provider.get_or::<String>("workers", String::new()).is_err());
+    }
+
+    #[test]
+    fn writers_only_lock_the_shard_of_their_key() {
+        let provider = InMemoryProvider::new().with_shards(4);
+        let keys: Vec<String> = (0..100).map(|i| format!("route.{}", i)).collect();
+        for key in &keys {
+            provider.put(key, key.clone()).unwrap();
+        }
+        let mut listed = provider.list().unwrap();
+        listed.sort();
+        let mut expected = keys.clone();
+        expected.sort();
+        assert_eq!(listed, expected);
+
+        // a writer holding the shard of one key leaves the keys of the other shards available
+        let _write_guard = provider.store.write("route.0").unwrap();
+        let shard = provider.store.shard_of("route.0");
+        let other = keys
+            .iter()
+            .find(|key| provider.store.shard_of(key) != shard)
+            .unwrap();
+        assert_eq!(provider.get::<String>(other).unwrap(), *other);
+        provider.put(other, "changed".to_string()).unwrap();
+    }
+
+    #[test]
+    fn pages_cover_every_key_once() {
+        let provider = InMemoryProvider::new();
+        for route in 0..25 {
+            provider
+                .put(&format!("routes.{:02}", route), route)
+                .unwrap();
+        }
+
+        let mut keys = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = provider.list_page(cursor.as_deref(), 10).unwrap();
+            assert!(page.keys.len() <= 10);
+            keys.extend(page.keys);
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        assert_eq!(keys.len(), 25);
+        assert_eq!(keys[0], "routes.00");
+        assert_eq!(keys[24], "routes.24");
+    }
+
+    #[test]
+    fn entries_pair_keys_with_values() {
+        let provider = InMemoryProvider::new();
+        provider.put("routes.api", 8080).unwrap();
+        provider.put("routes.web", 80).unwrap();
+        provider.put("workers", 4).unwrap();
+
+        let mut routes = provider.entries::<u16>("routes.").unwrap();
+        routes.sort();
+        assert_eq!(
+            routes,
+            vec![
+                ("routes.api".to_string(), 8080),
+                ("routes.web".to_string(), 80)
+            ]
+        );
+        assert!(provider.entries::<String>("routes.").is_err());
+    }
+
+    #[test]
+    fn export_round_trips_through_import() {
+        let provider = InMemoryProvider::new();
+        provider
+            .put("routes.api", json!({ "upstream": "10.0.0.1" }))
+            .unwrap();
+        provider.put("workers", 4).unwrap();
+
+        let mut exported = Vec::new();
+        provider.export(Format::Json, &mut exported).unwrap();
+
+        let imported = InMemoryProvider::new();
+        imported.put("workers", 1).unwrap();
+        imported.put("listen", ":80".to_string()).unwrap();
+        imported.import(exported.as_slice(), Format::Json).unwrap();
+
+        assert_eq!(imported.get::<u32>("workers").unwrap(), 4);
+        assert_eq!(
+            imported.get::<Value>("routes.api").unwrap(),
+            json!({ "upstream": "10.0.0.1" })
+        );
+        assert!(imported.has("listen").unwrap());
+    }
+
+    #[test]
+    fn restore_rolls_back_to_the_snapshot() {
+        let provider = InMemoryProvider::new();
+        provider.put("policy.admin", "deny".to_string()).unwrap();
+        provider.put("workers", 4).unwrap();
+        let snapshot = provider.snapshot().unwrap();
+
+        provider.put("policy.admin", "allow".to_string()).unwrap();
+        provider.put("policy.guest", "allow".to_string()).unwrap();
+        provider.delete("workers").unwrap();
+
+        provider.restore(snapshot.clone()).unwrap();
+        assert_eq!(provider.get::<String>("policy.admin").unwrap(), "deny");
+        assert!(!provider.has("policy.guest").unwrap());
+        assert_eq!(provider.snapshot().unwrap(), snapshot);
+    }
+
+    #[test]
+    fn get_or_insert_with_computes_without_holding_the_shard() {
+        let provider = Arc::new(InMemoryProvider::new().with_shards(1));
+        provider.put("workers", 4).unwrap();
+
+        // the value can be derived from other keys of the same shard
+        let threads: u32 = provider
+            .get_or_insert_with("threads", || provider.get::<u32>("workers").unwrap() * 2)
+            .unwrap();
+        assert_eq!(threads, 8);
+
+        // a value inserted while it was computed wins
+        let raced: u32 = provider
+            .get_or_insert_with("raced", || {
+                provider.put("raced", 1).unwrap();
+                2
+            })
+            .unwrap();
+        assert_eq!(raced, 1);
+        assert_eq!(provider.get::<u32>("raced").unwrap(), 1);
+
+        // a panic while computing doesn't break the provider
+        let panicking = Arc::clone(&provider);
+        thread::spawn(move || {
+            panicking
+                .get_or_insert_with::<u32, _>("panicked", || panic!("no value"))
+                .unwrap();
+        })
+        .join()
+        .unwrap_err();
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        assert!(!provider.has("panicked").unwrap());
+    }
+
+    #[test]
+    fn get_or_insert_with_initializes_once() {
+        let provider = Arc::new(InMemoryProvider::new());
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let provider = Arc::clone(&provider);
+                thread::spawn(move || {
+                    provider
+                        .get_or_insert_with("node.id", || format!("node-{}", i))
+                        .unwrap()
+                })
+            })
+            .collect();
+        let ids: Vec<String> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        assert!(ids.iter().all(|id| *id == ids[0]));
+        assert_eq!(provider.get::<String>("node.id").unwrap(), ids[0]);
+    }
+
+    #[test]
+    fn put_if_version_rejects_concurrent_writes() {
+        let provider = InMemoryProvider::new();
+        provider
+            .put_if_version("policy.admin", "allow".to_string(), None)
+            .unwrap();
+        let version = provider.version("policy.admin").unwrap().unwrap();
+
+        // another admin edits the policy between our read and write
+        provider.put("policy.admin", "deny".to_string()).unwrap();
+        let err = provider
+            .put_if_version("policy.admin", "audit".to_string(), Some(&version))
+            .unwrap_err();
+        assert!(matches!(err, ConfigError::Conflict { .. }));
+        assert_eq!(err.key(), Some("policy.admin"));
+        assert!(provider
+            .put_if_version("policy.admin", "audit".to_string(), None)
+            .is_err());
+
+        let version = provider.version("policy.admin").unwrap();
+        provider
+            .put_if_version("policy.admin", "audit".to_string(), version.as_deref())
+            .unwrap();
+        assert_eq!(provider.get::<String>("policy.admin").unwrap(), "audit");
+        assert!(provider.version("policy.missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn transactions_apply_all_or_nothing() {
+        let provider = InMemoryProvider::new();
+        provider.put("route.api.cert", "old".to_string()).unwrap();
+        provider.put("tls.old", "pem".to_string()).unwrap();
+
+        provider
+            .transaction(|txn| {
+                txn.put("route.api.cert", "new".to_string())?
+                    .put("tls.new", "pem".to_string())?
+                    .delete("tls.old");
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(provider.get::<String>("route.api.cert").unwrap(), "new");
+        assert!(provider.has("tls.new").unwrap());
+        assert!(!provider.has("tls.old").unwrap());
+
+        let failed = provider.transaction(|txn| {
+            txn.put("route.api.cert", "broken".to_string())?;
+            Err::<(), _>(ConfigError::backend("canary", "validation failed"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(provider.get::<String>("route.api.cert").unwrap(), "new");
+    }
+
+    #[test]
+    fn entries_with_ttl_expire_before_the_reaper_runs() {
+        let provider = InMemoryProvider::new();
+        provider
+            .put_with_ttl(
+                "enrollment.token",
+                "abc".to_string(),
+                Duration::from_millis(50),
+            )
+            .unwrap();
+        provider
+            .put_with_ttl(
+                "enrollment.grant",
+                "xyz".to_string(),
+                Duration::from_secs(60),
+            )
+            .unwrap();
+        assert_eq!(provider.get::<String>("enrollment.token").unwrap(), "abc");
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(matches!(
+            provider.get::<String>("enrollment.token"),
+            Err(ConfigError::NotFound { .. })
+        ));
+        assert_eq!(provider.list().unwrap(), vec!["enrollment.grant"]);
+    }
+
+    #[test]
+    #[cfg(feature = "threads")]
+    fn expired_entries_are_reaped_and_reported_as_deleted() {
+        let provider = InMemoryProvider::new();
+        let watcher = provider.watch("enrollment.").unwrap();
+        provider
+            .put_with_ttl(
+                "enrollment.token",
+                "abc".to_string(),
+                Duration::from_millis(50),
+            )
+            .unwrap();
+
+        let deleted = watcher
+            .iter()
+            .find(|event| matches!(event, ChangeEvent::Delete { .. }))
+            .unwrap();
+        assert_eq!(
+            deleted,
+            ChangeEvent::Delete {
+                key: "enrollment.token".to_string(),
+                old: json!("abc"),
+            }
+        );
+        assert!(!provider
+            .store
+            .read("enrollment.token")
+            .unwrap()
+            .contains_key("enrollment.token"));
+        // the put and the removal
+        assert_eq!(provider.changes.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn entries_with_ttl_are_not_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        let provider = InMemoryProvider::new();
+        provider.put("workers", 4).unwrap();
+        provider
+            .put_with_ttl(
+                "enrollment.token",
+                "abc".to_string(),
+                Duration::from_secs(60),
+            )
+            .unwrap();
+        provider.save(&path).unwrap();
+
+        let loaded = InMemoryProvider::new();
+        loaded.load(&path).unwrap();
+        assert_eq!(loaded.list().unwrap(), vec!["workers"]);
+    }
+
+    #[test]
+    fn a_plain_put_makes_an_entry_permanent() {
+        let provider = InMemoryProvider::new();
+        provider
+            .put_with_ttl(
+                "enrollment.grant",
+                "xyz".to_string(),
+                Duration::from_millis(10),
+            )
+            .unwrap();
+        provider.put("enrollment.grant", "xyz".to_string()).unwrap();
+
+        thread::sleep(Duration::from_millis(20));
+        assert!(provider.has("enrollment.grant").unwrap());
+    }
+
+    #[test]
+    fn errors_name_the_key_and_provider() {
+        let provider = InMemoryProvider::new();
+        provider.put("workers", "eight".to_string()).unwrap();
+
+        let err = provider.get::<u32>("listen").unwrap_err();
+        assert!(err.is_not_found());
+        assert_eq!(err.key(), Some("listen"));
+        assert_eq!(err.provider(), "in_memory");
+
+        let err = provider.get::<u32>("workers").unwrap_err();
+        assert!(matches!(err, ConfigError::Deserialization { .. }));
+        assert_eq!(err.key(), Some("workers"));
+        assert!(err.to_string().starts_with("in_memory: "));
+    }
+
+    #[test]
+    fn poisoned_provider_fails_instead_of_panicking() {
+        let provider = InMemoryProvider::new();
+        provider.put("workers", 4).unwrap();
+        let watch = provider.watch("").unwrap();
+
+        let store = provider.store.clone();
+        thread::spawn(move || {
+            let _write_guard = store.write("workers").unwrap();
+            panic!("handler crashed while holding the lock");
+        })
+        .join()
+        .unwrap_err();
+
+        assert!(matches!(
+            provider.get::<u32>("workers"),
+            Err(ConfigError::Backend { .. })
+        ));
+        assert!(matches!(
+            provider.put("workers", 8),
+            Err(ConfigError::Backend { .. })
+        ));
+        assert!(provider.list().is_err());
+        assert!(provider.watch("workers").is_ok());
+        assert!(watch.try_recv().is_err());
+    }
+
+    #[test]
+    fn raw_values_move_between_providers() {
+        let provider = InMemoryProvider::new();
+        provider.put("workers", 4).unwrap();
+        provider.put("listen", ":443".to_string()).unwrap();
+        provider
+            .put_raw("tls.cert", "-----BEGIN CERTIFICATE-----")
+            .unwrap();
+
+        assert_eq!(provider.get_raw("workers").unwrap(), "4");
+        assert_eq!(provider.get_raw("listen").unwrap(), ":443");
+        assert_eq!(
+            provider.get::<String>("tls.cert").unwrap(),
+            "-----BEGIN CERTIFICATE-----"
+        );
+
+        let copy = InMemoryProvider::new();
+        for key in provider.list().unwrap() {
+            copy.put_raw(&key, &provider.get_raw(&key).unwrap())
+                .unwrap();
+        }
+        assert_eq!(copy.snapshot().unwrap(), provider.snapshot().unwrap());
+    }
+
+    #[test]
+    fn binary_values_are_stored_as_base64() {
+        let provider = InMemoryProvider::new();
+        let der = [0x30, 0x82, 0x01, 0x0a, 0x02, 0x82, 0x01, 0x01, 0x00, 0xff];
+        provider.put_bytes("tls.cert", &der).unwrap();
+
+        assert_eq!(provider.get_bytes("tls.cert").unwrap(), der);
+        assert_eq!(provider.get_raw("tls.cert").unwrap(), "MIIBCgKCAQEA/w==");
+        provider.put("workers", "eight!".to_string()).unwrap();
+        assert!(matches!(
+            provider.get_bytes("workers"),
+            Err(ConfigError::Deserialization { .. })
+        ));
+    }
+
+    #[test]
+    fn json_pointers_reach_into_structured_values() {
+        let provider = InMemoryProvider::new();
+        provider
+            .put_value(
+                "tls",
+                json!({"cert_file": "/etc/tls/cert.pem", "ciphers": ["aes128", "aes256"]}),
+            )
+            .unwrap();
+
+        assert_eq!(
+            provider.get_path("tls", "/cert_file").unwrap(),
+            json!("/etc/tls/cert.pem")
+        );
+        assert_eq!(
+            provider.get_path("tls", "/ciphers/1").unwrap(),
+            json!("aes256")
+        );
+        assert!(provider
+            .get_path("tls", "/key_file")
+            .unwrap_err()
+            .is_not_found());
+
+        provider
+            .put_path("tls", "/cert_file", json!("/run/tls/cert.pem"))
+            .unwrap();
+        assert_eq!(
+            provider.get_value("tls").unwrap(),
+            json!({"cert_file": "/run/tls/cert.pem", "ciphers": ["aes128", "aes256"]})
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn save_keeps_rotated_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        let provider = InMemoryProvider::new().with_backups(2);
+
+        for workers in 1..=4 {
+            provider.put("workers", workers).unwrap();
+            provider.save(&path).unwrap();
+        }
+
+        let generation = |path: &Path| {
+            let restored = InMemoryProvider::new();
+            restored.load(path).unwrap();
+            restored.get::<u32>("workers").unwrap()
+        };
+        assert_eq!(generation(&path), 4);
+        assert_eq!(generation(&crate::file::backup_path(&path, 1)), 3);
+        assert_eq!(generation(&crate::file::backup_path(&path, 2)), 2);
+        assert!(!crate::file::backup_path(&path, 3).exists());
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn loading_keeps_earlier_edits_unsaved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        let saved = InMemoryProvider::new();
+        saved.put("workers", 4).unwrap();
+        saved.save(&path).unwrap();
+
+        let provider = InMemoryProvider::new();
+        provider.load(&path).unwrap();
+        assert!(!provider.is_dirty());
+
+        provider.put("listen", ":8080".to_string()).unwrap();
+        provider.load(&path).unwrap();
+        assert!(provider.is_dirty());
+
+        provider.save(&path).unwrap();
+        assert!(!provider.is_dirty());
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn stats_count_the_values_and_file_accesses() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        let provider = InMemoryProvider::new();
+        provider.put("workers", 4).unwrap();
+        provider.put("listen", ":8080".to_string()).unwrap();
+
+        let stats = provider.stats().unwrap();
+        assert_eq!(stats.keys, 2);
+        assert_eq!(
+            stats.bytes,
+            Some(("workers4".len() + "listen\":8080\"".len()) as u64)
+        );
+        assert_eq!((stats.last_load, stats.last_save), (None, None));
+
+        let before = SystemTime::now();
+        provider.save(&path).unwrap();
+        let loaded = InMemoryProvider::new();
+        loaded.load(&path).unwrap();
+        assert!(provider.stats().unwrap().last_save.unwrap() >= before);
+        let stats = loaded.stats().unwrap();
+        assert!(stats.last_load.unwrap() >= before);
+        assert_eq!((stats.keys, stats.last_save, stats.hits), (2, None, None));
+    }
+
+    #[test]
+    fn bounded_provider_evicts_the_least_recently_used_entries() {
+        let provider = InMemoryProvider::new().with_capacity(2);
+        let changes = provider.watch("vault.").unwrap();
+        provider.put("vault.db", "s3cret".to_string()).unwrap();
+        provider.put("vault.tls", "key".to_string()).unwrap();
+
+        // reading the older entry makes the other one the least recently used
+        assert_eq!(provider.get::<String>("vault.db").unwrap(), "s3cret");
+        provider.put("vault.api", "token".to_string()).unwrap();
+
+        assert!(!provider.has("vault.tls").unwrap());
+        assert!(provider.has("vault.db").unwrap());
+        assert!(provider.has("vault.api").unwrap());
+        assert_eq!(provider.list().unwrap().len(), 2);
+        let evicted = changes.try_iter().last().unwrap();
+        assert_eq!(
+            evicted,
+            ChangeEvent::Delete {
+                key: "vault.tls".to_string(),
+                old: json!("key"),
+            }
+        );
+
+        // replacing a value doesn't evict anything
+        provider.put("vault.db", "rotated".to_string()).unwrap();
+        assert_eq!(provider.list().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn bounded_provider_stays_within_its_capacity() {
+        let provider = Arc::new(InMemoryProvider::new().with_capacity(50));
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let provider = provider.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        let key = format!("vault.{}.{}", writer, i);
+                        provider.put(&key, i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(provider.store.len(), 50);
+        assert_eq!(provider.list().unwrap().len(), 50);
+
+        // the count follows deletes and transactions as well
+        let key = provider.list().unwrap().remove(0);
+        provider.delete(&key).unwrap();
+        provider.delete(&key).unwrap();
+        assert_eq!(provider.store.len(), 49);
+        let mut transaction = Transaction::new();
+        transaction.put("vault.new", 1).unwrap();
+        provider.commit(transaction).unwrap();
+        assert_eq!(provider.store.len(), 50);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn corrupted_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        let provider = InMemoryProvider::new();
+        provider.put("workers", 4).unwrap();
+        provider.put("listen", ":8080".to_string()).unwrap();
+        provider.save(&path).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains(r#""$format": 1"#), "{}", saved);
+
+        let load = |raw: &str| {
+            std::fs::write(&path, raw).unwrap();
+            InMemoryProvider::new().load(&path)
+        };
+        load(&saved).unwrap();
+        for corrupted in [
+            saved[..saved.len() / 2].to_string(),
+            saved.replace(r#"\":8080\""#, r#"\":9090\""#),
+        ] {
+            assert!(matches!(
+                load(&corrupted),
+                Err(ConfigError::Corrupted { .. })
+            ));
+        }
+
+        // files written before the checksum was added are still read
+        load(r#"{"workers": "4"}"#).unwrap();
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn spans_record_the_provider_and_the_key() {
+        use std::fmt::{Debug, Write};
+        use tracing::field::Field;
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut recorded = span.metadata().name().to_string();
+                span.record(&mut |field: &Field, value: &dyn Debug| {
+                    write!(recorded, " {}={:?}", field.name(), value).unwrap();
+                });
+                let mut spans = self.0.lock().unwrap();
+                spans.push(recorded);
+                Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event<'_>) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let provider = InMemoryProvider::new();
+        tracing::subscriber::with_default(Recorder(spans.clone()), || {
+            provider.put("workers", 4).unwrap();
+            provider.has("listen").unwrap();
+        });
+
+        assert_eq!(
+            *spans.lock().unwrap(),
+            [
+                r#"put key="workers" provider="in_memory""#,
+                r#"has key="listen" provider="in_memory""#,
+            ]
+        );
     }
 }
@@ -0,0 +1,271 @@
+use crate::file::{lock_exclusive, lock_shared, write_atomic};
+use crate::provider::env::{decode_scalar, encode_scalar};
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, ProviderStats, Transaction,
+    TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// File aware provider persisting its values as INI
+///
+/// `key = value` pairs inside a `[section]` map to `section.key`, pairs before the first
+/// section keep their plain name. Values are parsed like environment variables: JSON where
+/// possible, otherwise as bare strings.
+#[derive(Default)]
+pub struct IniProvider {
+    inner: InMemoryProvider,
+}
+
+impl IniProvider {
+    pub fn new() -> Self {
+        Self {
+            inner: InMemoryProvider::new(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("ini"))]
+impl ConfigProvider for IniProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.inner.stats()
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("ini"))]
+impl TransactionalConfigProvider for IniProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl FileAwareConfigProvider for IniProvider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_shared("ini", path.as_ref())?;
+        let raw = fs::read_to_string(path).map_err(|err| ConfigError::io("ini", err))?;
+        let values = parse(&raw)?;
+
+        let mut write_guard = self.inner.write()?;
+
+        for (k, v) in values {
+            let value: Value = decode_scalar(&v)?;
+            let serialized = serde_json::to_string(&value)
+                .map_err(|err| ConfigError::serialization("ini", err))?;
+            write_guard.insert(k, serialized);
+        }
+        self.inner.mark_loaded(changes);
+
+        Ok(())
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_exclusive("ini", path.as_ref())?;
+        write_atomic("ini", path, |file| {
+            let read_guard = self.inner.read()?;
+
+            // group the keys by section, the global section (empty name) sorts first
+            let mut sections: BTreeMap<&str, BTreeMap<&str, String>> = BTreeMap::new();
+            for (k, v) in read_guard.persistent() {
+                let value: Value = serde_json::from_str(v)
+                    .map_err(|err| ConfigError::deserialization("ini", err))?;
+                let (section, name) = k.split_once('.').unwrap_or(("", k));
+
+                sections
+                    .entry(section)
+                    .or_default()
+                    .insert(name, render_value(value)?);
+            }
+
+            let mut rendered = String::new();
+            for (section, entries) in sections {
+                if !section.is_empty() {
+                    if !rendered.is_empty() {
+                        rendered.push('\n');
+                    }
+                    rendered.push_str(&format!("[{}]\n", section));
+                }
+                for (name, value) in entries {
+                    rendered.push_str(&format!("{} = {}\n", name, value));
+                }
+            }
+
+            file.write_all(rendered.as_bytes())
+                .map_err(|err| ConfigError::io("ini", err))
+        })?;
+        self.inner.mark_saved(changes);
+
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+/// Render a value, quoting strings that would not survive a `load` unchanged.
+fn render_value(value: Value) -> Result<String, ConfigError> {
+    if let Value::String(s) = &value {
+        let ambiguous = s.is_empty()
+            || s.trim() != s
+            || s.starts_with('"')
+            || serde_json::from_str::<Value>(s).is_ok();
+
+        if ambiguous {
+            return Ok(format!("\"{}\"", s));
+        }
+    }
+
+    encode_scalar(value)
+}
+
+/// Parse INI content into `(section.key, raw value)` pairs.
+fn parse(raw: &str) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut section = String::new();
+    let mut values = Vec::new();
+
+    for (index, line) in raw.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            section = name.trim().to_string();
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').ok_or_else(|| {
+            ConfigError::deserialization(
+                "ini",
+                format!("line {}: expected `key = value`", index + 1),
+            )
+        })?;
+        let (key, value) = (key.trim(), value.trim());
+
+        let key = if section.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", section, key)
+        };
+
+        // quotes only protect surrounding whitespace, the value itself is a plain string
+        let value = match value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+        {
+            Some(quoted) => serde_json::to_string(quoted)
+                .map_err(|err| ConfigError::serialization("ini", err))?,
+            None => value.to_string(),
+        };
+
+        values.push((key, value));
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_round_trip_as_dotted_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.ini");
+        fs::write(
+            &path,
+            "name = gatekeeper\n\n; upstream pool\n[upstream]\ntimeout = 5\nhost = \" localhost \"\n",
+        )
+        .unwrap();
+
+        let provider = IniProvider::new();
+        provider.load(&path).unwrap();
+
+        let mut keys = provider.list().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["name", "upstream.host", "upstream.timeout"]);
+        assert_eq!(provider.get::<u32>("upstream.timeout").unwrap(), 5);
+        assert_eq!(
+            provider.get::<String>("upstream.host").unwrap(),
+            " localhost "
+        );
+        assert_eq!(provider.get::<String>("name").unwrap(), "gatekeeper");
+
+        provider.put("upstream.retries", 3).unwrap();
+        provider.put("upstream.port", "8080".to_string()).unwrap();
+        provider.save(&path).unwrap();
+
+        let reloaded = IniProvider::new();
+        reloaded.load(&path).unwrap();
+        assert_eq!(reloaded.get::<u32>("upstream.retries").unwrap(), 3);
+        assert_eq!(reloaded.get::<String>("upstream.port").unwrap(), "8080");
+        assert_eq!(
+            reloaded.get::<String>("upstream.host").unwrap(),
+            " localhost "
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{AsyncConfigProvider, ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Adapter exposing a synchronous provider as an [`AsyncConfigProvider`] without a runtime
+///
+/// Calls run in place when the future is polled, so the futures are ready on the first poll and
+/// work with any executor. Only suited for providers that never block, like the
+/// `InMemoryProvider` or a `CachedProvider`, wrap the others in `Blocking` instead.
+pub struct Inline<P> {
+    inner: P,
+}
+
+impl<P> Inline<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+
+    /// Access the wrapped provider, e.g. for provider specific methods.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("inline"))]
+impl<P> AsyncConfigProvider for Inline<P>
+where
+    P: ConfigProvider + Sync,
+{
+    async fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        self.inner.get(key)
+    }
+
+    async fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    async fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize + Send + 'static,
+    {
+        self.inner.put(key, value)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    async fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// Poll the future once, which is all an inline call needs.
+    fn ready<F>(future: F) -> F::Output
+    where
+        F: Future,
+    {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("inline calls never wait"),
+        }
+    }
+
+    #[test]
+    fn calls_complete_without_a_runtime() {
+        let provider = Inline::new(InMemoryProvider::new());
+
+        ready(provider.put("workers", 4)).unwrap();
+        assert!(ready(provider.has("workers")).unwrap());
+        assert_eq!(ready(provider.get::<u32>("workers")).unwrap(), 4);
+        assert_eq!(ready(provider.list()).unwrap(), vec!["workers"]);
+
+        ready(provider.delete("workers")).unwrap();
+        assert!(ready(provider.get::<u32>("workers"))
+            .unwrap_err()
+            .is_not_found());
+        assert!(!provider.into_inner().has("workers").unwrap());
+    }
+}
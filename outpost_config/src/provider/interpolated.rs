@@ -0,0 +1,280 @@
+use crate::{ConfigError, ConfigProvider, KeyPage};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::env;
+use std::time::SystemTime;
+
+/// View of another provider that resolves placeholders in string values on `get`
+///
+/// `${NAME}` is replaced with the environment variable `NAME` and `${key:other.key}` with the
+/// value of another key of the inner provider, whose placeholders are resolved as well. `$${`
+/// stands for a literal `${`. Placeholders are resolved in every string of a value, including
+/// the ones nested in arrays and objects, values are stored with their placeholders.
+///
+/// Unset variables, missing or cyclic references fail with `ConfigError::Backend`, so the key
+/// isn't mistaken for a missing one.
+pub struct InterpolatedProvider<P> {
+    inner: P,
+}
+
+impl<P> InterpolatedProvider<P>
+where
+    P: ConfigProvider,
+{
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+
+    fn interpolate(
+        &self,
+        key: &str,
+        value: Value,
+        resolving: &mut Vec<String>,
+    ) -> Result<Value, ConfigError> {
+        match value {
+            Value::String(raw) => Ok(Value::String(self.interpolate_str(key, &raw, resolving)?)),
+            Value::Array(items) => Ok(Value::Array(
+                items
+                    .into_iter()
+                    .map(|item| self.interpolate(key, item, resolving))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Value::Object(fields) => Ok(Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(field, item)| Ok((field, self.interpolate(key, item, resolving)?)))
+                    .collect::<Result<_, ConfigError>>()?,
+            )),
+            value => Ok(value),
+        }
+    }
+
+    fn interpolate_str(
+        &self,
+        key: &str,
+        raw: &str,
+        resolving: &mut Vec<String>,
+    ) -> Result<String, ConfigError> {
+        let mut resolved = String::with_capacity(raw.len());
+        let mut rest = raw;
+
+        while let Some(start) = rest.find("${") {
+            if rest[..start].ends_with('$') {
+                resolved.push_str(&rest[..start - 1]);
+                resolved.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+
+            resolved.push_str(&rest[..start]);
+            let end = rest[start..].find('}').ok_or_else(|| {
+                ConfigError::deserialization("interpolated", "unterminated placeholder")
+                    .with_key(key)
+            })?;
+            let placeholder = &rest[start + 2..start + end];
+            resolved.push_str(&self.resolve(key, placeholder, resolving)?);
+            rest = &rest[start + end + 1..];
+        }
+        resolved.push_str(rest);
+
+        Ok(resolved)
+    }
+
+    /// The text a placeholder of the given key is replaced with.
+    fn resolve(
+        &self,
+        key: &str,
+        placeholder: &str,
+        resolving: &mut Vec<String>,
+    ) -> Result<String, ConfigError> {
+        let other = match placeholder.strip_prefix("key:") {
+            Some(other) => other,
+            None => {
+                return env::var(placeholder).map_err(|_| {
+                    ConfigError::backend(
+                        "interpolated",
+                        format!("environment variable {} isn't set", placeholder),
+                    )
+                    .with_key(key)
+                })
+            }
+        };
+
+        if resolving.iter().any(|seen| seen == other) {
+            return Err(ConfigError::backend(
+                "interpolated",
+                format!("{} references itself through {}", key, other),
+            )
+            .with_key(key));
+        }
+
+        let value = self.inner.get_value(other).map_err(|err| {
+            if err.is_not_found() {
+                ConfigError::backend(
+                    "interpolated",
+                    format!("{} references the missing key {}", key, other),
+                )
+                .with_key(key)
+            } else {
+                err
+            }
+        })?;
+
+        resolving.push(other.to_string());
+        let value = self.interpolate(other, value, resolving)?;
+        resolving.pop();
+
+        match value {
+            Value::String(text) => Ok(text),
+            value => Ok(value.to_string()),
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("interpolated"))]
+impl<P> ConfigProvider for InterpolatedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let value = self.inner.get_value(key)?;
+        let value = self.interpolate(key, value, &mut vec![key.to_string()])?;
+
+        serde_json::from_value(value)
+            .map_err(|err| ConfigError::deserialization("interpolated", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_many(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use serde_json::json;
+
+    #[test]
+    fn env_placeholders_resolve_at_read_time() {
+        env::set_var("OUTPOST_TEST_INTERPOLATED_HOST", "gk.example.com");
+        let provider = InterpolatedProvider::new(InMemoryProvider::new());
+        provider
+            .put(
+                "api.url",
+                "https://${OUTPOST_TEST_INTERPOLATED_HOST}/api".to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            provider.get::<String>("api.url").unwrap(),
+            "https://gk.example.com/api"
+        );
+    }
+
+    #[test]
+    fn key_placeholders_resolve_inside_structured_values() {
+        let provider = InterpolatedProvider::new(InMemoryProvider::new());
+        provider
+            .put("api.url", "https://gk.example.com/api".to_string())
+            .unwrap();
+        provider.put("api.port", 8443).unwrap();
+        provider
+            .put_value(
+                "health",
+                json!({"url": "${key:api.url}:${key:api.port}/health", "note": "$${literal}"}),
+            )
+            .unwrap();
+
+        assert_eq!(
+            provider.get_value("health").unwrap(),
+            json!({"url": "https://gk.example.com/api:8443/health", "note": "${literal}"})
+        );
+    }
+
+    #[test]
+    fn cycles_fail_the_read() {
+        let provider = InterpolatedProvider::new(InMemoryProvider::new());
+        provider.put("a", "${key:b}".to_string()).unwrap();
+        provider.put("b", "${key:a}".to_string()).unwrap();
+
+        assert!(matches!(
+            provider.get_opt::<String>("a"),
+            Err(ConfigError::Backend { .. })
+        ));
+    }
+
+    #[test]
+    fn missing_keys_and_unset_variables_fail_the_read() {
+        let provider = InterpolatedProvider::new(InMemoryProvider::new());
+        provider.put("c", "${key:missing}".to_string()).unwrap();
+        provider
+            .put("d", "${OUTPOST_TEST_INTERPOLATED_UNSET}".to_string())
+            .unwrap();
+
+        for key in ["c", "d"] {
+            assert!(matches!(
+                provider.get_opt::<String>(key),
+                Err(ConfigError::Backend { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{
+    glob, ConfigError, ConfigProvider, KeyPage, Transaction, TransactionOp,
+    TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::SystemTime;
+
+/// Characters of a key allowed by [`KeyPolicy::new`], as a glob character class.
+const DEFAULT_CHARSET: &str = "[a-z0-9._-]";
+
+/// Rules the keys of a config have to follow
+///
+/// Every key must be made of characters of the charset, at most the maximum length long and, if
+/// any patterns are given, match one of them. Keys are always rejected if they are empty or have
+/// an empty dot separated segment, like `workers.` or `routes..api`. The default charset allows
+/// lowercase ASCII letters, digits, `.`, `_` and `-`, which catches trailing whitespace and
+/// uppercase typos.
+///
+/// ```ignore
+/// let policy = KeyPolicy::new()
+///     .with_max_len(128)
+///     .with_pattern("tenants.*")
+///     .with_pattern("plugins.*");
+/// policy.check("tenants.acme.quota")?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct KeyPolicy {
+    charset: String,
+    max_len: Option<usize>,
+    patterns: Vec<String>,
+}
+
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyPolicy {
+    pub fn new() -> Self {
+        Self {
+            charset: DEFAULT_CHARSET.to_string(),
+            max_len: None,
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Allow only the characters of the given glob character class, e.g. `[a-zA-Z0-9._/]`.
+    pub fn with_charset(mut self, charset: &str) -> Self {
+        self.charset = charset.to_string();
+        self
+    }
+
+    /// Reject keys longer than the given number of characters.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Require every key to match one of the given glob patterns, e.g. `tenants.*`.
+    pub fn with_pattern(mut self, pattern: &str) -> Self {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Check the key against the rules, failing with `ConfigError::InvalidKey`.
+    pub fn check(&self, key: &str) -> Result<(), ConfigError> {
+        if key.split('.').any(str::is_empty) {
+            return Err(invalid(key, "the key has an empty segment"));
+        }
+        if let Some(c) = key
+            .chars()
+            .find(|c| !glob::matches(&self.charset, &c.to_string()))
+        {
+            return Err(invalid(key, format!("{:?} is not in {}", c, self.charset)));
+        }
+        if let Some(max) = self.max_len.filter(|max| key.chars().count() > *max) {
+            return Err(invalid(
+                key,
+                format!("the key is longer than {} characters", max),
+            ));
+        }
+        if !self.patterns.is_empty()
+            && !self
+                .patterns
+                .iter()
+                .any(|pattern| glob::matches(pattern, key))
+        {
+            return Err(invalid(
+                key,
+                format!("the key matches none of {}", self.patterns.join(", ")),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Provider rejecting writes to keys that break a [`KeyPolicy`]
+///
+/// Puts fail with `ConfigError::InvalidKey` before they reach the inner provider, so a typo
+/// doesn't create a ghost key next to the one that was meant. Reads and deletes are passed
+/// through unchecked, keys written before the policy was introduced can still be read and
+/// cleaned up.
+pub struct KeyPolicyProvider<P> {
+    inner: P,
+    policy: KeyPolicy,
+}
+
+impl<P> KeyPolicyProvider<P> {
+    pub fn new(inner: P, policy: KeyPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn policy(&self) -> &KeyPolicy {
+        &self.policy
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("key_policy"))]
+impl<P> ConfigProvider for KeyPolicyProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.policy.check(key)?;
+
+        self.inner.put(key, value)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.policy.check(key)?;
+
+        self.inner.put_if_version(key, value, expected_version)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get_many(keys)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        for (key, _) in &entries {
+            self.policy.check(key)?;
+        }
+
+        self.inner.put_many(entries)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("key_policy"))]
+impl<P> TransactionalConfigProvider for KeyPolicyProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let mut checked = Transaction::new();
+        for op in transaction.into_ops() {
+            match op {
+                TransactionOp::Put { key, value } => {
+                    self.policy.check(&key)?;
+                    checked.put(&key, value)?;
+                }
+                TransactionOp::Delete { key } => {
+                    checked.delete(&key);
+                }
+            }
+        }
+
+        self.inner.commit(checked)
+    }
+}
+
+fn invalid<M>(key: &str, message: M) -> ConfigError
+where
+    M: Into<String>,
+{
+    ConfigError::invalid_key("key_policy", key, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+
+    #[test]
+    fn keys_breaking_the_policy_are_rejected() {
+        let policy = KeyPolicy::new()
+            .with_max_len(24)
+            .with_pattern("tenants.*")
+            .with_pattern("workers");
+        let provider = KeyPolicyProvider::new(InMemoryProvider::new(), policy);
+        provider.put("tenants.acme.quota", 10).unwrap();
+        provider.put("workers", 4).unwrap();
+
+        for key in [
+            "workers ",
+            "Workers",
+            "tenants.acme..quota",
+            "tenants.",
+            "tenants.a-very-long-tenant-name",
+            "plugins.auth",
+        ] {
+            let err = provider.put(key, 1).unwrap_err();
+            assert!(matches!(err, ConfigError::InvalidKey { .. }), "{}", err);
+            assert_eq!(err.key(), Some(key));
+        }
+        assert_eq!(provider.list().unwrap().len(), 2);
+
+        // a single bad key fails the whole batch
+        let entries = vec![("workers".to_string(), 8), ("Workers".to_string(), 8)];
+        assert!(provider.put_many(entries).is_err());
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+
+        // keys written before the policy can still be removed
+        provider.inner().put("Workers", 8).unwrap();
+        provider.delete("Workers").unwrap();
+        assert!(!provider.has("Workers").unwrap());
+    }
+}
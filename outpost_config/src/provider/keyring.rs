@@ -0,0 +1,195 @@
+use crate::{no_compare_and_set, ConfigError, ConfigProvider};
+use keyring::Entry;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::Mutex;
+
+/// Account name of the entry holding the list of stored keys.
+const INDEX_ACCOUNT: &str = ".outpost-config-keys";
+
+/// Provider storing its values in the platform secret store
+///
+/// Uses the Secret Service on Linux, the Keychain on macOS and the Credential Manager on
+/// Windows. Every key is an entry of the configured service. Secret stores can't enumerate
+/// entries, so the provider maintains the list of its keys in an additional entry.
+pub struct KeyringProvider {
+    service: String,
+    // serializes updates of the key index
+    index: Mutex<()>,
+}
+
+impl KeyringProvider {
+    /// Create a provider storing its entries under the given service name, e.g. `gatekeeper`.
+    pub fn new<S>(service: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            service: service.into(),
+            index: Mutex::new(()),
+        }
+    }
+
+    fn entry(&self, account: &str) -> Result<Entry, ConfigError> {
+        Entry::new(&self.service, account).map_err(keyring_error)
+    }
+
+    fn fetch(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        match self.entry(key)?.get_password() {
+            Ok(raw) => Ok(Some(raw)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(keyring_error(err).with_key(key)),
+        }
+    }
+
+    fn keys(&self) -> Result<Vec<String>, ConfigError> {
+        match self.fetch(INDEX_ACCOUNT)? {
+            Some(raw) => serde_json::from_str(&raw)
+                .map_err(|err| ConfigError::deserialization("keyring", err)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn update_index<F>(&self, update: F) -> Result<(), ConfigError>
+    where
+        F: FnOnce(&mut Vec<String>),
+    {
+        let _guard = self.index.lock().unwrap();
+        let mut keys = self.keys()?;
+        let before = keys.clone();
+
+        update(&mut keys);
+
+        if keys != before {
+            let serialized = serde_json::to_string(&keys)
+                .map_err(|err| ConfigError::serialization("keyring", err))?;
+            self.entry(INDEX_ACCOUNT)?
+                .set_password(&serialized)
+                .map_err(keyring_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("keyring"))]
+impl ConfigProvider for KeyringProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .fetch(key)?
+            .ok_or_else(|| ConfigError::not_found("keyring", key))?;
+        let deserialized = serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("keyring", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.fetch(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        if key == INDEX_ACCOUNT {
+            return Err(ConfigError::backend(
+                "keyring",
+                format!("key {} is reserved", INDEX_ACCOUNT),
+            )
+            .with_key(key));
+        }
+
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("keyring", err).with_key(key))?;
+
+        self.entry(key)?
+            .set_password(&serialized)
+            .map_err(|err| keyring_error(err).with_key(key))?;
+
+        self.update_index(|keys| add_key(keys, key))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        match self.entry(key)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(err) => return Err(keyring_error(err).with_key(key)),
+        }
+
+        self.update_index(|keys| keys.retain(|k| k != key))
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.keys()
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        _value: T,
+        _expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(no_compare_and_set("keyring", key))
+    }
+}
+
+/// Add the key to the index unless it is listed already.
+fn add_key(keys: &mut Vec<String>, key: &str) {
+    if !keys.iter().any(|k| k == key) {
+        keys.push(key.to_string());
+    }
+}
+
+/// Map a locked or inaccessible store to ConfigError::PermissionDenied and values that aren't
+/// text to ConfigError::Deserialization.
+fn keyring_error(err: keyring::Error) -> ConfigError {
+    match err {
+        keyring::Error::NoStorageAccess(_) => ConfigError::permission_denied("keyring", err),
+        keyring::Error::BadEncoding(_) => ConfigError::deserialization("keyring", err),
+        err => ConfigError::backend("keyring", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_index_entry_is_reserved() {
+        // refused before the store is touched
+        assert!(matches!(
+            KeyringProvider::new("gatekeeper").put(INDEX_ACCOUNT, 1),
+            Err(ConfigError::Backend { key: Some(_), .. })
+        ));
+    }
+
+    #[test]
+    fn keys_are_indexed_once() {
+        let mut keys = vec!["workers".to_string()];
+        add_key(&mut keys, "listen");
+        add_key(&mut keys, "workers");
+        assert_eq!(keys, vec!["workers".to_string(), "listen".to_string()]);
+    }
+
+    #[test]
+    fn locked_stores_are_denied_permission() {
+        assert!(matches!(
+            keyring_error(keyring::Error::NoStorageAccess("locked".into())),
+            ConfigError::PermissionDenied { .. }
+        ));
+        assert!(matches!(
+            keyring_error(keyring::Error::BadEncoding(vec![0xff])),
+            ConfigError::Deserialization { .. }
+        ));
+        assert!(matches!(
+            keyring_error(keyring::Error::PlatformFailure("dbus".into())),
+            ConfigError::Backend { .. }
+        ));
+    }
+}
@@ -0,0 +1,486 @@
+use crate::http::{http_error, not_found_as_none};
+use crate::{raw_version, ConfigError, ConfigProvider};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rustls::{ClientConfig, RootCertStore};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::CertificateDer;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use ureq::{Agent, AgentBuilder, Request};
+
+const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// Provider keeping its values in a Kubernetes ConfigMap, and optionally sensitive keys in a
+/// Secret
+///
+/// Every operation talks to the API server, so edits made with `kubectl` are visible right
+/// away. Keys starting with one of the sensitive prefixes are stored in the Secret instead of
+/// the ConfigMap. Both objects are created on the first `put` if they don't exist yet.
+pub struct KubeProvider {
+    agent: Agent,
+    api_server: String,
+    credentials: Credentials,
+    namespace: String,
+    config_map: String,
+    secret: Option<SecretTarget>,
+}
+
+enum Credentials {
+    Token(String),
+    // service account tokens are rotated by the kubelet, so the file is read on every request
+    TokenFile(PathBuf),
+}
+
+struct SecretTarget {
+    name: String,
+    prefixes: Vec<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    ConfigMap,
+    Secret,
+}
+
+/// The decoded data of an object and its resource version.
+type Versioned = (BTreeMap<String, String>, Option<String>);
+
+#[derive(Deserialize)]
+struct Object {
+    #[serde(default)]
+    data: BTreeMap<String, String>,
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+    resource_version: Option<String>,
+}
+
+impl KubeProvider {
+    /// Create a provider talking to the given API server with a bearer token.
+    pub fn new<A, T, N, C>(api_server: A, token: T, namespace: N, config_map: C) -> Self
+    where
+        A: Into<String>,
+        T: Into<String>,
+        N: Into<String>,
+        C: Into<String>,
+    {
+        Self {
+            agent: Agent::new(),
+            api_server: api_server.into().trim_end_matches('/').to_string(),
+            credentials: Credentials::Token(token.into()),
+            namespace: namespace.into(),
+            config_map: config_map.into(),
+            secret: None,
+        }
+    }
+
+    /// Create a provider for a pod, using its service account and namespace.
+    pub fn in_cluster<C>(config_map: C) -> Result<Self, ConfigError>
+    where
+        C: Into<String>,
+    {
+        let service_account = Path::new(SERVICE_ACCOUNT);
+        let host =
+            env::var("KUBERNETES_SERVICE_HOST").map_err(|err| ConfigError::backend("kube", err))?;
+        let port =
+            env::var("KUBERNETES_SERVICE_PORT").map_err(|err| ConfigError::backend("kube", err))?;
+        let namespace = fs::read_to_string(service_account.join("namespace"))
+            .map_err(|err| ConfigError::io("kube", err))?;
+
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(service_account.join("ca.crt"))
+            .map_err(|err| ConfigError::backend("kube", err))?
+        {
+            let cert = cert.map_err(|err| ConfigError::backend("kube", err))?;
+            roots
+                .add(cert)
+                .map_err(|err| ConfigError::backend("kube", err))?;
+        }
+        let tls_config =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(|err| ConfigError::backend("kube", err))?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+
+        // the service host may be an ipv6 address
+        let host = if host.contains(':') {
+            format!("[{}]", host)
+        } else {
+            host
+        };
+
+        Ok(Self {
+            agent: AgentBuilder::new().tls_config(Arc::new(tls_config)).build(),
+            api_server: format!("https://{}:{}", host, port),
+            credentials: Credentials::TokenFile(service_account.join("token")),
+            namespace: namespace.trim().to_string(),
+            config_map: config_map.into(),
+            secret: None,
+        })
+    }
+
+    /// Store keys starting with one of the given prefixes in the named Secret.
+    pub fn with_secret<S>(mut self, name: S, prefixes: Vec<String>) -> Self
+    where
+        S: Into<String>,
+    {
+        self.secret = Some(SecretTarget {
+            name: name.into(),
+            prefixes,
+        });
+        self
+    }
+
+    fn kind_of(&self, key: &str) -> Kind {
+        match &self.secret {
+            Some(secret) if secret.prefixes.iter().any(|prefix| key.starts_with(prefix)) => {
+                Kind::Secret
+            }
+            _ => Kind::ConfigMap,
+        }
+    }
+
+    fn collection(&self, kind: Kind) -> String {
+        let resource = match kind {
+            Kind::ConfigMap => "configmaps",
+            Kind::Secret => "secrets",
+        };
+
+        format!(
+            "{}/api/v1/namespaces/{}/{}",
+            self.api_server, self.namespace, resource
+        )
+    }
+
+    fn name(&self, kind: Kind) -> &str {
+        match (kind, &self.secret) {
+            (Kind::Secret, Some(secret)) => &secret.name,
+            _ => &self.config_map,
+        }
+    }
+
+    fn request(&self, method: &str, url: &str) -> Result<Request, ConfigError> {
+        let token = match &self.credentials {
+            Credentials::Token(token) => token.clone(),
+            Credentials::TokenFile(path) => fs::read_to_string(path)
+                .map_err(|err| ConfigError::io("kube", err))?
+                .trim()
+                .to_string(),
+        };
+
+        Ok(self
+            .agent
+            .request(method, url)
+            .set("Authorization", &format!("Bearer {}", token)))
+    }
+
+    /// Fetch the decoded data of the given object, `None` if it doesn't exist.
+    fn fetch(&self, kind: Kind) -> Result<Option<BTreeMap<String, String>>, ConfigError> {
+        Ok(self.fetch_versioned(kind)?.map(|(data, _)| data))
+    }
+
+    /// Fetch the decoded data of the given object together with its resource version.
+    fn fetch_versioned(&self, kind: Kind) -> Result<Option<Versioned>, ConfigError> {
+        let url = format!("{}/{}", self.collection(kind), self.name(kind));
+        let response = match not_found_as_none("kube", self.request("GET", &url)?.call())? {
+            Some(response) => response,
+            None => return Ok(None),
+        };
+        let object: Object = response
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("kube", err))?;
+        let data = decode_data(kind, object.data)?;
+
+        Ok(Some((data, object.metadata.resource_version)))
+    }
+
+    /// Set (or with `None` remove) a single key of the object, creating it if necessary. With a
+    /// resource version the API server refuses the patch if the object changed since.
+    fn patch(
+        &self,
+        kind: Kind,
+        key: &str,
+        value: Option<String>,
+        resource_version: Option<&str>,
+    ) -> Result<(), ConfigError> {
+        let (field, body) = patch_body(kind, key, value, resource_version);
+        let data = body[field].clone();
+
+        let url = format!("{}/{}", self.collection(kind), self.name(kind));
+        let patched = match self
+            .request("PATCH", &url)?
+            .set("Content-Type", "application/merge-patch+json")
+            .send_json(body)
+        {
+            Ok(response) => Some(response),
+            Err(ureq::Error::Status(404, _)) => None,
+            Err(ureq::Error::Status(409, _)) => return Err(ConfigError::conflict("kube", key)),
+            Err(err) => return Err(http_error("kube", err).with_key(key)),
+        };
+
+        if patched.is_none() {
+            let kind_name = match kind {
+                Kind::ConfigMap => "ConfigMap",
+                Kind::Secret => "Secret",
+            };
+
+            self.request("POST", &self.collection(kind))?
+                .send_json(json!({
+                    "apiVersion": "v1",
+                    "kind": kind_name,
+                    "metadata": { "name": self.name(kind), "namespace": self.namespace },
+                    field: data,
+                }))
+                .map_err(|err| match err {
+                    // another writer created the object in the meantime
+                    ureq::Error::Status(409, _) => ConfigError::conflict("kube", key),
+                    err => http_error("kube", err).with_key(key),
+                })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The data of an object as plain strings, secrets encode their values as base64.
+fn decode_data(
+    kind: Kind,
+    data: BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>, ConfigError> {
+    match kind {
+        Kind::ConfigMap => Ok(data),
+        Kind::Secret => data
+            .into_iter()
+            .map(|(key, value)| {
+                let raw = STANDARD
+                    .decode(value)
+                    .map_err(|err| ConfigError::deserialization("kube", err).with_key(&key))?;
+                let raw = String::from_utf8(raw)
+                    .map_err(|err| ConfigError::deserialization("kube", err).with_key(&key))?;
+                Ok((key, raw))
+            })
+            .collect(),
+    }
+}
+
+/// The merge patch setting (or with `None` removing) the key, and the field holding the data.
+fn patch_body(
+    kind: Kind,
+    key: &str,
+    value: Option<String>,
+    resource_version: Option<&str>,
+) -> (&'static str, serde_json::Value) {
+    // secrets accept plain strings through `stringData`, removals have to go through `data`
+    let field = match (kind, &value) {
+        (Kind::Secret, Some(_)) => "stringData",
+        _ => "data",
+    };
+    let mut body = json!({ field: { key: value } });
+    if let Some(resource_version) = resource_version {
+        body["metadata"] = json!({ "resourceVersion": resource_version });
+    }
+
+    (field, body)
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("kube"))]
+impl ConfigProvider for KubeProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .fetch(self.kind_of(key))?
+            .and_then(|mut data| data.remove(key))
+            .ok_or_else(|| ConfigError::not_found("kube", key))?;
+        let deserialized = serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("kube", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self
+            .fetch(self.kind_of(key))?
+            .map(|data| data.contains_key(key))
+            .unwrap_or(false))
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("kube", err).with_key(key))?;
+
+        self.patch(self.kind_of(key), key, Some(serialized), None)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let kind = self.kind_of(key);
+
+        // don't create an empty object just to remove a key from it
+        if self.fetch(kind)?.is_none() {
+            return Ok(());
+        }
+
+        self.patch(kind, key, None, None)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let mut keys: Vec<String> = self
+            .fetch(Kind::ConfigMap)?
+            .unwrap_or_default()
+            .into_keys()
+            .collect();
+
+        if self.secret.is_some() {
+            keys.extend(self.fetch(Kind::Secret)?.unwrap_or_default().into_keys());
+        }
+
+        Ok(keys)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("kube", err).with_key(key))?;
+        let kind = self.kind_of(key);
+
+        let current = self.fetch_versioned(kind)?;
+        let version = current
+            .as_ref()
+            .and_then(|(data, _)| data.get(key))
+            .map(|raw| raw_version(raw));
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("kube", key));
+        }
+
+        // a missing object is created, which fails if another writer created it first
+        let resource_version = current.and_then(|(_, resource_version)| resource_version);
+        self.patch(kind, key, Some(serialized), resource_version.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> KubeProvider {
+        KubeProvider::new("https://kube:6443/", "token", "edge", "gatekeeper").with_secret(
+            "gatekeeper-secrets",
+            vec!["tls.".to_string(), "auth.".to_string()],
+        )
+    }
+
+    #[test]
+    fn sensitive_keys_go_to_the_secret() {
+        let provider = provider();
+        assert!(matches!(provider.kind_of("tls.key"), Kind::Secret));
+        assert!(matches!(provider.kind_of("auth.token"), Kind::Secret));
+        assert!(matches!(provider.kind_of("workers"), Kind::ConfigMap));
+        assert_eq!(provider.name(Kind::Secret), "gatekeeper-secrets");
+        assert_eq!(provider.name(Kind::ConfigMap), "gatekeeper");
+
+        // without a secret everything stays in the config map
+        let provider = KubeProvider::new("https://kube:6443", "token", "edge", "gatekeeper");
+        assert!(matches!(provider.kind_of("tls.key"), Kind::ConfigMap));
+        assert_eq!(provider.name(Kind::Secret), "gatekeeper");
+    }
+
+    #[test]
+    fn objects_live_in_the_namespace() {
+        let provider = provider();
+        assert_eq!(
+            provider.collection(Kind::ConfigMap),
+            "https://kube:6443/api/v1/namespaces/edge/configmaps"
+        );
+        assert_eq!(
+            provider.collection(Kind::Secret),
+            "https://kube:6443/api/v1/namespaces/edge/secrets"
+        );
+    }
+
+    #[test]
+    fn secret_values_are_decoded() {
+        let data = BTreeMap::from([("tls.key".to_string(), "cGVt".to_string())]);
+        assert_eq!(
+            decode_data(Kind::Secret, data.clone()).unwrap()["tls.key"],
+            "pem"
+        );
+        assert_eq!(
+            decode_data(Kind::ConfigMap, data).unwrap()["tls.key"],
+            "cGVt"
+        );
+
+        let data = BTreeMap::from([("tls.key".to_string(), "*".to_string())]);
+        assert!(matches!(
+            decode_data(Kind::Secret, data),
+            Err(ConfigError::Deserialization { key: Some(_), .. })
+        ));
+    }
+
+    #[test]
+    fn patches_set_and_remove_single_keys() {
+        let (field, body) = patch_body(Kind::Secret, "tls.key", Some("pem".to_string()), None);
+        assert_eq!(field, "stringData");
+        assert_eq!(body, json!({ "stringData": { "tls.key": "pem" } }));
+
+        // removals go through data, which takes null for secrets as well
+        let (field, body) = patch_body(Kind::Secret, "tls.key", None, None);
+        assert_eq!(field, "data");
+        assert_eq!(body, json!({ "data": { "tls.key": null } }));
+
+        let (_, body) = patch_body(
+            Kind::ConfigMap,
+            "workers",
+            Some("4".to_string()),
+            Some("42"),
+        );
+        assert_eq!(
+            body,
+            json!({ "data": { "workers": "4" }, "metadata": { "resourceVersion": "42" } })
+        );
+    }
+
+    #[test]
+    fn rotated_tokens_are_read_on_every_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+        let mut provider = provider();
+        provider.credentials = Credentials::TokenFile(path.clone());
+
+        fs::write(&path, "first\n").unwrap();
+        let request = provider.request("GET", "https://kube:6443/").unwrap();
+        assert_eq!(request.header("Authorization"), Some("Bearer first"));
+
+        fs::write(&path, "second\n").unwrap();
+        let request = provider.request("GET", "https://kube:6443/").unwrap();
+        assert_eq!(request.header("Authorization"), Some("Bearer second"));
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            provider.request("GET", "https://kube:6443/"),
+            Err(ConfigError::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{ConfigError, ConfigProvider, ErasedConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Provider resolving keys through several providers in priority order
+///
+/// Layers are consulted in the order they were added, so the first layer added has the highest
+/// priority, e.g. command line arguments, then environment variables, then the config file,
+/// then defaults. `get` returns the value of the first layer that has the key, and `list`
+/// merges the keys of all layers.
+///
+/// `put` and `delete` only touch the layer added with
+/// [`LayeredProvider::with_writable_layer`] and fail with `ConfigError::ReadOnly` if there is
+/// none. A deleted key can still be provided by the other layers.
+#[derive(Default)]
+pub struct LayeredProvider {
+    layers: Vec<Box<dyn ErasedConfigProvider + Send + Sync>>,
+    writable: Option<usize>,
+}
+
+impl LayeredProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a read only layer with a lower priority than all layers added before.
+    pub fn with_layer<P>(mut self, provider: P) -> Self
+    where
+        P: ConfigProvider + Send + Sync + 'static,
+    {
+        self.layers.push(Box::new(provider));
+        self
+    }
+
+    /// Add the layer receiving all writes, with a lower priority than all layers added before.
+    ///
+    /// Replaces the previous writable layer, which stays in place as a read only layer.
+    pub fn with_writable_layer<P>(mut self, provider: P) -> Self
+    where
+        P: ConfigProvider + Send + Sync + 'static,
+    {
+        self.writable = Some(self.layers.len());
+        self.with_layer(provider)
+    }
+
+    fn writable(&self) -> Result<&(dyn ErasedConfigProvider + Send + Sync), ConfigError> {
+        self.writable
+            .map(|index| self.layers[index].as_ref())
+            .ok_or_else(|| ConfigError::read_only("layered"))
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("layered"))]
+impl ConfigProvider for LayeredProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        for layer in &self.layers {
+            match layer.erased_get(key) {
+                Ok(value) => {
+                    return serde_json::from_value(value)
+                        .map_err(|err| ConfigError::deserialization("layered", err).with_key(key))
+                }
+                Err(ConfigError::NotFound { .. }) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(ConfigError::not_found("layered", key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        for layer in &self.layers {
+            if layer.erased_has(key)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("layered", err).with_key(key))?;
+
+        self.writable()?.erased_put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.writable()?.erased_delete(key)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        for layer in &self.layers {
+            if let Some(version) = layer.erased_version(key)? {
+                return Ok(Some(version));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Compares against the version of the visible value, which may come from a layer with a
+    /// higher priority than the writable one, and sets the value in the writable layer.
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("layered", err).with_key(key))?;
+        let index = self
+            .writable
+            .ok_or_else(|| ConfigError::read_only("layered"))?;
+        let writable = self.layers[index].as_ref();
+
+        // the writable layer compares its own version again, so a write racing this one fails
+        let stored = writable.erased_version(key)?;
+        let mut visible = None;
+        for (position, layer) in self.layers.iter().enumerate() {
+            visible = if position == index {
+                stored.clone()
+            } else {
+                layer.erased_version(key)?
+            };
+            if visible.is_some() {
+                break;
+            }
+        }
+        if visible.as_deref() != expected_version {
+            return Err(ConfigError::conflict("layered", key));
+        }
+
+        writable.erased_put_if_version(key, value, stored.as_deref())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let mut seen = HashSet::new();
+        let mut keys = Vec::new();
+
+        for layer in &self.layers {
+            for key in layer.erased_list()? {
+                if seen.insert(key.clone()) {
+                    keys.push(key);
+                }
+            }
+        }
+
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::sync::Arc;
+
+    /// Overrides above a writable file above defaults, returning the first two.
+    fn layers() -> (
+        LayeredProvider,
+        Arc<InMemoryProvider>,
+        Arc<InMemoryProvider>,
+    ) {
+        let overrides = Arc::new(InMemoryProvider::new());
+        overrides.put("workers", 8).unwrap();
+
+        let file = Arc::new(InMemoryProvider::new());
+        file.put("workers", 4).unwrap();
+        file.put("listen", "0.0.0.0:443".to_string()).unwrap();
+
+        let defaults = InMemoryProvider::new();
+        defaults
+            .put("listen", "127.0.0.1:8080".to_string())
+            .unwrap();
+        defaults.put("log", "info".to_string()).unwrap();
+
+        let provider = LayeredProvider::new()
+            .with_layer(overrides.clone())
+            .with_writable_layer(file.clone())
+            .with_layer(defaults);
+
+        (provider, overrides, file)
+    }
+
+    #[test]
+    fn resolves_by_priority() {
+        let (provider, _, _) = layers();
+
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 8);
+        assert_eq!(provider.get::<String>("listen").unwrap(), "0.0.0.0:443");
+        assert_eq!(provider.get::<String>("log").unwrap(), "info");
+        assert!(matches!(
+            provider.get::<String>("missing"),
+            Err(ConfigError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn lists_the_keys_of_all_layers_once() {
+        let (provider, _, _) = layers();
+
+        let mut keys = provider.list().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["listen", "log", "workers"]);
+    }
+
+    #[test]
+    fn writes_go_to_the_writable_layer() {
+        let (provider, _, file) = layers();
+
+        provider.put("log", "debug".to_string()).unwrap();
+        assert_eq!(file.get::<String>("log").unwrap(), "debug");
+        assert_eq!(provider.get::<String>("log").unwrap(), "debug");
+    }
+
+    #[test]
+    fn deletes_let_lower_layers_show_through() {
+        let (provider, _, _) = layers();
+
+        provider.delete("listen").unwrap();
+        assert_eq!(provider.get::<String>("listen").unwrap(), "127.0.0.1:8080");
+    }
+
+    #[test]
+    fn compares_against_the_visible_value() {
+        let (provider, overrides, file) = layers();
+
+        // from whichever layer it comes
+        let version = provider.version("workers").unwrap();
+        assert_eq!(version, overrides.version("workers").unwrap());
+        assert!(matches!(
+            provider.put_if_version("workers", 2, file.version("workers").unwrap().as_deref()),
+            Err(ConfigError::Conflict { .. })
+        ));
+        provider
+            .put_if_version("workers", 2, version.as_deref())
+            .unwrap();
+        assert_eq!(file.get::<u32>("workers").unwrap(), 2);
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 8);
+    }
+
+    #[test]
+    fn is_read_only_without_a_writable_layer() {
+        let read_only = LayeredProvider::new().with_layer(InMemoryProvider::new());
+        assert!(matches!(
+            read_only.put("log", "debug".to_string()),
+            Err(ConfigError::ReadOnly { .. })
+        ));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::file::{index_checked, lock_exclusive, lock_shared, write_atomic, write_checked};
+use crate::{value_version, ConfigError, ConfigProvider, FileAwareConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::BufWriter;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// File aware provider parsing the values of its file only when they are first read
+///
+/// Opening a file written by [`InMemoryProvider`](crate::provider::in_memory::InMemoryProvider)
+/// only indexes it: the checksum is verified and the position of every value in the text is
+/// noted, but no value is parsed. A value is parsed when it is first read and kept parsed
+/// afterwards, so startup doesn't pay for large sections like archived policies that are rarely
+/// or never read. A value that doesn't parse fails its own reads with
+/// `ConfigError::Deserialization` rather than the whole load.
+///
+/// The text of the loaded files stays in memory as long as some of its values do. Writes are
+/// kept in memory until the next `save`, which writes the same format.
+///
+/// ```ignore
+/// let config = LazyFileProvider::open("/etc/gatekeeper/gatekeeper.json")?;
+/// let workers: u32 = config.get("workers")?;
+/// ```
+#[derive(Default)]
+pub struct LazyFileProvider {
+    entries: RwLock<HashMap<String, Slot>>,
+}
+
+enum Slot {
+    /// The JSON string of the raw value within the text of a file.
+    Indexed {
+        text: Arc<str>,
+        range: Range<usize>,
+    },
+    Parsed(Value),
+}
+
+impl Slot {
+    fn parse(&mut self, key: &str) -> Result<&Value, ConfigError> {
+        if let Slot::Indexed { text, range } = self {
+            let raw: String = serde_json::from_str(&text[range.clone()])
+                .map_err(|err| ConfigError::deserialization("lazy_file", err).with_key(key))?;
+            let value = serde_json::from_str(&raw)
+                .map_err(|err| ConfigError::deserialization("lazy_file", err).with_key(key))?;
+            *self = Slot::Parsed(value);
+        }
+
+        match self {
+            Slot::Parsed(value) => Ok(value),
+            Slot::Indexed { .. } => unreachable!("the slot was just parsed"),
+        }
+    }
+
+    /// The raw value to save, as `InMemoryProvider` stores it.
+    fn raw(&self, key: &str) -> Result<String, ConfigError> {
+        match self {
+            Slot::Indexed { text, range } => serde_json::from_str(&text[range.clone()])
+                .map_err(|err| ConfigError::deserialization("lazy_file", err).with_key(key)),
+            Slot::Parsed(value) => Ok(value.to_string()),
+        }
+    }
+}
+
+impl LazyFileProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index the file, its values are parsed when they are first read.
+    pub fn open<P>(path: P) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let provider = Self::new();
+        provider.load(path)?;
+
+        Ok(provider)
+    }
+
+    /// The number of values that were parsed so far, including written ones.
+    pub fn parsed(&self) -> usize {
+        self.entries()
+            .values()
+            .filter(|slot| matches!(slot, Slot::Parsed(_)))
+            .count()
+    }
+
+    fn entries(&self) -> RwLockReadGuard<'_, HashMap<String, Slot>> {
+        self.entries.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn entries_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, Slot>> {
+        self.entries.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("lazy_file"))]
+impl ConfigProvider for LazyFileProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let parsed = match self.entries().get(key) {
+            Some(Slot::Parsed(value)) => Some(value.clone()),
+            Some(Slot::Indexed { .. }) => None,
+            None => return Err(ConfigError::not_found("lazy_file", key)),
+        };
+        let value = match parsed {
+            Some(value) => value,
+            // another reader may have parsed it in the meantime, or a writer removed it
+            None => self
+                .entries_mut()
+                .get_mut(key)
+                .ok_or_else(|| ConfigError::not_found("lazy_file", key))?
+                .parse(key)?
+                .clone(),
+        };
+
+        serde_json::from_value(value)
+            .map_err(|err| ConfigError::deserialization("lazy_file", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.entries().contains_key(key))
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("lazy_file", err).with_key(key))?;
+        self.entries_mut()
+            .insert(key.to_string(), Slot::Parsed(value));
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.entries_mut().remove(key);
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        Ok(self.entries().keys().cloned().collect())
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("lazy_file", err).with_key(key))?;
+        let mut entries = self.entries_mut();
+        let version = match entries.get_mut(key) {
+            Some(slot) => Some(value_version(slot.parse(key)?)),
+            None => None,
+        };
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("lazy_file", key));
+        }
+
+        entries.insert(key.to_string(), Slot::Parsed(value));
+
+        Ok(())
+    }
+}
+
+impl FileAwareConfigProvider for LazyFileProvider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let _lock = lock_shared("lazy_file", path)?;
+        let text: Arc<str> = fs::read_to_string(path)
+            .map_err(|err| ConfigError::io("lazy_file", err))?
+            .into();
+        let index = index_checked("lazy_file", path, &text)?;
+
+        let mut entries = self.entries_mut();
+        for (key, range) in index {
+            let text = text.clone();
+            entries.insert(key, Slot::Indexed { text, range });
+        }
+
+        Ok(())
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let _lock = lock_exclusive("lazy_file", path.as_ref())?;
+        write_atomic("lazy_file", &path, |file| {
+            let entries = self.entries();
+            let sorted: BTreeMap<_, _> = entries.iter().collect();
+            let raw = sorted
+                .into_iter()
+                .map(|(key, slot)| slot.raw(key).map(|raw| (key, raw)));
+
+            write_checked("lazy_file", raw, BufWriter::new(file))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::path::PathBuf;
+
+    fn written(dir: &Path) -> PathBuf {
+        let path = dir.join("gatekeeper.json");
+        let written = InMemoryProvider::new();
+        written.put("workers", 4).unwrap();
+        written.put("listen", ":8080".to_string()).unwrap();
+        written
+            .put("archive", vec!["policy".to_string(); 1000])
+            .unwrap();
+        written.save(&path).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn values_are_parsed_on_first_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = written(dir.path());
+
+        let provider = LazyFileProvider::open(&path).unwrap();
+        assert_eq!(provider.list().unwrap().len(), 3);
+        assert_eq!(provider.parsed(), 0);
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        assert_eq!(provider.parsed(), 1);
+    }
+
+    #[test]
+    fn missing_keys_are_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = written(dir.path());
+
+        let provider = LazyFileProvider::open(&path).unwrap();
+        assert!(matches!(
+            provider.get::<u32>("missing"),
+            Err(ConfigError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn saving_keeps_the_unparsed_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = written(dir.path());
+
+        let provider = LazyFileProvider::open(&path).unwrap();
+        provider.put("workers", 8).unwrap();
+        provider.save(&path).unwrap();
+
+        let reloaded = InMemoryProvider::new();
+        reloaded.load(&path).unwrap();
+        assert_eq!(reloaded.get::<u32>("workers").unwrap(), 8);
+        assert_eq!(reloaded.get::<Vec<String>>("archive").unwrap().len(), 1000);
+    }
+
+    #[test]
+    fn a_broken_value_only_fails_its_own_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        fs::write(&path, r#"{"workers": "4", "archive": "[\"pol"}"#).unwrap();
+
+        let provider = LazyFileProvider::open(&path).unwrap();
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        assert!(matches!(
+            provider.get::<Vec<String>>("archive"),
+            Err(ConfigError::Deserialization { .. })
+        ));
+    }
+
+    #[test]
+    fn a_changed_file_fails_the_whole_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = written(dir.path());
+
+        let tampered = fs::read_to_string(&path).unwrap().replace(":8080", ":9090");
+        fs::write(&path, tampered).unwrap();
+        assert!(matches!(
+            LazyFileProvider::open(&path),
+            Err(ConfigError::Corrupted { .. })
+        ));
+    }
+}
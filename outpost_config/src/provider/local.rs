@@ -0,0 +1,163 @@
+use crate::{ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// String key value store the values of a [`LocalProvider`] are kept in as JSON
+///
+/// Implement it for the `localStorage` of a browser, e.g. through `web_sys::Storage`, to keep the
+/// config of a web console across page loads.
+pub trait LocalStorage {
+    fn get_item(&self, key: &str) -> Result<Option<String>, ConfigError>;
+
+    fn set_item(&mut self, key: &str, value: String) -> Result<(), ConfigError>;
+
+    fn remove_item(&mut self, key: &str) -> Result<(), ConfigError>;
+
+    fn keys(&self) -> Result<Vec<String>, ConfigError>;
+}
+
+impl LocalStorage for BTreeMap<String, String> {
+    fn get_item(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        Ok(self.get(key).cloned())
+    }
+
+    fn set_item(&mut self, key: &str, value: String) -> Result<(), ConfigError> {
+        self.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn remove_item(&mut self, key: &str) -> Result<(), ConfigError> {
+        self.remove(key);
+        Ok(())
+    }
+
+    fn keys(&self) -> Result<Vec<String>, ConfigError> {
+        Ok(self.keys().cloned().collect())
+    }
+}
+
+/// Single threaded provider without locks, threads, files or clocks
+///
+/// Values live in a `RefCell` around a [`LocalStorage`], an in memory map by default, so the
+/// provider works on targets lacking all of them like `wasm32-unknown-unknown`. It isn't `Sync`,
+/// use the `InMemoryProvider` to share values between threads.
+#[derive(Default)]
+pub struct LocalProvider<S = BTreeMap<String, String>> {
+    storage: RefCell<S>,
+}
+
+impl LocalProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> LocalProvider<S> {
+    /// Keep the values in the given storage, e.g. the `localStorage` of the browser.
+    pub fn with_storage(storage: S) -> Self {
+        Self {
+            storage: RefCell::new(storage),
+        }
+    }
+
+    pub fn into_storage(self) -> S {
+        self.storage.into_inner()
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("local"))]
+impl<S> ConfigProvider for LocalProvider<S>
+where
+    S: LocalStorage,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .storage
+            .borrow()
+            .get_item(key)?
+            .ok_or_else(|| ConfigError::not_found("local", key))?;
+
+        serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("local", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.storage.borrow().get_item(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let raw = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("local", err).with_key(key))?;
+
+        self.storage.borrow_mut().set_item(key, raw)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.storage.borrow_mut().remove_item(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.storage.borrow().keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Storage shared with other apps, like the `localStorage` of an origin.
+    #[derive(Default)]
+    struct Namespaced(BTreeMap<String, String>);
+
+    impl LocalStorage for Namespaced {
+        fn get_item(&self, key: &str) -> Result<Option<String>, ConfigError> {
+            Ok(self.0.get(&format!("gk:{}", key)).cloned())
+        }
+
+        fn set_item(&mut self, key: &str, value: String) -> Result<(), ConfigError> {
+            self.0.insert(format!("gk:{}", key), value);
+            Ok(())
+        }
+
+        fn remove_item(&mut self, key: &str) -> Result<(), ConfigError> {
+            self.0.remove(&format!("gk:{}", key));
+            Ok(())
+        }
+
+        fn keys(&self) -> Result<Vec<String>, ConfigError> {
+            Ok(self
+                .0
+                .keys()
+                .filter_map(|key| key.strip_prefix("gk:").map(str::to_string))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn values_live_in_the_storage() {
+        let provider = LocalProvider::new();
+        provider.put("workers", 4).unwrap();
+        provider.put_value("tls", json!({"cert": "pem"})).unwrap();
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        assert_eq!(provider.get_path("tls", "/cert").unwrap(), json!("pem"));
+        provider.delete("workers").unwrap();
+        assert!(provider.get::<u32>("workers").unwrap_err().is_not_found());
+
+        let provider = LocalProvider::with_storage(Namespaced::default());
+        provider.put("listen", ":8080".to_string()).unwrap();
+        assert_eq!(provider.list().unwrap(), vec!["listen"]);
+        assert_eq!(
+            provider.into_storage().0.get("gk:listen").unwrap(),
+            "\":8080\""
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::format::{self, Format};
+use crate::{glob, ConfigError, ConfigProvider, KeyPage, Snapshot};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+use std::time::SystemTime;
+
+/// Replacement of a secret value in masked output.
+pub const MASK: &str = "***";
+
+/// View of another provider that hides the values of secret keys from output meant for humans
+///
+/// Keys are marked as secret with glob patterns, e.g. `tls.key`, `vault.*` or `*.password`, see
+/// [`ConfigProvider::list_glob`] for the syntax. `export` writes `***` instead of their values and
+/// [`MaskedProvider::get_masked`] and [`MaskedProvider::mask_snapshot`] do the same for a CLI,
+/// an admin API or log messages, while `get` still returns the real value.
+///
+/// Writes are passed through unchanged and `save` of a file provider keeps the real values,
+/// export the masked provider to write a redacted copy.
+pub struct MaskedProvider<P> {
+    inner: P,
+    secrets: Vec<String>,
+}
+
+impl<P> MaskedProvider<P>
+where
+    P: ConfigProvider,
+{
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            secrets: Vec::new(),
+        }
+    }
+
+    /// Mark the keys matching the glob pattern as secret.
+    pub fn with_secret<S>(mut self, pattern: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.secrets.push(pattern.into());
+        self
+    }
+
+    /// Mark all keys below the prefix as secret.
+    pub fn with_secret_prefix(self, prefix: &str) -> Self {
+        let pattern = format!("{}*", glob::escape(prefix));
+        self.with_secret(pattern)
+    }
+
+    pub fn is_secret(&self, key: &str) -> bool {
+        self.secrets
+            .iter()
+            .any(|pattern| glob::matches(pattern, key))
+    }
+
+    /// The value of the key, or `***` if the key is secret.
+    pub fn get_masked(&self, key: &str) -> Result<Value, ConfigError> {
+        let value = self.inner.get_value(key)?;
+
+        Ok(self.mask(key, value))
+    }
+
+    /// Replace the values of all secret keys of a snapshot with `***`, e.g. to print it or a
+    /// [`diff`](crate::diff) of it.
+    pub fn mask_snapshot(&self, snapshot: Snapshot) -> Snapshot {
+        Snapshot {
+            values: snapshot
+                .into_values()
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = self.mask(&key, value);
+                    (key, value)
+                })
+                .collect(),
+        }
+    }
+
+    fn mask(&self, key: &str, value: Value) -> Value {
+        if self.is_secret(key) {
+            Value::String(MASK.to_string())
+        } else {
+            value
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("masked"))]
+impl<P> ConfigProvider for MaskedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_many(entries)
+    }
+
+    fn export<W>(&self, format: Format, writer: W) -> Result<(), ConfigError>
+    where
+        W: Write,
+    {
+        let values = self.mask_snapshot(self.inner.snapshot()?).into_values();
+
+        format::write(format, &values, writer)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use serde_json::json;
+
+    #[test]
+    fn secrets_are_masked_in_output_only() {
+        let provider = MaskedProvider::new(InMemoryProvider::new())
+            .with_secret("*.password")
+            .with_secret_prefix("tls.");
+        provider.put("db.password", "hunter2".to_string()).unwrap();
+        provider.put("db.user", "gk".to_string()).unwrap();
+        provider.put("tls.key", "pem".to_string()).unwrap();
+
+        assert_eq!(provider.get::<String>("db.password").unwrap(), "hunter2");
+        assert_eq!(provider.get_masked("db.password").unwrap(), json!(MASK));
+        assert_eq!(provider.get_masked("db.user").unwrap(), json!("gk"));
+
+        let mut exported = Vec::new();
+        provider.export(Format::Json, &mut exported).unwrap();
+        let exported: Value = serde_json::from_slice(&exported).unwrap();
+        assert_eq!(
+            exported,
+            json!({"db.password": MASK, "db.user": "gk", "tls.key": MASK})
+        );
+    }
+}
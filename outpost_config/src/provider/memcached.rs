@@ -0,0 +1,354 @@
+use crate::{raw_version, ConfigError, ConfigProvider};
+use memcache::{Client, MemcacheError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Name of the entry (below the prefix) holding the list of stored keys.
+const INDEX_KEY: &str = ".outpost-config-keys";
+
+/// Attempts to update the key index before giving up on concurrent writers.
+const INDEX_RETRIES: usize = 16;
+
+/// Provider sharing its values through memcached
+///
+/// Meant for short lived config shared between workers: values expire after the configured
+/// expiration and may be evicted by memcached at any time. All keys are stored below an
+/// optional namespace prefix. Memcached can't enumerate keys, so the provider maintains the
+/// list of its keys in an additional entry, updated with compare-and-swap so concurrent
+/// workers don't lose each others keys.
+pub struct MemcachedProvider {
+    client: Client,
+    prefix: String,
+    expiration: u32,
+}
+
+impl MemcachedProvider {
+    /// Connect to the given server(s), e.g. `memcache://127.0.0.1:11211`.
+    pub fn connect(url: &str) -> Result<Self, ConfigError> {
+        let client = Client::connect(url).map_err(|err| ConfigError::backend("memcached", err))?;
+
+        Ok(Self {
+            client,
+            prefix: String::new(),
+            expiration: 0,
+        })
+    }
+
+    /// Store all keys below the given prefix.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Let memcached expire values after the given duration, by default values never expire.
+    ///
+    /// Memcached only supports expirations of up to 30 days, longer ones are capped.
+    pub fn with_expiration(mut self, expiration: Duration) -> Self {
+        self.expiration = expiration_secs(expiration);
+        self
+    }
+
+    /// Insert a key value pair that memcached expires after the given duration, overriding the
+    /// expiration of the provider. The same limits as for
+    /// [`MemcachedProvider::with_expiration`] apply.
+    pub fn put_with_ttl<T>(&self, key: &str, value: T, ttl: Duration) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.store(key, value, expiration_secs(ttl))
+    }
+
+    fn memcached_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn fetch(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.client
+            .get(&self.memcached_key(key))
+            .map_err(|err| ConfigError::backend("memcached", err).with_key(key))
+    }
+
+    fn store<T>(&self, key: &str, value: T, expiration: u32) -> Result<(), ConfigError>
+    where
+        T: Serialize,
+    {
+        check_reserved(key)?;
+
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("memcached", err).with_key(key))?;
+
+        self.client
+            .set(&self.memcached_key(key), serialized.as_str(), expiration)
+            .map_err(|err| ConfigError::backend("memcached", err).with_key(key))?;
+
+        self.update_index(|keys| add_key(keys, key))
+    }
+
+    fn update_index<F>(&self, update: F) -> Result<(), ConfigError>
+    where
+        F: Fn(&mut Vec<String>),
+    {
+        let index_key = self.memcached_key(INDEX_KEY);
+
+        for _ in 0..INDEX_RETRIES {
+            let mut current: HashMap<String, (Vec<u8>, u32, Option<u64>)> = self
+                .client
+                .gets(&[&index_key])
+                .map_err(|err| ConfigError::backend("memcached", err))?;
+
+            let (mut keys, cas) = match current.remove(&index_key) {
+                Some((raw, _, cas)) => (decode_index(raw.as_slice())?, cas),
+                None => (Vec::new(), None),
+            };
+            let before = keys.clone();
+
+            update(&mut keys);
+
+            if keys == before {
+                return Ok(());
+            }
+
+            let serialized = serde_json::to_string(&keys)
+                .map_err(|err| ConfigError::serialization("memcached", err))?;
+
+            // the index itself never expires, expired keys are dropped when listing
+            let stored = match cas {
+                Some(cas) => self.client.cas(&index_key, serialized.as_str(), 0, cas),
+                // the text protocol doesn't report whether an add lost against a concurrent
+                // one, so read the index again to see if the update made it
+                None => self
+                    .client
+                    .add(&index_key, serialized.as_str(), 0)
+                    .map(|()| false),
+            };
+
+            match stored {
+                Ok(true) => return Ok(()),
+                Ok(false) | Err(MemcacheError::CommandError(_)) => {}
+                Err(err) => return Err(ConfigError::backend("memcached", err)),
+            }
+        }
+
+        Err(ConfigError::backend(
+            "memcached",
+            "too many concurrent updates of the key index",
+        ))
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("memcached"))]
+impl ConfigProvider for MemcachedProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .fetch(key)?
+            .ok_or_else(|| ConfigError::not_found("memcached", key))?;
+        let deserialized = serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("memcached", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.fetch(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.store(key, value, self.expiration)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.client
+            .delete(&self.memcached_key(key))
+            .map_err(|err| ConfigError::backend("memcached", err).with_key(key))?;
+
+        self.update_index(|keys| keys.retain(|k| k != key))
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let keys = match self.fetch(INDEX_KEY)? {
+            Some(raw) => decode_index(raw.as_bytes())?,
+            None => return Ok(Vec::new()),
+        };
+
+        // the index may still name keys that expired or were evicted
+        let memcached_keys: Vec<String> = keys.iter().map(|k| self.memcached_key(k)).collect();
+        let lookup: Vec<&str> = memcached_keys.iter().map(String::as_str).collect();
+        let present: HashMap<String, String> = self
+            .client
+            .gets(&lookup)
+            .map_err(|err| ConfigError::backend("memcached", err))?;
+
+        Ok(keys
+            .into_iter()
+            .filter(|key| present.contains_key(&self.memcached_key(key)))
+            .collect())
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let memcached_keys: Vec<String> = keys.iter().map(|k| self.memcached_key(k)).collect();
+        let lookup: Vec<&str> = memcached_keys.iter().map(String::as_str).collect();
+        let mut found: HashMap<String, String> = self
+            .client
+            .gets(&lookup)
+            .map_err(|err| ConfigError::backend("memcached", err))?;
+
+        memcached_keys
+            .iter()
+            .map(|key| {
+                found
+                    .remove(key)
+                    .map(|raw| serde_json::from_str(&raw))
+                    .transpose()
+                    .map_err(|err| ConfigError::deserialization("memcached", err))
+            })
+            .collect()
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        check_reserved(key)?;
+
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("memcached", err).with_key(key))?;
+        let memcached_key = self.memcached_key(key);
+
+        let mut current: HashMap<String, (Vec<u8>, u32, Option<u64>)> = self
+            .client
+            .gets(&[&memcached_key])
+            .map_err(|err| ConfigError::backend("memcached", err).with_key(key))?;
+        let current = current.remove(&memcached_key);
+        let version = current
+            .as_ref()
+            .map(|(raw, _, _)| raw_version(&String::from_utf8_lossy(raw)));
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("memcached", key));
+        }
+
+        // the cas token makes memcached refuse the write if another one came first
+        let stored = match current {
+            Some((_, _, Some(cas))) => self
+                .client
+                .cas(&memcached_key, serialized.as_str(), self.expiration, cas)
+                .map_err(|err| ConfigError::backend("memcached", err).with_key(key))?,
+            Some((_, _, None)) => {
+                return Err(ConfigError::backend(
+                    "memcached",
+                    "the server sent no cas token to compare against",
+                )
+                .with_key(key))
+            }
+            // an add loses against a concurrent one, which only shows when reading it back
+            None => match self
+                .client
+                .add(&memcached_key, serialized.as_str(), self.expiration)
+            {
+                Ok(()) | Err(MemcacheError::CommandError(_)) => {
+                    self.fetch(key)?.as_deref() == Some(serialized.as_str())
+                }
+                Err(err) => return Err(ConfigError::backend("memcached", err).with_key(key)),
+            },
+        };
+        if !stored {
+            return Err(ConfigError::conflict("memcached", key));
+        }
+
+        self.update_index(|keys| add_key(keys, key))
+    }
+}
+
+/// Convert a duration to the expiration in seconds understood by memcached.
+fn expiration_secs(expiration: Duration) -> u32 {
+    // memcached reads larger values as unix timestamps, a zero expiration never expires
+    expiration.as_secs().clamp(1, 30 * 24 * 60 * 60) as u32
+}
+
+/// Refuse writes to the entry holding the key index.
+fn check_reserved(key: &str) -> Result<(), ConfigError> {
+    if key == INDEX_KEY {
+        return Err(
+            ConfigError::backend("memcached", format!("key {} is reserved", INDEX_KEY))
+                .with_key(key),
+        );
+    }
+
+    Ok(())
+}
+
+fn decode_index(raw: &[u8]) -> Result<Vec<String>, ConfigError> {
+    serde_json::from_slice(raw).map_err(|err| ConfigError::deserialization("memcached", err))
+}
+
+/// Add the key to the index unless it is listed already.
+fn add_key(keys: &mut Vec<String>, key: &str) {
+    if !keys.iter().any(|k| k == key) {
+        keys.push(key.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expirations_are_capped_at_thirty_days() {
+        assert_eq!(expiration_secs(Duration::from_secs(90)), 90);
+        assert_eq!(
+            expiration_secs(Duration::from_secs(31 * 24 * 60 * 60)),
+            2_592_000
+        );
+    }
+
+    #[test]
+    fn short_expirations_still_expire() {
+        // zero would never expire
+        assert_eq!(expiration_secs(Duration::ZERO), 1);
+        assert_eq!(expiration_secs(Duration::from_millis(500)), 1);
+    }
+
+    #[test]
+    fn the_index_entry_is_reserved() {
+        assert!(matches!(
+            check_reserved(INDEX_KEY),
+            Err(ConfigError::Backend { key: Some(_), .. })
+        ));
+        assert!(check_reserved("workers").is_ok());
+    }
+
+    #[test]
+    fn the_index_lists_every_key_once() {
+        let mut keys = decode_index(br#"["workers"]"#).unwrap();
+        add_key(&mut keys, "listen");
+        add_key(&mut keys, "workers");
+        assert_eq!(keys, vec!["workers".to_string(), "listen".to_string()]);
+
+        assert!(matches!(
+            decode_index(b"workers"),
+            Err(ConfigError::Deserialization { .. })
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::{ConfigError, ConfigProvider, KeyPage, Transaction, TransactionalConfigProvider};
+use metrics::{counter, histogram};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Instant;
+
+/// Counter of the operations, labeled with `provider`, `operation` and `outcome`.
+pub const OPERATIONS_METRIC: &str = "outpost_config_operations_total";
+
+/// Histogram of the operation latencies in seconds, labeled like [`OPERATIONS_METRIC`].
+pub const DURATION_METRIC: &str = "outpost_config_operation_duration_seconds";
+
+/// Provider recording the count and latency of all operations of another provider
+///
+/// Metrics are recorded through the [`metrics`] facade, so they end up wherever the installed
+/// recorder sends them, e.g. a Prometheus exporter. The `outcome` label is `ok` or the kind of
+/// the error: `not_found`, `read_only`, `conflict`, `quota_exceeded`, `invalid_key`, `invalid`,
+/// `serialization`, `deserialization`, `corrupted`, `io`, `permission_denied` or `backend`.
+pub struct MeteredProvider<P> {
+    inner: P,
+    name: String,
+}
+
+impl<P> MeteredProvider<P> {
+    /// Wrap the provider, labeling its metrics with the given provider name.
+    pub fn new<S>(inner: P, name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            inner,
+            name: name.into(),
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn measure<R, F>(&self, operation: &'static str, f: F) -> Result<R, ConfigError>
+    where
+        F: FnOnce() -> Result<R, ConfigError>,
+    {
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+
+        let outcome = match &result {
+            Ok(_) => "ok",
+            Err(ConfigError::NotFound { .. }) => "not_found",
+            Err(ConfigError::ReadOnly { .. }) => "read_only",
+            Err(ConfigError::Conflict { .. }) => "conflict",
+            Err(ConfigError::QuotaExceeded { .. }) => "quota_exceeded",
+            Err(ConfigError::InvalidKey { .. }) => "invalid_key",
+            Err(ConfigError::Corrupted { .. }) => "corrupted",
+            Err(ConfigError::Validation { .. }) => "invalid",
+            Err(ConfigError::Serialization { .. }) => "serialization",
+            Err(ConfigError::Deserialization { .. }) => "deserialization",
+            Err(ConfigError::Io { .. }) => "io",
+            Err(ConfigError::PermissionDenied { .. }) => "permission_denied",
+            Err(ConfigError::Backend { .. }) => "backend",
+        };
+        let labels = [
+            ("provider", self.name.clone()),
+            ("operation", operation.to_string()),
+            ("outcome", outcome.to_string()),
+        ];
+        counter!(OPERATIONS_METRIC, &labels).increment(1);
+        histogram!(DURATION_METRIC, &labels).record(elapsed.as_secs_f64());
+
+        result
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("metered"))]
+impl<P> ConfigProvider for MeteredProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.measure("get", || self.inner.get(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.measure("has", || self.inner.has(key))
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.measure("put", || self.inner.put(key, value))
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.measure("version", || self.inner.version(key))
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.measure("put_if_version", || {
+            self.inner.put_if_version(key, value, expected_version)
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.measure("delete", || self.inner.delete(key))
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.measure("list", || self.inner.list())
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.measure("list_prefix", || self.inner.list_prefix(prefix))
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.measure("list_glob", || self.inner.list_glob(pattern))
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.measure("list_page", || self.inner.list_page(cursor, limit))
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.measure("get_or_insert_with", || {
+            self.inner.get_or_insert_with(key, f)
+        })
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.measure("get_many", || self.inner.get_many(keys))
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.measure("put_many", || self.inner.put_many(entries))
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("metered"))]
+impl<P> TransactionalConfigProvider for MeteredProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.measure("commit", || self.inner.commit(transaction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    /// Run `f` against a metered provider and return the counters by their labels.
+    fn counts<F>(f: F) -> Vec<(String, u64)>
+    where
+        F: FnOnce(&MeteredProvider<InMemoryProvider>),
+    {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        metrics::with_local_recorder(&recorder, || {
+            f(&MeteredProvider::new(InMemoryProvider::new(), "memory"))
+        });
+
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .filter(|(key, ..)| key.kind() == MetricKind::Counter)
+            .map(|(key, _, _, value)| {
+                let labels: Vec<String> = key
+                    .key()
+                    .labels()
+                    .map(|label| label.value().to_string())
+                    .collect();
+                let count = match value {
+                    DebugValue::Counter(count) => count,
+                    _ => unreachable!(),
+                };
+                (labels.join(","), count)
+            })
+            .collect()
+    }
+
+    fn count(counts: &[(String, u64)], labels: &str) -> Option<u64> {
+        counts
+            .iter()
+            .find(|(l, _)| l == labels)
+            .map(|(_, count)| *count)
+    }
+
+    #[test]
+    fn records_successful_operations() {
+        let counts = counts(|provider| {
+            provider.put("workers", 4).unwrap();
+            provider.get::<u32>("workers").unwrap();
+            provider.get::<u32>("workers").unwrap();
+        });
+
+        assert_eq!(count(&counts, "memory,put,ok"), Some(1));
+        assert_eq!(count(&counts, "memory,get,ok"), Some(2));
+    }
+
+    #[test]
+    fn records_failed_operations_by_error() {
+        let counts = counts(|provider| {
+            assert!(provider.get::<u32>("threads").is_err());
+        });
+
+        assert_eq!(count(&counts, "memory,get,not_found"), Some(1));
+        assert_eq!(count(&counts, "memory,get,ok"), None);
+    }
+}
@@ -1 +1,105 @@
+pub mod aliased;
+#[cfg(feature = "fs")]
+pub mod any_file;
+pub mod args;
+#[cfg(feature = "audit")]
+pub mod audited;
+#[cfg(feature = "azure")]
+pub mod azure_key_vault;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod cached;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "compression")]
+pub mod compressed;
+pub mod concurrent;
+#[cfg(feature = "consul")]
+pub mod consul;
+#[cfg(feature = "dir")]
+pub mod dir;
+#[cfg(feature = "dotenv")]
+pub mod dotenv;
+#[cfg(feature = "dynamo")]
+pub mod dynamo;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+pub mod env;
+#[cfg(feature = "etcd")]
+pub mod etcd;
+#[cfg(feature = "gcp")]
+pub mod gcp_secret;
+#[cfg(feature = "git")]
+pub mod git;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
 pub mod in_memory;
+#[cfg(feature = "ini")]
+pub mod ini;
+#[cfg(feature = "async")]
+pub mod inline;
+pub mod interpolated;
+pub mod key_policy;
+#[cfg(feature = "keyring")]
+pub mod keyring;
+#[cfg(feature = "kube")]
+pub mod kube;
+pub mod layered;
+#[cfg(feature = "fs")]
+pub mod lazy_file;
+pub mod local;
+pub mod masked;
+#[cfg(feature = "memcache")]
+pub mod memcached;
+#[cfg(feature = "metrics")]
+pub mod metered;
+#[cfg(feature = "mongodb")]
+pub mod mongo;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod nested;
+pub mod persistent;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod quota;
+pub mod read_only;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(all(windows, feature = "registry"))]
+pub mod registry;
+#[cfg(feature = "ron")]
+pub mod ron;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod scoped;
+#[cfg(feature = "sealed")]
+pub mod sealed;
+#[cfg(feature = "secrets-manager")]
+pub mod secrets_manager;
+mod sharded;
+#[cfg(feature = "signing")]
+pub mod signed;
+#[cfg(feature = "sled")]
+pub mod sled;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "ssm")]
+pub mod ssm;
+pub mod tenant;
+#[cfg(feature = "toml")]
+pub mod toml;
+#[cfg(feature = "schema")]
+pub mod validated;
+#[cfg(feature = "vault")]
+pub mod vault;
+pub mod versioned;
+#[cfg(all(feature = "fs", feature = "threads"))]
+pub mod watched_file;
+#[cfg(feature = "yaml")]
+pub mod yaml;
+#[cfg(feature = "zookeeper")]
+pub mod zookeeper;
@@ -0,0 +1,310 @@
+use crate::{value_version, ConfigError, ConfigProvider};
+use mongodb::bson::{self, doc, Bson, Document, Regex};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::sync::{Client, Collection};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Provider storing one MongoDB document per key
+///
+/// Documents have the shape `{ _id: <prefix><key>, value: <value> }`, values are stored as
+/// native BSON so they remain queryable from the mongo shell. Listing runs an anchored regex
+/// over `_id`, which MongoDB answers from the `_id` index.
+pub struct MongoProvider {
+    collection: Collection<Document>,
+    prefix: String,
+}
+
+impl MongoProvider {
+    /// Connect to the deployment behind the given connection string and use the given
+    /// collection, e.g. `mongodb://localhost:27017`, `gatekeeper` and `config`.
+    pub fn connect(uri: &str, database: &str, collection: &str) -> Result<Self, ConfigError> {
+        let client = Client::with_uri_str(uri).map_err(|err| ConfigError::backend("mongo", err))?;
+
+        Ok(Self::with_collection(
+            client.database(database).collection(collection),
+        ))
+    }
+
+    /// Use an existing collection handle.
+    pub fn with_collection(collection: Collection<Document>) -> Self {
+        Self {
+            collection,
+            prefix: String::new(),
+        }
+    }
+
+    /// Store all keys below the given prefix.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn id(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("mongo"))]
+impl ConfigProvider for MongoProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let mut document = self
+            .collection
+            .find_one(doc! { "_id": self.id(key) })
+            .run()
+            .map_err(|err| ConfigError::backend("mongo", err).with_key(key))?
+            .ok_or_else(|| ConfigError::not_found("mongo", key))?;
+
+        let value = document
+            .remove("value")
+            .ok_or_else(|| ConfigError::not_found("mongo", key))?;
+
+        decode(key, value)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let count = self
+            .collection
+            .count_documents(doc! { "_id": self.id(key) })
+            .limit(1)
+            .run()
+            .map_err(|err| ConfigError::backend("mongo", err).with_key(key))?;
+
+        Ok(count > 0)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = encode(key, &value)?;
+        let id = self.id(key);
+
+        self.collection
+            .replace_one(doc! { "_id": &id }, doc! { "_id": &id, "value": value })
+            .upsert(true)
+            .run()
+            .map_err(|err| ConfigError::backend("mongo", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.collection
+            .delete_one(doc! { "_id": self.id(key) })
+            .run()
+            .map_err(|err| ConfigError::backend("mongo", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.list_prefix("")
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        let prefix = id_pattern(&self.prefix, prefix);
+
+        let cursor = self
+            .collection
+            .find(doc! { "_id": prefix })
+            .projection(doc! { "_id": 1 })
+            .run()
+            .map_err(|err| ConfigError::backend("mongo", err))?;
+
+        let mut keys = Vec::new();
+        for document in cursor {
+            let document = document.map_err(|err| ConfigError::backend("mongo", err))?;
+
+            // documents written by others may use non string ids
+            if let Ok(id) = document.get_str("_id") {
+                if let Some(key) = id.strip_prefix(&self.prefix) {
+                    keys.push(key.to_string());
+                }
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let ids: Vec<String> = keys.iter().map(|key| self.id(key)).collect();
+        let mut values = HashMap::new();
+
+        let cursor = self
+            .collection
+            .find(doc! { "_id": { "$in": &ids } })
+            .run()
+            .map_err(|err| ConfigError::backend("mongo", err))?;
+
+        for document in cursor {
+            let mut document = document.map_err(|err| ConfigError::backend("mongo", err))?;
+            if let (Some(Bson::String(id)), Some(value)) =
+                (document.remove("_id"), document.remove("value"))
+            {
+                values.insert(id, value);
+            }
+        }
+
+        // the same key may be requested more than once, so don't take the values out
+        ids.iter()
+            .zip(keys)
+            .map(|(id, key)| {
+                values
+                    .get(id)
+                    .map(|value| decode(key, value.clone()))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = encode(key, &value)?;
+        let id = self.id(key);
+
+        let current = self
+            .collection
+            .find_one(doc! { "_id": &id })
+            .run()
+            .map_err(|err| ConfigError::backend("mongo", err).with_key(key))?
+            .and_then(|mut document| document.remove("value"));
+        let version = current
+            .clone()
+            .map(|current| decode::<serde_json::Value>(key, current))
+            .transpose()?
+            .as_ref()
+            .map(value_version);
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("mongo", key));
+        }
+
+        // the filter only matches the document if nobody replaced the value that was compared
+        let written = match current {
+            Some(current) => self
+                .collection
+                .replace_one(
+                    doc! { "_id": &id, "value": current },
+                    doc! { "_id": &id, "value": value },
+                )
+                .run()
+                .map(|result| result.matched_count == 1),
+            None => match self
+                .collection
+                .insert_one(doc! { "_id": &id, "value": value })
+                .run()
+            {
+                Ok(_) => Ok(true),
+                Err(err) if is_duplicate_key(&err) => Ok(false),
+                Err(err) => Err(err),
+            },
+        }
+        .map_err(|err| ConfigError::backend("mongo", err).with_key(key))?;
+
+        if written {
+            Ok(())
+        } else {
+            Err(ConfigError::conflict("mongo", key))
+        }
+    }
+}
+
+fn encode<T>(key: &str, value: &T) -> Result<Bson, ConfigError>
+where
+    T: Serialize,
+{
+    bson::to_bson(value).map_err(|err| ConfigError::serialization("mongo", err).with_key(key))
+}
+
+fn decode<T>(key: &str, value: Bson) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+{
+    bson::from_bson(value).map_err(|err| ConfigError::deserialization("mongo", err).with_key(key))
+}
+
+/// The anchored pattern matching the ids of all keys below `prefix`.
+fn id_pattern(key_prefix: &str, prefix: &str) -> Regex {
+    Regex {
+        pattern: format!("^{}{}", escape_regex(key_prefix), escape_regex(prefix)),
+        options: String::new(),
+    }
+}
+
+/// Whether the write failed because a document with the same `_id` exists.
+fn is_duplicate_key(err: &mongodb::error::Error) -> bool {
+    matches!(
+        err.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(error)) if error.code == 11000
+    )
+}
+
+/// Escape the meta characters of a PCRE pattern.
+fn escape_regex(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn regex_meta_characters_are_escaped() {
+        assert_eq!(escape_regex("gatekeeper/"), "gatekeeper/");
+        assert_eq!(escape_regex("a.b*c"), r"a\.b\*c");
+        assert_eq!(
+            escape_regex(r"^(x|y)[0-9]{2}+?$\"),
+            r"\^\(x\|y\)\[0-9\]\{2\}\+\?\$\\"
+        );
+    }
+
+    #[test]
+    fn listing_is_anchored_below_the_key_prefix() {
+        let pattern = id_pattern("gatekeeper.", "routes.");
+        assert_eq!(pattern.pattern, r"^gatekeeper\.routes\.");
+        assert!(pattern.options.is_empty());
+        assert_eq!(id_pattern("", "").pattern, "^");
+    }
+
+    #[test]
+    fn values_round_trip_as_native_bson() {
+        let value = json!({ "listen": ":8080", "workers": 4, "tags": ["a", "b"] });
+        let encoded = encode("gatekeeper", &value).unwrap();
+        let document = encoded.as_document().unwrap();
+        assert_eq!(document.get_str("listen").unwrap(), ":8080");
+        assert_eq!(document.get_array("tags").unwrap().len(), 2);
+        assert_eq!(decode::<Value>("gatekeeper", encoded).unwrap(), value);
+    }
+
+    #[test]
+    fn undecodable_values_are_deserialization_errors() {
+        let err = decode::<u32>("workers", Bson::String("four".to_string())).unwrap_err();
+        assert!(matches!(err, ConfigError::Deserialization { .. }));
+        assert_eq!(err.key(), Some("workers"));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::file::{lock_exclusive, lock_shared, write_atomic};
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, ProviderStats, Transaction,
+    TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// File aware provider persisting its values as MessagePack
+///
+/// The file holds a single map from config keys to values. The binary encoding is a lot smaller
+/// and faster to parse than pretty printed JSON, which pays off for large route tables.
+#[derive(Default)]
+pub struct MsgpackProvider {
+    inner: InMemoryProvider,
+}
+
+impl MsgpackProvider {
+    pub fn new() -> Self {
+        Self {
+            inner: InMemoryProvider::new(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("msgpack"))]
+impl ConfigProvider for MsgpackProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.inner.stats()
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("msgpack"))]
+impl TransactionalConfigProvider for MsgpackProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl FileAwareConfigProvider for MsgpackProvider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_shared("msgpack", path.as_ref())?;
+        let file = File::open(path).map_err(|err| ConfigError::io("msgpack", err))?;
+
+        let values: HashMap<String, Value> = rmp_serde::from_read(BufReader::new(file))
+            .map_err(|err| ConfigError::deserialization("msgpack", err))?;
+
+        let mut write_guard = self.inner.write()?;
+
+        for (k, v) in values {
+            let serialized = serde_json::to_string(&v)
+                .map_err(|err| ConfigError::serialization("msgpack", err))?;
+            write_guard.insert(k, serialized);
+        }
+        self.inner.mark_loaded(changes);
+
+        Ok(())
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_exclusive("msgpack", path.as_ref())?;
+        write_atomic("msgpack", path, |file| {
+            let read_guard = self.inner.read()?;
+
+            let values = read_guard
+                .persistent()
+                .map(|(k, v)| Ok((k.as_str(), serde_json::from_str(v)?)))
+                .collect::<Result<BTreeMap<&str, Value>, serde_json::Error>>()
+                .map_err(|err| ConfigError::deserialization("msgpack", err))?;
+
+            rmp_serde::encode::write_named(file, &values)
+                .map_err(|err| ConfigError::serialization("msgpack", err))
+        })?;
+        self.inner.mark_saved(changes);
+
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn values_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.msgpack");
+
+        let provider = MsgpackProvider::new();
+        for i in 0..1000 {
+            provider
+                .put(&format!("routes.{}", i), format!("/api/{}", i))
+                .unwrap();
+        }
+        provider.put("workers", 4).unwrap();
+        provider
+            .put("upstream", vec!["a".to_string(), "b".to_string()])
+            .unwrap();
+        provider.save(&path).unwrap();
+
+        // no quotes or indentation, just the payload
+        assert!(fs::metadata(&path).unwrap().len() < 20_000);
+
+        let reloaded = MsgpackProvider::new();
+        reloaded.load(&path).unwrap();
+        assert_eq!(reloaded.list().unwrap().len(), 1002);
+        assert_eq!(reloaded.get::<String>("routes.42").unwrap(), "/api/42");
+        assert_eq!(reloaded.get::<u32>("workers").unwrap(), 4);
+        assert_eq!(
+            reloaded.get::<Vec<String>>("upstream").unwrap(),
+            vec!["a", "b"]
+        );
+    }
+}
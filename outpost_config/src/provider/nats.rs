@@ -0,0 +1,264 @@
+use crate::{raw_version, ConfigError, ConfigProvider};
+use async_nats::jetstream::kv::{Config, Operation, Store, UpdateError, UpdateErrorKind};
+use futures_util::{StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use tokio::runtime::{Builder, Runtime};
+
+/// A change observed through [`NatsKvProvider::watch`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum NatsKvEvent {
+    Put { key: String, value: Value },
+    Delete { key: String },
+}
+
+/// Provider storing its values in a NATS JetStream key value bucket
+///
+/// Values are written as JSON, config keys map to bucket keys as they are, so dotted keys
+/// become NATS subject tokens. Changes made by other nodes can be followed through
+/// [`NatsKvProvider::watch`]. The client runs on a small private runtime, so the provider can
+/// be used from synchronous code.
+pub struct NatsKvProvider {
+    runtime: Runtime,
+    store: Store,
+}
+
+impl NatsKvProvider {
+    /// Connect to the given NATS server(s), e.g. `nats://127.0.0.1:4222`, and open the bucket.
+    ///
+    /// The bucket is created with the server defaults if it doesn't exist yet.
+    pub fn connect(url: &str, bucket: &str) -> Result<Self, ConfigError> {
+        // one worker keeps the connection alive between the blocking calls
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(|err| ConfigError::backend("nats", err))?;
+
+        let store = runtime.block_on(async {
+            let client = async_nats::connect(url)
+                .await
+                .map_err(|err| ConfigError::backend("nats", err))?;
+            let context = async_nats::jetstream::new(client);
+
+            match context.get_key_value(bucket).await {
+                Ok(store) => Ok(store),
+                Err(_) => context
+                    .create_key_value(Config {
+                        bucket: bucket.to_string(),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|err| ConfigError::backend("nats", err)),
+            }
+        })?;
+
+        Ok(Self { runtime, store })
+    }
+
+    /// Follow all changes of the bucket.
+    ///
+    /// The current value of every key is reported first, followed by updates as they happen.
+    /// The watch runs on a background thread until the returned receiver is dropped and the next
+    /// event arrives, or until the connection to NATS is lost.
+    pub fn watch(&self) -> Result<Receiver<NatsKvEvent>, ConfigError> {
+        let mut watch = self
+            .runtime
+            .block_on(self.store.watch_all())
+            .map_err(|err| ConfigError::backend("nats", err))?;
+
+        let (sender, receiver) = mpsc::channel();
+        let handle = self.runtime.handle().clone();
+
+        thread::spawn(move || {
+            while let Some(Ok(entry)) = handle.block_on(watch.next()) {
+                let event = match event(entry.key, entry.operation, &entry.value) {
+                    Some(event) => event,
+                    None => continue,
+                };
+
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("nats"))]
+impl ConfigProvider for NatsKvProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .runtime
+            .block_on(self.store.get(key))
+            .map_err(|err| ConfigError::backend("nats", err).with_key(key))?
+            .ok_or_else(|| ConfigError::not_found("nats", key))?;
+        let deserialized = serde_json::from_slice(&raw)
+            .map_err(|err| ConfigError::deserialization("nats", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let raw = self
+            .runtime
+            .block_on(self.store.get(key))
+            .map_err(|err| ConfigError::backend("nats", err).with_key(key))?;
+
+        Ok(raw.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_vec(&value)
+            .map_err(|err| ConfigError::serialization("nats", err).with_key(key))?;
+
+        self.runtime
+            .block_on(self.store.put(key, serialized.into()))
+            .map_err(|err| ConfigError::backend("nats", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.runtime
+            .block_on(self.store.delete(key))
+            .map_err(|err| ConfigError::backend("nats", err).with_key(key))
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.runtime.block_on(async {
+            let keys = self
+                .store
+                .keys()
+                .await
+                .map_err(|err| ConfigError::backend("nats", err))?;
+
+            keys.try_collect()
+                .await
+                .map_err(|err| ConfigError::backend("nats", err))
+        })
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_vec(&value)
+            .map_err(|err| ConfigError::serialization("nats", err).with_key(key))?;
+
+        let entry = self
+            .runtime
+            .block_on(self.store.entry(key))
+            .map_err(|err| ConfigError::backend("nats", err).with_key(key))?;
+        let version = entry
+            .as_ref()
+            .and_then(|entry| stored_version(&entry.operation, &entry.value));
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("nats", key));
+        }
+
+        // the server refuses the write if the key got another revision in the meantime
+        let revision = entry.map_or(0, |entry| entry.revision);
+        match self
+            .runtime
+            .block_on(self.store.update(key, serialized.into(), revision))
+        {
+            Ok(_) => Ok(()),
+            Err(err) => Err(update_error(key, err)),
+        }
+    }
+}
+
+/// The event for a watched entry, skipping values that aren't JSON.
+fn event(key: String, operation: Operation, value: &[u8]) -> Option<NatsKvEvent> {
+    match operation {
+        Operation::Put => serde_json::from_slice(value)
+            .ok()
+            .map(|value| NatsKvEvent::Put { key, value }),
+        Operation::Delete | Operation::Purge => Some(NatsKvEvent::Delete { key }),
+    }
+}
+
+/// The version of the value of an entry.
+fn stored_version(operation: &Operation, value: &[u8]) -> Option<String> {
+    // a deleted key keeps an entry with the revision of the delete
+    match operation {
+        Operation::Put => Some(raw_version(&String::from_utf8_lossy(value))),
+        Operation::Delete | Operation::Purge => None,
+    }
+}
+
+fn update_error(key: &str, err: UpdateError) -> ConfigError {
+    // the server refuses the write if the key got another revision in the meantime
+    match err.kind() {
+        UpdateErrorKind::WrongLastRevision => ConfigError::conflict("nats", key),
+        _ => ConfigError::backend("nats", err).with_key(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn watched_puts_carry_their_json_value() {
+        assert_eq!(
+            event("workers".to_string(), Operation::Put, b"4"),
+            Some(NatsKvEvent::Put {
+                key: "workers".to_string(),
+                value: json!(4),
+            })
+        );
+        assert_eq!(event("workers".to_string(), Operation::Put, b"four"), None);
+    }
+
+    #[test]
+    fn watched_deletes_and_purges_are_deletes() {
+        for operation in [Operation::Delete, Operation::Purge].iter().cloned() {
+            assert_eq!(
+                event("workers".to_string(), operation, b""),
+                Some(NatsKvEvent::Delete {
+                    key: "workers".to_string()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn deleted_entries_have_no_version() {
+        assert_eq!(
+            stored_version(&Operation::Put, b"4"),
+            Some(raw_version("4"))
+        );
+        assert_eq!(stored_version(&Operation::Delete, b""), None);
+        assert_eq!(stored_version(&Operation::Purge, b""), None);
+    }
+
+    #[test]
+    fn wrong_revisions_are_conflicts() {
+        let err = update_error("workers", UpdateErrorKind::WrongLastRevision.into());
+        assert!(matches!(err, ConfigError::Conflict { .. }));
+        assert_eq!(err.key(), Some("workers"));
+
+        let err = update_error("workers", UpdateErrorKind::TimedOut.into());
+        assert!(matches!(err, ConfigError::Backend { .. }));
+        assert_eq!(err.key(), Some("workers"));
+    }
+}
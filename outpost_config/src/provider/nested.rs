@@ -0,0 +1,341 @@
+use crate::{value_version, ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// View of another provider that resolves dotted keys into nested objects
+///
+/// A key that doesn't exist in the inner provider is looked up in the objects stored under its
+/// ancestors, e.g. `proxy.upstream.timeout` resolves to the field `timeout` of the object
+/// `upstream` stored under `proxy`. `list` returns the dotted paths of all leaves instead of the
+/// stored keys, arrays count as leaves.
+///
+/// `put` and `delete` change the field inside the object if an ancestor of the key is stored,
+/// missing objects in between are created. Changing a field rewrites the whole stored value, which
+/// may race with concurrent writers of the same ancestor.
+pub struct NestedProvider<P> {
+    inner: P,
+}
+
+impl<P> NestedProvider<P>
+where
+    P: ConfigProvider,
+{
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+
+    /// The wrapped provider, which only knows the stored keys.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// The longest stored ancestor of a key that isn't stored itself, together with the path
+    /// from the ancestor to the key.
+    fn stored_ancestor<'a>(
+        &self,
+        key: &'a str,
+    ) -> Result<Option<(&'a str, Vec<&'a str>)>, ConfigError> {
+        for (split, _) in key.rmatch_indices('.') {
+            let ancestor = &key[..split];
+            if self.inner.has(ancestor)? {
+                return Ok(Some((ancestor, key[split + 1..].split('.').collect())));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The value of a key that isn't stored itself, resolved through its stored ancestor.
+    fn nested_value(&self, key: &str) -> Result<Option<Value>, ConfigError> {
+        let (ancestor, path) = match self.stored_ancestor(key)? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        Ok(field_at(self.inner.get_value(ancestor)?, &path))
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("nested"))]
+impl<P> ConfigProvider for NestedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        if self.inner.has(key)? {
+            return self.inner.get(key);
+        }
+
+        let value = self
+            .nested_value(key)?
+            .ok_or_else(|| ConfigError::not_found("nested", key))?;
+
+        serde_json::from_value(value)
+            .map_err(|err| ConfigError::deserialization("nested", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.inner.has(key)? || self.nested_value(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        if self.inner.has(key)? {
+            return self.inner.put(key, value);
+        }
+        let (ancestor, path) = match self.stored_ancestor(key)? {
+            Some(found) => found,
+            None => return self.inner.put(key, value),
+        };
+
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("nested", err).with_key(key))?;
+        let mut whole = self.inner.get_value(ancestor)?;
+        set_field(&mut whole, &path, value).map_err(|()| not_an_object(key, ancestor))?;
+
+        self.inner.put_value(ancestor, whole)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        if self.inner.has(key)? {
+            return self.inner.version(key);
+        }
+
+        Ok(self.nested_value(key)?.as_ref().map(value_version))
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        if self.inner.has(key)? {
+            return self.inner.put_if_version(key, value, expected_version);
+        }
+        let (ancestor, path) = match self.stored_ancestor(key)? {
+            Some(found) => found,
+            None => return self.inner.put_if_version(key, value, expected_version),
+        };
+
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("nested", err).with_key(key))?;
+        // a change of the ancestor after its version was read makes the inner provider refuse
+        // the write, so the field can be compared on the value read afterwards
+        let stored_version = self.inner.version(ancestor)?;
+        let mut whole = self.inner.get_value(ancestor)?;
+        let current = field_at(whole.clone(), &path);
+        if current.as_ref().map(value_version).as_deref() != expected_version {
+            return Err(ConfigError::conflict("nested", key));
+        }
+        set_field(&mut whole, &path, value).map_err(|()| not_an_object(key, ancestor))?;
+
+        self.inner
+            .put_if_version(ancestor, whole, stored_version.as_deref())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        if self.inner.has(key)? {
+            return self.inner.delete(key);
+        }
+        let (ancestor, path) = match self.stored_ancestor(key)? {
+            Some(found) => found,
+            None => return Ok(()),
+        };
+
+        let mut whole = self.inner.get_value(ancestor)?;
+        let (field, parents) = path.split_last().expect("a key below its ancestor");
+        let mut object = Some(&mut whole);
+        for parent in parents {
+            object = object.and_then(|value| value.get_mut(*parent));
+        }
+        let removed = match object {
+            Some(Value::Object(fields)) => fields.remove(*field).is_some(),
+            _ => false,
+        };
+
+        if removed {
+            self.inner.put_value(ancestor, whole)?;
+        }
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let mut leaves = Vec::new();
+        for (key, value) in self.inner.entries::<Value>("")? {
+            collect_leaves(key, &value, &mut leaves);
+        }
+
+        Ok(leaves)
+    }
+}
+
+/// The field the path points to inside of the value, `None` if it doesn't exist.
+fn field_at(mut value: Value, path: &[&str]) -> Option<Value> {
+    for field in path {
+        value = match value {
+            Value::Object(mut object) => object.remove(*field)?,
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+/// Set the field the path points to inside of the value, creating missing objects in between.
+/// Fails if the path runs into something else than an object.
+fn set_field(whole: &mut Value, path: &[&str], value: Value) -> Result<(), ()> {
+    let (field, parents) = path.split_last().expect("a key below its ancestor");
+    let mut object = whole;
+    for parent in parents {
+        object = match object {
+            Value::Object(fields) => fields
+                .entry(parent.to_string())
+                .or_insert_with(|| Value::Object(Map::new())),
+            _ => break,
+        };
+    }
+
+    match object {
+        Value::Object(fields) => {
+            fields.insert(field.to_string(), value);
+            Ok(())
+        }
+        _ => Err(()),
+    }
+}
+
+fn not_an_object(key: &str, ancestor: &str) -> ConfigError {
+    ConfigError::backend(
+        "nested",
+        format!(
+            "{} is stored below {}, which isn't an object",
+            key, ancestor
+        ),
+    )
+    .with_key(key)
+}
+
+/// Add the dotted paths of all leaves of the value stored at the key.
+fn collect_leaves(key: String, value: &Value, leaves: &mut Vec<String>) {
+    match value {
+        Value::Object(fields) if !fields.is_empty() => {
+            for (field, value) in fields {
+                collect_leaves(format!("{}.{}", key, field), value, leaves);
+            }
+        }
+        _ => leaves.push(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use serde_json::json;
+
+    fn proxy() -> NestedProvider<InMemoryProvider> {
+        let provider = NestedProvider::new(InMemoryProvider::new());
+        provider
+            .put_value(
+                "proxy",
+                json!({"upstream": {"timeout": 30, "hosts": ["a", "b"]}, "listen": ":443"}),
+            )
+            .unwrap();
+        provider.put("workers", 4).unwrap();
+        provider
+    }
+
+    #[test]
+    fn dotted_keys_reach_into_stored_objects() {
+        let provider = proxy();
+
+        assert_eq!(provider.get::<u32>("proxy.upstream.timeout").unwrap(), 30);
+        assert!(provider.has("proxy.upstream").unwrap());
+        assert!(provider
+            .get::<u32>("proxy.upstream.retries")
+            .unwrap_err()
+            .is_not_found());
+    }
+
+    #[test]
+    fn listing_yields_the_leaves() {
+        let provider = proxy();
+
+        let mut leaves = provider.list_prefix("proxy.").unwrap();
+        leaves.sort();
+        assert_eq!(
+            leaves,
+            vec![
+                "proxy.listen",
+                "proxy.upstream.hosts",
+                "proxy.upstream.timeout"
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_update_the_stored_object() {
+        let provider = proxy();
+
+        provider.put("proxy.upstream.timeout", 10).unwrap();
+        provider.put("proxy.tls.cert", "pem".to_string()).unwrap();
+        provider.delete("proxy.listen").unwrap();
+        assert_eq!(
+            provider.inner().get_value("proxy").unwrap(),
+            json!({"upstream": {"timeout": 10, "hosts": ["a", "b"]}, "tls": {"cert": "pem"}})
+        );
+        assert_eq!(provider.inner().list().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn scalars_have_no_fields_to_write() {
+        let provider = proxy();
+
+        assert!(provider.put("workers.max", 8).is_err());
+        assert_eq!(provider.inner().get::<u32>("workers").unwrap(), 4);
+    }
+
+    #[test]
+    fn put_if_version_compares_the_field() {
+        let provider = NestedProvider::new(InMemoryProvider::new());
+        provider
+            .put_value(
+                "proxy",
+                json!({"upstream": {"timeout": 30}, "listen": ":443"}),
+            )
+            .unwrap();
+
+        let version = provider.version("proxy.upstream.timeout").unwrap();
+        assert_eq!(version, Some(value_version(&json!(30))));
+        provider
+            .put_if_version("proxy.upstream.timeout", 10, version.as_deref())
+            .unwrap();
+        assert!(matches!(
+            provider.put_if_version("proxy.upstream.timeout", 20, version.as_deref()),
+            Err(ConfigError::Conflict { .. })
+        ));
+
+        // a field is created only if it doesn't exist yet
+        provider
+            .put_if_version("proxy.upstream.retries", 3, None)
+            .unwrap();
+        assert!(provider
+            .put_if_version("proxy.upstream.retries", 3, None)
+            .is_err());
+        assert_eq!(
+            provider.inner().get_value("proxy").unwrap(),
+            json!({"upstream": {"timeout": 10, "retries": 3}, "listen": ":443"})
+        );
+    }
+}
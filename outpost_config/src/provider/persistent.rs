@@ -0,0 +1,485 @@
+use crate::provider::in_memory::InMemoryProvider;
+#[cfg(feature = "fs")]
+use crate::FileAwareConfigProvider;
+use crate::{
+    ChangeEvent, ConfigError, ConfigProvider, KeyPage, ProviderStats, Transaction, TransactionOp,
+    TransactionalConfigProvider, WatchableConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::mem;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+#[cfg(feature = "threads")]
+use std::sync::Weak;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "threads")]
+use std::thread;
+#[cfg(feature = "threads")]
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// When a [`PersistentProvider`] hands its writes to the backend
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PersistencePolicy {
+    /// Persist every write before it returns, a failure is returned by the write.
+    WriteThrough,
+    /// Persist the written keys in batches from a background thread at the given interval and
+    /// once more when the provider is dropped.
+    #[cfg(feature = "threads")]
+    WriteBack(Duration),
+    /// Persist only on [`PersistentProvider::flush`].
+    Manual,
+}
+
+/// Provider keeping its values in memory and persisting the writes to a backend
+///
+/// The values of the backend are copied into memory when the provider is created, reads never
+/// reach the backend afterwards. Written and deleted keys are remembered until they were
+/// persisted, when that happens is chosen by the [`PersistencePolicy`]. Keys that fail to
+/// persist stay pending and are retried by the next flush, so a backend that is down for a while
+/// loses nothing as long as the provider lives.
+///
+/// A remote backend gets the current values of the pending keys put and the deleted ones
+/// deleted. A file aware backend opened with [`PersistentProvider::open`] is saved to its file
+/// after that as well.
+///
+/// ```ignore
+/// let provider = PersistentProvider::open(
+///     TomlProvider::new(),
+///     "/etc/gatekeeper/config.toml",
+///     PersistencePolicy::WriteBack(Duration::from_secs(5)),
+/// )?;
+/// ```
+pub struct PersistentProvider<P>
+where
+    P: ConfigProvider,
+{
+    shared: Arc<Shared<P>>,
+    policy: PersistencePolicy,
+}
+
+struct Shared<P> {
+    store: InMemoryProvider,
+    backend: P,
+    #[cfg(feature = "fs")]
+    file: Option<(PathBuf, Save<P>)>,
+    pending: Mutex<BTreeSet<String>>,
+    /// Held while flushing, so the backend gets the values in the order they were written.
+    last_flush: Mutex<Option<SystemTime>>,
+}
+
+/// Saves a file aware backend to the file.
+#[cfg(feature = "fs")]
+type Save<P> = fn(&P, &Path) -> Result<(), ConfigError>;
+
+impl<P> PersistentProvider<P>
+where
+    P: ConfigProvider + Send + Sync + 'static,
+{
+    /// Copy the values of the backend into memory, writes are persisted by putting them to it.
+    pub fn new(backend: P, policy: PersistencePolicy) -> Result<Self, ConfigError> {
+        Self::start(
+            Shared {
+                store: InMemoryProvider::new(),
+                backend,
+                #[cfg(feature = "fs")]
+                file: None,
+                pending: Mutex::new(BTreeSet::new()),
+                last_flush: Mutex::new(None),
+            },
+            policy,
+        )
+    }
+
+    fn start(shared: Shared<P>, policy: PersistencePolicy) -> Result<Self, ConfigError> {
+        shared
+            .store
+            .put_many(shared.backend.entries::<Value>("")?)?;
+        let shared = Arc::new(shared);
+
+        #[cfg(feature = "threads")]
+        if let PersistencePolicy::WriteBack(interval) = policy {
+            let weak = Arc::downgrade(&shared);
+            thread::spawn(move || write_back(weak, interval));
+        }
+
+        Ok(Self { shared, policy })
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<P> PersistentProvider<P>
+where
+    P: FileAwareConfigProvider + Send + Sync + 'static,
+{
+    /// Load the file into the backend unless it doesn't exist yet and copy its values into
+    /// memory, writes are persisted by saving the backend to the file.
+    pub fn open<S>(backend: P, path: S, policy: PersistencePolicy) -> Result<Self, ConfigError>
+    where
+        S: Into<PathBuf>,
+    {
+        let path = path.into();
+        if path.exists() {
+            backend.load(&path)?;
+        }
+        let save: Save<P> = |backend, path| backend.save(path);
+
+        Self::start(
+            Shared {
+                store: InMemoryProvider::new(),
+                backend,
+                file: Some((path, save)),
+                pending: Mutex::new(BTreeSet::new()),
+                last_flush: Mutex::new(None),
+            },
+            policy,
+        )
+    }
+}
+
+impl<P> PersistentProvider<P>
+where
+    P: ConfigProvider,
+{
+    /// Persist all pending writes now.
+    pub fn flush(&self) -> Result<(), ConfigError> {
+        self.shared.flush()
+    }
+
+    /// The number of written or deleted keys that weren't persisted yet.
+    pub fn pending(&self) -> usize {
+        self.shared.lock_pending().len()
+    }
+
+    pub fn policy(&self) -> PersistencePolicy {
+        self.policy
+    }
+
+    /// The backend the writes are persisted to.
+    pub fn inner(&self) -> &P {
+        &self.shared.backend
+    }
+
+    /// Remember the keys as pending and persist them if the policy says so.
+    fn written<'k, I>(&self, keys: I) -> Result<(), ConfigError>
+    where
+        I: IntoIterator<Item = &'k str>,
+    {
+        self.shared
+            .lock_pending()
+            .extend(keys.into_iter().map(str::to_string));
+
+        match self.policy {
+            PersistencePolicy::WriteThrough => self.shared.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<P> Shared<P>
+where
+    P: ConfigProvider,
+{
+    fn lock_pending(&self) -> MutexGuard<'_, BTreeSet<String>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn flush(&self) -> Result<(), ConfigError> {
+        let mut last_flush = self
+            .last_flush
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let keys = mem::take(&mut *self.lock_pending());
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let result = self.persist(&keys);
+        match result {
+            Ok(()) => *last_flush = Some(SystemTime::now()),
+            // keys written meanwhile are pending already, the current values are persisted
+            Err(_) => self.lock_pending().extend(keys),
+        }
+
+        result
+    }
+
+    /// Hand the current values of the keys to the backend.
+    fn persist(&self, keys: &BTreeSet<String>) -> Result<(), ConfigError> {
+        let refs: Vec<&str> = keys.iter().map(String::as_str).collect();
+        let values = self.store.get_many::<Value>(&refs)?;
+
+        let mut puts = Vec::new();
+        for (key, value) in keys.iter().zip(values) {
+            match value {
+                Some(value) => puts.push((key.clone(), value)),
+                None => match self.backend.delete(key) {
+                    Err(err) if !err.is_not_found() => return Err(err),
+                    _ => {}
+                },
+            }
+        }
+        if !puts.is_empty() {
+            self.backend.put_many(puts)?;
+        }
+
+        #[cfg(feature = "fs")]
+        if let Some((path, save)) = &self.file {
+            save(&self.backend, path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Flush the pending writes at the interval until the provider is dropped.
+#[cfg(feature = "threads")]
+fn write_back<P>(shared: Weak<Shared<P>>, interval: Duration)
+where
+    P: ConfigProvider,
+{
+    loop {
+        thread::sleep(interval);
+        match shared.upgrade() {
+            // failures stay pending and are retried with the next flush
+            Some(shared) => {
+                let _ = shared.flush();
+            }
+            None => return,
+        }
+    }
+}
+
+impl<P> Drop for PersistentProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn drop(&mut self) {
+        #[cfg(feature = "threads")]
+        if let PersistencePolicy::WriteBack(_) = self.policy {
+            // nobody is left to report a failure to
+            let _ = self.shared.flush();
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("persistent"))]
+impl<P> ConfigProvider for PersistentProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.shared.store.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.shared.store.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.shared.store.put(key, value)?;
+
+        self.written([key])
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.shared.store.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.shared
+            .store
+            .put_if_version(key, value, expected_version)?;
+
+        self.written([key])
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.shared.store.delete(key)?;
+
+        self.written([key])
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.shared.store.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        Ok(ProviderStats {
+            last_save: *self
+                .shared
+                .last_flush
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            ..self.shared.store.stats()?
+        })
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.shared.store.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.shared.store.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.shared.store.list_page(cursor, limit)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.shared.store.get_many(keys)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let keys: Vec<String> = entries.iter().map(|(key, _)| key.clone()).collect();
+        self.shared.store.put_many(entries)?;
+
+        self.written(keys.iter().map(String::as_str))
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("persistent"))]
+impl<P> TransactionalConfigProvider for PersistentProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let keys: Vec<String> = transaction
+            .ops()
+            .iter()
+            .map(|op| match op {
+                TransactionOp::Put { key, .. } | TransactionOp::Delete { key } => key.clone(),
+            })
+            .collect();
+        self.shared.store.commit(transaction)?;
+
+        self.written(keys.iter().map(String::as_str))
+    }
+}
+
+impl<P> WatchableConfigProvider for PersistentProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn watch(&self, key_prefix: &str) -> Result<Receiver<ChangeEvent>, ConfigError> {
+        self.shared.store.watch(key_prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend() -> Arc<InMemoryProvider> {
+        let backend = Arc::new(InMemoryProvider::new());
+        backend.put("workers", 4).unwrap();
+        backend
+    }
+
+    #[test]
+    fn manual_writes_wait_for_the_flush() {
+        let backend = backend();
+
+        let manual = PersistentProvider::new(backend.clone(), PersistencePolicy::Manual).unwrap();
+        assert_eq!(manual.get::<u32>("workers").unwrap(), 4);
+        manual.put("workers", 8).unwrap();
+        manual.put("listen", ":8080".to_string()).unwrap();
+        assert_eq!(
+            (manual.pending(), backend.get::<u32>("workers").unwrap()),
+            (2, 4)
+        );
+        manual.flush().unwrap();
+        assert_eq!(
+            (manual.pending(), backend.get::<u32>("workers").unwrap()),
+            (0, 8)
+        );
+        assert!(manual.stats().unwrap().last_save.is_some());
+    }
+
+    #[test]
+    fn writes_through_reach_the_backend_at_once() {
+        let backend = backend();
+        backend.put("listen", ":8080".to_string()).unwrap();
+
+        let through =
+            PersistentProvider::new(backend.clone(), PersistencePolicy::WriteThrough).unwrap();
+        through.delete("listen").unwrap();
+        assert!(!backend.has("listen").unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "threads")]
+    fn writes_back_reach_the_backend_in_the_background() {
+        let backend = backend();
+
+        let back = PersistentProvider::new(
+            backend.clone(),
+            PersistencePolicy::WriteBack(Duration::from_millis(10)),
+        )
+        .unwrap();
+        back.put("workers", 16).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(backend.get::<u32>("workers").unwrap(), 16);
+    }
+
+    #[test]
+    #[cfg(feature = "threads")]
+    fn dropping_persists_the_pending_writes_back() {
+        let backend = backend();
+
+        // with an interval the thread doesn't get to
+        let back = PersistentProvider::new(
+            backend.clone(),
+            PersistencePolicy::WriteBack(Duration::from_secs(60)),
+        )
+        .unwrap();
+        back.put("workers", 32).unwrap();
+        drop(back);
+        assert_eq!(backend.get::<u32>("workers").unwrap(), 32);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn file_aware_backends_are_saved_to_their_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+
+        let provider = PersistentProvider::open(
+            InMemoryProvider::new(),
+            &path,
+            PersistencePolicy::WriteThrough,
+        )
+        .unwrap();
+        provider.put("workers", 4).unwrap();
+
+        let reopened =
+            PersistentProvider::open(InMemoryProvider::new(), &path, PersistencePolicy::Manual)
+                .unwrap();
+        assert_eq!(reopened.get::<u32>("workers").unwrap(), 4);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{raw_version, ConfigError, ConfigProvider};
+use postgres::{Client, NoTls};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::sync::Mutex;
+
+/// Schema changes applied in order, the position in this list is the schema version.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE outpost_config (key TEXT PRIMARY KEY NOT NULL, value TEXT NOT NULL)",
+    // the primary key index only serves prefix lookups under the C collation
+    "CREATE INDEX outpost_config_key_prefix ON outpost_config (key text_pattern_ops)",
+];
+
+/// Provider persisting its values in a table of a PostgreSQL database
+///
+/// The provider creates and migrates its own schema when connecting. The version is tracked
+/// in `outpost_config_schema`, so several nodes sharing a database migrate it exactly once.
+pub struct PostgresProvider {
+    client: Mutex<Client>,
+}
+
+impl PostgresProvider {
+    /// Connect to the database behind the given connection string, e.g.
+    /// `host=localhost user=gatekeeper dbname=gatekeeper`.
+    pub fn connect(params: &str) -> Result<Self, ConfigError> {
+        let client =
+            Client::connect(params, NoTls).map_err(|err| ConfigError::backend("postgres", err))?;
+
+        Self::with_client(client)
+    }
+
+    /// Create a provider on top of an already established connection.
+    pub fn with_client(mut client: Client) -> Result<Self, ConfigError> {
+        migrate(&mut client)?;
+
+        Ok(Self {
+            client: Mutex::new(client),
+        })
+    }
+
+    fn fetch(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        let mut client = self.client.lock().unwrap();
+
+        let row = client
+            .query_opt("SELECT value FROM outpost_config WHERE key = $1", &[&key])
+            .map_err(|err| ConfigError::backend("postgres", err).with_key(key))?;
+
+        Ok(row.map(|row| row.get(0)))
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("postgres"))]
+impl ConfigProvider for PostgresProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .fetch(key)?
+            .ok_or_else(|| ConfigError::not_found("postgres", key))?;
+        let deserialized = serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("postgres", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.fetch(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("postgres", err).with_key(key))?;
+        let mut client = self.client.lock().unwrap();
+
+        client
+            .execute(
+                "INSERT INTO outpost_config (key, value) VALUES ($1, $2) \
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                &[&key, &serialized],
+            )
+            .map_err(|err| ConfigError::backend("postgres", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let mut client = self.client.lock().unwrap();
+
+        client
+            .execute("DELETE FROM outpost_config WHERE key = $1", &[&key])
+            .map_err(|err| ConfigError::backend("postgres", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let mut client = self.client.lock().unwrap();
+
+        // ordering by key lets postgres answer from the primary key index alone
+        let rows = client
+            .query("SELECT key FROM outpost_config ORDER BY key", &[])
+            .map_err(|err| ConfigError::backend("postgres", err))?;
+
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("postgres", err).with_key(key))?;
+        let mut client = self.client.lock().unwrap();
+        let mut transaction = client
+            .transaction()
+            .map_err(|err| ConfigError::backend("postgres", err).with_key(key))?;
+
+        let written = match expected_version {
+            // the insert does nothing if another writer created the key in the meantime
+            None => transaction.execute(
+                "INSERT INTO outpost_config (key, value) VALUES ($1, $2) \
+                 ON CONFLICT (key) DO NOTHING",
+                &[&key, &serialized],
+            ),
+            // the row stays locked against other writers until the transaction ends
+            Some(expected) => transaction
+                .query_opt(
+                    "SELECT value FROM outpost_config WHERE key = $1 FOR UPDATE",
+                    &[&key],
+                )
+                .and_then(|row| match row {
+                    Some(row) if raw_version(row.get(0)) == expected => transaction.execute(
+                        "UPDATE outpost_config SET value = $2 WHERE key = $1",
+                        &[&key, &serialized],
+                    ),
+                    _ => Ok(0),
+                }),
+        }
+        .map_err(|err| ConfigError::backend("postgres", err).with_key(key))?;
+
+        transaction
+            .commit()
+            .map_err(|err| ConfigError::backend("postgres", err).with_key(key))?;
+
+        if written == 1 {
+            Ok(())
+        } else {
+            Err(ConfigError::conflict("postgres", key))
+        }
+    }
+}
+
+/// Bring the schema up to the latest version.
+fn migrate(client: &mut Client) -> Result<(), ConfigError> {
+    let mut transaction = client
+        .transaction()
+        .map_err(|err| ConfigError::backend("postgres", err))?;
+
+    transaction
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS outpost_config_schema (version INTEGER NOT NULL); \
+             LOCK TABLE outpost_config_schema IN EXCLUSIVE MODE",
+        )
+        .map_err(|err| ConfigError::backend("postgres", err))?;
+
+    let version: i32 = transaction
+        .query_opt("SELECT version FROM outpost_config_schema", &[])
+        .map_err(|err| ConfigError::backend("postgres", err))?
+        .map(|row| row.get(0))
+        .unwrap_or(0);
+
+    let pending = pending_migrations(version)?;
+    for migration in pending {
+        transaction
+            .batch_execute(migration)
+            .map_err(|err| ConfigError::backend("postgres", err))?;
+    }
+
+    // a schema migrated by a newer release keeps its version
+    if !pending.is_empty() {
+        let latest = MIGRATIONS.len() as i32;
+        if version == 0 {
+            transaction.execute(
+                "INSERT INTO outpost_config_schema (version) VALUES ($1)",
+                &[&latest],
+            )
+        } else {
+            transaction.execute("UPDATE outpost_config_schema SET version = $1", &[&latest])
+        }
+        .map_err(|err| ConfigError::backend("postgres", err))?;
+    }
+
+    transaction
+        .commit()
+        .map_err(|err| ConfigError::backend("postgres", err))
+}
+
+/// The migrations a schema at the given version still lacks.
+fn pending_migrations(version: i32) -> Result<&'static [&'static str], ConfigError> {
+    let applied = usize::try_from(version).map_err(|_| {
+        ConfigError::backend("postgres", format!("invalid schema version {}", version))
+    })?;
+
+    Ok(MIGRATIONS.get(applied..).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applied_migrations_are_skipped() {
+        assert_eq!(pending_migrations(0).unwrap(), MIGRATIONS);
+        assert_eq!(pending_migrations(1).unwrap(), &MIGRATIONS[1..]);
+        assert!(pending_migrations(MIGRATIONS.len() as i32)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn schemas_of_newer_releases_are_left_alone() {
+        assert!(pending_migrations(MIGRATIONS.len() as i32 + 1)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn negative_schema_versions_are_refused() {
+        assert!(matches!(
+            pending_migrations(-1),
+            Err(ConfigError::Backend { .. })
+        ));
+    }
+
+    #[test]
+    fn the_first_migration_creates_the_table() {
+        assert!(MIGRATIONS[0].starts_with("CREATE TABLE outpost_config "));
+    }
+}
@@ -0,0 +1,351 @@
+use crate::{
+    ConfigError, ConfigProvider, KeyPage, Transaction, TransactionOp, TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+/// Provider limiting the size and number of the values written to another provider
+///
+/// Every write is checked against the limits before it reaches the inner provider and fails
+/// with `ConfigError::QuotaExceeded` if the value is too large or the config would hold too many
+/// keys or bytes afterwards, so a misbehaving plugin or tenant can't grow the config without
+/// bound. Sizes are measured as the length of the JSON of a value.
+///
+/// The usage is counted once when the provider is created and then kept up to date by the
+/// writes through it, writes made directly to the inner provider aren't counted. Writes through
+/// the provider are serialized, the limits hold even if many threads write at once.
+pub struct QuotaProvider<P> {
+    inner: P,
+    max_value_size: Option<usize>,
+    max_keys: Option<usize>,
+    max_total_size: Option<usize>,
+    usage: Mutex<Usage>,
+}
+
+/// The size of every stored value and their sum.
+#[derive(Default)]
+struct Usage {
+    sizes: HashMap<String, usize>,
+    total: usize,
+}
+
+impl<P> QuotaProvider<P>
+where
+    P: ConfigProvider,
+{
+    /// Wrap the provider without any limits yet, reading all values to count their sizes.
+    pub fn new(inner: P) -> Result<Self, ConfigError> {
+        let mut usage = Usage::default();
+        for (key, value) in inner.entries::<Value>("")? {
+            usage.set(&key, Some(json_size(&key, &value)?));
+        }
+
+        Ok(Self {
+            inner,
+            max_value_size: None,
+            max_keys: None,
+            max_total_size: None,
+            usage: Mutex::new(usage),
+        })
+    }
+}
+
+impl<P> QuotaProvider<P> {
+    /// Reject values whose JSON is larger than the given number of bytes.
+    pub fn with_max_value_size(mut self, bytes: usize) -> Self {
+        self.max_value_size = Some(bytes);
+        self
+    }
+
+    /// Reject new keys once the config holds the given number of keys.
+    pub fn with_max_keys(mut self, keys: usize) -> Self {
+        self.max_keys = Some(keys);
+        self
+    }
+
+    /// Reject writes that would grow the JSON of all values beyond the given number of bytes.
+    pub fn with_max_total_size(mut self, bytes: usize) -> Self {
+        self.max_total_size = Some(bytes);
+        self
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// The number of keys and the total size of their values as counted by the provider.
+    pub fn usage(&self) -> (usize, usize) {
+        let usage = self.lock_usage();
+        (usage.sizes.len(), usage.total)
+    }
+
+    fn lock_usage(&self) -> MutexGuard<'_, Usage> {
+        // the counts are only changed after a successful write, a panic can't leave them halfway
+        self.usage.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Check the writes in order against the limits, `None` deletes a key.
+    fn admit(&self, usage: &Usage, writes: &[(String, Option<usize>)]) -> Result<(), ConfigError> {
+        let mut written: HashMap<&str, Option<usize>> = HashMap::new();
+        let mut keys = usage.sizes.len();
+        let mut total = usage.total;
+        for (key, size) in writes {
+            let previous = match written.insert(key, *size) {
+                Some(previous) => previous,
+                None => usage.sizes.get(key).copied(),
+            };
+            keys = keys + usize::from(size.is_some()) - usize::from(previous.is_some());
+            total = total + size.unwrap_or(0) - previous.unwrap_or(0);
+            let size = match size {
+                Some(size) => *size,
+                None => continue,
+            };
+
+            if let Some(max) = self.max_value_size.filter(|max| size > *max) {
+                return Err(exceeded(
+                    key,
+                    format!("the value has {} bytes, at most {} are allowed", size, max),
+                ));
+            }
+            // writes that don't add a key or bytes are allowed even above the limits
+            if let Some(max) = self.max_keys.filter(|max| keys > *max) {
+                if previous.is_none() {
+                    return Err(exceeded(key, format!("at most {} keys are allowed", max)));
+                }
+            }
+            if let Some(max) = self.max_total_size.filter(|max| total > *max) {
+                if size > previous.unwrap_or(0) {
+                    return Err(exceeded(
+                        key,
+                        format!(
+                            "the values would have {} bytes, at most {} are allowed",
+                            total, max
+                        ),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Usage {
+    /// Record the sizes of admitted writes.
+    fn apply(&mut self, writes: Vec<(String, Option<usize>)>) {
+        for (key, size) in writes {
+            self.set(&key, size);
+        }
+    }
+
+    /// Record the size of a key, `None` if it was deleted, and return its previous size.
+    fn set(&mut self, key: &str, size: Option<usize>) -> Option<usize> {
+        let previous = match size {
+            Some(size) => self.sizes.insert(key.to_string(), size),
+            None => self.sizes.remove(key),
+        };
+        self.total = self.total - previous.unwrap_or(0) + size.unwrap_or(0);
+
+        previous
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("quota"))]
+impl<P> ConfigProvider for QuotaProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = to_value(key, value)?;
+        let size = json_size(key, &value)?;
+
+        let writes = vec![(key.to_string(), Some(size))];
+
+        let mut usage = self.lock_usage();
+        self.admit(&usage, &writes)?;
+        self.inner.put_value(key, value)?;
+        usage.apply(writes);
+
+        Ok(())
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = to_value(key, value)?;
+        let size = json_size(key, &value)?;
+
+        let writes = vec![(key.to_string(), Some(size))];
+
+        let mut usage = self.lock_usage();
+        self.admit(&usage, &writes)?;
+        self.inner.put_if_version(key, value, expected_version)?;
+        usage.apply(writes);
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let mut usage = self.lock_usage();
+        self.inner.delete(key)?;
+        usage.set(key, None);
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let mut values = Vec::with_capacity(entries.len());
+        let mut writes = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let value = to_value(&key, value)?;
+            writes.push((key.clone(), Some(json_size(&key, &value)?)));
+            values.push((key, value));
+        }
+
+        let mut usage = self.lock_usage();
+        self.admit(&usage, &writes)?;
+        self.inner.put_many(values)?;
+        usage.apply(writes);
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("quota"))]
+impl<P> TransactionalConfigProvider for QuotaProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let mut writes = Vec::new();
+        let mut checked = Transaction::new();
+        for op in transaction.into_ops() {
+            match op {
+                TransactionOp::Put { key, value } => {
+                    writes.push((key.clone(), Some(json_size(&key, &value)?)));
+                    checked.put(&key, value)?;
+                }
+                TransactionOp::Delete { key } => {
+                    checked.delete(&key);
+                    writes.push((key, None));
+                }
+            }
+        }
+
+        let mut usage = self.lock_usage();
+        self.admit(&usage, &writes)?;
+        self.inner.commit(checked)?;
+        usage.apply(writes);
+
+        Ok(())
+    }
+}
+
+fn to_value<T>(key: &str, value: T) -> Result<Value, ConfigError>
+where
+    T: Serialize,
+{
+    serde_json::to_value(value)
+        .map_err(|err| ConfigError::serialization("quota", err).with_key(key))
+}
+
+/// The length of the JSON of a value.
+fn json_size(key: &str, value: &Value) -> Result<usize, ConfigError> {
+    serde_json::to_vec(value)
+        .map(|json| json.len())
+        .map_err(|err| ConfigError::serialization("quota", err).with_key(key))
+}
+
+fn exceeded(key: &str, message: String) -> ConfigError {
+    ConfigError::quota_exceeded("quota", key, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+
+    #[test]
+    fn writes_beyond_the_limits_are_rejected() {
+        let inner = InMemoryProvider::new();
+        inner.put("listen", ":8080".to_string()).unwrap();
+        let provider = QuotaProvider::new(inner)
+            .unwrap()
+            .with_max_value_size(64)
+            .with_max_keys(3)
+            .with_max_total_size(70);
+        assert_eq!(provider.usage(), (1, 7));
+
+        let err = provider.put("plugin.blob", "x".repeat(100)).unwrap_err();
+        assert!(matches!(err, ConfigError::QuotaExceeded { .. }));
+        assert_eq!(err.key(), Some("plugin.blob"));
+        assert!(!provider.has("plugin.blob").unwrap());
+
+        provider.put("workers", 4).unwrap();
+        provider.put("plugin.a", "a".repeat(50)).unwrap();
+        assert_eq!(provider.usage(), (3, 60));
+        assert!(provider.put("plugin.b", 1).is_err());
+        // replacing a key doesn't add one, shrinking a value is always allowed
+        provider.put("workers", 8).unwrap();
+        assert!(provider.put("plugin.a", "a".repeat(62)).is_err());
+        provider.put("plugin.a", "a".repeat(10)).unwrap();
+
+        // writes of a transaction count in order, a delete makes room for the put after it
+        let mut transaction = Transaction::new();
+        transaction.delete("plugin.a").put("plugin.b", 1).unwrap();
+        provider.commit(transaction).unwrap();
+        assert_eq!(provider.usage(), (3, 9));
+        assert_eq!(provider.inner().list().unwrap().len(), 3);
+    }
+}
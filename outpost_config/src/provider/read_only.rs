@@ -0,0 +1,136 @@
+use crate::{ChangeEvent, ConfigError, ConfigProvider, KeyPage, WatchableConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::mpsc::Receiver;
+use std::time::SystemTime;
+
+/// View of another provider that rejects all writes
+///
+/// Reads are passed through, `put` and `delete` fail with `ConfigError::ReadOnly`. Wrap a
+/// shared provider (e.g. an `Arc`) to hand out config to code that must not change it.
+pub struct ReadOnlyProvider<P> {
+    inner: P,
+}
+
+impl<P> ReadOnlyProvider<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("read_only"))]
+impl<P> ConfigProvider for ReadOnlyProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, _value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(ConfigError::read_only("read_only").with_key(key))
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        _value: T,
+        _expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(ConfigError::read_only("read_only").with_key(key))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        Err(ConfigError::read_only("read_only").with_key(key))
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get_many(keys)
+    }
+
+    fn put_many<T>(&self, _entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(ConfigError::read_only("read_only"))
+    }
+}
+
+impl<P> WatchableConfigProvider for ReadOnlyProvider<P>
+where
+    P: WatchableConfigProvider,
+{
+    fn watch(&self, key_prefix: &str) -> Result<Receiver<ChangeEvent>, ConfigError> {
+        self.inner.watch(key_prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::sync::Arc;
+
+    #[test]
+    fn reads_pass_and_writes_fail() {
+        let shared = Arc::new(InMemoryProvider::new());
+        shared.put("workers", 4).unwrap();
+        let plugin_view = ReadOnlyProvider::new(shared.clone());
+
+        assert_eq!(plugin_view.get::<u32>("workers").unwrap(), 4);
+        assert_eq!(plugin_view.list().unwrap(), vec!["workers"]);
+        assert!(matches!(
+            plugin_view.put("workers", 8),
+            Err(ConfigError::ReadOnly { .. })
+        ));
+        assert!(matches!(
+            plugin_view.delete("workers"),
+            Err(ConfigError::ReadOnly { .. })
+        ));
+        assert!(matches!(
+            plugin_view.get_or_insert_with("node.id", || "a".to_string()),
+            Err(ConfigError::ReadOnly { .. })
+        ));
+        assert_eq!(shared.get::<u32>("workers").unwrap(), 4);
+    }
+}
@@ -0,0 +1,470 @@
+#[cfg(feature = "tokio")]
+use crate::AsyncConfigProvider;
+use crate::{
+    glob, raw_version, ConfigError, ConfigProvider, Transaction, TransactionOp,
+    TransactionalConfigProvider,
+};
+#[cfg(feature = "tokio")]
+use redis::aio::MultiplexedConnection;
+#[cfg(feature = "tokio")]
+use redis::AsyncCommands;
+use redis::{Client, Commands, Connection};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// Provider storing its values as Redis strings
+///
+/// All keys are stored below an optional prefix, so several configs can share one Redis
+/// database. Listing walks the keyspace with `SCAN` instead of blocking the server with `KEYS`.
+pub struct RedisProvider {
+    connection: Mutex<Connection>,
+    prefix: String,
+}
+
+impl RedisProvider {
+    /// Connect to the Redis server behind the given url, e.g. `redis://127.0.0.1/0`.
+    pub fn connect(url: &str) -> Result<Self, ConfigError> {
+        let client = Client::open(url).map_err(|err| ConfigError::backend("redis", err))?;
+        let connection = client
+            .get_connection()
+            .map_err(|err| ConfigError::backend("redis", err))?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+            prefix: String::new(),
+        })
+    }
+
+    /// Store all keys below the given prefix.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Lock the connection, it stays usable even if a thread panicked while holding it.
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Insert a key value pair that Redis expires after the given duration.
+    pub fn put_with_ttl<T>(&self, key: &str, value: T, ttl: Duration) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("redis", err).with_key(key))?;
+        let mut connection = self.connection();
+
+        redis::cmd("SET")
+            .arg(self.redis_key(key))
+            .arg(serialized)
+            .arg("PX")
+            .arg(expiry_millis(ttl))
+            .query::<()>(&mut *connection)
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))
+    }
+
+    fn redis_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// The keys below the prefix matching the pattern, which is relative to the prefix.
+    fn scan(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        let mut connection = self.connection();
+
+        let keys: Vec<String> = connection
+            .scan_match(scan_pattern(&self.prefix, pattern))
+            .map_err(|err| ConfigError::backend("redis", err))?
+            .collect();
+
+        Ok(keys
+            .iter()
+            .filter_map(|key| relative_key(&self.prefix, key))
+            .collect())
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("redis"))]
+impl ConfigProvider for RedisProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let mut connection = self.connection();
+        let raw: Option<String> = connection
+            .get(self.redis_key(key))
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))?;
+        let raw = raw.ok_or_else(|| ConfigError::not_found("redis", key))?;
+        let deserialized = serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("redis", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let mut connection = self.connection();
+
+        connection
+            .exists(self.redis_key(key))
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("redis", err).with_key(key))?;
+        let mut connection = self.connection();
+
+        connection
+            .set(self.redis_key(key), serialized)
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let mut connection = self.connection();
+
+        connection
+            .del(self.redis_key(key))
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.scan("*")
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.scan(&format!("{}*", glob::escape(prefix)))
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        // SCAN MATCH understands the same patterns
+        self.scan(pattern)
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        if let Some(value) = self.get_opt(key)? {
+            return Ok(value);
+        }
+
+        let value = f();
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("redis", err).with_key(key))?;
+
+        // SET NX only stores the value if no other client got there first, whose value wins
+        let inserted: bool = {
+            let mut connection = self.connection();
+            connection
+                .set_nx(self.redis_key(key), serialized)
+                .map_err(|err| ConfigError::backend("redis", err).with_key(key))?
+        };
+
+        if inserted {
+            Ok(value)
+        } else {
+            self.get(key)
+        }
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let redis_keys: Vec<String> = keys.iter().map(|key| self.redis_key(key)).collect();
+        let mut connection = self.connection();
+
+        // `Commands::get` falls back to GET for a single key, which doesn't reply with an array
+        let raw: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(&redis_keys)
+            .query(&mut *connection)
+            .map_err(|err| ConfigError::backend("redis", err))?;
+
+        raw.into_iter()
+            .map(|raw| {
+                raw.map(|raw| serde_json::from_str(&raw))
+                    .transpose()
+                    .map_err(|err| ConfigError::deserialization("redis", err))
+            })
+            .collect()
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("redis", err).with_key(key))?;
+        let redis_key = self.redis_key(key);
+        let mut connection = self.connection();
+
+        // EXEC discards the write if another client changed the watched key after WATCH
+        redis::cmd("WATCH")
+            .arg(&redis_key)
+            .query::<()>(&mut *connection)
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))?;
+        let current: Option<String> = connection
+            .get(&redis_key)
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))?;
+        if current.as_deref().map(raw_version).as_deref() != expected_version {
+            redis::cmd("UNWATCH")
+                .query::<()>(&mut *connection)
+                .map_err(|err| ConfigError::backend("redis", err).with_key(key))?;
+            return Err(ConfigError::conflict("redis", key));
+        }
+
+        let written: Option<()> = redis::pipe()
+            .atomic()
+            .set(&redis_key, serialized)
+            .ignore()
+            .query(&mut *connection)
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))?;
+
+        written.ok_or_else(|| ConfigError::conflict("redis", key))
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut pairs = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let serialized = serde_json::to_string(&value)
+                .map_err(|err| ConfigError::serialization("redis", err))?;
+            pairs.push((self.redis_key(&key), serialized));
+        }
+
+        let mut connection = self.connection();
+        connection
+            .mset(&pairs)
+            .map_err(|err| ConfigError::backend("redis", err))
+    }
+}
+
+/// Runs all writes in one `MULTI`/`EXEC` block, so other clients see none or all of them.
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("redis"))]
+impl TransactionalConfigProvider for RedisProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let mut pipeline = redis::pipe();
+        pipeline.atomic();
+
+        for op in transaction.ops() {
+            match op {
+                TransactionOp::Put { key, value } => {
+                    let serialized = serde_json::to_string(value)
+                        .map_err(|err| ConfigError::serialization("redis", err))?;
+                    pipeline.set(self.redis_key(key), serialized).ignore();
+                }
+                TransactionOp::Delete { key } => {
+                    pipeline.del(self.redis_key(key)).ignore();
+                }
+            }
+        }
+
+        let mut connection = self.connection();
+        pipeline
+            .query::<()>(&mut *connection)
+            .map_err(|err| ConfigError::backend("redis", err))
+    }
+}
+
+/// Async counterpart of [`RedisProvider`] using a multiplexed tokio connection
+///
+/// Stores keys exactly like the [`RedisProvider`], so both can be used on the same database.
+/// The connection is shared between concurrent calls.
+#[cfg(feature = "tokio")]
+pub struct AsyncRedisProvider {
+    connection: MultiplexedConnection,
+    prefix: String,
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRedisProvider {
+    /// Connect to the Redis server behind the given url, e.g. `redis://127.0.0.1/0`.
+    pub async fn connect(url: &str) -> Result<Self, ConfigError> {
+        let client = Client::open(url).map_err(|err| ConfigError::backend("redis", err))?;
+        let connection = client
+            .get_multiplexed_tokio_connection()
+            .await
+            .map_err(|err| ConfigError::backend("redis", err))?;
+
+        Ok(Self {
+            connection,
+            prefix: String::new(),
+        })
+    }
+
+    /// Store all keys below the given prefix.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Insert a key value pair that Redis expires after the given duration.
+    pub async fn put_with_ttl<T>(
+        &self,
+        key: &str,
+        value: T,
+        ttl: Duration,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("redis", err).with_key(key))?;
+        let mut connection = self.connection.clone();
+
+        redis::cmd("SET")
+            .arg(self.redis_key(key))
+            .arg(serialized)
+            .arg("PX")
+            .arg(expiry_millis(ttl))
+            .query_async::<()>(&mut connection)
+            .await
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))
+    }
+
+    fn redis_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("redis"))]
+impl AsyncConfigProvider for AsyncRedisProvider {
+    async fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let mut connection = self.connection.clone();
+        let raw: Option<String> = connection
+            .get(self.redis_key(key))
+            .await
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))?;
+        let raw = raw.ok_or_else(|| ConfigError::not_found("redis", key))?;
+        let deserialized = serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("redis", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    async fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let mut connection = self.connection.clone();
+
+        connection
+            .exists(self.redis_key(key))
+            .await
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))
+    }
+
+    async fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize + Send + 'static,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("redis", err).with_key(key))?;
+        let mut connection = self.connection.clone();
+
+        connection
+            .set(self.redis_key(key), serialized)
+            .await
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let mut connection = self.connection.clone();
+
+        connection
+            .del(self.redis_key(key))
+            .await
+            .map_err(|err| ConfigError::backend("redis", err).with_key(key))
+    }
+
+    async fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let mut connection = self.connection.clone();
+        let mut iter = connection
+            .scan_match::<_, String>(scan_pattern(&self.prefix, "*"))
+            .await
+            .map_err(|err| ConfigError::backend("redis", err))?;
+
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            keys.extend(relative_key(&self.prefix, &key));
+        }
+
+        Ok(keys)
+    }
+}
+
+/// The `SCAN MATCH` pattern for the keys below the prefix matching the relative pattern.
+fn scan_pattern(prefix: &str, pattern: &str) -> String {
+    format!("{}{}", glob::escape(prefix), pattern)
+}
+
+/// The key relative to the prefix, `None` for keys of other configs.
+fn relative_key(prefix: &str, redis_key: &str) -> Option<String> {
+    redis_key.strip_prefix(prefix).map(str::to_string)
+}
+
+/// The expiry in milliseconds for `SET PX`.
+fn expiry_millis(ttl: Duration) -> u64 {
+    // a shorter expiry would let the key live too long, and redis rejects an expiry of zero
+    ttl.as_nanos().div_ceil(1_000_000).max(1) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_patterns_escape_the_prefix() {
+        assert_eq!(scan_pattern("gk:", "*"), "gk:*");
+        assert_eq!(scan_pattern("gk[1]*", "routes.*"), "gk\\[1\\]\\*routes.*");
+        assert_eq!(scan_pattern("", "a?"), "a?");
+    }
+
+    #[test]
+    fn keys_are_relative_to_the_prefix() {
+        assert_eq!(
+            relative_key("gk:", "gk:workers"),
+            Some("workers".to_string())
+        );
+        assert_eq!(relative_key("gk:", "other:workers"), None);
+        assert_eq!(relative_key("", "workers"), Some("workers".to_string()));
+    }
+
+    #[test]
+    fn expiries_round_up_to_a_millisecond() {
+        assert_eq!(expiry_millis(Duration::from_secs(2)), 2000);
+        assert_eq!(expiry_millis(Duration::from_micros(1500)), 2);
+        assert_eq!(expiry_millis(Duration::from_micros(1)), 1);
+        assert_eq!(expiry_millis(Duration::ZERO), 1);
+    }
+}
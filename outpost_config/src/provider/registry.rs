@@ -0,0 +1,216 @@
+use crate::provider::env::{decode_scalar, encode_scalar};
+use crate::{no_compare_and_set, ConfigError, ConfigProvider};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::Serialize;
+use std::io::ErrorKind;
+use winreg::enums::{RegType, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, KEY_READ};
+use winreg::types::FromRegValue;
+use winreg::{RegKey, RegValue};
+
+/// Registry hive a [`RegistryProvider`] stores its values in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hive {
+    LocalMachine,
+    CurrentUser,
+}
+
+/// Provider storing its values in the Windows registry
+///
+/// Every config key is a value below the configured registry key, e.g.
+/// `HKLM\SOFTWARE\Policies\Gatekeeper`, so config can be rolled out with Group Policy.
+/// String values are parsed like environment variables (JSON where possible, bare strings
+/// otherwise) and DWORD/QWORD values are read as numbers. Writes always use `REG_SZ`.
+pub struct RegistryProvider {
+    hive: Hive,
+    path: String,
+}
+
+impl RegistryProvider {
+    pub fn new<S>(hive: Hive, path: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            hive,
+            path: path.into(),
+        }
+    }
+
+    fn root(&self) -> RegKey {
+        match self.hive {
+            Hive::LocalMachine => RegKey::predef(HKEY_LOCAL_MACHINE),
+            Hive::CurrentUser => RegKey::predef(HKEY_CURRENT_USER),
+        }
+    }
+
+    /// Open the configured key for reading, `None` if it doesn't exist yet.
+    fn open(&self) -> Result<Option<RegKey>, ConfigError> {
+        match self.root().open_subkey_with_flags(&self.path, KEY_READ) {
+            Ok(key) => Ok(Some(key)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(ConfigError::backend("registry", err)),
+        }
+    }
+
+    fn create(&self) -> Result<RegKey, ConfigError> {
+        let (key, _) = self
+            .root()
+            .create_subkey(&self.path)
+            .map_err(|err| ConfigError::backend("registry", err))?;
+
+        Ok(key)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("registry"))]
+impl ConfigProvider for RegistryProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let reg_key = self
+            .open()?
+            .ok_or_else(|| ConfigError::not_found("registry", key))?;
+        let raw = match reg_key.get_raw_value(key) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(ConfigError::not_found("registry", key))
+            }
+            Err(err) => return Err(ConfigError::backend("registry", err).with_key(key)),
+        };
+
+        decode_value(raw)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let reg_key = match self.open()? {
+            Some(reg_key) => reg_key,
+            None => return Ok(false),
+        };
+
+        match reg_key.get_raw_value(key) {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(ConfigError::backend("registry", err).with_key(key)),
+        }
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let encoded = encode_scalar(value)?;
+
+        self.create()?
+            .set_value(key, &encoded)
+            .map_err(|err| ConfigError::backend("registry", err).with_key(key))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let reg_key = match self.open()? {
+            Some(_) => self.create()?,
+            None => return Ok(()),
+        };
+
+        match reg_key.delete_value(key) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(ConfigError::backend("registry", err).with_key(key)),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let reg_key = match self.open()? {
+            Some(reg_key) => reg_key,
+            None => return Ok(Vec::new()),
+        };
+
+        reg_key
+            .enum_values()
+            .map(|value| {
+                value
+                    .map(|(name, _)| name)
+                    .map_err(|err| ConfigError::backend("registry", err))
+            })
+            .collect()
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        _value: T,
+        _expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(no_compare_and_set("registry", key))
+    }
+}
+
+fn decode_value<T>(raw: RegValue) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+{
+    let number = match raw.vtype {
+        RegType::REG_DWORD if raw.bytes.len() >= 4 => {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&raw.bytes[..4]);
+            u64::from(u32::from_le_bytes(bytes))
+        }
+        RegType::REG_QWORD if raw.bytes.len() >= 8 => {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&raw.bytes[..8]);
+            u64::from_le_bytes(bytes)
+        }
+        _ => {
+            let text = String::from_reg_value(&raw)
+                .map_err(|err| ConfigError::backend("registry", err))?;
+            return decode_scalar(&text);
+        }
+    };
+
+    T::deserialize(number.into_deserializer())
+        .map_err(|err: serde::de::value::Error| ConfigError::deserialization("registry", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winreg::types::ToRegValue;
+
+    #[test]
+    fn numbers_are_read_from_dwords_and_qwords() {
+        assert_eq!(decode_value::<u32>(8u32.to_reg_value()).unwrap(), 8);
+        assert_eq!(
+            decode_value::<u64>(5_000_000_000u64.to_reg_value()).unwrap(),
+            5_000_000_000
+        );
+        assert!(matches!(
+            decode_value::<String>(8u32.to_reg_value()),
+            Err(ConfigError::Deserialization { .. })
+        ));
+    }
+
+    #[test]
+    fn strings_are_read_like_environment_variables() {
+        assert_eq!(decode_value::<u32>("4".to_reg_value()).unwrap(), 4);
+        assert_eq!(
+            decode_value::<Vec<String>>(r#"["api", "admin"]"#.to_reg_value()).unwrap(),
+            vec!["api".to_string(), "admin".to_string()]
+        );
+        assert_eq!(
+            decode_value::<String>(":8080".to_reg_value()).unwrap(),
+            ":8080"
+        );
+    }
+
+    #[test]
+    fn truncated_numbers_are_refused() {
+        let raw = RegValue {
+            bytes: vec![1, 0],
+            vtype: RegType::REG_DWORD,
+        };
+        assert!(decode_value::<u32>(raw).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::file::{lock_exclusive, lock_shared, write_atomic};
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, ProviderStats, Transaction,
+    TransactionalConfigProvider,
+};
+use ron::ser::PrettyConfig;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// File aware provider persisting its values as RON
+///
+/// The file holds a single map from config keys to values.
+#[derive(Default)]
+pub struct RonProvider {
+    inner: InMemoryProvider,
+}
+
+impl RonProvider {
+    pub fn new() -> Self {
+        Self {
+            inner: InMemoryProvider::new(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("ron"))]
+impl ConfigProvider for RonProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.inner.stats()
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("ron"))]
+impl TransactionalConfigProvider for RonProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl FileAwareConfigProvider for RonProvider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_shared("ron", path.as_ref())?;
+        let raw = fs::read_to_string(path).map_err(|err| ConfigError::io("ron", err))?;
+
+        let values: HashMap<String, Value> =
+            ron::from_str(&raw).map_err(|err| ConfigError::deserialization("ron", err))?;
+
+        let mut write_guard = self.inner.write()?;
+
+        for (k, v) in values {
+            let serialized =
+                serde_json::to_string(&v).map_err(|err| ConfigError::serialization("ron", err))?;
+            write_guard.insert(k, serialized);
+        }
+        self.inner.mark_loaded(changes);
+
+        Ok(())
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_exclusive("ron", path.as_ref())?;
+        write_atomic("ron", path, |file| {
+            let read_guard = self.inner.read()?;
+
+            let values = read_guard
+                .persistent()
+                .map(|(k, v)| Ok((k.as_str(), serde_json::from_str(v)?)))
+                .collect::<Result<BTreeMap<&str, Value>, serde_json::Error>>()
+                .map_err(|err| ConfigError::deserialization("ron", err))?;
+
+            let rendered = ron::ser::to_string_pretty(&values, PrettyConfig::default())
+                .map_err(|err| ConfigError::serialization("ron", err))?;
+
+            file.write_all(rendered.as_bytes())
+                .map_err(|err| ConfigError::io("ron", err))
+        })?;
+        self.inner.mark_saved(changes);
+
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Policy {
+        Allow,
+        RateLimit { per_second: u32 },
+        Redirect(String),
+    }
+
+    #[test]
+    fn enums_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.ron");
+
+        let provider = RonProvider::new();
+        provider.put("default", Policy::Allow).unwrap();
+        provider
+            .put("api", Policy::RateLimit { per_second: 10 })
+            .unwrap();
+        provider
+            .put("legacy", Policy::Redirect("/v2".to_string()))
+            .unwrap();
+        provider.save(&path).unwrap();
+
+        let reloaded = RonProvider::new();
+        reloaded.load(&path).unwrap();
+        assert_eq!(reloaded.get::<Policy>("default").unwrap(), Policy::Allow);
+        assert_eq!(
+            reloaded.get::<Policy>("api").unwrap(),
+            Policy::RateLimit { per_second: 10 }
+        );
+        assert_eq!(
+            reloaded.get::<Policy>("legacy").unwrap(),
+            Policy::Redirect("/v2".to_string())
+        );
+    }
+}
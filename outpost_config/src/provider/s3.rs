@@ -0,0 +1,270 @@
+use crate::aws::{uri_encode, AwsClient, AwsConfig, AwsError};
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, ProviderStats, Transaction,
+    TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// File aware provider persisting its values as a JSON object in an S3 bucket
+///
+/// The path passed to `load` and `save` is the object key. A save only succeeds if the object
+/// did not change since it was last loaded or saved by this provider (and only creates objects
+/// that don't exist yet), so concurrent updates are rejected instead of silently overwritten.
+pub struct S3Provider {
+    inner: InMemoryProvider,
+    client: AwsClient,
+    bucket: String,
+    etags: Mutex<HashMap<String, String>>,
+}
+
+impl S3Provider {
+    pub fn new<S>(config: AwsConfig, bucket: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            inner: InMemoryProvider::new(),
+            client: AwsClient::new(config, "s3", "s3"),
+            bucket: bucket.into(),
+            etags: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        let key = uri_encode(key.trim_start_matches('/'), false);
+
+        // custom endpoints (minio, localstack) usually only support path style addressing
+        match &self.client.config().endpoint {
+            Some(endpoint) => format!("{}/{}/{}", endpoint.trim_end_matches('/'), self.bucket, key),
+            None => format!(
+                "https://{}.s3.{}.amazonaws.com/{}",
+                self.bucket,
+                self.client.config().region,
+                key
+            ),
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("s3"))]
+impl ConfigProvider for S3Provider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.inner.stats()
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("s3"))]
+impl TransactionalConfigProvider for S3Provider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl FileAwareConfigProvider for S3Provider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let object = path.as_ref().to_string_lossy().into_owned();
+
+        let response = self
+            .client
+            .send("GET", &self.object_url(&object), &[], &[], &[])?
+            .map_err(|err| object_error(&object, err))?;
+        let etag = response.header("ETag").map(str::to_string);
+
+        let values: HashMap<String, Value> = response
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("s3", err))?;
+
+        let mut write_guard = self.inner.write()?;
+
+        for (k, v) in values {
+            let serialized =
+                serde_json::to_string(&v).map_err(|err| ConfigError::serialization("s3", err))?;
+            write_guard.insert(k, serialized);
+        }
+
+        if let Some(etag) = etag {
+            self.etags.lock().unwrap().insert(object, etag);
+        }
+        self.inner.mark_loaded(changes);
+
+        Ok(())
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let object = path.as_ref().to_string_lossy().into_owned();
+        let mut etags = self.etags.lock().unwrap();
+
+        let body = {
+            let read_guard = self.inner.read()?;
+
+            let values = read_guard
+                .persistent()
+                .map(|(k, v)| Ok((k.as_str(), serde_json::from_str(v)?)))
+                .collect::<Result<BTreeMap<&str, Value>, serde_json::Error>>()
+                .map_err(|err| ConfigError::deserialization("s3", err))?;
+
+            serde_json::to_vec_pretty(&values)
+                .map_err(|err| ConfigError::serialization("s3", err))?
+        };
+
+        // only overwrite the version we know about, or create the object if we never saw it
+        let condition = match etags.get(&object) {
+            Some(etag) => ("if-match", etag.as_str()),
+            None => ("if-none-match", "*"),
+        };
+
+        let response = self
+            .client
+            .send(
+                "PUT",
+                &self.object_url(&object),
+                &[],
+                &[("content-type", "application/json"), condition],
+                &body,
+            )?
+            .map_err(|err| object_error(&object, err))?;
+
+        match response.header("ETag") {
+            Some(etag) => etags.insert(object, etag.to_string()),
+            None => etags.remove(&object),
+        };
+        self.inner.mark_saved(changes);
+
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+/// Map a missing object to ConfigError::NotFound and a failed condition to
+/// ConfigError::Conflict, other errors as usual.
+fn object_error(object: &str, err: AwsError) -> ConfigError {
+    match err.kind.as_str() {
+        "NotFound" | "NoSuchKey" => ConfigError::not_found("s3", object),
+        // another writer changed the object since it was loaded, or created it
+        "PreconditionFailed" | "ConditionalRequestConflict" => ConfigError::conflict("s3", object),
+        _ => err.into_config_error("s3"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(endpoint: Option<&str>) -> AwsConfig {
+        AwsConfig {
+            region: "eu-west-1".to_string(),
+            access_key_id: "AKID".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+            endpoint: endpoint.map(str::to_string),
+        }
+    }
+
+    fn error(kind: &str) -> AwsError {
+        AwsError {
+            kind: kind.to_string(),
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn objects_are_addressed_by_virtual_host() {
+        let provider = S3Provider::new(config(None), "gatekeeper");
+        assert_eq!(
+            provider.object_url("/configs/gatekeeper.json"),
+            "https://gatekeeper.s3.eu-west-1.amazonaws.com/configs/gatekeeper.json"
+        );
+    }
+
+    #[test]
+    fn custom_endpoints_are_addressed_by_path() {
+        let provider = S3Provider::new(config(Some("http://localhost:9000/")), "gatekeeper");
+        assert_eq!(
+            provider.object_url("configs/gate keeper.json"),
+            "http://localhost:9000/gatekeeper/configs/gate%20keeper.json"
+        );
+    }
+
+    #[test]
+    fn failed_conditions_conflict() {
+        assert!(matches!(
+            object_error("gatekeeper.json", error("PreconditionFailed")),
+            ConfigError::Conflict { .. }
+        ));
+        assert!(matches!(
+            object_error("gatekeeper.json", error("NotFound")),
+            ConfigError::NotFound { .. }
+        ));
+        assert!(matches!(
+            object_error("gatekeeper.json", error("AccessDenied")),
+            ConfigError::PermissionDenied { .. }
+        ));
+        assert!(matches!(
+            object_error("gatekeeper.json", error("HttpError")),
+            ConfigError::Backend { .. }
+        ));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    glob, ConfigError, ConfigProvider, Transaction, TransactionOp, TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// View of another provider limited to the keys below a prefix
+///
+/// Keys are passed to the inner provider with the prefix prepended, e.g. `cert` becomes
+/// `tls.cert` for the prefix `tls.`, and `list` only returns the keys below the prefix with the
+/// prefix stripped. Wrap a shared provider (e.g. an `Arc`) to hand every subsystem its own
+/// view of one config.
+pub struct ScopedProvider<P> {
+    inner: P,
+    prefix: String,
+}
+
+impl<P> ScopedProvider<P> {
+    pub fn new<S>(inner: P, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            inner,
+            prefix: prefix.into(),
+        }
+    }
+
+    /// The prefix of all keys of this view.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The wrapped provider, not limited to the prefix.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn scoped_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("scoped"))]
+impl<P> ConfigProvider for ScopedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(&self.scoped_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(&self.scoped_key(key))
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(&self.scoped_key(key), value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(&self.scoped_key(key))
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        Ok(self
+            .inner
+            .list()?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+            .collect())
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        Ok(self
+            .inner
+            .list_prefix(&self.scoped_key(prefix))?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+            .collect())
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        let pattern = format!("{}{}", glob::escape(&self.prefix), pattern);
+
+        Ok(self
+            .inner
+            .list_glob(&pattern)?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+            .collect())
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(&self.scoped_key(key), f)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(&self.scoped_key(key))
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner
+            .put_if_version(&self.scoped_key(key), value, expected_version)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let scoped: Vec<String> = keys.iter().map(|key| self.scoped_key(key)).collect();
+        let scoped: Vec<&str> = scoped.iter().map(String::as_str).collect();
+
+        self.inner.get_many(&scoped)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_many(
+            entries
+                .into_iter()
+                .map(|(key, value)| (self.scoped_key(&key), value))
+                .collect(),
+        )
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("scoped"))]
+impl<P> TransactionalConfigProvider for ScopedProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let mut scoped = Transaction::new();
+        for op in transaction.into_ops() {
+            match op {
+                TransactionOp::Put { key, value } => {
+                    scoped.put(&self.scoped_key(&key), value)?;
+                }
+                TransactionOp::Delete { key } => {
+                    scoped.delete(&self.scoped_key(&key));
+                }
+            }
+        }
+
+        self.inner.commit(scoped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::sync::Arc;
+
+    #[test]
+    fn views_only_see_their_namespace() {
+        let shared = Arc::new(InMemoryProvider::new());
+        let tls = ScopedProvider::new(shared.clone(), "tls.");
+        let proxy = ScopedProvider::new(shared.clone(), "proxy.");
+
+        tls.put("cert", "/etc/gk/cert.pem".to_string()).unwrap();
+        proxy
+            .put("cert", "/etc/gk/upstream.pem".to_string())
+            .unwrap();
+        proxy.put("timeout", 30).unwrap();
+
+        assert_eq!(tls.get::<String>("cert").unwrap(), "/etc/gk/cert.pem");
+        assert_eq!(tls.list().unwrap(), vec!["cert"]);
+        assert!(!tls.has("timeout").unwrap());
+        assert_eq!(
+            shared.get::<String>("proxy.cert").unwrap(),
+            "/etc/gk/upstream.pem"
+        );
+
+        proxy.put("routes.api", "10.0.0.1".to_string()).unwrap();
+        proxy.put("routes.web", "10.0.0.2".to_string()).unwrap();
+        assert_eq!(proxy.list_prefix("routes.").unwrap().len(), 2);
+        let mut routes = proxy.list_glob("routes.[a-v]*").unwrap();
+        routes.sort();
+        assert_eq!(routes, vec!["routes.api"]);
+        assert!(tls.list_glob("*routes*").unwrap().is_empty());
+
+        tls.delete("cert").unwrap();
+        assert!(proxy.has("cert").unwrap());
+        assert_eq!(shared.list().unwrap().len(), 4);
+    }
+}
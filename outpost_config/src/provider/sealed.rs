@@ -0,0 +1,313 @@
+use crate::file::{lock_exclusive, lock_shared, write_atomic};
+use crate::format::Format;
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, KeyPage, Transaction,
+    TransactionalConfigProvider,
+};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use pbkdf2::pbkdf2_hmac_array;
+use pbkdf2::sha2::Sha256;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Name of the only supported cipher, stored in every file.
+const ALGORITHM: &str = "A256GCM";
+
+/// Name of the only supported key derivation, stored in every file.
+const KDF: &str = "PBKDF2-SHA256";
+
+/// Iterations of the key derivation used by [`SealedFileProvider::new`], as recommended by
+/// OWASP for PBKDF2-HMAC-SHA256.
+const DEFAULT_ROUNDS: u32 = 600_000;
+
+/// File aware provider storing the whole config encrypted under a passphrase
+///
+/// Outposts in physically insecure locations can't rely on the permissions of their disk.
+/// [`EncryptedProvider`](crate::provider::encrypted::EncryptedProvider) hides the values but
+/// leaves the keys readable, this provider encrypts the entire file instead. Saving exports all
+/// values of the wrapped provider as JSON and encrypts them with AES-256-GCM under a key derived
+/// from the passphrase with PBKDF2-HMAC-SHA256 and a fresh random salt. Loading decrypts the file
+/// and imports the values into the wrapped provider, which therefore doesn't need to be file
+/// aware itself. A wrong passphrase or a changed file fail the load with
+/// `ConfigError::Corrupted`, the two can't be told apart.
+///
+/// The file holds the salt, the iteration count and the nonce next to the ciphertext, so the
+/// passphrase is the only secret to provision. Plaintext never touches the disk.
+///
+/// ```ignore
+/// let config = SealedFileProvider::from_env(InMemoryProvider::new(), "GATEKEEPER_PASSPHRASE")?;
+/// config.load("/etc/gatekeeper/gatekeeper.sealed")?;
+/// ```
+pub struct SealedFileProvider<P> {
+    inner: P,
+    passphrase: String,
+    rounds: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Envelope {
+    #[serde(rename = "$sealed")]
+    alg: String,
+    kdf: String,
+    rounds: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl<P> SealedFileProvider<P> {
+    pub fn new(inner: P, passphrase: &str) -> Self {
+        Self {
+            inner,
+            passphrase: passphrase.to_string(),
+            rounds: DEFAULT_ROUNDS,
+        }
+    }
+
+    /// Use the passphrase stored in the environment variable.
+    pub fn from_env(inner: P, var: &str) -> Result<Self, ConfigError> {
+        let passphrase = env::var(var).map_err(|err| ConfigError::backend("sealed", err))?;
+
+        Ok(Self::new(inner, &passphrase))
+    }
+
+    /// Derive the key of saved files with the given number of iterations. Loading uses the
+    /// count stored in the file.
+    pub fn with_rounds(mut self, rounds: u32) -> Self {
+        self.rounds = rounds;
+        self
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn cipher(&self, salt: &[u8], rounds: u32) -> Aes256Gcm {
+        let key = pbkdf2_hmac_array::<Sha256, 32>(self.passphrase.as_bytes(), salt, rounds);
+
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> Result<Envelope, ConfigError> {
+        let mut salt = [0; 16];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher(&salt, self.rounds)
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| ConfigError::serialization("sealed", "encryption failed"))?;
+
+        Ok(Envelope {
+            alg: ALGORITHM.to_string(),
+            kdf: KDF.to_string(),
+            rounds: self.rounds,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        })
+    }
+
+    fn open(&self, path: &Path, envelope: Envelope) -> Result<Vec<u8>, ConfigError> {
+        if envelope.alg != ALGORITHM || envelope.kdf != KDF {
+            return Err(ConfigError::deserialization(
+                "sealed",
+                format!(
+                    "unsupported encryption {} with {}",
+                    envelope.alg, envelope.kdf
+                ),
+            ));
+        }
+
+        let malformed = |_| ConfigError::corrupted("sealed", path, "the file is malformed");
+        let salt = STANDARD.decode(&envelope.salt).map_err(malformed)?;
+        let nonce = STANDARD.decode(&envelope.nonce).map_err(malformed)?;
+        let ciphertext = STANDARD.decode(&envelope.ciphertext).map_err(malformed)?;
+        if nonce.len() != 12 {
+            return Err(ConfigError::corrupted(
+                "sealed",
+                path,
+                "the file is malformed",
+            ));
+        }
+
+        self.cipher(&salt, envelope.rounds)
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| {
+                ConfigError::corrupted(
+                    "sealed",
+                    path,
+                    "the passphrase is wrong or the file was changed",
+                )
+            })
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("sealed"))]
+impl<P> ConfigProvider for SealedFileProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get_many(keys)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_many(entries)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("sealed"))]
+impl<P> TransactionalConfigProvider for SealedFileProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl<P> FileAwareConfigProvider for SealedFileProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn load<Q>(&self, path: Q) -> Result<(), ConfigError>
+    where
+        Q: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let _lock = lock_shared("sealed", path)?;
+        let bytes = fs::read(path).map_err(|err| ConfigError::io("sealed", err))?;
+        let envelope: Envelope = serde_json::from_slice(&bytes)
+            .map_err(|err| ConfigError::corrupted("sealed", path, err.to_string()))?;
+        let plaintext = self.open(path, envelope)?;
+
+        self.inner.import(plaintext.as_slice(), Format::Json)
+    }
+
+    fn save<Q>(&self, path: Q) -> Result<(), ConfigError>
+    where
+        Q: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let _lock = lock_exclusive("sealed", path)?;
+        let mut plaintext = Vec::new();
+        self.inner.export(Format::Json, &mut plaintext)?;
+        let envelope = self.seal(&plaintext)?;
+
+        write_atomic("sealed", path, |file: &mut File| {
+            serde_json::to_writer_pretty(BufWriter::new(file), &envelope)
+                .map_err(|err| ConfigError::serialization("sealed", err))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+
+    #[test]
+    fn the_whole_file_is_encrypted_under_the_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.sealed");
+        let provider =
+            SealedFileProvider::new(InMemoryProvider::new(), "correct horse").with_rounds(1000);
+        provider
+            .put("tenants.acme.token", "s3cr3t".to_string())
+            .unwrap();
+        provider.save(&path).unwrap();
+
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(
+            !written.contains("tenants") && !written.contains("s3cr3t"),
+            "{}",
+            written
+        );
+
+        let outpost = SealedFileProvider::new(InMemoryProvider::new(), "correct horse");
+        outpost.load(&path).unwrap();
+        assert_eq!(
+            outpost.get::<String>("tenants.acme.token").unwrap(),
+            "s3cr3t"
+        );
+
+        let thief = SealedFileProvider::new(InMemoryProvider::new(), "battery staple");
+        let err = thief.load(&path).unwrap_err();
+        assert!(matches!(err, ConfigError::Corrupted { .. }), "{}", err);
+        assert!(thief.list().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+use crate::aws::{AwsClient, AwsConfig};
+use crate::{no_compare_and_set, ConfigError, ConfigProvider};
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Provider mapping config keys to secrets of AWS Secrets Manager
+///
+/// Every key is a secret named `<prefix><key>`. Fetched secrets are cached and re-read once
+/// the refresh interval passed, so rotated credentials are picked up without querying the
+/// service on every lookup. Secret strings are parsed as JSON and fall back to plain strings,
+/// which covers both rotated database credentials and bare API keys.
+pub struct SecretsManagerProvider {
+    client: AwsClient,
+    prefix: String,
+    refresh_interval: Duration,
+    cache: Mutex<HashMap<String, CachedSecret>>,
+}
+
+struct CachedSecret {
+    fetched: Instant,
+    value: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetSecretValueResponse {
+    secret_string: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListSecretsResponse {
+    #[serde(default)]
+    secret_list: Vec<SecretEntry>,
+    next_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SecretEntry {
+    name: String,
+}
+
+impl SecretsManagerProvider {
+    pub fn new(config: AwsConfig) -> Self {
+        Self {
+            client: AwsClient::new(config, "secretsmanager", "secrets_manager"),
+            prefix: String::new(),
+            refresh_interval: Duration::from_secs(300),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Name all secrets with the given prefix, e.g. `gatekeeper/`.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Re-read cached secrets once they are older than the given interval.
+    ///
+    /// A zero interval disables caching.
+    pub fn with_refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_interval = refresh_interval;
+        self
+    }
+
+    /// Drop all cached secrets, so the next lookups go to the service.
+    pub fn invalidate(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn secret_id(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn fetch(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        if let Some(cached) = self.cache.lock().unwrap().get(key) {
+            if cached.fetched.elapsed() < self.refresh_interval {
+                return Ok(cached.value.clone());
+            }
+        }
+
+        let response: Option<GetSecretValueResponse> =
+            self.call("GetSecretValue", json!({ "SecretId": self.secret_id(key) }))?;
+        let value = response.and_then(|response| response.secret_string);
+
+        self.remember(key, value.clone());
+
+        Ok(value)
+    }
+
+    fn remember(&self, key: &str, value: Option<String>) {
+        let mut cache = self.cache.lock().unwrap();
+        let _ = cache.insert(
+            key.to_string(),
+            CachedSecret {
+                fetched: Instant::now(),
+                value,
+            },
+        );
+    }
+
+    fn call<R>(&self, operation: &str, body: Value) -> Result<Option<R>, ConfigError>
+    where
+        R: DeserializeOwned,
+    {
+        self.client
+            .call_json(&format!("secretsmanager.{}", operation), "1.1", &body)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("secrets_manager"))]
+impl ConfigProvider for SecretsManagerProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .fetch(key)?
+            .ok_or_else(|| ConfigError::not_found("secrets_manager", key))?;
+
+        parse_value(key, &raw)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.fetch(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("secrets_manager", err).with_key(key))?;
+
+        let updated: Option<Value> = self.call(
+            "PutSecretValue",
+            json!({ "SecretId": self.secret_id(key), "SecretString": serialized }),
+        )?;
+
+        // PutSecretValue only adds versions to existing secrets
+        if updated.is_none() {
+            let _: Option<Value> = self.call(
+                "CreateSecret",
+                json!({ "Name": self.secret_id(key), "SecretString": serialized }),
+            )?;
+        }
+
+        self.remember(key, Some(serialized));
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let _: Option<Value> = self.call(
+            "DeleteSecret",
+            json!({ "SecretId": self.secret_id(key), "ForceDeleteWithoutRecovery": true }),
+        )?;
+
+        self.remember(key, None);
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let mut keys = Vec::new();
+        let mut next_token = None;
+
+        loop {
+            let mut body = json!({});
+            if !self.prefix.is_empty() {
+                body["Filters"] = json!([{ "Key": "name", "Values": [self.prefix] }]);
+            }
+            if let Some(token) = next_token {
+                body["NextToken"] = json!(token);
+            }
+
+            let response: ListSecretsResponse = match self.call("ListSecrets", body)? {
+                Some(response) => response,
+                None => break,
+            };
+            // the name filter matches prefixes of words, so check the prefix once more
+            keys.extend(
+                response.secret_list.into_iter().filter_map(|secret| {
+                    secret.name.strip_prefix(&self.prefix).map(str::to_string)
+                }),
+            );
+
+            next_token = response.next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        _value: T,
+        _expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(no_compare_and_set("secrets_manager", key))
+    }
+}
+
+/// Parse a secret string as JSON, falling back to the plain string.
+fn parse_value<T>(key: &str, raw: &str) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_str(raw).or_else(|_| {
+        let deserializer: StrDeserializer<ValueError> = raw.into_deserializer();
+        T::deserialize(deserializer)
+            .map_err(|err| ConfigError::deserialization("secrets_manager", err).with_key(key))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> SecretsManagerProvider {
+        // nothing listens there, so every call that isn't answered from the cache fails
+        SecretsManagerProvider::new(AwsConfig {
+            region: "eu-west-1".to_string(),
+            access_key_id: "AKID".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+            endpoint: Some("http://127.0.0.1:1".to_string()),
+        })
+    }
+
+    #[test]
+    fn secrets_are_named_below_the_prefix() {
+        let provider = provider().with_prefix("gatekeeper/");
+        assert_eq!(provider.secret_id("tls.cert"), "gatekeeper/tls.cert");
+    }
+
+    #[test]
+    fn cached_secrets_are_served_until_the_refresh_interval() {
+        let provider = provider();
+        provider.remember("password", Some("hunter2".to_string()));
+        provider.remember("deleted", None);
+
+        assert_eq!(provider.get::<String>("password").unwrap(), "hunter2");
+        assert!(!provider.has("deleted").unwrap());
+
+        provider.invalidate();
+        assert!(matches!(
+            provider.get::<String>("password"),
+            Err(ConfigError::Backend { .. })
+        ));
+    }
+
+    #[test]
+    fn a_zero_refresh_interval_disables_the_cache() {
+        let provider = provider().with_refresh_interval(Duration::from_secs(0));
+        provider.remember("password", Some("hunter2".to_string()));
+
+        assert!(provider.get::<String>("password").is_err());
+    }
+
+    #[test]
+    fn secret_strings_fall_back_to_plain_strings() {
+        assert_eq!(parse_value::<u32>("workers", "4").unwrap(), 4);
+        assert_eq!(
+            parse_value::<String>("password", "hunter2").unwrap(),
+            "hunter2"
+        );
+
+        let err = parse_value::<u32>("workers", "four").unwrap_err();
+        assert!(matches!(err, ConfigError::Deserialization { .. }));
+        assert_eq!(err.key(), Some("workers"));
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LockResult, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Number of shards of a default map, well above the cores of an outpost.
+pub(crate) const DEFAULT_SHARDS: usize = 64;
+
+type Shard<V> = HashMap<String, V>;
+
+/// Map spreading its keys by hash over independently locked shards
+///
+/// Single keys lock only their shard. Operations on several keys lock all shards they touch in
+/// ascending order, so they never deadlock with each other and see or make their changes
+/// atomically across shards.
+///
+/// All changes go through [`ShardMut`] or [`WriteAll`], which keep the number of entries up to
+/// date, so it is known without locking any shard.
+pub(crate) struct ShardedMap<V> {
+    shards: Box<[RwLock<Shard<V>>]>,
+    hasher: RandomState,
+    len: AtomicUsize,
+}
+
+/// A single shard locked for writing.
+pub(crate) struct ShardMut<'a, V> {
+    len: &'a AtomicUsize,
+    guard: RwLockWriteGuard<'a, Shard<V>>,
+}
+
+/// Shards locked for reading, all of them.
+pub(crate) struct ReadAll<'a, V> {
+    guards: Vec<RwLockReadGuard<'a, Shard<V>>>,
+}
+
+/// Shards locked for writing, by index.
+pub(crate) struct WriteAll<'a, V> {
+    map: &'a ShardedMap<V>,
+    guards: BTreeMap<usize, RwLockWriteGuard<'a, Shard<V>>>,
+}
+
+impl<V> Default for ShardedMap<V> {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARDS)
+    }
+}
+
+impl<V> ShardedMap<V> {
+    /// Spread the keys over the given number of shards, at least one.
+    pub(crate) fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| RwLock::default()).collect(),
+            hasher: RandomState::new(),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of entries across all shards.
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub(crate) fn shard_of(&self, key: &str) -> usize {
+        (self.hasher.hash_one(key) % self.shards.len() as u64) as usize
+    }
+
+    /// Lock the shard of the key for reading.
+    pub(crate) fn read(&self, key: &str) -> LockResult<RwLockReadGuard<'_, Shard<V>>> {
+        self.shards[self.shard_of(key)].read()
+    }
+
+    /// Lock the shard of the key for writing.
+    pub(crate) fn write(&self, key: &str) -> LockResult<ShardMut<'_, V>> {
+        self.write_shard(self.shard_of(key))
+    }
+
+    /// Lock the shard with the given index for reading.
+    pub(crate) fn read_shard(&self, shard: usize) -> LockResult<RwLockReadGuard<'_, Shard<V>>> {
+        self.shards[shard].read()
+    }
+
+    /// Lock the shard with the given index for writing.
+    pub(crate) fn write_shard(&self, shard: usize) -> LockResult<ShardMut<'_, V>> {
+        let len = &self.len;
+        match self.shards[shard].write() {
+            Ok(guard) => Ok(ShardMut { len, guard }),
+            Err(err) => Err(PoisonError::new(ShardMut {
+                len,
+                guard: err.into_inner(),
+            })),
+        }
+    }
+
+    pub(crate) fn read_all(&self) -> Result<ReadAll<'_, V>, PoisonError<()>> {
+        let guards = self
+            .shards
+            .iter()
+            .map(|shard| shard.read().map_err(|_| PoisonError::new(())))
+            .collect::<Result<_, _>>()?;
+
+        Ok(ReadAll { guards })
+    }
+
+    #[cfg(any(feature = "fs", feature = "s3"))]
+    pub(crate) fn write_all(&self) -> Result<WriteAll<'_, V>, PoisonError<()>> {
+        self.lock(0..self.shards.len())
+    }
+
+    /// Lock the shards of the given keys for writing, only those can be changed.
+    pub(crate) fn write_keys<'k, I>(&self, keys: I) -> Result<WriteAll<'_, V>, PoisonError<()>>
+    where
+        I: IntoIterator<Item = &'k str>,
+    {
+        let mut shards: Vec<usize> = keys.into_iter().map(|key| self.shard_of(key)).collect();
+        shards.sort_unstable();
+        shards.dedup();
+
+        self.lock(shards)
+    }
+
+    /// Lock the given shards, which must be in ascending order.
+    fn lock<I>(&self, shards: I) -> Result<WriteAll<'_, V>, PoisonError<()>>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let guards = shards
+            .into_iter()
+            .map(|shard| {
+                let guard = self.shards[shard]
+                    .write()
+                    .map_err(|_| PoisonError::new(()))?;
+                Ok((shard, guard))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(WriteAll { map: self, guards })
+    }
+}
+
+impl<V> ReadAll<'_, V> {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &V)> {
+        self.guards.iter().flat_map(|shard| shard.iter())
+    }
+}
+
+impl<V> Deref for ShardMut<'_, V> {
+    type Target = Shard<V>;
+
+    fn deref(&self) -> &Shard<V> {
+        &self.guard
+    }
+}
+
+impl<V> ShardMut<'_, V> {
+    pub(crate) fn insert(&mut self, key: String, value: V) -> Option<V> {
+        let previous = self.guard.insert(key, value);
+        if previous.is_none() {
+            self.len.fetch_add(1, Ordering::SeqCst);
+        }
+
+        previous
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> Option<V> {
+        let removed = self.guard.remove(key);
+        if removed.is_some() {
+            self.len.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        removed
+    }
+
+    /// Keep only the entries the predicate returns `true` for.
+    #[cfg(feature = "threads")]
+    pub(crate) fn retain<F>(&mut self, predicate: F)
+    where
+        F: FnMut(&String, &mut V) -> bool,
+    {
+        let before = self.guard.len();
+        self.guard.retain(predicate);
+        self.len
+            .fetch_sub(before - self.guard.len(), Ordering::SeqCst);
+    }
+}
+
+impl<V> WriteAll<'_, V> {
+    fn shard(&mut self, key: &str) -> &mut Shard<V> {
+        self.guards
+            .get_mut(&self.map.shard_of(key))
+            .expect("the shard of the key is locked")
+    }
+
+    pub(crate) fn insert(&mut self, key: String, value: V) -> Option<V> {
+        let previous = self.shard(&key).insert(key, value);
+        if previous.is_none() {
+            self.map.len.fetch_add(1, Ordering::SeqCst);
+        }
+
+        previous
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> Option<V> {
+        let removed = self.shard(key).remove(key);
+        if removed.is_some() {
+            self.map.len.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        removed
+    }
+}
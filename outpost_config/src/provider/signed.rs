@@ -0,0 +1,324 @@
+use crate::file::{lock_shared, write_atomic};
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, KeyPage, Transaction,
+    TransactionalConfigProvider,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryInto;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// File aware provider signing the files it saves and only loading files with a valid signature
+///
+/// Config bundles are produced by the central gatekeeper and shipped to the outposts, which must
+/// not accept a bundle somebody else wrote or changed on the way. Saving writes the file through
+/// the wrapped provider and an Ed25519 signature of its bytes to `<file>.sig`, loading checks the
+/// file against that signature and the configured public key before the wrapped provider reads
+/// it. A missing or mismatching signature fails the load with `ConfigError::Corrupted` and keeps
+/// the current values. Works with every file format, the file itself is left unchanged.
+///
+/// Outposts only need the public key. Saving requires the private key as well, without it a save
+/// fails with `ConfigError::PermissionDenied`.
+///
+/// ```ignore
+/// // central gatekeeper
+/// let bundle = SignedFileProvider::new(TomlProvider::new(), &public_key)?
+///     .with_signing_key(&private_key)?;
+/// bundle.save("bundle/gatekeeper.toml")?;
+///
+/// // outpost
+/// let config = SignedFileProvider::new(TomlProvider::new(), &public_key)?;
+/// config.load("/etc/gatekeeper/gatekeeper.toml")?;
+/// ```
+pub struct SignedFileProvider<P> {
+    inner: P,
+    verifying_key: VerifyingKey,
+    signing_key: Option<SigningKey>,
+}
+
+impl<P> SignedFileProvider<P> {
+    /// Only load files signed by the owner of the given 32 byte Ed25519 public key.
+    pub fn new(inner: P, public_key: &[u8]) -> Result<Self, ConfigError> {
+        let public_key = public_key
+            .try_into()
+            .map_err(|_| ConfigError::backend("signed", "the public key must be 32 bytes long"))?;
+        let verifying_key = VerifyingKey::from_bytes(public_key)
+            .map_err(|err| ConfigError::backend("signed", err))?;
+
+        Ok(Self {
+            inner,
+            verifying_key,
+            signing_key: None,
+        })
+    }
+
+    /// Sign saved files with the given 32 byte Ed25519 private key.
+    pub fn with_signing_key(mut self, private_key: &[u8]) -> Result<Self, ConfigError> {
+        let private_key = private_key
+            .try_into()
+            .map_err(|_| ConfigError::backend("signed", "the private key must be 32 bytes long"))?;
+        self.signing_key = Some(SigningKey::from_bytes(private_key));
+
+        Ok(self)
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Check the file against its signature.
+    fn verify(&self, path: &Path) -> Result<(), ConfigError> {
+        let bytes = fs::read(path).map_err(|err| ConfigError::io("signed", err))?;
+        let encoded = match fs::read_to_string(signature_path(path)) {
+            Ok(encoded) => encoded,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(ConfigError::corrupted(
+                    "signed",
+                    path,
+                    "the file isn't signed",
+                ));
+            }
+            Err(err) => return Err(ConfigError::io("signed", err)),
+        };
+        let signature = STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|raw| Signature::from_slice(&raw).ok())
+            .ok_or_else(|| ConfigError::corrupted("signed", path, "the signature is malformed"))?;
+
+        self.verifying_key
+            .verify_strict(&bytes, &signature)
+            .map_err(|_| {
+                ConfigError::corrupted("signed", path, "the signature doesn't match the file")
+            })
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("signed"))]
+impl<P> ConfigProvider for SignedFileProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get_many(keys)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_many(entries)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("signed"))]
+impl<P> TransactionalConfigProvider for SignedFileProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl<P> FileAwareConfigProvider for SignedFileProvider<P>
+where
+    P: FileAwareConfigProvider,
+{
+    fn load<Q>(&self, path: Q) -> Result<(), ConfigError>
+    where
+        Q: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        // keeps a save from replacing the file between the check and the load
+        let _lock = lock_shared("signed", path)?;
+        self.verify(path)?;
+
+        self.inner.load(path)
+    }
+
+    fn save<Q>(&self, path: Q) -> Result<(), ConfigError>
+    where
+        Q: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let signing_key = self.signing_key.as_ref().ok_or_else(|| {
+            ConfigError::permission_denied("signed", "saving requires the private key")
+        })?;
+        self.inner.save(path)?;
+
+        let bytes = fs::read(path).map_err(|err| ConfigError::io("signed", err))?;
+        let signature = STANDARD.encode(signing_key.sign(&bytes).to_bytes());
+        write_atomic("signed", signature_path(path), |file| {
+            writeln!(file, "{}", signature).map_err(|err| ConfigError::io("signed", err))
+        })
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+/// Path of the signature of the file, `<file>.sig`.
+fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(OsString::from(".sig"));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+
+    const PRIVATE_KEY: [u8; 32] = [7; 32];
+
+    fn public_key() -> [u8; 32] {
+        SigningKey::from_bytes(&PRIVATE_KEY)
+            .verifying_key()
+            .to_bytes()
+    }
+
+    /// A provider verifying with the public key, without signing.
+    fn outpost() -> SignedFileProvider<InMemoryProvider> {
+        SignedFileProvider::new(InMemoryProvider::new(), &public_key()).unwrap()
+    }
+
+    /// Save a file signed with the given key.
+    fn signed(dir: &Path, signing_key: &[u8; 32]) -> PathBuf {
+        let path = dir.join("gatekeeper.json");
+        let central = outpost().with_signing_key(signing_key).unwrap();
+        central.put("workers", 4).unwrap();
+        central.save(&path).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn files_with_a_valid_signature_are_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = signed(dir.path(), &PRIVATE_KEY);
+
+        let outpost = outpost();
+        outpost.load(&path).unwrap();
+        assert_eq!(outpost.get::<u32>("workers").unwrap(), 4);
+    }
+
+    #[test]
+    fn saving_needs_the_signing_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+
+        assert!(matches!(
+            outpost().save(&path),
+            Err(ConfigError::PermissionDenied { .. })
+        ));
+    }
+
+    #[test]
+    fn tampered_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = signed(dir.path(), &PRIVATE_KEY);
+        let outpost = outpost();
+        outpost.load(&path).unwrap();
+
+        // changed on the way, even with a valid checksum
+        let tampered = InMemoryProvider::new();
+        tampered.put("workers", 64).unwrap();
+        tampered.save(&path).unwrap();
+        let err = outpost.load(&path).unwrap_err();
+        assert!(matches!(err, ConfigError::Corrupted { .. }), "{}", err);
+        assert_eq!(outpost.get::<u32>("workers").unwrap(), 4);
+    }
+
+    #[test]
+    fn files_signed_with_another_key_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = signed(dir.path(), &[8; 32]);
+
+        assert!(matches!(
+            outpost().load(&path),
+            Err(ConfigError::Corrupted { .. })
+        ));
+    }
+
+    #[test]
+    fn files_without_a_signature_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = signed(dir.path(), &PRIVATE_KEY);
+        fs::remove_file(signature_path(&path)).unwrap();
+
+        assert!(matches!(
+            outpost().load(&path),
+            Err(ConfigError::Corrupted { .. })
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{raw_version, ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+
+/// Provider persisting its values in an embedded sled database
+///
+/// Writes are durable once sled flushed them to disk, which happens periodically in the
+/// background or explicitly through [`SledProvider::flush`].
+pub struct SledProvider {
+    db: sled::Db,
+}
+
+impl SledProvider {
+    /// Open (or create) the database at the given path.
+    pub fn open<P>(path: P) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let db = sled::open(path).map_err(|err| ConfigError::backend("sled", err))?;
+
+        Ok(Self { db })
+    }
+
+    /// Create a provider backed by a database that is removed once dropped.
+    pub fn temporary() -> Result<Self, ConfigError> {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .map_err(|err| ConfigError::backend("sled", err))?;
+
+        Ok(Self { db })
+    }
+
+    /// Synchronously write all pending changes to disk.
+    pub fn flush(&self) -> Result<(), ConfigError> {
+        self.db
+            .flush()
+            .map_err(|err| ConfigError::backend("sled", err))?;
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("sled"))]
+impl ConfigProvider for SledProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .db
+            .get(key)
+            .map_err(|err| ConfigError::backend("sled", err).with_key(key))?
+            .ok_or_else(|| ConfigError::not_found("sled", key))?;
+        let deserialized = serde_json::from_slice(&raw)
+            .map_err(|err| ConfigError::deserialization("sled", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.db
+            .contains_key(key)
+            .map_err(|err| ConfigError::backend("sled", err).with_key(key))
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_vec(&value)
+            .map_err(|err| ConfigError::serialization("sled", err).with_key(key))?;
+        let _ = self
+            .db
+            .insert(key, serialized)
+            .map_err(|err| ConfigError::backend("sled", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let _ = self
+            .db
+            .remove(key)
+            .map_err(|err| ConfigError::backend("sled", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.db
+            .iter()
+            .keys()
+            .map(|key| {
+                let key = key.map_err(|err| ConfigError::backend("sled", err))?;
+                String::from_utf8(key.to_vec())
+                    .map_err(|err| ConfigError::serialization("sled", err))
+            })
+            .collect()
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_vec(&value)
+            .map_err(|err| ConfigError::serialization("sled", err).with_key(key))?;
+
+        let current = self
+            .db
+            .get(key)
+            .map_err(|err| ConfigError::backend("sled", err).with_key(key))?;
+        let version = current
+            .as_ref()
+            .map(|raw| raw_version(&String::from_utf8_lossy(raw)));
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("sled", key));
+        }
+
+        // swapping fails if another writer replaced the bytes that were just compared
+        self.db
+            .compare_and_swap(key, current, Some(serialized))
+            .map_err(|err| ConfigError::backend("sled", err).with_key(key))?
+            .map_err(|_| ConfigError::conflict("sled", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.sled");
+
+        let provider = SledProvider::open(&path).unwrap();
+        provider.put("workers", 4).unwrap();
+        provider.put("listen", "0.0.0.0:80".to_string()).unwrap();
+        provider.delete("listen").unwrap();
+        provider.flush().unwrap();
+        drop(provider);
+
+        let reopened = SledProvider::open(&path).unwrap();
+        assert_eq!(reopened.list().unwrap(), vec!["workers"]);
+        assert_eq!(reopened.get::<u32>("workers").unwrap(), 4);
+        assert!(!reopened.has("listen").unwrap());
+    }
+
+    #[test]
+    fn put_if_version_swaps_the_compared_value() {
+        let provider = SledProvider::temporary().unwrap();
+        provider.put_if_version("workers", 4, None).unwrap();
+        let version = provider.version("workers").unwrap();
+
+        provider.put("workers", 8).unwrap();
+        assert!(matches!(
+            provider.put_if_version("workers", 16, version.as_deref()),
+            Err(ConfigError::Conflict { .. })
+        ));
+        let version = provider.version("workers").unwrap();
+        provider
+            .put_if_version("workers", 16, version.as_deref())
+            .unwrap();
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 16);
+    }
+}
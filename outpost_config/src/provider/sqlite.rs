@@ -0,0 +1,492 @@
+use crate::{
+    raw_version, ConfigError, ConfigProvider, KeyPage, Transaction, TransactionOp,
+    TransactionalConfigProvider,
+};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// Provider persisting its values in a single table of a SQLite database
+///
+/// Every mutation runs in its own immediate transaction, so concurrent writers from other
+/// threads or processes sharing the database file never observe partial updates. Committing a
+/// [`Transaction`] runs all of its writes in a single database transaction.
+pub struct SqliteProvider {
+    connection: Mutex<Connection>,
+}
+
+const UPSERT: &str = "INSERT INTO config (key, value) VALUES (?1, ?2) \
+     ON CONFLICT(key) DO UPDATE SET value = excluded.value";
+
+const SELECT: &str = "SELECT value FROM config WHERE key = ?1";
+
+const DELETE: &str = "DELETE FROM config WHERE key = ?1";
+
+impl SqliteProvider {
+    /// Open (or create) the database at the given path.
+    pub fn open<P>(path: P) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let connection =
+            Connection::open(path).map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        // WAL lets readers proceed while another connection writes
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        Self::with_connection(connection)
+    }
+
+    /// Create a provider backed by a private in memory database.
+    pub fn open_in_memory() -> Result<Self, ConfigError> {
+        let connection =
+            Connection::open_in_memory().map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        Self::with_connection(connection)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, ConfigError> {
+        connection
+            .busy_timeout(Duration::from_secs(5))
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS config (key TEXT PRIMARY KEY NOT NULL, value TEXT NOT NULL)",
+                [],
+            )
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Lock the connection, a panic while it was locked leaves no transaction open.
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn fetch(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        let connection = self.connection();
+
+        connection
+            .query_row(SELECT, params![key], |row| row.get(0))
+            .optional()
+            .map_err(|err| ConfigError::backend("sqlite", err).with_key(key))
+    }
+
+    fn execute(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<(), ConfigError> {
+        self.write(|transaction| transaction.execute(sql, params).map(|_| ()))
+    }
+
+    /// Run the writes in one immediate transaction, rolled back if they fail.
+    fn write<F, R>(&self, f: F) -> Result<R, ConfigError>
+    where
+        F: FnOnce(&rusqlite::Transaction) -> rusqlite::Result<R>,
+    {
+        let mut connection = self.connection();
+
+        let transaction = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        let result = f(&transaction).map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        transaction
+            .commit()
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        Ok(result)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("sqlite"))]
+impl ConfigProvider for SqliteProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self
+            .fetch(key)?
+            .ok_or_else(|| ConfigError::not_found("sqlite", key))?;
+        let deserialized = serde_json::from_str(&raw)
+            .map_err(|err| ConfigError::deserialization("sqlite", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        Ok(self.fetch(key)?.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("sqlite", err).with_key(key))?;
+
+        self.execute(UPSERT, params![key, serialized])
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.execute(DELETE, params![key])
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let connection = self.connection();
+
+        let mut statement = connection
+            .prepare("SELECT key FROM config")
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        let keys = statement
+            .query_map([], |row| row.get(0))
+            .map_err(|err| ConfigError::backend("sqlite", err))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        Ok(keys)
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        let connection = self.connection();
+
+        // unlike LIKE, comparing the start of the key needs no escaping and is case sensitive
+        let mut statement = connection
+            .prepare("SELECT key FROM config WHERE substr(key, 1, length(?1)) = ?1")
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        let keys = statement
+            .query_map(params![prefix], |row| row.get(0))
+            .map_err(|err| ConfigError::backend("sqlite", err))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        Ok(keys)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        let connection = self.connection();
+
+        let mut statement = connection
+            .prepare("SELECT key FROM config WHERE ?1 IS NULL OR key > ?1 ORDER BY key LIMIT ?2")
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        // one more key than requested tells if there is another page
+        let fetch = limit.max(1).saturating_add(1) as i64;
+        let keys = statement
+            .query_map(params![cursor, fetch], |row| row.get(0))
+            .map_err(|err| ConfigError::backend("sqlite", err))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        Ok(KeyPage::truncated(keys, limit))
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        let deserialize = |raw: String| {
+            serde_json::from_str(&raw)
+                .map_err(|err| ConfigError::deserialization("sqlite", err).with_key(key))
+        };
+
+        if let Some(raw) = self.fetch(key)? {
+            return deserialize(raw);
+        }
+
+        // computed without holding the connection, f may use this provider itself
+        let value = f();
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("sqlite", err).with_key(key))?;
+
+        // the immediate transaction locks out other writers between the check and the insert
+        let existing = self.write(|transaction| {
+            let existing: Option<String> = transaction
+                .query_row(SELECT, params![key], |row| row.get(0))
+                .optional()?;
+
+            if existing.is_none() {
+                transaction.execute(UPSERT, params![key, serialized])?;
+            }
+
+            Ok(existing)
+        })?;
+
+        match existing {
+            // another writer inserted it in the meantime
+            Some(raw) => deserialize(raw),
+            None => Ok(value),
+        }
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("sqlite", err).with_key(key))?;
+
+        // the immediate transaction locks out other writers between the comparison and the write
+        let written = self.write(|transaction| {
+            let existing: Option<String> = transaction
+                .query_row(SELECT, params![key], |row| row.get(0))
+                .optional()?;
+            if existing.as_deref().map(raw_version).as_deref() != expected_version {
+                return Ok(false);
+            }
+
+            transaction.execute(UPSERT, params![key, serialized])?;
+            Ok(true)
+        })?;
+
+        if written {
+            Ok(())
+        } else {
+            Err(ConfigError::conflict("sqlite", key))
+        }
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let connection = self.connection();
+
+        let mut statement = connection
+            .prepare(SELECT)
+            .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+        keys.iter()
+            .map(|key| {
+                let raw: Option<String> = statement
+                    .query_row(params![key], |row| row.get(0))
+                    .optional()
+                    .map_err(|err| ConfigError::backend("sqlite", err))?;
+
+                raw.map(|raw| serde_json::from_str(&raw))
+                    .transpose()
+                    .map_err(|err| ConfigError::deserialization("sqlite", err))
+            })
+            .collect()
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let mut rows = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let serialized = serde_json::to_string(&value)
+                .map_err(|err| ConfigError::serialization("sqlite", err))?;
+            rows.push((key, serialized));
+        }
+
+        self.write(|transaction| {
+            for (key, value) in &rows {
+                transaction.execute(UPSERT, params![key, value])?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("sqlite"))]
+impl TransactionalConfigProvider for SqliteProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        let mut rows = Vec::new();
+        for op in transaction.ops() {
+            let row = match op {
+                TransactionOp::Put { key, value } => {
+                    let serialized = serde_json::to_string(value)
+                        .map_err(|err| ConfigError::serialization("sqlite", err))?;
+                    (key, Some(serialized))
+                }
+                TransactionOp::Delete { key } => (key, None),
+            };
+            rows.push(row);
+        }
+
+        self.write(|transaction| {
+            for (key, value) in &rows {
+                match value {
+                    Some(value) => transaction.execute(UPSERT, params![key, value])?,
+                    None => transaction.execute(DELETE, params![key])?,
+                };
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn values_persist_across_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.db");
+
+        let provider = SqliteProvider::open(&path).unwrap();
+        provider.put("listen", "0.0.0.0:80".to_string()).unwrap();
+        provider.put("workers", 4).unwrap();
+        provider.put("workers", 8).unwrap();
+        provider.delete("listen").unwrap();
+        drop(provider);
+
+        let reopened = SqliteProvider::open(&path).unwrap();
+        assert_eq!(reopened.list().unwrap(), vec!["workers"]);
+        assert_eq!(reopened.get::<u32>("workers").unwrap(), 8);
+        assert!(!reopened.has("listen").unwrap());
+
+        reopened
+            .put_many(vec![("min".to_string(), 1), ("max".to_string(), 16)])
+            .unwrap();
+        assert_eq!(
+            reopened.get_many::<u32>(&["max", "listen", "min"]).unwrap(),
+            vec![Some(16), None, Some(1)]
+        );
+
+        let first = reopened.list_page(None, 2).unwrap();
+        assert_eq!(first.keys, vec!["max", "min"]);
+        let last = reopened.list_page(first.next.as_deref(), 2).unwrap();
+        assert_eq!(last.keys, vec!["workers"]);
+        assert_eq!(last.next, None);
+    }
+
+    #[test]
+    fn failed_transactions_leave_no_trace() {
+        let provider = SqliteProvider::open_in_memory().unwrap();
+        provider.put("route.api.cert", "old".to_string()).unwrap();
+
+        provider
+            .transaction(|txn| {
+                txn.put("route.api.cert", "new".to_string())?
+                    .put("tls.new", "pem".to_string())?;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(provider.get::<String>("route.api.cert").unwrap(), "new");
+
+        // a constraint violation in the middle rolls back the earlier writes
+        provider
+            .connection()
+            .execute_batch(
+                "CREATE TRIGGER reject BEFORE INSERT ON config WHEN NEW.key = 'poison' \
+                 BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+            )
+            .unwrap();
+        let failed = provider.transaction(|txn| {
+            txn.delete("tls.new").put("poison", 1)?;
+            Ok(())
+        });
+        assert!(failed.is_err());
+        assert!(provider.has("tls.new").unwrap());
+    }
+
+    #[test]
+    fn put_if_version_compares_in_the_transaction() {
+        let provider = Arc::new(SqliteProvider::open_in_memory().unwrap());
+        provider.put("workers", 0).unwrap();
+
+        // every writer reads, then increments only if nobody else did in between
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let provider = Arc::clone(&provider);
+                thread::spawn(move || loop {
+                    let version = provider.version("workers").unwrap();
+                    let workers: u32 = provider.get("workers").unwrap();
+                    match provider.put_if_version("workers", workers + 1, version.as_deref()) {
+                        Ok(()) => return,
+                        Err(ConfigError::Conflict { .. }) => continue,
+                        Err(err) => panic!("{}", err),
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 8);
+
+        assert!(matches!(
+            provider.put_if_version("workers", 1, None),
+            Err(ConfigError::Conflict { .. })
+        ));
+        provider
+            .put_if_version("listen", ":8080".to_string(), None)
+            .unwrap();
+        assert!(provider.version("listen").unwrap().is_some());
+    }
+
+    #[test]
+    fn get_or_insert_with_computes_without_holding_the_connection() {
+        let provider = Arc::new(SqliteProvider::open_in_memory().unwrap());
+        provider.put("workers", 4).unwrap();
+
+        // the value can be derived from other keys of the same provider
+        let threads: u32 = provider
+            .get_or_insert_with("threads", || provider.get::<u32>("workers").unwrap() * 2)
+            .unwrap();
+        assert_eq!(threads, 8);
+        let threads: u32 = provider.get_or_insert_with("threads", || 1).unwrap();
+        assert_eq!(threads, 8);
+
+        // a value inserted while it was computed wins
+        let raced: u32 = provider
+            .get_or_insert_with("raced", || {
+                provider.put("raced", 1).unwrap();
+                2
+            })
+            .unwrap();
+        assert_eq!(raced, 1);
+        assert_eq!(provider.get::<u32>("raced").unwrap(), 1);
+
+        // a panic while the connection was locked doesn't break the provider
+        let poisoned = Arc::clone(&provider);
+        thread::spawn(move || {
+            let _connection = poisoned.connection();
+            panic!("poisoned");
+        })
+        .join()
+        .unwrap_err();
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        let provider = Arc::new(SqliteProvider::open_in_memory().unwrap());
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let provider = Arc::clone(&provider);
+                thread::spawn(move || provider.put(&format!("key{}", i), i).unwrap())
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(provider.list().unwrap().len(), 8);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::aws::{AwsClient, AwsConfig};
+use crate::{no_compare_and_set, ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// `GetParameters` accepts at most this many names per call.
+const BATCH_SIZE: usize = 10;
+
+/// Provider mapping config keys to parameters of the AWS SSM Parameter Store
+///
+/// A key is stored as the parameter `<path>/<key>`. Values are always read with decryption, so
+/// SecureString parameters are returned in plain text; writes use SecureString once
+/// [`SsmProvider::with_secure_strings`] is enabled.
+pub struct SsmProvider {
+    client: AwsClient,
+    path: String,
+    secure: bool,
+    kms_key_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Parameter {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetParameterResponse {
+    parameter: Parameter,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetParametersResponse {
+    #[serde(default)]
+    parameters: Vec<Parameter>,
+    next_token: Option<String>,
+}
+
+impl SsmProvider {
+    /// Create a provider storing its parameters below the given path, e.g. `/gatekeeper`.
+    pub fn new<S>(config: AwsConfig, path: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            client: AwsClient::new(config, "ssm", "ssm"),
+            path: path.into().trim_end_matches('/').to_string(),
+            secure: false,
+            kms_key_id: None,
+        }
+    }
+
+    /// Write new parameters as SecureString, optionally encrypted with the given KMS key
+    /// instead of the account default.
+    pub fn with_secure_strings(mut self, kms_key_id: Option<String>) -> Self {
+        self.secure = true;
+        self.kms_key_id = kms_key_id;
+        self
+    }
+
+    /// Get several values at once, batching the lookups into as few calls as possible.
+    ///
+    /// Keys that don't exist are missing from the returned map.
+    pub fn get_many<T>(&self, keys: &[&str]) -> Result<HashMap<String, T>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let mut values = HashMap::with_capacity(keys.len());
+
+        for chunk in keys.chunks(BATCH_SIZE) {
+            let names: Vec<String> = chunk.iter().map(|key| self.name(key)).collect();
+            let response: GetParametersResponse = self
+                .call(
+                    "GetParameters",
+                    json!({ "Names": names, "WithDecryption": true }),
+                )?
+                .ok_or_else(|| ConfigError::not_found("ssm", chunk.join(", ")))?;
+
+            for parameter in response.parameters {
+                if let Some(key) = self.key(&parameter.name) {
+                    values.insert(key, decode(&parameter.value)?);
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
+    fn name(&self, key: &str) -> String {
+        format!("{}/{}", self.path, key)
+    }
+
+    fn key(&self, name: &str) -> Option<String> {
+        name.strip_prefix(&self.path)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .map(str::to_string)
+    }
+
+    fn call<R>(&self, operation: &str, body: Value) -> Result<Option<R>, ConfigError>
+    where
+        R: DeserializeOwned,
+    {
+        self.client
+            .call_json(&format!("AmazonSSM.{}", operation), "1.1", &body)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("ssm"))]
+impl ConfigProvider for SsmProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let response: GetParameterResponse = self
+            .call(
+                "GetParameter",
+                json!({ "Name": self.name(key), "WithDecryption": true }),
+            )?
+            .ok_or_else(|| ConfigError::not_found("ssm", key))?;
+
+        decode(&response.parameter.value)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let response: Option<Value> =
+            self.call("GetParameter", json!({ "Name": self.name(key) }))?;
+
+        Ok(response.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_string(&value)
+            .map_err(|err| ConfigError::serialization("ssm", err).with_key(key))?;
+
+        let mut body = json!({
+            "Name": self.name(key),
+            "Value": serialized,
+            "Type": if self.secure { "SecureString" } else { "String" },
+            "Overwrite": true,
+        });
+        if let Some(kms_key_id) = &self.kms_key_id {
+            body["KeyId"] = json!(kms_key_id);
+        }
+
+        let _: Option<Value> = self.call("PutParameter", body)?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let _: Option<Value> = self.call("DeleteParameter", json!({ "Name": self.name(key) }))?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        let mut keys = Vec::new();
+        let mut next_token = None;
+
+        loop {
+            let mut body = json!({ "Path": path, "Recursive": true });
+            if let Some(token) = next_token {
+                body["NextToken"] = json!(token);
+            }
+
+            let response: GetParametersResponse = match self.call("GetParametersByPath", body)? {
+                Some(response) => response,
+                None => break,
+            };
+            keys.extend(
+                response
+                    .parameters
+                    .iter()
+                    .filter_map(|parameter| self.key(&parameter.name)),
+            );
+
+            next_token = response.next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        _value: T,
+        _expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        Err(no_compare_and_set("ssm", key))
+    }
+}
+
+fn decode<T>(raw: &str) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_str(raw).map_err(|err| ConfigError::deserialization("ssm", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(path: &str) -> SsmProvider {
+        let config = AwsConfig {
+            region: "eu-west-1".to_string(),
+            access_key_id: "AKID".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+            endpoint: None,
+        };
+
+        SsmProvider::new(config, path)
+    }
+
+    #[test]
+    fn keys_map_to_parameters_below_the_path() {
+        let provider = provider("/gatekeeper/");
+        assert_eq!(provider.name("routes.api"), "/gatekeeper/routes.api");
+        assert_eq!(provider.name("tls/cert"), "/gatekeeper/tls/cert");
+    }
+
+    #[test]
+    fn parameters_outside_the_path_have_no_key() {
+        let provider = provider("/gatekeeper");
+        assert_eq!(
+            provider.key("/gatekeeper/tls/cert"),
+            Some("tls/cert".to_string())
+        );
+        assert_eq!(provider.key("/gatekeeper-staging/workers"), None);
+        assert_eq!(provider.key("/other/workers"), None);
+    }
+
+    #[test]
+    fn values_are_json() {
+        assert_eq!(decode::<u32>("4").unwrap(), 4);
+        assert_eq!(decode::<String>("\":8080\"").unwrap(), ":8080");
+        assert!(matches!(
+            decode::<u32>(":8080"),
+            Err(ConfigError::Deserialization { .. })
+        ));
+    }
+
+    #[test]
+    fn batch_responses_may_omit_the_parameters() {
+        let response: GetParametersResponse =
+            serde_json::from_str(r#"{"InvalidParameters": ["/gatekeeper/missing"]}"#).unwrap();
+        assert!(response.parameters.is_empty());
+        assert!(response.next_token.is_none());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::provider::scoped::ScopedProvider;
+use crate::{ConfigError, ConfigProvider, Transaction, TransactionalConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Prefix of the keys of all tenants, followed by the tenant id and a `.`.
+pub const TENANTS_PREFIX: &str = "tenants.";
+
+/// View of a shared provider limited to the keys of one tenant
+///
+/// Every key is stored below `tenants.<id>.`, e.g. `proxy.timeout` of the tenant `acme` becomes
+/// `tenants.acme.proxy.timeout`, and `list` only returns the keys of the tenant with the prefix
+/// stripped. Tenant ids are restricted to ASCII letters, digits, `-` and `_`, so the prefix of
+/// one tenant is never the beginning of another one's and keys, prefixes and glob patterns can't
+/// reach outside of it.
+pub struct TenantProvider<P> {
+    scoped: ScopedProvider<P>,
+    tenant: String,
+}
+
+impl<P> TenantProvider<P> {
+    /// Fails with `ConfigError::Backend` if the tenant id is empty or contains other characters
+    /// than ASCII letters, digits, `-` and `_`.
+    pub fn new<S>(inner: P, tenant: S) -> Result<Self, ConfigError>
+    where
+        S: Into<String>,
+    {
+        let tenant = tenant.into();
+        let valid = !tenant.is_empty()
+            && tenant
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(ConfigError::backend(
+                "tenant",
+                format!("{:?} isn't a valid tenant id", tenant),
+            ));
+        }
+
+        Ok(Self {
+            scoped: ScopedProvider::new(inner, format!("{}{}.", TENANTS_PREFIX, tenant)),
+            tenant,
+        })
+    }
+
+    /// The id of the tenant whose keys this view holds.
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    /// The wrapped provider holding the keys of all tenants.
+    pub fn inner(&self) -> &P {
+        self.scoped.inner()
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("tenant", tenant = %self.tenant))]
+impl<P> ConfigProvider for TenantProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.scoped.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.scoped.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.scoped.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.scoped.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        // asking for the prefix lets remote backends filter before sending the keys
+        self.scoped.list_prefix("")
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.scoped.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.scoped.list_glob(pattern)
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.scoped.get_or_insert_with(key, f)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.scoped.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.scoped.put_if_version(key, value, expected_version)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.scoped.get_many(keys)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.scoped.put_many(entries)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("tenant", tenant = %self.tenant))]
+impl<P> TransactionalConfigProvider for TenantProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.scoped.commit(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::sync::Arc;
+
+    #[test]
+    fn tenants_never_see_each_other() {
+        let shared = Arc::new(InMemoryProvider::new());
+        let acme = TenantProvider::new(shared.clone(), "acme").unwrap();
+        let acme_eu = TenantProvider::new(shared.clone(), "acme-eu").unwrap();
+
+        acme.put("proxy.timeout", 30).unwrap();
+        acme_eu.put("proxy.timeout", 60).unwrap();
+        acme_eu.put("proxy.listen", ":443".to_string()).unwrap();
+
+        assert_eq!(acme.get::<u32>("proxy.timeout").unwrap(), 30);
+        assert_eq!(acme.list().unwrap(), vec!["proxy.timeout"]);
+        assert_eq!(acme.list_glob("*").unwrap(), vec!["proxy.timeout"]);
+        assert!(!acme.has("proxy.listen").unwrap());
+        assert_eq!(acme.snapshot().unwrap().values().len(), 1);
+        assert_eq!(
+            shared.get::<u32>("tenants.acme-eu.proxy.timeout").unwrap(),
+            60
+        );
+
+        acme.delete("proxy.timeout").unwrap();
+        assert_eq!(acme_eu.list().unwrap().len(), 2);
+
+        for tenant in ["", "acme.eu", "acme*", "../acme"] {
+            assert!(TenantProvider::new(shared.clone(), tenant).is_err());
+        }
+    }
+}
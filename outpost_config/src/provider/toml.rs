@@ -0,0 +1,261 @@
+use crate::file::{lock_exclusive, lock_shared, write_atomic};
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, ProviderStats, Transaction,
+    TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::RwLock;
+use toml_edit::{DocumentMut, Item};
+
+/// File aware provider persisting its values as TOML
+///
+/// Top level TOML keys map to config keys. The document read by the last `load` is kept around
+/// so that comments and formatting of unchanged entries survive a `save`.
+#[derive(Default)]
+pub struct TomlProvider {
+    inner: InMemoryProvider,
+    document: RwLock<DocumentMut>,
+}
+
+impl TomlProvider {
+    pub fn new() -> Self {
+        Self {
+            inner: InMemoryProvider::new(),
+            document: RwLock::new(DocumentMut::new()),
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("toml"))]
+impl ConfigProvider for TomlProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.inner.stats()
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("toml"))]
+impl TransactionalConfigProvider for TomlProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl FileAwareConfigProvider for TomlProvider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_shared("toml", path.as_ref())?;
+        let raw = fs::read_to_string(path).map_err(|err| ConfigError::io("toml", err))?;
+        let document: DocumentMut = raw
+            .parse()
+            .map_err(|err| ConfigError::deserialization("toml", err))?;
+
+        let values: HashMap<String, Value> = toml_edit::de::from_document(document.clone())
+            .map_err(|err| ConfigError::deserialization("toml", err))?;
+
+        let mut write_guard = self.inner.write()?;
+
+        for (k, v) in values {
+            let serialized =
+                serde_json::to_string(&v).map_err(|err| ConfigError::serialization("toml", err))?;
+            write_guard.insert(k, serialized);
+        }
+
+        *self.document.write().unwrap() = document;
+        self.inner.mark_loaded(changes);
+
+        Ok(())
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_exclusive("toml", path.as_ref())?;
+        let mut document = self.document.write().unwrap();
+
+        write_atomic("toml", path, |file| {
+            let read_guard = self.inner.read()?;
+
+            let values = read_guard
+                .persistent()
+                .map(|(k, v)| Ok((k.as_str(), serde_json::from_str(v)?)))
+                .collect::<Result<BTreeMap<&str, Value>, serde_json::Error>>()
+                .map_err(|err| ConfigError::deserialization("toml", err))?;
+
+            // render the current values and merge them into the previously loaded document so
+            // entries that did not change keep their comments and formatting
+            let rendered = toml_edit::ser::to_document(&values)
+                .map_err(|err| ConfigError::serialization("toml", err))?;
+
+            let stale: Vec<String> = document
+                .iter()
+                .map(|(k, _)| k.to_string())
+                .filter(|k| !values.contains_key(k.as_str()))
+                .collect();
+
+            for key in stale {
+                document.remove(&key);
+            }
+
+            for (key, item) in rendered.iter() {
+                merge_item(document.as_table_mut(), key, item.clone());
+            }
+
+            file.write_all(document.to_string().as_bytes())
+                .map_err(|err| ConfigError::io("toml", err))
+        })?;
+        self.inner.mark_saved(changes);
+
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+/// Replace the item stored under key unless it already holds the same value, carrying over the
+/// decoration (comments, whitespace) of the item that gets replaced.
+fn merge_item(table: &mut toml_edit::Table, key: &str, mut item: Item) {
+    let existing = match table.get_mut(key) {
+        Some(existing) => existing,
+        None => {
+            table.insert(key, item);
+            return;
+        }
+    };
+
+    if same_value(existing, &item) {
+        return;
+    }
+
+    match (&*existing, &mut item) {
+        (Item::Value(old), Item::Value(new)) => *new.decor_mut() = old.decor().clone(),
+        (Item::Table(old), Item::Table(new)) => *new.decor_mut() = old.decor().clone(),
+        _ => {}
+    }
+
+    *existing = item;
+}
+
+fn same_value(a: &Item, b: &Item) -> bool {
+    let to_json = |item: &Item| {
+        let mut table = toml_edit::Table::new();
+        table.insert("v", item.clone());
+        toml_edit::de::from_document::<HashMap<String, Value>>(DocumentMut::from(table)).ok()
+    };
+
+    match (to_json(a), to_json(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+
+    #[test]
+    fn save_preserves_comments_of_untouched_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "# where to listen\nlisten = \"0.0.0.0:80\"\n\n# upstream pool\n[upstream]\ntimeout = 5 # seconds\n",
+        )
+        .unwrap();
+
+        let provider = TomlProvider::new();
+        provider.load(&path).unwrap();
+        assert_eq!(provider.get::<String>("listen").unwrap(), "0.0.0.0:80");
+
+        provider
+            .put("listen", "127.0.0.1:8080".to_string())
+            .unwrap();
+        provider.put("workers", 4).unwrap();
+        provider.save(&path).unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("# where to listen\nlisten = \"127.0.0.1:8080\""));
+        assert!(saved.contains("# upstream pool\n[upstream]\ntimeout = 5 # seconds"));
+
+        let reloaded = TomlProvider::new();
+        reloaded.load(&path).unwrap();
+        assert_eq!(reloaded.get::<u32>("workers").unwrap(), 4);
+    }
+
+    #[test]
+    fn exports_any_provider_as_toml() {
+        let source = InMemoryProvider::new();
+        source.put("routes.api", "10.0.0.1".to_string()).unwrap();
+        source.put("workers", 4).unwrap();
+
+        let mut exported = Vec::new();
+        source.export(Format::Toml, &mut exported).unwrap();
+        let exported = String::from_utf8(exported).unwrap();
+        assert!(exported.contains("\"routes.api\" = \"10.0.0.1\""));
+
+        let provider = TomlProvider::new();
+        provider.import(exported.as_bytes(), Format::Toml).unwrap();
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+        assert_eq!(provider.get::<String>("routes.api").unwrap(), "10.0.0.1");
+    }
+}
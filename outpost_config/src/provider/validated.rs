@@ -0,0 +1,224 @@
+use crate::{
+    ConfigError, ConfigProvider, KeyPage, Transaction, TransactionOp, TransactionalConfigProvider,
+};
+use jsonschema::Validator;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::SystemTime;
+
+/// Provider rejecting writes that don't match the JSON Schema of their key
+///
+/// Schemas are registered per key prefix and every write is checked against all schemas whose
+/// prefix matches the key, before it reaches the inner provider. Schemas generated with
+/// `schemars` can be passed after converting them with `serde_json::to_value`. Reads are passed
+/// through unchecked.
+pub struct ValidatedProvider<P> {
+    inner: P,
+    schemas: Vec<(String, Validator)>,
+}
+
+impl<P> ValidatedProvider<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            schemas: Vec::new(),
+        }
+    }
+
+    /// Validate the values of all keys starting with the given prefix against the schema.
+    /// Returns a ConfigError if the schema itself is invalid.
+    pub fn with_schema<S>(mut self, key_prefix: S, schema: &Value) -> Result<Self, ConfigError>
+    where
+        S: Into<String>,
+    {
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|err| ConfigError::backend("validated", format!("invalid schema: {}", err)))?;
+        self.schemas.push((key_prefix.into(), validator));
+
+        Ok(self)
+    }
+
+    /// The wrapped provider, writes to it aren't validated.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn validate(&self, key: &str, value: &Value) -> Result<(), ConfigError> {
+        for (prefix, validator) in &self.schemas {
+            if !key.starts_with(prefix.as_str()) {
+                continue;
+            }
+
+            if let Err(err) = validator.validate(value) {
+                return Err(ConfigError::Validation {
+                    key: key.to_string(),
+                    provider: "validated",
+                    path: err.instance_path.as_str().to_string(),
+                    message: err.to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn to_validated_value<T>(&self, key: &str, value: T) -> Result<Value, ConfigError>
+    where
+        T: Serialize,
+    {
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("validated", err).with_key(key))?;
+        self.validate(key, &value)?;
+
+        Ok(value)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("validated"))]
+impl<P> ConfigProvider for ValidatedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = self.to_validated_value(key, value)?;
+
+        self.inner.put(key, value)
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = self.to_validated_value(key, value)?;
+
+        self.inner.put_if_version(key, value, expected_version)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_prefix(prefix)
+    }
+
+    fn list_glob(&self, pattern: &str) -> Result<Vec<String>, ConfigError> {
+        self.inner.list_glob(pattern)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.inner.list_page(cursor, limit)
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        self.inner.modified(key)
+    }
+
+    fn get_many<T>(&self, keys: &[&str]) -> Result<Vec<Option<T>>, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get_many(keys)
+    }
+
+    fn put_many<T>(&self, entries: Vec<(String, T)>) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        // validate everything up front, so an invalid entry doesn't leave a partial write
+        let mut validated = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let value = self.to_validated_value(&key, value)?;
+            validated.push((key, value));
+        }
+
+        self.inner.put_many(validated)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("validated"))]
+impl<P> TransactionalConfigProvider for ValidatedProvider<P>
+where
+    P: TransactionalConfigProvider,
+{
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        for op in transaction.ops() {
+            if let TransactionOp::Put { key, value } = op {
+                self.validate(key, value)?;
+            }
+        }
+
+        self.inner.commit(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use serde_json::json;
+
+    #[test]
+    fn rejects_values_violating_the_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "methods": { "type": "array", "items": { "enum": ["GET", "POST"] } }
+            },
+            "required": ["methods"]
+        });
+        let provider = ValidatedProvider::new(InMemoryProvider::new())
+            .with_schema("policy.", &schema)
+            .unwrap();
+
+        provider
+            .put("policy.admin", json!({ "methods": ["GET", "POST"] }))
+            .unwrap();
+        // keys outside of the prefix aren't checked
+        provider.put("workers", 4).unwrap();
+
+        match provider.put("policy.guest", json!({ "methods": ["GET", "DELETE"] })) {
+            Err(ConfigError::Validation { key, path, .. }) => {
+                assert_eq!(key, "policy.guest");
+                assert_eq!(path, "/methods/1");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(!provider.has("policy.guest").unwrap());
+
+        let batch = vec![
+            ("policy.a".to_string(), json!({ "methods": [] })),
+            ("policy.b".to_string(), json!({})),
+        ];
+        assert!(provider.put_many(batch).is_err());
+        assert!(!provider.has("policy.a").unwrap());
+    }
+}
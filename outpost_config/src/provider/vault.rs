@@ -0,0 +1,404 @@
+use crate::http::{http_error, not_found_as_none};
+use crate::{value_version, ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use ureq::{Agent, Request};
+
+/// Provider reading and writing secrets of a Vault KV version 2 engine
+///
+/// Every config key maps to its own secret below the prefix, holding the value in a single
+/// `value` field. The token is renewed in the background of regular requests once half of its
+/// ttl has passed, so long running nodes keep their access without re-authenticating.
+pub struct VaultProvider {
+    agent: Agent,
+    endpoint: String,
+    token: String,
+    mount: String,
+    prefix: String,
+    renew_at: Mutex<Option<Instant>>,
+}
+
+#[derive(Deserialize)]
+struct SecretResponse {
+    data: SecretData,
+}
+
+#[derive(Deserialize)]
+struct SecretData {
+    data: SecretValue,
+    metadata: Option<SecretMetadata>,
+}
+
+#[derive(Deserialize)]
+struct SecretMetadata {
+    version: u64,
+}
+
+#[derive(Deserialize)]
+struct SecretValue {
+    value: Value,
+}
+
+#[derive(Deserialize)]
+struct ListResponse {
+    data: ListData,
+}
+
+#[derive(Deserialize)]
+struct ListData {
+    keys: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct LookupResponse {
+    data: TokenData,
+}
+
+#[derive(Deserialize)]
+struct TokenData {
+    ttl: u64,
+    #[serde(default)]
+    renewable: bool,
+}
+
+#[derive(Deserialize)]
+struct RenewResponse {
+    auth: RenewData,
+}
+
+#[derive(Deserialize)]
+struct RenewData {
+    lease_duration: u64,
+    #[serde(default)]
+    renewable: bool,
+}
+
+impl VaultProvider {
+    /// Connect to the Vault server at the given url, authenticating with the given token.
+    ///
+    /// The token is looked up once to find out when it has to be renewed.
+    pub fn connect<S, T>(endpoint: S, token: T) -> Result<Self, ConfigError>
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        let provider = Self {
+            agent: Agent::new(),
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            token: token.into(),
+            mount: "secret".to_string(),
+            prefix: String::new(),
+            renew_at: Mutex::new(None),
+        };
+
+        let lookup: LookupResponse = provider
+            .request("GET", "auth/token/lookup-self")
+            .call()
+            .map_err(|err| http_error("vault", err))?
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("vault", err))?;
+        *provider.renew_at.lock().unwrap() = renew_deadline(lookup.data.ttl, lookup.data.renewable);
+
+        Ok(provider)
+    }
+
+    /// Use the KV engine mounted at the given path instead of `secret`.
+    pub fn with_mount<S>(mut self, mount: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.mount = mount.into().trim_matches('/').to_string();
+        self
+    }
+
+    /// Store all secrets below the given path prefix.
+    pub fn with_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Renew the token right away, regardless of its remaining ttl.
+    pub fn renew_token(&self) -> Result<(), ConfigError> {
+        let mut renew_at = self.renew_at.lock().unwrap();
+
+        let renewed: RenewResponse = self
+            .request("POST", "auth/token/renew-self")
+            .call()
+            .map_err(|err| http_error("vault", err))?
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("vault", err))?;
+        *renew_at = renew_deadline(renewed.auth.lease_duration, renewed.auth.renewable);
+
+        Ok(())
+    }
+
+    fn ensure_token(&self) -> Result<(), ConfigError> {
+        let due = match *self.renew_at.lock().unwrap() {
+            Some(renew_at) => Instant::now() >= renew_at,
+            None => false,
+        };
+
+        if due {
+            self.renew_token()?;
+        }
+
+        Ok(())
+    }
+
+    fn request(&self, method: &str, path: &str) -> Request {
+        self.agent
+            .request(method, &format!("{}/v1/{}", self.endpoint, path))
+            .set("X-Vault-Token", &self.token)
+    }
+
+    fn secret_path(&self, kind: &str, key: &str) -> String {
+        format!("{}/{}/{}{}", self.mount, kind, self.prefix, key)
+    }
+
+    fn list_path(&self, path: &str) -> Result<Vec<String>, ConfigError> {
+        self.ensure_token()?;
+
+        let response = match not_found_as_none(
+            "vault",
+            self.request("LIST", &self.secret_path("metadata", path))
+                .call(),
+        )? {
+            Some(response) => response,
+            None => return Ok(Vec::new()),
+        };
+        let listed: ListResponse = response
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("vault", err))?;
+
+        let mut keys = Vec::new();
+        for key in listed.data.keys {
+            let full = format!("{}{}", path, key);
+
+            // vault lists one level at a time, folders carry a trailing slash
+            if full.ends_with('/') {
+                keys.extend(self.list_path(&full)?);
+            } else {
+                keys.push(full);
+            }
+        }
+
+        Ok(keys)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("vault"))]
+impl ConfigProvider for VaultProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.ensure_token()?;
+
+        let response = not_found_as_none(
+            "vault",
+            self.request("GET", &self.secret_path("data", key)).call(),
+        )?
+        .ok_or_else(|| ConfigError::not_found("vault", key))?;
+        let secret: SecretResponse = response
+            .into_json()
+            .map_err(|err| ConfigError::deserialization("vault", err).with_key(key))?;
+
+        serde_json::from_value(secret.data.data.value)
+            .map_err(|err| ConfigError::deserialization("vault", err).with_key(key))
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.ensure_token()?;
+
+        let response = not_found_as_none(
+            "vault",
+            self.request("GET", &self.secret_path("data", key)).call(),
+        )?;
+
+        Ok(response.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(&value)
+            .map_err(|err| ConfigError::serialization("vault", err).with_key(key))?;
+        self.ensure_token()?;
+
+        self.request("POST", &self.secret_path("data", key))
+            .send_json(json!({ "data": { "value": value } }))
+            .map_err(|err| http_error("vault", err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.ensure_token()?;
+
+        // removing the metadata drops all versions, so the key disappears from `list` as well
+        not_found_as_none(
+            "vault",
+            self.request("DELETE", &self.secret_path("metadata", key))
+                .call(),
+        )?;
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.list_path("")
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let value = serde_json::to_value(&value)
+            .map_err(|err| ConfigError::serialization("vault", err).with_key(key))?;
+        self.ensure_token()?;
+
+        let current: Option<SecretResponse> = not_found_as_none(
+            "vault",
+            self.request("GET", &self.secret_path("data", key)).call(),
+        )?
+        .map(|response| response.into_json())
+        .transpose()
+        .map_err(|err| ConfigError::deserialization("vault", err).with_key(key))?;
+        let version = current
+            .as_ref()
+            .map(|secret| value_version(&secret.data.data.value));
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("vault", key));
+        }
+
+        // vault refuses the write if the secret got another version, 0 only creates it
+        let cas = current
+            .and_then(|secret| secret.data.metadata)
+            .map_or(0, |metadata| metadata.version);
+        self.request("POST", &self.secret_path("data", key))
+            .send_json(json!({ "options": { "cas": cas }, "data": { "value": value } }))
+            .map_err(|err| cas_error(key, err))?;
+
+        Ok(())
+    }
+}
+
+/// Map a refused check-and-set write to ConfigError::Conflict, other failures as usual.
+fn cas_error(key: &str, err: ureq::Error) -> ConfigError {
+    match err {
+        ureq::Error::Status(400, response) => {
+            let body = response.into_string().unwrap_or_default();
+            if body.contains("check-and-set") {
+                ConfigError::conflict("vault", key)
+            } else {
+                ConfigError::backend("vault", body).with_key(key)
+            }
+        }
+        err => http_error("vault", err).with_key(key),
+    }
+}
+
+/// Renew once half of the ttl has passed. Tokens without ttl never expire.
+fn renew_deadline(ttl: u64, renewable: bool) -> Option<Instant> {
+    if ttl == 0 || !renewable {
+        return None;
+    }
+
+    Some(Instant::now() + Duration::from_secs(ttl) / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> VaultProvider {
+        VaultProvider {
+            agent: Agent::new(),
+            endpoint: "http://vault:8200".to_string(),
+            token: "token".to_string(),
+            mount: "secret".to_string(),
+            prefix: String::new(),
+            renew_at: Mutex::new(None),
+        }
+    }
+
+    fn status(status: u16, body: &str) -> ureq::Error {
+        ureq::Error::Status(status, ureq::Response::new(status, "", body).unwrap())
+    }
+
+    #[test]
+    fn secrets_live_below_the_mount_and_prefix() {
+        assert_eq!(
+            provider().secret_path("data", "workers"),
+            "secret/data/workers"
+        );
+
+        let provider = provider().with_mount("/kv/").with_prefix("gk/");
+        assert_eq!(
+            provider.secret_path("data", "workers"),
+            "kv/data/gk/workers"
+        );
+        assert_eq!(
+            provider.secret_path("metadata", "routes/"),
+            "kv/metadata/gk/routes/"
+        );
+    }
+
+    #[test]
+    fn tokens_are_renewed_at_half_their_ttl() {
+        assert_eq!(renew_deadline(0, true), None);
+        assert_eq!(renew_deadline(3600, false), None);
+
+        let deadline = renew_deadline(3600, true).unwrap();
+        let remaining = deadline - Instant::now();
+        assert!(remaining <= Duration::from_secs(1800));
+        assert!(remaining > Duration::from_secs(1790));
+    }
+
+    #[test]
+    fn responses_carry_the_value_and_version() {
+        let secret: SecretResponse = serde_json::from_str(
+            r#"{"data": {"data": {"value": [1, 2]}, "metadata": {"version": 3, "destroyed": false}}}"#,
+        )
+        .unwrap();
+        assert_eq!(secret.data.data.value, json!([1, 2]));
+        assert_eq!(secret.data.metadata.unwrap().version, 3);
+
+        let lookup: LookupResponse = serde_json::from_str(r#"{"data": {"ttl": 0}}"#).unwrap();
+        assert_eq!((lookup.data.ttl, lookup.data.renewable), (0, false));
+    }
+
+    #[test]
+    fn refused_check_and_set_writes_conflict() {
+        let refused = status(
+            400,
+            r#"{"errors": ["check-and-set parameter did not match the current version"]}"#,
+        );
+        assert!(matches!(
+            cas_error("workers", refused),
+            ConfigError::Conflict { .. }
+        ));
+
+        let invalid = status(400, r#"{"errors": ["no data provided"]}"#);
+        let err = cas_error("workers", invalid);
+        assert!(matches!(err, ConfigError::Backend { .. }));
+        assert!(err.to_string().contains("no data provided"), "{}", err);
+
+        assert!(matches!(
+            cas_error("workers", status(403, "")),
+            ConfigError::PermissionDenied { .. }
+        ));
+    }
+}
@@ -0,0 +1,281 @@
+use crate::{ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Default prefix of the keys holding the revisions of the other keys.
+const HISTORY_PREFIX: &str = ".history.";
+
+/// Provider recording every change of a key as a new revision
+///
+/// The revisions of a key are stored in the inner provider next to the key itself, below a
+/// reserved prefix (`.history.` by default) that is hidden from `list`, so a persistent inner
+/// provider keeps the history across restarts. Revisions are numbered per key starting at 1.
+///
+/// Writes through this provider are serialized, but writes made to the inner provider directly
+/// aren't recorded.
+pub struct VersionedProvider<P> {
+    inner: P,
+    history_prefix: String,
+    write_lock: Mutex<()>,
+}
+
+/// A recorded change of a key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Revision {
+    pub revision: u64,
+    pub timestamp: SystemTime,
+    /// The value written by the change, `None` if the key was deleted.
+    pub value: Option<Value>,
+}
+
+impl<P> VersionedProvider<P>
+where
+    P: ConfigProvider,
+{
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            history_prefix: HISTORY_PREFIX.to_string(),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Store the revisions below the given prefix instead of `.history.`.
+    pub fn with_history_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.history_prefix = prefix.into();
+        self
+    }
+
+    /// All recorded revisions of the key, oldest first.
+    pub fn history(&self, key: &str) -> Result<Vec<Revision>, ConfigError> {
+        Ok(self
+            .inner
+            .get_opt(&self.history_key(key))?
+            .unwrap_or_default())
+    }
+
+    /// The value of the key as of the given revision.
+    /// Returns a ConfigError::NotFound if the revision doesn't exist or deleted the key.
+    pub fn get_at<T>(&self, key: &str, revision: u64) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let revision = self
+            .history(key)?
+            .into_iter()
+            .find(|r| r.revision == revision)
+            .ok_or_else(|| ConfigError::not_found("versioned", key))?;
+
+        from_revision(key, revision)
+    }
+
+    /// The value the key had at the given point in time.
+    /// Returns a ConfigError::NotFound if the key didn't exist at that time.
+    pub fn get_as_of<T>(&self, key: &str, time: SystemTime) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let revision = self
+            .history(key)?
+            .into_iter()
+            .take_while(|r| r.timestamp <= time)
+            .last()
+            .ok_or_else(|| ConfigError::not_found("versioned", key))?;
+
+        from_revision(key, revision)
+    }
+
+    /// Restore the value the key had at the given revision, recorded as a new revision.
+    pub fn revert(&self, key: &str, revision: u64) -> Result<(), ConfigError> {
+        let _guard = self.write_lock.lock().unwrap();
+
+        let value = self
+            .history(key)?
+            .into_iter()
+            .find(|r| r.revision == revision)
+            .ok_or_else(|| ConfigError::not_found("versioned", key))?
+            .value;
+
+        match &value {
+            Some(value) => self.inner.put(key, value.clone())?,
+            None => self.inner.delete(key)?,
+        }
+        self.record(key, value)
+    }
+
+    fn history_key(&self, key: &str) -> String {
+        format!("{}{}", self.history_prefix, key)
+    }
+
+    fn check_key(&self, key: &str) -> Result<(), ConfigError> {
+        if key.starts_with(self.history_prefix.as_str()) {
+            return Err(ConfigError::backend(
+                "versioned",
+                format!("keys starting with {} are reserved", self.history_prefix),
+            )
+            .with_key(key));
+        }
+
+        Ok(())
+    }
+
+    fn record(&self, key: &str, value: Option<Value>) -> Result<(), ConfigError> {
+        let mut history = self.history(key)?;
+        let revision = history.last().map_or(1, |last| last.revision + 1);
+
+        history.push(Revision {
+            revision,
+            timestamp: SystemTime::now(),
+            value,
+        });
+
+        self.inner.put(&self.history_key(key), history)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("versioned"))]
+impl<P> ConfigProvider for VersionedProvider<P>
+where
+    P: ConfigProvider,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.check_key(key)?;
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("versioned", err).with_key(key))?;
+
+        let _guard = self.write_lock.lock().unwrap();
+        self.inner.put(key, value.clone())?;
+        self.record(key, Some(value))
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.check_key(key)?;
+        let value = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("versioned", err).with_key(key))?;
+
+        let _guard = self.write_lock.lock().unwrap();
+        self.inner
+            .put_if_version(key, value.clone(), expected_version)?;
+        self.record(key, Some(value))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.check_key(key)?;
+
+        let _guard = self.write_lock.lock().unwrap();
+        if !self.inner.has(key)? {
+            return Ok(());
+        }
+        self.inner.delete(key)?;
+        self.record(key, None)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        Ok(self
+            .inner
+            .list()?
+            .into_iter()
+            .filter(|key| !key.starts_with(self.history_prefix.as_str()))
+            .collect())
+    }
+
+    fn modified(&self, key: &str) -> Result<Option<SystemTime>, ConfigError> {
+        Ok(match self.history(key)?.pop() {
+            Some(Revision {
+                timestamp,
+                value: Some(_),
+                ..
+            }) => Some(timestamp),
+            _ => None,
+        })
+    }
+}
+
+fn from_revision<T>(key: &str, revision: Revision) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+{
+    let value = revision
+        .value
+        .ok_or_else(|| ConfigError::not_found("versioned", key))?;
+
+    serde_json::from_value(value)
+        .map_err(|err| ConfigError::deserialization("versioned", err).with_key(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::time::Duration;
+
+    #[test]
+    fn records_and_reverts_revisions() {
+        let provider = VersionedProvider::new(InMemoryProvider::new());
+
+        provider.put("policy.admin", "allow".to_string()).unwrap();
+        let before_change = SystemTime::now();
+        std::thread::sleep(Duration::from_millis(5));
+        provider.put("policy.admin", "deny".to_string()).unwrap();
+        provider.delete("policy.admin").unwrap();
+
+        let history = provider.history("policy.admin").unwrap();
+        assert_eq!(
+            history.iter().map(|r| r.revision).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(history[2].value, None);
+        assert_eq!(
+            provider.get_at::<String>("policy.admin", 2).unwrap(),
+            "deny"
+        );
+        assert!(matches!(
+            provider.get_at::<String>("policy.admin", 3),
+            Err(ConfigError::NotFound { .. })
+        ));
+        assert_eq!(
+            provider
+                .get_as_of::<String>("policy.admin", before_change)
+                .unwrap(),
+            "allow"
+        );
+        assert!(provider.list().unwrap().is_empty());
+
+        provider.revert("policy.admin", 1).unwrap();
+        assert_eq!(provider.get::<String>("policy.admin").unwrap(), "allow");
+        assert_eq!(provider.history("policy.admin").unwrap().len(), 4);
+        assert!(provider.put(".history.policy.admin", 1).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use crate::diff::DiffEntry;
+use crate::{
+    ChangeEvent, ConfigError, ConfigProvider, FileAwareConfigProvider, KeyPage, ProviderStats,
+    WatchableConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// File aware provider reloading its file whenever it changes on disk
+///
+/// A background thread checks the modification time and size of the file every poll interval
+/// and loads it into a fresh provider of the wrapped type once they changed. The differences to
+/// the current values are applied to the wrapped provider and reported to the watchers, so
+/// editing the file reconfigures a running process. Writes made through this provider are
+/// reported as well, but only persisted by an explicit `save`.
+///
+/// A file that is missing or fails to load keeps the current values, the next change is picked
+/// up again. The thread ends with the provider.
+pub struct WatchedFileProvider<P> {
+    shared: Arc<Shared<P>>,
+}
+
+struct Shared<P> {
+    inner: P,
+    path: PathBuf,
+    poll_interval_ms: AtomicU64,
+    stamp: Mutex<Option<Stamp>>,
+    watchers: Mutex<Vec<Watcher>>,
+}
+
+/// Modification time and size of the file when it was last loaded.
+type Stamp = (SystemTime, u64);
+
+/// Key prefix of a watch and the channel its changes are sent to.
+type Watcher = (String, Sender<ChangeEvent>);
+
+impl<P> WatchedFileProvider<P>
+where
+    P: FileAwareConfigProvider + Default + Send + Sync + 'static,
+{
+    /// Load the file and start watching it for changes, polling once a second.
+    pub fn new<S>(path: S) -> Result<Self, ConfigError>
+    where
+        S: Into<PathBuf>,
+    {
+        let shared = Arc::new(Shared {
+            inner: P::default(),
+            path: path.into(),
+            poll_interval_ms: AtomicU64::new(1000),
+            stamp: Mutex::new(None),
+            watchers: Mutex::new(Vec::new()),
+        });
+        shared.reload()?;
+
+        let weak = Arc::downgrade(&shared);
+        thread::spawn(move || poll(weak));
+
+        Ok(Self { shared })
+    }
+
+    /// Check the file for changes at the given interval.
+    pub fn with_poll_interval(self, poll_interval: Duration) -> Self {
+        self.shared
+            .poll_interval_ms
+            .store(poll_interval.as_millis() as u64, Ordering::Relaxed);
+        self
+    }
+
+    /// Load the file now if it changed since it was last loaded, returns if it did.
+    pub fn reload(&self) -> Result<bool, ConfigError> {
+        self.shared.reload()
+    }
+
+    /// The wrapped provider holding the current values.
+    pub fn inner(&self) -> &P {
+        &self.shared.inner
+    }
+}
+
+impl<P> Shared<P>
+where
+    P: FileAwareConfigProvider + Default,
+{
+    fn reload(&self) -> Result<bool, ConfigError> {
+        let metadata =
+            fs::metadata(&self.path).map_err(|err| ConfigError::io("watched_file", err))?;
+        let stamp = (
+            metadata
+                .modified()
+                .map_err(|err| ConfigError::io("watched_file", err))?,
+            metadata.len(),
+        );
+
+        // one reload at a time, the stamp must match the values applied last
+        let mut last = self.stamp.lock().unwrap_or_else(PoisonError::into_inner);
+        if *last == Some(stamp) {
+            return Ok(false);
+        }
+
+        let fresh = P::default();
+        fresh.load(&self.path)?;
+        for entry in self.inner.snapshot()?.diff(&fresh.snapshot()?) {
+            match entry {
+                DiffEntry::Added { key, value } => {
+                    self.inner.put_value(&key, value.clone())?;
+                    self.notify(ChangeEvent::Put {
+                        key,
+                        old: None,
+                        new: value,
+                    });
+                }
+                DiffEntry::Changed { key, old, new } => {
+                    self.inner.put_value(&key, new.clone())?;
+                    self.notify(ChangeEvent::Put {
+                        key,
+                        old: Some(old),
+                        new,
+                    });
+                }
+                DiffEntry::Removed { key, value } => {
+                    self.inner.delete(&key)?;
+                    self.notify(ChangeEvent::Delete { key, old: value });
+                }
+            }
+        }
+        *last = Some(stamp);
+
+        Ok(true)
+    }
+
+    /// Send the event to every watcher of the changed key and forget the dropped ones.
+    fn notify(&self, event: ChangeEvent) {
+        let key = event.key();
+
+        let mut watchers = self.watchers.lock().unwrap_or_else(PoisonError::into_inner);
+        watchers.retain(|(prefix, sender)| {
+            !key.starts_with(prefix.as_str()) || sender.send(event.clone()).is_ok()
+        });
+    }
+}
+
+/// Reload the file of the provider until it is dropped.
+fn poll<P>(shared: Weak<Shared<P>>)
+where
+    P: FileAwareConfigProvider + Default,
+{
+    loop {
+        let pause = match shared.upgrade() {
+            Some(shared) => {
+                // failures are retried with the next poll
+                let _ = shared.reload();
+                Duration::from_millis(shared.poll_interval_ms.load(Ordering::Relaxed))
+            }
+            None => return,
+        };
+
+        thread::sleep(pause);
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("watched_file"))]
+impl<P> ConfigProvider for WatchedFileProvider<P>
+where
+    P: FileAwareConfigProvider + Default,
+{
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.shared.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.shared.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let new = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("watched_file", err).with_key(key))?;
+        let old = self.shared.inner.get_opt(key)?;
+        self.shared.inner.put_value(key, new.clone())?;
+        self.shared.notify(ChangeEvent::Put {
+            key: key.to_string(),
+            old,
+            new,
+        });
+
+        Ok(())
+    }
+
+    fn version(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.shared.inner.version(key)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let new = serde_json::to_value(value)
+            .map_err(|err| ConfigError::serialization("watched_file", err).with_key(key))?;
+        let old = self.shared.inner.get_opt(key)?;
+        self.shared
+            .inner
+            .put_if_version(key, new.clone(), expected_version)?;
+        self.shared.notify(ChangeEvent::Put {
+            key: key.to_string(),
+            old,
+            new,
+        });
+
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        let old = self.shared.inner.get_opt(key)?;
+        self.shared.inner.delete(key)?;
+        if let Some(old) = old {
+            self.shared.notify(ChangeEvent::Delete {
+                key: key.to_string(),
+                old,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.shared.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.shared.inner.stats()
+    }
+
+    fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, ConfigError> {
+        self.shared.inner.list_prefix(prefix)
+    }
+
+    fn list_page(&self, cursor: Option<&str>, limit: usize) -> Result<KeyPage, ConfigError> {
+        self.shared.inner.list_page(cursor, limit)
+    }
+
+    fn get_value(&self, key: &str) -> Result<Value, ConfigError> {
+        self.shared.inner.get_value(key)
+    }
+}
+
+impl<P> WatchableConfigProvider for WatchedFileProvider<P>
+where
+    P: FileAwareConfigProvider + Default,
+{
+    fn watch(&self, key_prefix: &str) -> Result<Receiver<ChangeEvent>, ConfigError> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared
+            .watchers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((key_prefix.to_string(), sender));
+
+        Ok(receiver)
+    }
+}
+
+impl<P> FileAwareConfigProvider for WatchedFileProvider<P>
+where
+    P: FileAwareConfigProvider + Default,
+{
+    /// Load another file into the current values, the watched file stays the same.
+    fn load<Q>(&self, path: Q) -> Result<(), ConfigError>
+    where
+        Q: AsRef<Path>,
+    {
+        self.shared.inner.load(path)
+    }
+
+    fn save<Q>(&self, path: Q) -> Result<(), ConfigError>
+    where
+        Q: AsRef<Path>,
+    {
+        self.shared.inner.save(path)
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.shared.inner.is_dirty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use serde_json::json;
+
+    /// Save a file with two keys and return the writer to edit it with.
+    fn written(path: &Path) -> InMemoryProvider {
+        let writer = InMemoryProvider::new();
+        writer.put("workers", 4).unwrap();
+        writer.put("listen", ":80".to_string()).unwrap();
+        writer.save(path).unwrap();
+        writer
+    }
+
+    #[test]
+    fn edits_of_the_file_reach_the_watchers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        let writer = written(&path);
+
+        let provider = WatchedFileProvider::<InMemoryProvider>::new(&path)
+            .unwrap()
+            .with_poll_interval(Duration::from_millis(10));
+        let changes = provider.watch("").unwrap();
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+
+        writer.put("workers", 8).unwrap();
+        writer.delete("listen").unwrap();
+        writer.save(&path).unwrap();
+
+        let mut received = vec![
+            changes.recv_timeout(Duration::from_secs(5)).unwrap(),
+            changes.recv_timeout(Duration::from_secs(5)).unwrap(),
+        ];
+        received.sort_by_key(|event| format!("{:?}", event));
+        assert_eq!(
+            received,
+            vec![
+                ChangeEvent::Delete {
+                    key: "listen".to_string(),
+                    old: json!(":80"),
+                },
+                ChangeEvent::Put {
+                    key: "workers".to_string(),
+                    old: Some(json!(4)),
+                    new: json!(8),
+                },
+            ]
+        );
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 8);
+    }
+
+    #[test]
+    fn unchanged_files_are_not_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        written(&path);
+
+        let provider = WatchedFileProvider::<InMemoryProvider>::new(&path).unwrap();
+        assert!(!provider.reload().unwrap());
+        assert_eq!(provider.get::<u32>("workers").unwrap(), 4);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::file::{lock_exclusive, lock_shared, write_atomic};
+use crate::provider::in_memory::InMemoryProvider;
+use crate::{
+    ConfigError, ConfigProvider, FileAwareConfigProvider, ProviderStats, Transaction,
+    TransactionalConfigProvider,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::path::Path;
+
+/// File aware provider persisting its values as YAML
+///
+/// Nested mappings are flattened into dotted keys on load (`proxy: { timeout: 5 }` becomes
+/// `proxy.timeout`) and expanded again on save.
+#[derive(Default)]
+pub struct YamlProvider {
+    inner: InMemoryProvider,
+}
+
+impl YamlProvider {
+    pub fn new() -> Self {
+        Self {
+            inner: InMemoryProvider::new(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("yaml"))]
+impl ConfigProvider for YamlProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        self.inner.get(key)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        self.inner.has(key)
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        self.inner.delete(key)
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.inner.list()
+    }
+
+    fn stats(&self) -> Result<ProviderStats, ConfigError> {
+        self.inner.stats()
+    }
+
+    fn get_or_insert_with<T, F>(&self, key: &str, f: F) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnOnce() -> T,
+    {
+        self.inner.get_or_insert_with(key, f)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        self.inner.put_if_version(key, value, expected_version)
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("yaml"))]
+impl TransactionalConfigProvider for YamlProvider {
+    fn commit(&self, transaction: Transaction) -> Result<(), ConfigError> {
+        self.inner.commit(transaction)
+    }
+}
+
+impl FileAwareConfigProvider for YamlProvider {
+    fn load<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_shared("yaml", path.as_ref())?;
+        let file = File::open(path).map_err(|err| ConfigError::io("yaml", err))?;
+
+        let document: Value = serde_yaml::from_reader(file)
+            .map_err(|err| ConfigError::deserialization("yaml", err))?;
+
+        let mut values = Vec::new();
+        match document {
+            Value::Object(map) => flatten(String::new(), map, &mut values),
+            // an empty document
+            Value::Null => {}
+            other => {
+                return Err(ConfigError::deserialization(
+                    "yaml",
+                    format!("expected a mapping at the document root, found {}", other),
+                ))
+            }
+        }
+
+        let mut write_guard = self.inner.write()?;
+
+        for (k, v) in values {
+            let serialized =
+                serde_json::to_string(&v).map_err(|err| ConfigError::serialization("yaml", err))?;
+            write_guard.insert(k, serialized);
+        }
+        self.inner.mark_loaded(changes);
+
+        Ok(())
+    }
+
+    fn save<P>(&self, path: P) -> Result<(), ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let changes = self.inner.changes();
+        let _lock = lock_exclusive("yaml", path.as_ref())?;
+        write_atomic("yaml", path, |file| {
+            let read_guard = self.inner.read()?;
+
+            let mut document = Map::new();
+            for (k, v) in read_guard.persistent() {
+                let value: Value = serde_json::from_str(v)
+                    .map_err(|err| ConfigError::deserialization("yaml", err))?;
+                insert_nested(&mut document, k, value)?;
+            }
+
+            serde_yaml::to_writer(file, &document)
+                .map_err(|err| ConfigError::serialization("yaml", err))
+        })?;
+        self.inner.mark_saved(changes);
+
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+}
+
+/// Collect the leaves of the given mapping as dotted keys.
+fn flatten(prefix: String, map: Map<String, Value>, out: &mut Vec<(String, Value)>) {
+    for (k, v) in map {
+        let key = if prefix.is_empty() {
+            k
+        } else {
+            format!("{}.{}", prefix, k)
+        };
+
+        match v {
+            Value::Object(nested) if !nested.is_empty() => flatten(key, nested, out),
+            leaf => out.push((key, leaf)),
+        }
+    }
+}
+
+/// Insert a value under a dotted key, creating the intermediate mappings on the way.
+fn insert_nested(
+    root: &mut Map<String, Value>,
+    key: &str,
+    value: Value,
+) -> Result<(), ConfigError> {
+    let conflict =
+        || ConfigError::serialization("yaml", "the key conflicts with another entry").with_key(key);
+
+    let mut segments = key.split('.').peekable();
+    let mut current = root;
+
+    while let Some(segment) = segments.next() {
+        if segments.peek().is_none() {
+            if current.contains_key(segment) {
+                return Err(conflict());
+            }
+            current.insert(segment.to_string(), value);
+            return Ok(());
+        }
+
+        current = match current
+            .entry(segment.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            Value::Object(nested) => nested,
+            _ => return Err(conflict()),
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn nested_mappings_round_trip_as_dotted_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        fs::write(
+            &path,
+            "proxy:\n  upstream:\n    timeout: 5\n  hosts:\n    - a\n    - b\nname: gatekeeper\n",
+        )
+        .unwrap();
+
+        let provider = YamlProvider::new();
+        provider.load(&path).unwrap();
+
+        let mut keys = provider.list().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["name", "proxy.hosts", "proxy.upstream.timeout"]);
+        assert_eq!(provider.get::<u32>("proxy.upstream.timeout").unwrap(), 5);
+
+        provider.put("proxy.upstream.retries", 3).unwrap();
+        provider.save(&path).unwrap();
+
+        let reloaded = YamlProvider::new();
+        reloaded.load(&path).unwrap();
+        assert_eq!(reloaded.get::<u32>("proxy.upstream.retries").unwrap(), 3);
+        assert_eq!(
+            reloaded.get::<Vec<String>>("proxy.hosts").unwrap(),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn save_rejects_overlapping_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = YamlProvider::new();
+        provider.put("proxy", 1).unwrap();
+        provider.put("proxy.timeout", 5).unwrap();
+
+        assert!(provider.save(dir.path().join("config.yaml")).is_err());
+    }
+}
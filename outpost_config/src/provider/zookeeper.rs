@@ -0,0 +1,296 @@
+use crate::{raw_version, ConfigError, ConfigProvider};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
+use zookeeper::{Acl, CreateMode, WatchedEvent, ZkError, ZooKeeper};
+
+/// Provider mapping config keys to znodes below a chroot path
+///
+/// Every key is a direct child of the chroot holding the JSON encoded value. Values written
+/// through [`ZookeeperProvider::put_ephemeral`] live in ephemeral znodes, which ZooKeeper
+/// removes once the session of this provider ends, e.g. because the outpost disconnected.
+pub struct ZookeeperProvider {
+    zk: ZooKeeper,
+    chroot: String,
+}
+
+impl ZookeeperProvider {
+    /// Connect to the ensemble, e.g. `zk1:2181,zk2:2181`, and store all keys below the given
+    /// chroot path, which is created if it doesn't exist.
+    pub fn connect(hosts: &str, chroot: &str, timeout: Duration) -> Result<Self, ConfigError> {
+        let zk = ZooKeeper::connect(hosts, timeout, |_: WatchedEvent| {})
+            .map_err(|err| ConfigError::backend("zookeeper", err))?;
+
+        let provider = Self {
+            zk,
+            chroot: normalize_chroot(chroot),
+        };
+        provider.ensure_chroot()?;
+
+        Ok(provider)
+    }
+
+    /// Insert a key value pair that disappears once this provider's session ends.
+    ///
+    /// An existing persistent value under the same key is replaced.
+    pub fn put_ephemeral<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_vec(&value)
+            .map_err(|err| ConfigError::serialization("zookeeper", err).with_key(key))?;
+        let path = self.path(key);
+
+        // the mode of a znode is fixed at creation, so an existing node has to go first
+        self.delete(key)?;
+
+        self.zk
+            .create(
+                &path,
+                serialized,
+                Acl::open_unsafe().clone(),
+                CreateMode::Ephemeral,
+            )
+            .map_err(|err| zk_error(err).with_key(key))?;
+
+        Ok(())
+    }
+
+    fn path(&self, key: &str) -> String {
+        znode_path(&self.chroot, key)
+    }
+
+    fn ensure_chroot(&self) -> Result<(), ConfigError> {
+        for path in ancestors(&self.chroot) {
+            match self.zk.create(
+                &path,
+                Vec::new(),
+                Acl::open_unsafe().clone(),
+                CreateMode::Persistent,
+            ) {
+                Ok(_) | Err(ZkError::NodeExists) => {}
+                Err(err) => return Err(zk_error(err)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "tracing", outpost_config_derive::traced("zookeeper"))]
+impl ConfigProvider for ZookeeperProvider {
+    fn get<T>(&self, key: &str) -> Result<T, ConfigError>
+    where
+        T: DeserializeOwned,
+    {
+        let (raw, _) = self
+            .zk
+            .get_data(&self.path(key), false)
+            .map_err(|err| match err {
+                ZkError::NoNode => ConfigError::not_found("zookeeper", key),
+                err => zk_error(err).with_key(key),
+            })?;
+        let deserialized = serde_json::from_slice(&raw)
+            .map_err(|err| ConfigError::deserialization("zookeeper", err).with_key(key))?;
+
+        Ok(deserialized)
+    }
+
+    fn has(&self, key: &str) -> Result<bool, ConfigError> {
+        let stat = self
+            .zk
+            .exists(&self.path(key), false)
+            .map_err(|err| zk_error(err).with_key(key))?;
+
+        Ok(stat.is_some())
+    }
+
+    fn put<T>(&self, key: &str, value: T) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_vec(&value)
+            .map_err(|err| ConfigError::serialization("zookeeper", err).with_key(key))?;
+        let path = self.path(key);
+
+        match self.zk.set_data(&path, serialized.clone(), None) {
+            Ok(_) => Ok(()),
+            Err(ZkError::NoNode) => self
+                .zk
+                .create(
+                    &path,
+                    serialized,
+                    Acl::open_unsafe().clone(),
+                    CreateMode::Persistent,
+                )
+                .map(|_| ())
+                .map_err(|err| zk_error(err).with_key(key)),
+            Err(err) => Err(zk_error(err).with_key(key)),
+        }
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ConfigError> {
+        match self.zk.delete(&self.path(key), None) {
+            Ok(()) | Err(ZkError::NoNode) => Ok(()),
+            Err(err) => Err(zk_error(err).with_key(key)),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, ConfigError> {
+        self.zk.get_children(&self.chroot, false).map_err(zk_error)
+    }
+
+    fn put_if_version<T>(
+        &self,
+        key: &str,
+        value: T,
+        expected_version: Option<&str>,
+    ) -> Result<(), ConfigError>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        let serialized = serde_json::to_vec(&value)
+            .map_err(|err| ConfigError::serialization("zookeeper", err).with_key(key))?;
+        let path = self.path(key);
+
+        let current = match self.zk.get_data(&path, false) {
+            Ok(current) => Some(current),
+            Err(ZkError::NoNode) => None,
+            Err(err) => return Err(zk_error(err).with_key(key)),
+        };
+        let version = current
+            .as_ref()
+            .map(|(raw, _)| raw_version(&String::from_utf8_lossy(raw)));
+        if version.as_deref() != expected_version {
+            return Err(ConfigError::conflict("zookeeper", key));
+        }
+
+        // the znode version makes zookeeper refuse the write if another one came first
+        let written = match current {
+            Some((_, stat)) => self
+                .zk
+                .set_data(&path, serialized, Some(stat.version))
+                .map(|_| ()),
+            None => self
+                .zk
+                .create(
+                    &path,
+                    serialized,
+                    Acl::open_unsafe().clone(),
+                    CreateMode::Persistent,
+                )
+                .map(|_| ()),
+        };
+
+        written.map_err(|err| write_error(key, err))
+    }
+}
+
+impl Drop for ZookeeperProvider {
+    fn drop(&mut self) {
+        // closing the session right away removes ephemeral values without waiting for a timeout
+        let _ = self.zk.close();
+    }
+}
+
+/// The chroot as an absolute path without a trailing slash, `/` for the root.
+fn normalize_chroot(chroot: &str) -> String {
+    format!("/{}", chroot.trim_matches('/'))
+}
+
+fn znode_path(chroot: &str, key: &str) -> String {
+    if chroot == "/" {
+        format!("/{}", key)
+    } else {
+        format!("{}/{}", chroot, key)
+    }
+}
+
+/// The paths from the topmost ancestor of the chroot down to the chroot itself.
+fn ancestors(chroot: &str) -> Vec<String> {
+    let mut path = String::new();
+
+    chroot
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            path.push('/');
+            path.push_str(segment);
+            path.clone()
+        })
+        .collect()
+}
+
+/// Map refused credentials to ConfigError::PermissionDenied, all other errors to
+/// ConfigError::Backend.
+fn zk_error(err: ZkError) -> ConfigError {
+    match err {
+        ZkError::NoAuth | ZkError::AuthFailed => ConfigError::permission_denied("zookeeper", err),
+        err => ConfigError::backend("zookeeper", err),
+    }
+}
+
+/// Map a write refused because another writer came first to ConfigError::Conflict.
+fn write_error(key: &str, err: ZkError) -> ConfigError {
+    match err {
+        ZkError::BadVersion | ZkError::NoNode | ZkError::NodeExists => {
+            ConfigError::conflict("zookeeper", key)
+        }
+        err => zk_error(err).with_key(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_children_of_the_chroot() {
+        assert_eq!(normalize_chroot("gatekeeper/edge/"), "/gatekeeper/edge");
+        assert_eq!(normalize_chroot("/"), "/");
+        assert_eq!(
+            znode_path("/gatekeeper/edge", "workers"),
+            "/gatekeeper/edge/workers"
+        );
+        assert_eq!(znode_path("/", "workers"), "/workers");
+    }
+
+    #[test]
+    fn chroots_are_created_from_the_top() {
+        assert_eq!(
+            ancestors("/gatekeeper/edge"),
+            vec!["/gatekeeper".to_string(), "/gatekeeper/edge".to_string()]
+        );
+        assert!(ancestors("/").is_empty());
+    }
+
+    #[test]
+    fn refused_credentials_are_denied_permission() {
+        assert!(matches!(
+            zk_error(ZkError::NoAuth),
+            ConfigError::PermissionDenied { .. }
+        ));
+        assert!(matches!(
+            zk_error(ZkError::AuthFailed),
+            ConfigError::PermissionDenied { .. }
+        ));
+        assert!(matches!(
+            zk_error(ZkError::ConnectionLoss),
+            ConfigError::Backend { .. }
+        ));
+    }
+
+    #[test]
+    fn writes_losing_a_race_conflict() {
+        for err in [ZkError::BadVersion, ZkError::NoNode, ZkError::NodeExists] {
+            assert!(matches!(
+                write_error("workers", err),
+                ConfigError::Conflict { .. }
+            ));
+        }
+        assert!(matches!(
+            write_error("workers", ZkError::NoAuth),
+            ConfigError::PermissionDenied { key: Some(_), .. }
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+//! Saving runtime changes before the process ends, see [`SaveGuard`]
+
+use crate::{ConfigError, FileAwareConfigProvider};
+use std::path::{Path, PathBuf};
+
+/// Guard saving a provider to its file when it is dropped
+///
+/// Config edited at runtime, e.g. through the admin API, is lost on restart unless somebody
+/// saves it. The guard saves the provider to the path when it goes out of scope at the end of
+/// `main`, and with the `tokio` feature [`SaveGuard::save_on_shutdown`] does so once the process
+/// is asked to stop. Only providers reporting [`FileAwareConfigProvider::is_dirty`] are saved,
+/// a failure on drop can't be returned and is dropped as well.
+///
+/// ```ignore
+/// let config = SaveGuard::new(Arc::new(TomlProvider::new()), "/etc/gatekeeper/config.toml");
+/// config.provider().load(config.path())?;
+/// ```
+pub struct SaveGuard<P>
+where
+    P: FileAwareConfigProvider,
+{
+    provider: P,
+    path: PathBuf,
+}
+
+impl<P> SaveGuard<P>
+where
+    P: FileAwareConfigProvider,
+{
+    pub fn new<S>(provider: P, path: S) -> Self
+    where
+        S: Into<PathBuf>,
+    {
+        Self {
+            provider,
+            path: path.into(),
+        }
+    }
+
+    /// Save the provider now if it has unsaved changes, returns if it did.
+    pub fn save(&self) -> Result<bool, ConfigError> {
+        if !self.provider.is_dirty() {
+            return Ok(false);
+        }
+        self.provider.save(&self.path)?;
+
+        Ok(true)
+    }
+
+    /// Wait for Ctrl-C or, on unix, `SIGTERM` and save the provider.
+    #[cfg(feature = "tokio")]
+    pub async fn save_on_shutdown(&self) -> Result<bool, ConfigError> {
+        shutdown_signal()
+            .await
+            .map_err(|err| ConfigError::io("save_guard", err))?;
+
+        self.save()
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<P> Drop for SaveGuard<P>
+where
+    P: FileAwareConfigProvider,
+{
+    fn drop(&mut self) {
+        let _ = self.save();
+    }
+}
+
+#[cfg(all(feature = "tokio", unix))]
+async fn shutdown_signal() -> std::io::Result<()> {
+    use std::future::{poll_fn, Future};
+    use std::task::Poll;
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut ctrl_c = std::pin::pin!(tokio::signal::ctrl_c());
+
+    poll_fn(|cx| {
+        if let Poll::Ready(result) = ctrl_c.as_mut().poll(cx) {
+            return Poll::Ready(result);
+        }
+        terminate.poll_recv(cx).map(|_| Ok(()))
+    })
+    .await
+}
+
+#[cfg(all(feature = "tokio", not(unix)))]
+async fn shutdown_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use crate::ConfigProvider;
+    use std::sync::Arc;
+
+    #[test]
+    fn unsaved_changes_are_saved_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gatekeeper.json");
+        let provider = Arc::new(InMemoryProvider::new());
+
+        let guard = SaveGuard::new(provider.clone(), &path);
+        assert!(!guard.save().unwrap());
+        provider.put("workers", 4).unwrap();
+        assert!(provider.is_dirty());
+        drop(guard);
+        assert!(!provider.is_dirty());
+
+        let restarted = InMemoryProvider::new();
+        restarted.load(&path).unwrap();
+        assert!(!restarted.is_dirty());
+        assert_eq!(restarted.get::<u32>("workers").unwrap(), 4);
+    }
+}
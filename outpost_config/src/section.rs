@@ -0,0 +1,120 @@
+//! Binding structs to the keys below a prefix, see [`ConfigSection`]
+
+use crate::{ConfigError, ConfigProvider};
+use serde::Serialize;
+use serde_json::Value;
+
+#[cfg(feature = "derive")]
+pub use outpost_config_derive::ConfigSection;
+
+/// A struct whose fields are stored as the keys below a prefix
+///
+/// Usually derived with `#[derive(ConfigSection)]`, which requires the cargo feature `derive`.
+/// Every field is stored at `<prefix>.<field>`, the behaviour of a field can be changed with
+/// attributes:
+///
+/// - `#[config(rename = "cert_file")]` stores the field under another name
+/// - `#[config(default)]` falls back to `Default::default()` if the key doesn't exist
+/// - `#[config(default = "path::to::function")]` falls back to the value returned by the function
+///
+/// ```ignore
+/// #[derive(ConfigSection)]
+/// struct TlsConfig {
+///     #[config(rename = "cert_file")]
+///     cert: PathBuf,
+///     key: PathBuf,
+///     #[config(default)]
+///     client_auth: bool,
+/// }
+///
+/// let tls = TlsConfig::load(&provider, "tls")?;
+/// ```
+pub trait ConfigSection: Sized {
+    /// Read every field from the keys below the prefix.
+    fn load<P>(provider: &P, prefix: &str) -> Result<Self, ConfigError>
+    where
+        P: ConfigProvider;
+
+    /// Write every field to the keys below the prefix with
+    /// [`ConfigProvider::put_many`](crate::ConfigProvider::put_many).
+    fn save<P>(&self, provider: &P, prefix: &str) -> Result<(), ConfigError>
+    where
+        P: ConfigProvider;
+}
+
+/// The key a field is stored at.
+#[doc(hidden)]
+pub fn field_key(prefix: &str, field: &str) -> String {
+    if prefix.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", prefix, field)
+    }
+}
+
+/// The key a field is stored at paired with its serialized value.
+#[doc(hidden)]
+pub fn field_entry<T>(prefix: &str, field: &str, value: &T) -> Result<(String, Value), ConfigError>
+where
+    T: Serialize,
+{
+    let key = field_key(prefix, field);
+    let value = serde_json::to_value(value)
+        .map_err(|err| ConfigError::serialization("section", err).with_key(&key))?;
+
+    Ok((key, value))
+}
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use std::path::PathBuf;
+
+    #[derive(ConfigSection, Debug, PartialEq)]
+    struct TlsConfig {
+        #[config(rename = "cert_file")]
+        cert: PathBuf,
+        key: PathBuf,
+        #[config(default)]
+        client_auth: bool,
+        #[config(default = "default_ciphers")]
+        ciphers: Vec<String>,
+    }
+
+    fn default_ciphers() -> Vec<String> {
+        vec!["aes256".to_string()]
+    }
+
+    #[test]
+    fn derived_sections_map_fields_to_dotted_keys() {
+        let provider = InMemoryProvider::new();
+        provider
+            .put("tls.cert_file", "/etc/tls/cert.pem".to_string())
+            .unwrap();
+        provider
+            .put("tls.key", "/etc/tls/key.pem".to_string())
+            .unwrap();
+
+        let tls = TlsConfig::load(&provider, "tls").unwrap();
+        assert_eq!(
+            tls,
+            TlsConfig {
+                cert: PathBuf::from("/etc/tls/cert.pem"),
+                key: PathBuf::from("/etc/tls/key.pem"),
+                client_auth: false,
+                ciphers: default_ciphers(),
+            }
+        );
+
+        tls.save(&provider, "backup.tls").unwrap();
+        assert_eq!(
+            provider.get::<Vec<String>>("backup.tls.ciphers").unwrap(),
+            default_ciphers()
+        );
+        assert_eq!(TlsConfig::load(&provider, "backup.tls").unwrap(), tls);
+
+        let err = TlsConfig::load(&provider, "proxy.tls").unwrap_err();
+        assert_eq!(err.key(), Some("proxy.tls.cert_file"));
+    }
+}
@@ -0,0 +1,132 @@
+//! Mirroring the config of one provider to another, e.g. a central etcd down to a local file
+
+use crate::diff::DiffEntry;
+use crate::{ConfigError, ConfigProvider};
+
+/// How [`sync`] treats the keys of the target
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncPolicy {
+    /// Delete the keys that only exist in the target.
+    pub prune: bool,
+    pub conflicts: ConflictStrategy,
+}
+
+/// Which value is kept if a key exists in both providers with different values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// The value of the source overwrites the one of the target.
+    #[default]
+    SourceWins,
+    /// The target keeps its value, only missing keys are copied.
+    TargetWins,
+    /// The most recently written value is kept, see [`ConfigProvider::modified`]. The source
+    /// wins if either provider doesn't know when the key was written.
+    NewestWins,
+}
+
+/// The keys changed in the target by [`sync`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub pruned: Vec<String>,
+    /// Keys whose value differs from the source but were kept because of the conflict strategy.
+    pub kept: Vec<String>,
+}
+
+/// Copy the keys missing in the target from the source and resolve the keys with different
+/// values according to the policy.
+/// The changes are written one by one, a failing write leaves the target partially synced and
+/// the next sync picks up where it stopped.
+pub fn sync<S, T>(source: &S, target: &T, policy: SyncPolicy) -> Result<SyncReport, ConfigError>
+where
+    S: ConfigProvider,
+    T: ConfigProvider,
+{
+    let mut report = SyncReport::default();
+
+    for entry in target.snapshot()?.diff(&source.snapshot()?) {
+        match entry {
+            DiffEntry::Added { key, value } => {
+                target.put(&key, value)?;
+                report.added.push(key);
+            }
+            DiffEntry::Removed { key, .. } => {
+                if policy.prune {
+                    target.delete(&key)?;
+                    report.pruned.push(key);
+                }
+            }
+            DiffEntry::Changed { key, new, .. } => {
+                if source_wins(source, target, &key, policy.conflicts)? {
+                    target.put(&key, new)?;
+                    report.updated.push(key);
+                } else {
+                    report.kept.push(key);
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn source_wins<S, T>(
+    source: &S,
+    target: &T,
+    key: &str,
+    strategy: ConflictStrategy,
+) -> Result<bool, ConfigError>
+where
+    S: ConfigProvider,
+    T: ConfigProvider,
+{
+    match strategy {
+        ConflictStrategy::SourceWins => Ok(true),
+        ConflictStrategy::TargetWins => Ok(false),
+        ConflictStrategy::NewestWins => match (source.modified(key)?, target.modified(key)?) {
+            (Some(source), Some(target)) => Ok(source >= target),
+            _ => Ok(true),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::in_memory::InMemoryProvider;
+    use crate::provider::versioned::VersionedProvider;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn mirrors_the_source_according_to_the_policy() {
+        let central = VersionedProvider::new(InMemoryProvider::new());
+        central.put("workers", 8).unwrap();
+        central.put("listen", ":443".to_string()).unwrap();
+        // keep the timestamps apart on coarse clocks
+        thread::sleep(Duration::from_millis(10));
+
+        let local = VersionedProvider::new(InMemoryProvider::new());
+        local.put("listen", ":8443".to_string()).unwrap();
+        local.put("debug", true).unwrap();
+
+        let policy = SyncPolicy {
+            prune: false,
+            conflicts: ConflictStrategy::NewestWins,
+        };
+        let report = sync(&central, &local, policy).unwrap();
+        assert_eq!(report.added, vec!["workers"]);
+        assert_eq!(report.kept, vec!["listen"]);
+        assert_eq!(local.get::<String>("listen").unwrap(), ":8443");
+
+        let policy = SyncPolicy {
+            prune: true,
+            conflicts: ConflictStrategy::SourceWins,
+        };
+        let report = sync(&central, &local, policy).unwrap();
+        assert_eq!(report.updated, vec!["listen"]);
+        assert_eq!(report.pruned, vec!["debug"]);
+        assert_eq!(local.snapshot().unwrap(), central.snapshot().unwrap());
+    }
+}
@@ -0,0 +1,257 @@
+//! Derive macros of `outpost_config`, use them through its `derive` feature, and the attribute
+//! instrumenting its providers with its `tracing` feature
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2, TokenTree};
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, ExprPath, Field, Fields, FnArg,
+    GenericArgument, ImplItem, ItemImpl, LitStr, Pat, PathArguments, ReturnType, Token, Type,
+};
+
+/// Implement `outpost_config::ConfigSection` for a struct with named fields
+///
+/// See the trait for the supported `#[config(..)]` attributes.
+#[proc_macro_derive(ConfigSection, attributes(config))]
+pub fn derive_config_section(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    config_section(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn config_section(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "ConfigSection requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "ConfigSection can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut loads = Vec::new();
+    let mut saves = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let options = FieldOptions::parse(field)?;
+        let name = options
+            .rename
+            .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+
+        let key = quote!(&::outpost_config::section::field_key(prefix, #name));
+        let load = match options.default {
+            None => quote!(::outpost_config::ConfigProvider::get(provider, #key)?),
+            Some(Fallback::Trait) => quote!(
+                ::outpost_config::ConfigProvider::get_opt(provider, #key)?.unwrap_or_default()
+            ),
+            Some(Fallback::Function(function)) => quote!(
+                ::outpost_config::ConfigProvider::get_opt(provider, #key)?
+                    .unwrap_or_else(#function)
+            ),
+        };
+        loads.push(quote!(#ident: #load));
+        saves.push(quote!(
+            ::outpost_config::section::field_entry(prefix, #name, &self.#ident)?
+        ));
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::outpost_config::ConfigSection for #ident #ty_generics #where_clause {
+            fn load<__P>(
+                provider: &__P,
+                prefix: &str,
+            ) -> ::std::result::Result<Self, ::outpost_config::ConfigError>
+            where
+                __P: ::outpost_config::ConfigProvider,
+            {
+                ::std::result::Result::Ok(Self { #(#loads,)* })
+            }
+
+            fn save<__P>(
+                &self,
+                provider: &__P,
+                prefix: &str,
+            ) -> ::std::result::Result<(), ::outpost_config::ConfigError>
+            where
+                __P: ::outpost_config::ConfigProvider,
+            {
+                ::outpost_config::ConfigProvider::put_many(provider, ::std::vec![#(#saves,)*])
+            }
+        }
+    })
+}
+
+/// Fallback of a field whose key doesn't exist.
+enum Fallback {
+    /// `#[config(default)]`
+    Trait,
+    /// `#[config(default = "path::to::function")]`
+    Function(ExprPath),
+}
+
+/// The `#[config(..)]` attributes of a field.
+struct FieldOptions {
+    rename: Option<LitStr>,
+    default: Option<Fallback>,
+}
+
+impl FieldOptions {
+    fn parse(field: &Field) -> syn::Result<Self> {
+        let mut options = FieldOptions {
+            rename: None,
+            default: None,
+        };
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("config"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    options.rename = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("default") {
+                    options.default = if meta.input.peek(Token![=]) {
+                        let function: LitStr = meta.value()?.parse()?;
+                        Some(Fallback::Function(function.parse()?))
+                    } else {
+                        Some(Fallback::Trait)
+                    };
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported config attribute"))
+                }
+            })?;
+        }
+
+        Ok(options)
+    }
+}
+
+/// Instrument every method of a provider impl returning a `Result` with a debug span
+///
+/// The span records the provider name given to the attribute, any further fields given after it
+/// and the arguments that are keys, counts or other plain values. Values to write, closures and
+/// transactions are skipped. Errors are recorded at debug level. Methods with their own `tracing::instrument` are left alone.
+///
+/// ```ignore
+/// #[cfg_attr(feature = "tracing", outpost_config_derive::traced("in_memory"))]
+/// impl ConfigProvider for InMemoryProvider { .. }
+///
+/// #[cfg_attr(feature = "tracing", outpost_config_derive::traced("tenant", tenant = %self.tenant))]
+/// impl<P> ConfigProvider for TenantProvider<P> { .. }
+/// ```
+#[doc(hidden)]
+#[proc_macro_attribute]
+pub fn traced(attr: TokenStream, item: TokenStream) -> TokenStream {
+    // the provider name and the further fields, separated by commas
+    let mut args = vec![TokenStream2::new()];
+    for token in TokenStream2::from(attr) {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => args.push(TokenStream2::new()),
+            _ => args.last_mut().expect("one argument").extend([token]),
+        }
+    }
+    let provider = args.remove(0);
+    if provider.is_empty() {
+        return syn::Error::new(Span::call_site(), "the provider name is missing")
+            .into_compile_error()
+            .into();
+    }
+    let fields: Vec<_> = args.into_iter().filter(|field| !field.is_empty()).collect();
+    let mut item = parse_macro_input!(item as ItemImpl);
+
+    for impl_item in &mut item.items {
+        let method = match impl_item {
+            ImplItem::Fn(method) => method,
+            _ => continue,
+        };
+        let instrumented = method.attrs.iter().any(|attr| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "instrument")
+        });
+        if instrumented || !returns_result(&method.sig.output) {
+            continue;
+        }
+
+        let skipped = method.sig.inputs.iter().filter_map(|input| match input {
+            FnArg::Receiver(_) => Some(quote!(self)),
+            FnArg::Typed(arg) => match &*arg.pat {
+                Pat::Ident(ident) if !is_plain(&arg.ty) => {
+                    let ident = &ident.ident;
+                    Some(quote!(#ident))
+                }
+                _ => None,
+            },
+        });
+        method.attrs.push(parse_quote!(
+            #[tracing::instrument(
+                level = "debug",
+                skip(#(#skipped),*),
+                fields(provider = #provider #(, #fields)*),
+                err(level = "debug")
+            )]
+        ));
+    }
+
+    quote!(#item).into()
+}
+
+fn returns_result(output: &ReturnType) -> bool {
+    match output {
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Result"),
+            _ => false,
+        },
+        ReturnType::Default => false,
+    }
+}
+
+/// Whether an argument of the type is worth recording, like a key, a list of keys or a count.
+fn is_plain(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) => is_plain(&reference.elem),
+        Type::Slice(slice) => is_plain(&slice.elem),
+        Type::Path(path) => {
+            let segment = match path.path.segments.last() {
+                Some(segment) => segment,
+                None => return false,
+            };
+            match &segment.arguments {
+                PathArguments::None => matches!(
+                    segment.ident.to_string().as_str(),
+                    "str" | "String" | "bool" | "usize" | "u32" | "u64" | "i64" | "Duration"
+                ),
+                PathArguments::AngleBracketed(args) if segment.ident == "Option" => {
+                    args.args.iter().all(|arg| match arg {
+                        GenericArgument::Type(ty) => is_plain(ty),
+                        _ => false,
+                    })
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
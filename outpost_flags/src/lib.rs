@@ -0,0 +1,382 @@
+//! Feature flags stored in a config provider
+//!
+//! A flag is the value of the key `flags.<name>`, either a plain `true` or `false` or a rule
+//! targeting a part of the fleet:
+//!
+//! ```json
+//! {
+//!     "enabled": false,
+//!     "outposts": ["edge-fra-1"],
+//!     "tenants": ["acme"],
+//!     "percentage": 25,
+//!     "variants": [
+//!         {"name": "control", "weight": 1},
+//!         {"name": "streaming", "weight": 1}
+//!     ]
+//! }
+//! ```
+//!
+//! A rule enables the flag if `enabled` is set, if the outpost or the tenant of the
+//! [`FlagContext`] is listed, or for the given percentage of the units. The unit is the stable id
+//! of the context, its unit, outpost or tenant, whichever is known first. Units land in the same
+//! percentile of a flag every time, so raising the percentage only adds units.
+//!
+//! Units the flag is enabled for are split into the `variants` of an experiment by weight, again
+//! by hashing their id, so a unit stays in its variant as long as the variants don't change.
+//! Missing flags are disabled.
+
+use outpost_config::{ChangeEvent, ConfigError, ConfigProvider, WatchableConfigProvider};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, PoisonError, RwLock, Weak};
+use std::thread;
+
+/// Prefix of the keys of all flags, followed by the name of the flag.
+pub const FLAGS_PREFIX: &str = "flags.";
+
+/// Feature flags read from a config provider
+///
+/// Flags created with [`Flags::new`] read the provider on every check, wrap a slow provider in a
+/// `CachedProvider` to spare it. [`Flags::live`] keeps all flags in memory instead and follows
+/// their changes, so checks never touch the provider.
+pub struct Flags<P> {
+    provider: P,
+    live: Option<Arc<RwLock<HashMap<String, Value>>>>,
+}
+
+/// The outpost, tenant and unit a flag is checked for.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlagContext {
+    outpost: Option<String>,
+    tenant: Option<String>,
+    unit: Option<String>,
+}
+
+impl FlagContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_outpost<S>(mut self, outpost: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.outpost = Some(outpost.into());
+        self
+    }
+
+    pub fn with_tenant<S>(mut self, tenant: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// The stable id percentages and variants are assigned by, e.g. a client or a route.
+    /// Defaults to the outpost, then the tenant.
+    pub fn with_unit<S>(mut self, unit: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.unit = Some(unit.into());
+        self
+    }
+
+    fn unit(&self) -> Option<&str> {
+        self.unit
+            .as_deref()
+            .or(self.outpost.as_deref())
+            .or(self.tenant.as_deref())
+    }
+}
+
+/// The stored value of a flag.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Flag {
+    Switch(bool),
+    Rule(Rule),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    outposts: Vec<String>,
+    #[serde(default)]
+    tenants: Vec<String>,
+    #[serde(default)]
+    percentage: u8,
+    #[serde(default)]
+    variants: Vec<Variant>,
+}
+
+/// An arm of an experiment, receiving its weight's share of the units.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Variant {
+    name: String,
+    weight: u32,
+}
+
+impl<P> Flags<P>
+where
+    P: ConfigProvider,
+{
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider,
+            live: None,
+        }
+    }
+
+    /// The provider the flags are read from.
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// Whether the flag is enabled without targeting, i.e. switched on or enabled by its rule.
+    pub fn is_enabled(&self, name: &str) -> Result<bool, ConfigError> {
+        self.is_enabled_for(name, &FlagContext::default())
+    }
+
+    /// Whether the flag is enabled for the given outpost and tenant. Fails with
+    /// `ConfigError::Deserialization` if the stored value is neither a bool nor a rule.
+    pub fn is_enabled_for(&self, name: &str, context: &FlagContext) -> Result<bool, ConfigError> {
+        Ok(match self.flag(name)? {
+            Some(Flag::Switch(enabled)) => enabled,
+            Some(Flag::Rule(rule)) => rule.matches(name, context),
+            None => false,
+        })
+    }
+
+    /// The variant of the experiment the unit of the context takes part in, `None` if the flag
+    /// is disabled for it, has no variants or the context has no unit.
+    pub fn variant(
+        &self,
+        name: &str,
+        context: &FlagContext,
+    ) -> Result<Option<String>, ConfigError> {
+        let rule = match self.flag(name)? {
+            Some(Flag::Rule(rule)) if rule.matches(name, context) => rule,
+            _ => return Ok(None),
+        };
+        let (unit, total) = match context.unit() {
+            Some(unit) => (
+                unit,
+                rule.variants.iter().map(|v| u64::from(v.weight)).sum(),
+            ),
+            None => return Ok(None),
+        };
+        if total == 0 {
+            return Ok(None);
+        }
+
+        // salted differently than the rollout, which would otherwise favor the first variants
+        let mut bucket = hash_bucket(name, "variant", unit, total);
+        for variant in rule.variants {
+            let weight = u64::from(variant.weight);
+            if bucket < weight {
+                return Ok(Some(variant.name));
+            }
+            bucket -= weight;
+        }
+
+        unreachable!("the bucket is below the total weight")
+    }
+
+    fn flag(&self, name: &str) -> Result<Option<Flag>, ConfigError> {
+        let key = format!("{}{}", FLAGS_PREFIX, name);
+        let value = match &self.live {
+            Some(flags) => flags
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(&key)
+                .cloned(),
+            None => self.provider.get_opt::<Value>(&key)?,
+        };
+
+        value
+            .map(|value| {
+                serde_json::from_value(value)
+                    .map_err(|err| ConfigError::deserialization("flags", err).with_key(&key))
+            })
+            .transpose()
+    }
+}
+
+impl<P> Flags<P>
+where
+    P: WatchableConfigProvider,
+{
+    /// Load all flags into memory and keep them up to date by watching the provider.
+    pub fn live(provider: P) -> Result<Self, ConfigError> {
+        // watch before loading, so no change in between is missed
+        let changes = provider.watch(FLAGS_PREFIX)?;
+        let flags: HashMap<String, Value> = provider.entries(FLAGS_PREFIX)?.into_iter().collect();
+
+        let flags = Arc::new(RwLock::new(flags));
+        let weak = Arc::downgrade(&flags);
+        thread::spawn(move || follow(weak, changes));
+
+        Ok(Self {
+            provider,
+            live: Some(flags),
+        })
+    }
+}
+
+impl Rule {
+    fn matches(&self, name: &str, context: &FlagContext) -> bool {
+        let listed =
+            |ids: &[String], id: &Option<String>| id.as_ref().is_some_and(|id| ids.contains(id));
+
+        self.enabled
+            || listed(&self.outposts, &context.outpost)
+            || listed(&self.tenants, &context.tenant)
+            || context.unit().is_some_and(|unit| {
+                hash_bucket(name, "rollout", unit, 100) < u64::from(self.percentage)
+            })
+    }
+}
+
+/// The stable bucket, below `buckets`, the unit falls into for the flag and purpose.
+fn hash_bucket(name: &str, salt: &str, unit: &str, buckets: u64) -> u64 {
+    // FNV-1a, which unlike the std hasher stays the same across releases and processes
+    let hash = [name, salt, unit]
+        .join(":")
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+
+    hash % buckets
+}
+
+/// Apply the changes of the flags until they or the provider are dropped.
+fn follow(flags: Weak<RwLock<HashMap<String, Value>>>, changes: Receiver<ChangeEvent>) {
+    for event in changes {
+        let flags = match flags.upgrade() {
+            Some(flags) => flags,
+            None => return,
+        };
+        let mut flags = flags.write().unwrap_or_else(PoisonError::into_inner);
+
+        match event {
+            ChangeEvent::Put { key, new, .. } => {
+                flags.insert(key, new);
+            }
+            ChangeEvent::Delete { key, .. } => {
+                flags.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use outpost_config::provider::in_memory::InMemoryProvider;
+    use serde_json::json;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn rules_target_outposts_tenants_and_percentages() {
+        let flags = Flags::new(InMemoryProvider::new());
+        let provider = flags.provider();
+        provider.put("flags.new_policy_engine", true).unwrap();
+        provider
+            .put_value(
+                "flags.http3",
+                json!({"outposts": ["edge-fra-1"], "tenants": ["acme"]}),
+            )
+            .unwrap();
+        provider
+            .put_value("flags.half", json!({"percentage": 50}))
+            .unwrap();
+        provider.put("flags.broken", "yes".to_string()).unwrap();
+
+        let fra = FlagContext::new().with_outpost("edge-fra-1");
+        let acme = FlagContext::new()
+            .with_outpost("edge-ams-1")
+            .with_tenant("acme");
+        assert!(flags.is_enabled("new_policy_engine").unwrap());
+        assert!(!flags.is_enabled("http3").unwrap());
+        assert!(flags.is_enabled_for("http3", &fra).unwrap());
+        assert!(flags.is_enabled_for("http3", &acme).unwrap());
+        assert!(!flags.is_enabled("missing").unwrap());
+        assert!(flags.is_enabled("broken").is_err());
+
+        let enabled = (0..1000)
+            .filter(|i| {
+                let outpost = FlagContext::new().with_outpost(format!("edge-{}", i));
+                flags.is_enabled_for("half", &outpost).unwrap()
+            })
+            .count();
+        assert!((400..600).contains(&enabled), "{} of 1000", enabled);
+        assert_eq!(
+            flags.is_enabled_for("half", &fra).unwrap(),
+            flags.is_enabled_for("half", &fra).unwrap()
+        );
+    }
+
+    #[test]
+    fn experiments_split_units_by_weight() {
+        let flags = Flags::new(InMemoryProvider::new());
+        flags
+            .provider()
+            .put_value(
+                "flags.streaming",
+                json!({
+                    "percentage": 50,
+                    "variants": [
+                        {"name": "control", "weight": 1},
+                        {"name": "streaming", "weight": 3}
+                    ]
+                }),
+            )
+            .unwrap();
+
+        let mut counts = HashMap::new();
+        for i in 0..4000 {
+            let client = FlagContext::new()
+                .with_outpost("edge-fra-1")
+                .with_unit(format!("client-{}", i));
+            let variant = flags.variant("streaming", &client).unwrap();
+            assert_eq!(
+                variant.is_some(),
+                flags.is_enabled_for("streaming", &client).unwrap()
+            );
+            assert_eq!(variant, flags.variant("streaming", &client).unwrap());
+            *counts.entry(variant).or_insert(0) += 1;
+        }
+
+        assert!((1800..2200).contains(&counts[&None]), "{:?}", counts);
+        let control = counts[&Some("control".to_string())];
+        assert!((350..650).contains(&control), "{:?}", counts);
+        assert!(flags
+            .variant("streaming", &FlagContext::new())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn live_flags_follow_changes() {
+        let provider = Arc::new(InMemoryProvider::new());
+        provider.put("flags.new_policy_engine", false).unwrap();
+        let flags = Flags::live(provider.clone()).unwrap();
+        assert!(!flags.is_enabled("new_policy_engine").unwrap());
+
+        provider.put("flags.new_policy_engine", true).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !flags.is_enabled("new_policy_engine").unwrap() {
+            assert!(Instant::now() < deadline, "the change never arrived");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}